@@ -0,0 +1,43 @@
+use std::alloc::{alloc_zeroed, handle_alloc_error, Layout};
+use std::ffi::c_void;
+use std::mem::align_of;
+use std::ptr;
+
+#[allow(non_camel_case_types)]
+type max_align_t = u128;
+const MAX_ALIGN: usize = align_of::<max_align_t>();
+
+fn aligned_size(size: usize) -> usize {
+    (size + (MAX_ALIGN - 1)) / MAX_ALIGN
+}
+
+fn max_align_layout(size: usize) -> Layout {
+    Layout::array::<max_align_t>(aligned_size(size)).unwrap()
+}
+
+/// Allocates `size` zeroed bytes aligned like `max_align_t` (as required by OSDI) and returns
+/// a null pointer for zero sized allocations.
+pub(crate) fn alloc(size: usize) -> *mut c_void {
+    if size == 0 {
+        return ptr::null_mut();
+    }
+    let layout = max_align_layout(size);
+    // # Safety: this is save because we check for zst above
+    let data = unsafe { alloc_zeroed(layout) } as *mut c_void;
+    if data.is_null() {
+        handle_alloc_error(layout)
+    } else {
+        data
+    }
+}
+
+/// # Safety
+/// `ptr` must be a pointer allocated by [`alloc`] with the same `size`
+pub(crate) unsafe fn dealloc(ptr: *mut c_void, size: usize) {
+    if ptr.is_null() {
+        return;
+    }
+
+    let layout = max_align_layout(size);
+    std::alloc::dealloc(ptr as *mut u8, layout)
+}
@@ -0,0 +1,414 @@
+use std::ffi::c_void;
+use std::fmt::{self, Debug};
+use std::slice;
+
+use anyhow::{bail, Result};
+use stdx::format_to;
+
+use crate::flags::{JacobianFlags, ParameterFlags};
+use crate::model::Model;
+use crate::osdi_0_3::{
+    OsdiDescriptor, OsdiInitInfo, OsdiJacobianEntry, OsdiNode, OsdiNodePair, OsdiNoiseSource,
+    OsdiParamOpvar, EVAL_RET_FLAG_FATAL, INIT_ERR_OUT_OF_BOUNDS, PARA_KIND_INST, PARA_KIND_MASK,
+    PARA_KIND_MODEL, PARA_TY_INT, PARA_TY_MASK, PARA_TY_REAL, PARA_TY_STR,
+};
+use crate::{osdi_0_4, osdi_str};
+
+/// A compact model in a loaded OSDI library.
+#[derive(Clone, Copy)]
+pub struct Descriptor {
+    raw: &'static OsdiDescriptor,
+    raw_0_4: Option<&'static osdi_0_4::OsdiDescriptor>,
+}
+
+impl Descriptor {
+    pub(crate) fn new(
+        raw: &'static OsdiDescriptor,
+        raw_0_4: Option<&'static osdi_0_4::OsdiDescriptor>,
+    ) -> Descriptor {
+        Descriptor { raw, raw_0_4 }
+    }
+
+    /// The raw OSDI 0.3 descriptor. Descriptors of OSDI 0.4 libraries start with the same
+    /// fields.
+    pub fn raw(self) -> &'static OsdiDescriptor {
+        self.raw
+    }
+
+    /// The raw OSDI 0.4 descriptor if the library implements OSDI 0.4.
+    pub fn v0_4(self) -> Option<&'static osdi_0_4::OsdiDescriptor> {
+        self.raw_0_4
+    }
+
+    pub fn name(self) -> &'static str {
+        // SAFETY: the descriptor is assumed valid
+        unsafe { osdi_str(self.raw.name) }
+    }
+
+    pub fn nodes(self) -> &'static [OsdiNode] {
+        // # SAFETY: OsdiDescriptor can only be constructed from FFI and is assumed to contain
+        // valid data
+        unsafe { slice::from_raw_parts(self.raw.nodes, self.raw.num_nodes as usize) }
+    }
+
+    pub fn num_terminals(self) -> u32 {
+        self.raw.num_terminals
+    }
+
+    pub fn terminals(self) -> &'static [OsdiNode] {
+        &self.nodes()[..self.raw.num_terminals as usize]
+    }
+
+    /// The name of `node` or `gnd` for `u32::MAX`.
+    pub fn node_name(self, node: u32) -> &'static str {
+        if node == u32::MAX {
+            "gnd"
+        } else {
+            // SAFETY: the descriptor is assumed valid
+            unsafe { osdi_str(self.nodes()[node as usize].name) }
+        }
+    }
+
+    fn param_opvars(self) -> &'static [OsdiParamOpvar] {
+        let len = self.raw.num_params + self.raw.num_opvars;
+        // # SAFETY: OsdiDescriptor can only be constructed from FFI and is assumed to contain
+        // valid data
+        unsafe { slice::from_raw_parts(self.raw.param_opvar, len as usize) }
+    }
+
+    /// The model and instance parameters of this module.
+    pub fn params(self) -> impl ExactSizeIterator<Item = Param> {
+        self.param_opvars()[..self.raw.num_params as usize]
+            .iter()
+            .enumerate()
+            .map(|(id, raw)| Param { id: id as u32, raw })
+    }
+
+    /// The operating point variables of this module.
+    pub fn opvars(self) -> impl ExactSizeIterator<Item = Param> {
+        let num_params = self.raw.num_params;
+        self.param_opvars()[num_params as usize..]
+            .iter()
+            .enumerate()
+            .map(move |(i, raw)| Param { id: num_params + i as u32, raw })
+    }
+
+    /// Returns the parameter or operating point variable with the OSDI id `id`.
+    pub fn param(self, id: u32) -> Option<Param> {
+        let raw = self.param_opvars().get(id as usize)?;
+        Some(Param { id, raw })
+    }
+
+    /// Looks up a parameter by its name or one of its aliases.
+    pub fn find_param(self, name: &str) -> Option<Param> {
+        self.params().find(|param| param.name() == name || param.aliases().any(|it| it == name))
+    }
+
+    pub fn jacobian_entries(self) -> &'static [OsdiJacobianEntry] {
+        // SAFETY: the descriptor is assumed valid
+        unsafe {
+            slice::from_raw_parts(self.raw.jacobian_entries, self.raw.num_jacobian_entries as usize)
+        }
+    }
+
+    pub fn collapsible(self) -> &'static [OsdiNodePair] {
+        // SAFETY: the descriptor is assumed valid
+        unsafe { slice::from_raw_parts(self.raw.collapsible, self.raw.num_collapsible as usize) }
+    }
+
+    pub fn noise_sources(self) -> &'static [OsdiNoiseSource] {
+        // SAFETY: the descriptor is assumed valid
+        unsafe { slice::from_raw_parts(self.raw.noise_sources, self.raw.num_noise_src as usize) }
+    }
+
+    /// The node pairs whose voltages are inputs of `eval` (empty before OSDI 0.4).
+    pub fn inputs(self) -> &'static [osdi_0_4::OsdiNodePair] {
+        match self.raw_0_4 {
+            // SAFETY: the descriptor is assumed valid
+            Some(raw) => unsafe { slice::from_raw_parts(raw.inputs, raw.num_inputs as usize) },
+            None => &[],
+        }
+    }
+
+    /// The nature of each node (unknown) that refers to the
+    /// [`Library::disciplines`](crate::Library::disciplines) (empty before OSDI 0.4).
+    pub fn unknown_natures(self) -> &'static [osdi_0_4::OsdiNatureRef] {
+        match self.raw_0_4 {
+            // SAFETY: the descriptor is assumed valid
+            Some(raw) => unsafe {
+                slice::from_raw_parts(raw.unknown_nature, raw.num_nodes as usize)
+            },
+            None => &[],
+        }
+    }
+
+    /// The nature of the residual of each node (empty before OSDI 0.4).
+    pub fn residual_natures(self) -> &'static [osdi_0_4::OsdiNatureRef] {
+        match self.raw_0_4 {
+            // SAFETY: the descriptor is assumed valid
+            Some(raw) => unsafe {
+                slice::from_raw_parts(raw.residual_nature, raw.num_nodes as usize)
+            },
+            None => &[],
+        }
+    }
+
+    pub fn num_states(self) -> u32 {
+        self.raw.num_states
+    }
+
+    pub fn new_model(self) -> Model {
+        Model::new(self)
+    }
+
+    pub(crate) fn check_init_result(self, res: OsdiInitInfo) -> Result<()> {
+        if (res.flags & EVAL_RET_FLAG_FATAL) != 0 {
+            bail!("Verilog-A $fatal was called")
+        }
+
+        if res.num_errors != 0 {
+            let mut msg = String::default();
+
+            for i in 0..res.num_errors as usize {
+                let err = unsafe { &*res.errors.add(i) };
+
+                match err.code {
+                    INIT_ERR_OUT_OF_BOUNDS => {
+                        let param = unsafe { err.payload.parameter_id };
+                        let param = self.param(param).map_or("<unknown>", |param| param.name());
+                        format_to!(msg, "value supplied for parameter '{param}' is out of bounds\n")
+                    }
+
+                    code => format_to!(msg, "unknown error: {code}\n"),
+                }
+            }
+
+            msg.pop();
+            bail!(msg)
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OsdiInitInfo {
+    fn drop(&mut self) {
+        // # SAFETY: this is save because OSDI api promises malloc allocated data and the struct can
+        // only be constructed by FFI
+        if self.num_errors != 0 && !self.errors.is_null() {
+            unsafe {
+                libc::free(self.errors as *mut c_void);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ParamTy {
+    Real,
+    Int,
+    Str,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ParamKind {
+    Model,
+    Instance,
+    Opvar,
+}
+
+/// A value that can be assigned to a parameter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamValue<'a> {
+    Real(f64),
+    Int(i32),
+    Str(&'a str),
+}
+
+impl ParamValue<'_> {
+    pub fn ty(&self) -> ParamTy {
+        match self {
+            ParamValue::Real(_) => ParamTy::Real,
+            ParamValue::Int(_) => ParamTy::Int,
+            ParamValue::Str(_) => ParamTy::Str,
+        }
+    }
+}
+
+impl From<f64> for ParamValue<'_> {
+    fn from(val: f64) -> Self {
+        ParamValue::Real(val)
+    }
+}
+
+impl From<i32> for ParamValue<'_> {
+    fn from(val: i32) -> Self {
+        ParamValue::Int(val)
+    }
+}
+
+impl<'a> From<&'a str> for ParamValue<'a> {
+    fn from(val: &'a str) -> Self {
+        ParamValue::Str(val)
+    }
+}
+
+/// A parameter or operating point variable of a [`Descriptor`].
+#[derive(Clone, Copy)]
+pub struct Param {
+    /// The id used to access this parameter with the OSDI `access` function.
+    pub id: u32,
+    raw: &'static OsdiParamOpvar,
+}
+
+impl Param {
+    pub fn raw(self) -> &'static OsdiParamOpvar {
+        self.raw
+    }
+
+    pub fn name(self) -> &'static str {
+        // SAFETY: the descriptor is assumed valid
+        unsafe { osdi_str(*self.raw.name) }
+    }
+
+    pub fn aliases(self) -> impl Iterator<Item = &'static str> {
+        // SAFETY: the descriptor is assumed valid
+        let aliases =
+            unsafe { slice::from_raw_parts(self.raw.name.add(1), self.raw.num_alias as usize) };
+        aliases.iter().map(|&alias| unsafe { osdi_str(alias) })
+    }
+
+    pub fn description(self) -> &'static str {
+        // SAFETY: the descriptor is assumed valid
+        unsafe { osdi_str(self.raw.description) }
+    }
+
+    pub fn units(self) -> &'static str {
+        // SAFETY: the descriptor is assumed valid
+        unsafe { osdi_str(self.raw.units) }
+    }
+
+    pub fn ty(self) -> ParamTy {
+        match self.raw.flags & PARA_TY_MASK {
+            PARA_TY_REAL => ParamTy::Real,
+            PARA_TY_INT => ParamTy::Int,
+            PARA_TY_STR => ParamTy::Str,
+            ty => unreachable!("unknown osdi type {ty}"),
+        }
+    }
+
+    pub fn kind(self) -> ParamKind {
+        match self.raw.flags & PARA_KIND_MASK {
+            PARA_KIND_MODEL => ParamKind::Model,
+            PARA_KIND_INST => ParamKind::Instance,
+            _ => ParamKind::Opvar,
+        }
+    }
+
+    /// The number of elements of an array parameter or 0 for scalars.
+    pub fn len(self) -> u32 {
+        self.raw.len
+    }
+
+    /// Checks that `val` can be assigned to this parameter.
+    pub(crate) fn check_value(self, val: &ParamValue, instance: bool) -> Result<()> {
+        let name = self.name();
+        match self.kind() {
+            ParamKind::Opvar => bail!("'{name}' is an operating point variable"),
+            ParamKind::Model if instance => bail!("'{name}' is not an instance parameter"),
+            _ => (),
+        }
+        if self.len() != 0 {
+            bail!("array parameter '{name}' can not be set")
+        }
+        let ty = self.ty();
+        if val.ty() != ty {
+            bail!("parameter '{name}' expects a {ty:?} value but {:?} was supplied", val.ty())
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Param")
+            .field("id", &self.id)
+            .field("name", &self.name())
+            .field("ty", &self.ty())
+            .field("kind", &self.kind())
+            .finish()
+    }
+}
+
+impl Debug for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        macro_rules! w {
+            ($($tt: tt)*) => {
+                write!(f, $($tt)*)?;
+            };
+        }
+        macro_rules! wn {
+            ($($tt: tt)*) => {
+                writeln!(f, $($tt)*)?;
+            };
+        }
+
+        for param in self.params() {
+            w!("param ");
+            w!("{:?}", param.name());
+            for alias in param.aliases() {
+                w!(", {:?}", alias);
+            }
+            if param.len() != 0 {
+                w!(" [{}]", param.len());
+            }
+            wn!();
+            let ty = ParameterFlags::from_bits(param.raw.flags).unwrap();
+            wn!("units = {:?}, desc = {:?}, flags = {ty:?}", param.units(), param.description());
+        }
+
+        wn!();
+        wn!("{} terminals", self.num_terminals());
+        for node in self.nodes() {
+            let flow = if node.is_flow { "(flow)" } else { "" };
+            // SAFETY: the descriptor is assumed valid
+            unsafe {
+                wn!(
+                    "node{flow} {:?} units = {:?}, runits = {:?}",
+                    osdi_str(node.name),
+                    osdi_str(node.units),
+                    osdi_str(node.residual_units)
+                );
+            }
+            wn!(
+                "residual {} {} {} {}",
+                node.resist_residual_off,
+                node.react_residual_off,
+                node.resist_limit_rhs_off,
+                node.react_limit_rhs_off
+            );
+        }
+        for matrix_entry in self.jacobian_entries() {
+            wn!(
+                "jacobian ({}, {}) {:?} react_ptr = {}",
+                self.node_name(matrix_entry.nodes.node_1),
+                self.node_name(matrix_entry.nodes.node_2),
+                JacobianFlags::from_bits(matrix_entry.flags).unwrap(),
+                matrix_entry.react_ptr_off,
+            );
+        }
+        for OsdiNodePair { node_1, node_2 } in self.collapsible() {
+            wn!("collapsible ({}, {})", self.node_name(*node_1), self.node_name(*node_2));
+        }
+        for OsdiNoiseSource { name, nodes: OsdiNodePair { node_1, node_2 } } in self.noise_sources()
+        {
+            // SAFETY: the descriptor is assumed valid
+            let name = unsafe { osdi_str(*name) };
+            wn!("noise {:?} ({}, {})", name, self.node_name(*node_1), self.node_name(*node_2));
+        }
+        wn!("{} states", self.raw.num_states);
+        wn!("has bound_step {}", self.raw.bound_step_offset != u32::MAX);
+        wn!("instance size {}", self.raw.instance_size);
+        wn!("model size {}", self.raw.model_size);
+        Ok(())
+    }
+}
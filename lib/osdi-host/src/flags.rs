@@ -0,0 +1,109 @@
+use bitflags::bitflags;
+
+use crate::osdi_0_3::*;
+
+bitflags! {
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub struct JacobianFlags: u32 {
+        const JACOBIAN_ENTRY_RESIST = JACOBIAN_ENTRY_RESIST;
+        const JACOBIAN_ENTRY_REACT = JACOBIAN_ENTRY_REACT;
+        const JACOBIAN_ENTRY_RESIST_CONST = JACOBIAN_ENTRY_RESIST_CONST;
+        const JACOBIAN_ENTRY_REACT_CONST = JACOBIAN_ENTRY_REACT_CONST;
+    }
+}
+
+bitflags! {
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub struct ParameterFlags: u32 {
+        const PARA_TY_REAL  = PARA_TY_REAL;
+        const PARA_TY_INT  = PARA_TY_INT;
+        const PARA_TY_STR  = PARA_TY_STR;
+        const PARA_KIND_MODEL  = PARA_KIND_MODEL;
+        const PARA_KIND_INST  = PARA_KIND_INST;
+        const PARA_KIND_OPVAR  = PARA_KIND_OPVAR;
+    }
+}
+
+bitflags! {
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub struct EvalFlags: u32 {
+        const CALC_RESIST_RESIDUAL = CALC_RESIST_RESIDUAL;
+        const CALC_REACT_RESIDUAL = CALC_REACT_RESIDUAL;
+        const CALC_RESIST_JACOBIAN = CALC_RESIST_JACOBIAN;
+        const CALC_REACT_JACOBIAN = CALC_REACT_JACOBIAN;
+        const CALC_NOISE = CALC_NOISE;
+        const CALC_OP = CALC_OP;
+        const CALC_RESIST_LIM_RHS = CALC_RESIST_LIM_RHS;
+        const CALC_REACT_LIM_RHS = CALC_REACT_LIM_RHS;
+        const ENABLE_LIM = ENABLE_LIM;
+        const INIT_LIM = INIT_LIM;
+        const ANALYSIS_NOISE = ANALYSIS_NOISE;
+        const ANALYSIS_DC = ANALYSIS_DC;
+        const ANALYSIS_AC = ANALYSIS_AC;
+        const ANALYSIS_TRAN = ANALYSIS_TRAN;
+        const ANALYSIS_IC = ANALYSIS_IC;
+        const ANALYSIS_STATIC = ANALYSIS_STATIC;
+        const ANALYSIS_NODESET = ANALYSIS_NODESET;
+
+        /// Calculate the resistive residual, Jacobian and limiting rhs.
+        const CALC_RESIST = CALC_RESIST_RESIDUAL | CALC_RESIST_JACOBIAN | CALC_RESIST_LIM_RHS;
+        /// Calculate the reactive residual, Jacobian and limiting rhs.
+        const CALC_REACT = CALC_REACT_RESIDUAL | CALC_REACT_JACOBIAN | CALC_REACT_LIM_RHS;
+    }
+}
+
+impl EvalFlags {
+    /// The flags for evaluating the DC operating point.
+    pub const DC_OP: EvalFlags =
+        EvalFlags::CALC_RESIST.union(EvalFlags::ANALYSIS_DC).union(EvalFlags::ANALYSIS_STATIC);
+
+    /// The flags for evaluating the operating point of an AC analysis. The reactive Jacobian
+    /// is calculated too so the small signal matrix can be assembled.
+    pub const AC_OP: EvalFlags = EvalFlags::CALC_RESIST
+        .union(EvalFlags::CALC_REACT_JACOBIAN)
+        .union(EvalFlags::ANALYSIS_AC)
+        .union(EvalFlags::ANALYSIS_STATIC);
+
+    /// The flags for evaluating a timestep of a transient analysis.
+    pub const TRAN: EvalFlags =
+        EvalFlags::CALC_RESIST.union(EvalFlags::CALC_REACT).union(EvalFlags::ANALYSIS_TRAN);
+
+    /// The flags for evaluating the operating point of a noise analysis.
+    pub const NOISE_OP: EvalFlags = EvalFlags::CALC_RESIST
+        .union(EvalFlags::CALC_REACT_JACOBIAN)
+        .union(EvalFlags::CALC_NOISE)
+        .union(EvalFlags::ANALYSIS_NOISE)
+        .union(EvalFlags::ANALYSIS_STATIC);
+
+    /// Adds the flags that enable `$limit` (and initialize it for the first iteration).
+    pub const fn with_limiting(self, first_iteration: bool) -> EvalFlags {
+        let flags = self.union(EvalFlags::ENABLE_LIM);
+        if first_iteration {
+            flags.union(EvalFlags::INIT_LIM)
+        } else {
+            flags
+        }
+    }
+}
+
+bitflags! {
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub struct EvalRetFlags: u32 {
+        const EVAL_RET_FLAG_LIM = EVAL_RET_FLAG_LIM;
+        const EVAL_RET_FLAG_FATAL = EVAL_RET_FLAG_FATAL;
+        const EVAL_RET_FLAG_FINISH = EVAL_RET_FLAG_FINISH;
+        const EVAL_RET_FLAG_STOP = EVAL_RET_FLAG_STOP;
+    }
+}
+
+impl EvalRetFlags {
+    /// Whether `$fatal` was called during the evaluation.
+    pub fn is_fatal(self) -> bool {
+        self.contains(EvalRetFlags::EVAL_RET_FLAG_FATAL)
+    }
+
+    /// Whether `$limit` changed any of the voltages the model was evaluated at.
+    pub fn was_limited(self) -> bool {
+        self.contains(EvalRetFlags::EVAL_RET_FLAG_LIM)
+    }
+}
@@ -0,0 +1,422 @@
+use std::ffi::c_void;
+use std::mem::swap;
+use std::{ptr, slice};
+
+use anyhow::{bail, Context, Result};
+use stdx::iter::zip;
+
+use crate::alloc::{alloc, dealloc};
+use crate::descriptor::{Descriptor, ParamKind, ParamTy, ParamValue};
+use crate::flags::{EvalFlags, EvalRetFlags};
+use crate::model::{Model, ParamStrings};
+use crate::osdi_0_3::{
+    OsdiInitInfo, OsdiNodePair, OsdiSimInfo, OsdiSimParas, ACCESS_FLAG_INSTANCE, ACCESS_FLAG_READ,
+    ACCESS_FLAG_SET,
+};
+
+/// The simulator state passed to [`Instance::eval`].
+pub struct SimInfo<'a> {
+    pub abstime: f64,
+    /// The solution of the previous iteration indexed by the simulator unknowns the nodes were
+    /// mapped to with [`Instance::map_nodes`].
+    pub prev_solve: &'a [f64],
+    /// The states (used by `$limit`) of the previous iteration, at least
+    /// [`Descriptor::num_states`] long.
+    pub prev_state: &'a [f64],
+    /// Receives the states of this iteration, at least [`Descriptor::num_states`] long.
+    pub next_state: &'a mut [f64],
+    pub flags: EvalFlags,
+}
+
+/// An instance of a [`Model`].
+pub struct Instance {
+    model: Model,
+    ptr: *mut c_void,
+    strings: ParamStrings,
+    /// The number of simulator unknowns the nodes are mapped to (including ground).
+    /// `None` until the nodes have been mapped.
+    num_unknowns: Option<usize>,
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        // SAFETY: this is save because we obtain data from `alloc`
+        unsafe { dealloc(self.ptr, self.descriptor().raw().instance_size as usize) }
+    }
+}
+
+impl Instance {
+    pub(crate) fn new(model: Model) -> Instance {
+        let ptr = alloc(model.descriptor().raw().instance_size as usize);
+        Instance { model, ptr, strings: ParamStrings::default(), num_unknowns: None }
+    }
+
+    pub fn descriptor(&self) -> Descriptor {
+        self.model.descriptor()
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// The raw instance data passed to the OSDI functions.
+    pub fn data(&self) -> *mut c_void {
+        self.ptr
+    }
+
+    /// The handle passed to the OSDI functions, the name of the module is used to identify log
+    /// messages.
+    pub fn handle(&self) -> *mut c_void {
+        self.descriptor().raw().name as *mut c_void
+    }
+
+    /// Assigns `val` to the instance parameter `name`.
+    pub fn set_param<'a>(&mut self, name: &str, val: impl Into<ParamValue<'a>>) -> Result<()> {
+        let descriptor = self.descriptor();
+        let param = descriptor
+            .find_param(name)
+            .with_context(|| format!("{} has no parameter '{name}'", descriptor.name()))?;
+        self.set_param_by_id(param.id, val)
+    }
+
+    /// Assigns `val` to the instance parameter with the OSDI id `id`.
+    pub fn set_param_by_id<'a>(&mut self, id: u32, val: impl Into<ParamValue<'a>>) -> Result<()> {
+        let val = val.into();
+        let descriptor = self.descriptor();
+        let param = match descriptor.param(id) {
+            Some(param) => param,
+            None => bail!("{} has no parameter with id {id}", descriptor.name()),
+        };
+        param.check_value(&val, true)?;
+        // SAFETY: the parameter was checked to be a scalar instance parameter with the type of
+        // `val`
+        unsafe {
+            let dst = (descriptor.raw().access)(
+                self.ptr,
+                self.model.data(),
+                id,
+                ACCESS_FLAG_SET | ACCESS_FLAG_INSTANCE,
+            );
+            self.strings.write(dst, val)
+        }
+    }
+
+    /// Runs `setup_instance` and collapses the nodes of this instance. Returns the internal
+    /// nodes that remain after collapsing. Afterwards [`node_mapping`](Self::node_mapping)
+    /// maps each node to one of the `connected_terminals` terminals, to `connected_terminals +
+    /// i` for the `i`-th returned internal node or to `u32::MAX` for ground.
+    pub fn process_params(&mut self, temp: f64, connected_terminals: u32) -> Result<Vec<u32>> {
+        let mut sim_params = OsdiSimParas {
+            names: &mut ptr::null_mut(),
+            vals: ptr::null_mut(),
+            names_str: &mut ptr::null_mut(),
+            vals_str: ptr::null_mut(),
+        };
+
+        let descriptor = self.descriptor();
+        let mut res = OsdiInitInfo { flags: 0, num_errors: 0, errors: ptr::null_mut() };
+        (descriptor.raw().setup_instance)(
+            self.handle(),
+            self.ptr,
+            self.model.data(),
+            temp,
+            connected_terminals,
+            &mut sim_params,
+            &mut res,
+        );
+        descriptor.check_init_result(res)?;
+
+        // SAFETY: self.ptr is a valid allocation and the descriptor is assumed valid
+        let collapsed = unsafe {
+            let ptr = (self.ptr as *const u8).add(descriptor.raw().collapsed_offset as usize);
+            slice::from_raw_parts(ptr as *const bool, descriptor.raw().num_collapsible as usize)
+        };
+        let internal_nodes = collapse_nodes(
+            descriptor.collapsible(),
+            collapsed,
+            self.node_mapping_mut(),
+            connected_terminals,
+        );
+        self.num_unknowns = None;
+        Ok(internal_nodes)
+    }
+
+    pub fn node_mapping(&self) -> &[u32] {
+        let descriptor = self.descriptor();
+        // SAFETY: self.ptr is a valid allocation and the descriptor is assumed valid
+        unsafe {
+            let ptr = (self.ptr as *const u8).add(descriptor.raw().node_mapping_offset as usize);
+            slice::from_raw_parts(ptr as *const u32, descriptor.raw().num_nodes as usize)
+        }
+    }
+
+    fn node_mapping_mut(&mut self) -> &mut [u32] {
+        let descriptor = self.descriptor();
+        // SAFETY: self.ptr is a valid allocation and the descriptor is assumed valid
+        unsafe {
+            let ptr = (self.ptr as *mut u8).add(descriptor.raw().node_mapping_offset as usize);
+            slice::from_raw_parts_mut(ptr as *mut u32, descriptor.raw().num_nodes as usize)
+        }
+    }
+
+    /// Replaces each entry of the [`node_mapping`](Self::node_mapping) with the simulator
+    /// unknown returned by `map`. Ground (`u32::MAX`) must be mapped to an unknown as well.
+    pub fn map_nodes(&mut self, mut map: impl FnMut(u32) -> u32) {
+        let mut num_unknowns = 0;
+        for node in self.node_mapping_mut() {
+            *node = map(*node);
+            assert_ne!(*node, u32::MAX, "all nodes must be mapped to a simulator unknown");
+            num_unknowns = num_unknowns.max(*node as usize + 1);
+        }
+        self.num_unknowns = Some(num_unknowns);
+    }
+
+    fn num_unknowns(&self) -> usize {
+        self.num_unknowns.expect("the nodes must be mapped with map_nodes before loading")
+    }
+
+    /// Binds the Jacobian entry `entry` (an index into [`Descriptor::jacobian_entries`]) to the
+    /// matrix elements `resist` and `react`. `react` is ignored for entries without a reactive
+    /// part.
+    ///
+    /// # Safety
+    ///
+    /// Both pointers must stay valid for as long as the Jacobian of this instance is loaded.
+    pub unsafe fn bind_jacobian(&mut self, entry: usize, resist: *mut f64, react: *mut f64) {
+        let descriptor = self.descriptor();
+        let info = &descriptor.jacobian_entries()[entry];
+        let data = self.ptr as *mut u8;
+        let resist_ptrs = data.add(descriptor.raw().jacobian_ptr_resist_offset as usize);
+        (resist_ptrs as *mut *mut f64).add(entry).write(resist);
+        if info.react_ptr_off != u32::MAX {
+            (data.add(info.react_ptr_off as usize) as *mut *mut f64).write(react);
+        }
+    }
+
+    pub fn eval(&mut self, info: &mut SimInfo) -> EvalRetFlags {
+        let num_states = self.descriptor().num_states() as usize;
+        assert!(info.prev_solve.len() >= self.num_unknowns(), "prev_solve is too short");
+        assert!(info.prev_state.len() >= num_states, "prev_state is too short");
+        assert!(info.next_state.len() >= num_states, "next_state is too short");
+
+        let sim_params = OsdiSimParas {
+            names: &mut ptr::null_mut(),
+            vals: ptr::null_mut(),
+            names_str: &mut ptr::null_mut(),
+            vals_str: ptr::null_mut(),
+        };
+        let mut sim_info = OsdiSimInfo {
+            paras: sim_params,
+            abstime: info.abstime,
+            prev_solve: info.prev_solve.as_ptr() as *mut f64,
+            prev_state: info.prev_state.as_ptr() as *mut f64,
+            next_state: info.next_state.as_mut_ptr(),
+            flags: info.flags.bits(),
+        };
+        let flags = (self.descriptor().raw().eval)(
+            self.handle(),
+            self.ptr,
+            self.model.data(),
+            &mut sim_info,
+        );
+        EvalRetFlags::from_bits_retain(flags)
+    }
+
+    pub fn load_residual_resist(&self, dst: &mut [f64]) {
+        assert!(dst.len() >= self.num_unknowns());
+        (self.descriptor().raw().load_residual_resist)(
+            self.ptr,
+            self.model.data(),
+            dst.as_mut_ptr(),
+        )
+    }
+
+    pub fn load_residual_react(&self, dst: &mut [f64]) {
+        assert!(dst.len() >= self.num_unknowns());
+        (self.descriptor().raw().load_residual_react)(self.ptr, self.model.data(), dst.as_mut_ptr())
+    }
+
+    pub fn load_limit_rhs_resist(&self, dst: &mut [f64]) {
+        assert!(dst.len() >= self.num_unknowns());
+        (self.descriptor().raw().load_limit_rhs_resist)(
+            self.ptr,
+            self.model.data(),
+            dst.as_mut_ptr(),
+        )
+    }
+
+    pub fn load_limit_rhs_react(&self, dst: &mut [f64]) {
+        assert!(dst.len() >= self.num_unknowns());
+        (self.descriptor().raw().load_limit_rhs_react)(
+            self.ptr,
+            self.model.data(),
+            dst.as_mut_ptr(),
+        )
+    }
+
+    pub fn load_spice_rhs_dc(&self, rhs: &mut [f64], prev_solve: &[f64]) {
+        assert!(rhs.len() >= self.num_unknowns());
+        assert!(prev_solve.len() >= self.num_unknowns());
+        (self.descriptor().raw().load_spice_rhs_dc)(
+            self.ptr,
+            self.model.data(),
+            rhs.as_mut_ptr(),
+            prev_solve.as_ptr() as *mut f64,
+        )
+    }
+
+    pub fn load_spice_rhs_tran(&self, rhs: &mut [f64], prev_solve: &[f64], alpha: f64) {
+        assert!(rhs.len() >= self.num_unknowns());
+        assert!(prev_solve.len() >= self.num_unknowns());
+        (self.descriptor().raw().load_spice_rhs_tran)(
+            self.ptr,
+            self.model.data(),
+            rhs.as_mut_ptr(),
+            prev_solve.as_ptr() as *mut f64,
+            alpha,
+        )
+    }
+
+    /// Writes the power spectral density of each noise source at `freq` to `dst`.
+    pub fn load_noise(&self, freq: f64, dst: &mut [f64]) {
+        assert!(dst.len() >= self.descriptor().noise_sources().len());
+        (self.descriptor().raw().load_noise)(self.ptr, self.model.data(), freq, dst.as_mut_ptr())
+    }
+
+    /// # Safety
+    ///
+    /// All Jacobian entries must be bound to valid matrix elements with
+    /// [`bind_jacobian`](Self::bind_jacobian).
+    pub unsafe fn load_jacobian_resist(&self) {
+        (self.descriptor().raw().load_jacobian_resist)(self.ptr, self.model.data())
+    }
+
+    /// # Safety
+    ///
+    /// All Jacobian entries must be bound to valid matrix elements with
+    /// [`bind_jacobian`](Self::bind_jacobian).
+    pub unsafe fn load_jacobian_react(&self, alpha: f64) {
+        (self.descriptor().raw().load_jacobian_react)(self.ptr, self.model.data(), alpha)
+    }
+
+    /// # Safety
+    ///
+    /// All Jacobian entries must be bound to valid matrix elements with
+    /// [`bind_jacobian`](Self::bind_jacobian).
+    pub unsafe fn load_jacobian_tran(&self, alpha: f64) {
+        (self.descriptor().raw().load_jacobian_tran)(self.ptr, self.model.data(), alpha)
+    }
+
+    /// The resistive residual of `node` computed by the last call to [`eval`](Self::eval).
+    pub fn residual_resist(&self, node: u32) -> f64 {
+        let off = self.descriptor().nodes()[node as usize].resist_residual_off;
+        self.read_f64(off)
+    }
+
+    /// The reactive residual of `node` computed by the last call to [`eval`](Self::eval).
+    pub fn residual_react(&self, node: u32) -> f64 {
+        let off = self.descriptor().nodes()[node as usize].react_residual_off;
+        self.read_f64(off)
+    }
+
+    /// The value of the scalar operating point variable with the OSDI id `id` computed by the
+    /// last call to [`eval`](Self::eval) with [`EvalFlags::CALC_OP`]. Integer variables are
+    /// converted to `f64`.
+    pub fn read_opvar(&self, id: u32) -> Result<f64> {
+        let descriptor = self.descriptor();
+        let param = match descriptor.param(id) {
+            Some(param) if param.kind() == ParamKind::Opvar => param,
+            _ => bail!("{} has no operating point variable with id {id}", descriptor.name()),
+        };
+        if param.len() != 0 {
+            bail!("array operating point variable '{}' can not be read", param.name())
+        }
+        let src = (descriptor.raw().access)(
+            self.ptr,
+            self.model.data(),
+            id,
+            ACCESS_FLAG_READ | ACCESS_FLAG_INSTANCE,
+        );
+        if src.is_null() {
+            bail!("invalid parameter access")
+        }
+        // SAFETY: the variable was checked to be a scalar with the type that is read
+        let val = unsafe {
+            match param.ty() {
+                ParamTy::Real => (src as *const f64).read(),
+                ParamTy::Int => (src as *const i32).read() as f64,
+                ParamTy::Str => {
+                    bail!("string operating point variable '{}' can not be read", param.name())
+                }
+            }
+        };
+        Ok(val)
+    }
+
+    fn read_f64(&self, off: u32) -> f64 {
+        if off == u32::MAX {
+            return 0.0;
+        }
+        // SAFETY: self.ptr is a valid allocation and the descriptor is assumed valid
+        unsafe { ((self.ptr as *const u8).add(off as usize) as *const f64).read() }
+    }
+}
+
+/// Collapses the nodes in `node_mapping` (initially each node is mapped to itself) according to
+/// the `collapsed` flags of the `collapsible` node pairs. Returns the remaining internal nodes.
+pub(crate) fn collapse_nodes(
+    collapsible: &[OsdiNodePair],
+    collapsed: &[bool],
+    node_mapping: &mut [u32],
+    connected_terminals: u32,
+) -> Vec<u32> {
+    let mut back_map: Vec<u32> = (connected_terminals..node_mapping.len() as u32).collect();
+
+    //  populate nodes with themselves
+    for (node, node_mapping) in node_mapping.iter_mut().enumerate() {
+        *node_mapping = node as u32
+    }
+
+    for (candidate, is_collapsed) in zip(collapsible, collapsed) {
+        if !is_collapsed {
+            continue;
+        }
+
+        let from = candidate.node_1;
+        let to = candidate.node_2;
+
+        let mut mapped_from = node_mapping[from as usize];
+        let mut collapse_to_gnd = to == u32::MAX;
+        let mut mapped_to = if collapse_to_gnd {
+            u32::MAX
+        } else {
+            let mapped = node_mapping[to as usize];
+            collapse_to_gnd |= mapped == u32::MAX;
+            mapped
+        };
+
+        // terminals cannot be collapsed
+        if mapped_from < connected_terminals && (collapse_to_gnd || mapped_to < connected_terminals)
+        {
+            continue;
+        }
+
+        // ensure that to is always the smaller node
+        if !collapse_to_gnd && mapped_from < mapped_to {
+            swap(&mut mapped_from, &mut mapped_to)
+        }
+
+        // replace nodes mapped to from with to and reduce the number of nodes
+        for dst in node_mapping.iter_mut() {
+            if *dst == mapped_from {
+                *dst = mapped_to
+            } else if *dst > mapped_from && *dst != u32::MAX {
+                *dst -= 1
+            }
+        }
+        // public nodes can not be removed to no need to track them
+        back_map.remove((mapped_from - connected_terminals) as usize);
+    }
+
+    back_map
+}
@@ -0,0 +1,242 @@
+//! Loads OSDI libraries and drives the compact models they contain.
+//!
+//! The raw FFI bindings for each supported OSDI revision are available in [`osdi_0_3`] and
+//! [`osdi_0_4`]. On top of these this crate offers typed handles that own the memory of models
+//! ([`Model`]) and instances ([`Instance`]) and check parameter types and buffer sizes, so that
+//! simulators and tests do not have to reimplement the unsafe parts of the OSDI API.
+
+use std::ffi::{c_char, c_void, CStr};
+use std::panic::catch_unwind;
+use std::{ptr, slice};
+
+use anyhow::{bail, Result};
+use camino::Utf8Path;
+use log::{debug, error, info, warn};
+
+pub use crate::descriptor::{Descriptor, Param, ParamKind, ParamTy, ParamValue};
+pub use crate::flags::{EvalFlags, EvalRetFlags, JacobianFlags, ParameterFlags};
+pub use crate::instance::{Instance, SimInfo};
+pub use crate::model::Model;
+
+mod alloc;
+mod descriptor;
+mod flags;
+mod instance;
+mod model;
+#[cfg(test)]
+mod tests;
+
+// autogenerated
+#[allow(warnings)]
+pub mod osdi_0_3;
+#[allow(warnings)]
+pub mod osdi_0_4;
+
+use crate::osdi_0_3::{
+    OsdiDescriptor, OsdiLimFunction, LOG_FMT_ERR, LOG_LVL_DEBUG, LOG_LVL_DISPLAY, LOG_LVL_ERR,
+    LOG_LVL_FATAL, LOG_LVL_INFO, LOG_LVL_MASK, LOG_LVL_WARN,
+};
+
+/// A loaded OSDI library.
+///
+/// Libraries are never unloaded so that the descriptors (and all strings they contain) can be
+/// handed out with a `'static` lifetime.
+pub struct Library {
+    lib: &'static libloading::Library,
+    version: (u32, u32),
+    descriptors: Vec<Descriptor>,
+    natures: &'static [osdi_0_4::OsdiNature],
+    disciplines: &'static [osdi_0_4::OsdiDiscipline],
+    attributes: &'static [osdi_0_4::OsdiAttribute],
+}
+
+impl Library {
+    /// Loads the OSDI 0.3 or 0.4 library at `path` and redirects its log messages to the `log`
+    /// crate.
+    ///
+    /// # Safety
+    ///
+    /// Loading a library runs its initialization routines. `path` must point to a valid OSDI
+    /// library.
+    pub unsafe fn load(path: &Utf8Path) -> Result<Library> {
+        let lib = libloading::Library::new(path)?;
+        let lib: &'static _ = Box::leak(Box::new(lib));
+
+        let major_version: &u32 = *lib.get(b"OSDI_VERSION_MAJOR\0")?;
+        let minor_version: &u32 = *lib.get(b"OSDI_VERSION_MINOR\0")?;
+        let version = (*major_version, *minor_version);
+
+        let num_descriptors: &u32 = *lib.get(b"OSDI_NUM_DESCRIPTORS\0")?;
+        let num_descriptors = *num_descriptors as usize;
+        let descriptors = match version {
+            (0, 3) => {
+                let descriptors: *const OsdiDescriptor = *lib.get(b"OSDI_DESCRIPTORS\0")?;
+                slice::from_raw_parts(descriptors, num_descriptors)
+                    .iter()
+                    .map(|raw| Descriptor::new(raw, None))
+                    .collect()
+            }
+            // OSDI 0.4 only appends fields to the descriptor
+            (0, 4) => {
+                let descriptors: *const osdi_0_4::OsdiDescriptor =
+                    *lib.get(b"OSDI_DESCRIPTORS\0")?;
+                slice::from_raw_parts(descriptors, num_descriptors)
+                    .iter()
+                    .map(|raw| {
+                        let prefix = &*(raw as *const osdi_0_4::OsdiDescriptor).cast();
+                        Descriptor::new(prefix, Some(raw))
+                    })
+                    .collect()
+            }
+            (major, minor) => bail!("{path} targets the unsupported OSDI version v{major}.{minor}"),
+        };
+
+        let (natures, disciplines, attributes) = if version == (0, 4) {
+            (
+                table(lib, b"OSDI_NATURES\0", b"OSDI_NUM_NATURES\0")?,
+                table(lib, b"OSDI_DISCIPLINES\0", b"OSDI_NUM_DISCIPLINES\0")?,
+                table(lib, b"OSDI_ATTRIBUTES\0", b"OSDI_NUM_ATTRIBUTES\0")?,
+            )
+        } else {
+            (&[][..], &[][..], &[][..])
+        };
+
+        if let Ok(osdi_log_ptr) =
+            lib.get::<*mut unsafe extern "C" fn(*mut c_void, *const c_char, u32)>(b"osdi_log\0")
+        {
+            osdi_log_ptr.write(osdi_log)
+        }
+
+        Ok(Library { lib, version, descriptors, natures, disciplines, attributes })
+    }
+
+    /// The `(major, minor)` OSDI version implemented by this library.
+    pub fn version(&self) -> (u32, u32) {
+        self.version
+    }
+
+    pub fn descriptors(&self) -> &[Descriptor] {
+        &self.descriptors
+    }
+
+    /// Returns the descriptor of the module called `name`.
+    pub fn descriptor(&self, name: &str) -> Option<Descriptor> {
+        self.descriptors.iter().copied().find(|descriptor| descriptor.name() == name)
+    }
+
+    /// The natures of the unknowns of all models (empty before OSDI 0.4).
+    pub fn natures(&self) -> &'static [osdi_0_4::OsdiNature] {
+        self.natures
+    }
+
+    /// The disciplines of the unknowns of all models (empty before OSDI 0.4).
+    pub fn disciplines(&self) -> &'static [osdi_0_4::OsdiDiscipline] {
+        self.disciplines
+    }
+
+    /// The attributes of the [`natures`](Self::natures) and [`disciplines`](Self::disciplines)
+    /// with literal values (empty before OSDI 0.4).
+    pub fn attributes(&self) -> &'static [osdi_0_4::OsdiAttribute] {
+        self.attributes
+    }
+
+    /// The `$limit` functions used by the models in this library.
+    pub fn lim_functions(&self) -> &[OsdiLimFunction] {
+        // SAFETY: the library was checked to be a valid OSDI library during loading
+        unsafe {
+            let (base, len) = self.lim_table();
+            if len == 0 {
+                return &[];
+            }
+            slice::from_raw_parts(base, len)
+        }
+    }
+
+    /// Registers `func` as the implementation of the `$limit` function `name`. Returns `false`
+    /// if no model in this library uses that function.
+    ///
+    /// # Safety
+    ///
+    /// `func` must be an `extern "C"` function with the signature OSDI expects for a `$limit`
+    /// function with the number of arguments listed in [`lim_functions`](Self::lim_functions).
+    pub unsafe fn register_lim_function(&self, name: &str, func: *mut c_void) -> bool {
+        let (base, len) = self.lim_table();
+        let mut found = false;
+        for i in 0..len {
+            let lim_func = base.add(i);
+            if osdi_str((*lim_func).name) == name {
+                (*lim_func).func_ptr = func;
+                found = true;
+            }
+        }
+        found
+    }
+
+    unsafe fn lim_table(&self) -> (*mut OsdiLimFunction, usize) {
+        let base: libloading::Symbol<*mut OsdiLimFunction> = match self.lib.get(b"OSDI_LIM_TABLE\0")
+        {
+            Ok(base) => base,
+            Err(_) => return (ptr::null_mut(), 0),
+        };
+        let len: libloading::Symbol<*const u32> = match self.lib.get(b"OSDI_LIM_TABLE_LEN\0") {
+            Ok(len) => len,
+            Err(_) => return (ptr::null_mut(), 0),
+        };
+        (*base, **len as usize)
+    }
+}
+
+/// Reads the array `sym` with `len_sym` entries exported by `lib`.
+unsafe fn table<T>(
+    lib: &'static libloading::Library,
+    sym: &[u8],
+    len_sym: &[u8],
+) -> Result<&'static [T]> {
+    let base: *const T = *lib.get(sym)?;
+    let len: &u32 = *lib.get(len_sym)?;
+    if *len == 0 {
+        return Ok(&[]);
+    }
+    Ok(slice::from_raw_parts(base, *len as usize))
+}
+
+/// Converts a string of an OSDI library to a `&str`.
+///
+/// # Safety
+///
+/// `raw` must point to a valid nul terminated string that lives for the rest of the program.
+pub unsafe fn osdi_str(raw: *const c_char) -> &'static str {
+    CStr::from_ptr(raw).to_str().expect("All OSDI strings must be encoded in UTF-8")
+}
+
+unsafe extern "C" fn osdi_log(handle: *mut c_void, msg: *const c_char, lvl: u32) {
+    let _ = catch_unwind(|| osdi_log_impl(handle, msg, lvl));
+}
+
+unsafe fn osdi_log_impl(handle: *mut c_void, msg: *const c_char, lvl: u32) {
+    // the handle is always the name of the module (see `Instance::handle`)
+    let instance = osdi_str(handle as *const c_char);
+    let msg = osdi_str(msg);
+
+    if (lvl & LOG_FMT_ERR) == 0 {
+        match lvl & LOG_LVL_MASK {
+            LOG_LVL_DEBUG => debug!("{instance} - {msg}"),
+            LOG_LVL_DISPLAY => print!("{instance} - {msg}"),
+            LOG_LVL_INFO => info!("{instance} - {msg}"),
+            LOG_LVL_WARN => warn!("{instance} - {msg}"),
+            LOG_LVL_ERR => error!("{instance} - {msg}"),
+            LOG_LVL_FATAL => error!("{instance} - FATAL {msg}"),
+            _ => error!("{instance} - UNKNOWN_LOG_LVL {msg}"),
+        }
+    } else {
+        match lvl & LOG_LVL_MASK {
+            LOG_LVL_DEBUG => debug!("{instance} - failed to format\"{msg}\""),
+            LOG_LVL_DISPLAY => println!("{instance} - failed to format\"{msg}\""),
+            LOG_LVL_INFO => info!("{instance} - failed to format\"{msg}\""),
+            LOG_LVL_WARN => warn!("{instance} - failed to format\"{msg}\""),
+            LOG_LVL_ERR => error!("{instance} - failed to format\"{msg}\""),
+            LOG_LVL_FATAL => error!("{instance} - FATAL failed to format\"{msg}\""),
+            _ => error!("{instance} - UNKNOWN_LOG_LVL failed to format\"{msg}\""),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CString};
+use std::ptr;
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+
+use crate::alloc::{alloc, dealloc};
+use crate::descriptor::{Descriptor, ParamValue};
+use crate::instance::Instance;
+use crate::osdi_0_3::{OsdiInitInfo, OsdiSimParas, ACCESS_FLAG_SET};
+
+/// A model of a [`Descriptor`]: the model parameters (and the defaults of the instance
+/// parameters) shared by multiple instances.
+///
+/// Cloning a model is cheap and returns a handle to the same model data.
+#[derive(Clone)]
+pub struct Model {
+    pub(crate) data: Rc<ModelData>,
+}
+
+pub(crate) struct ModelData {
+    pub(crate) descriptor: Descriptor,
+    pub(crate) ptr: *mut c_void,
+    strings: ParamStrings,
+}
+
+impl Drop for ModelData {
+    fn drop(&mut self) {
+        // SAFETY: this is save because we obtain data from `alloc`
+        unsafe { dealloc(self.ptr, self.descriptor.raw().model_size as usize) }
+    }
+}
+
+impl Model {
+    pub(crate) fn new(descriptor: Descriptor) -> Model {
+        let ptr = alloc(descriptor.raw().model_size as usize);
+        Model { data: Rc::new(ModelData { descriptor, ptr, strings: ParamStrings::default() }) }
+    }
+
+    pub fn descriptor(&self) -> Descriptor {
+        self.data.descriptor
+    }
+
+    /// The raw model data passed to the OSDI functions.
+    pub fn data(&self) -> *mut c_void {
+        self.data.ptr
+    }
+
+    /// Assigns `val` to the model (or instance) parameter `name`. Instance parameters set on the
+    /// model serve as defaults for all instances that do not override them.
+    pub fn set_param<'a>(&self, name: &str, val: impl Into<ParamValue<'a>>) -> Result<()> {
+        let descriptor = self.descriptor();
+        let param = descriptor
+            .find_param(name)
+            .with_context(|| format!("{} has no parameter '{name}'", descriptor.name()))?;
+        self.set_param_by_id(param.id, val)
+    }
+
+    /// Assigns `val` to the parameter with the OSDI id `id`.
+    pub fn set_param_by_id<'a>(&self, id: u32, val: impl Into<ParamValue<'a>>) -> Result<()> {
+        let val = val.into();
+        let descriptor = self.descriptor();
+        let param = match descriptor.param(id) {
+            Some(param) => param,
+            None => bail!("{} has no parameter with id {id}", descriptor.name()),
+        };
+        param.check_value(&val, false)?;
+        // SAFETY: the parameter was checked to be a scalar model or instance parameter with
+        // the type of `val` and no instance is passed so the model data is accessed
+        unsafe {
+            let dst = (descriptor.raw().access)(ptr::null_mut(), self.data(), id, ACCESS_FLAG_SET);
+            self.data.strings.write(dst, val)
+        }
+    }
+
+    /// Runs `setup_model` and reports the errors it found.
+    pub fn process_params(&self) -> Result<()> {
+        let mut sim_params = OsdiSimParas {
+            names: &mut ptr::null_mut(),
+            vals: ptr::null_mut(),
+            names_str: &mut ptr::null_mut(),
+            vals_str: ptr::null_mut(),
+        };
+
+        let descriptor = self.descriptor();
+        let mut res = OsdiInitInfo { flags: 0, num_errors: 0, errors: ptr::null_mut() };
+        (descriptor.raw().setup_model)(
+            descriptor.raw().name as *mut c_void,
+            self.data(),
+            &mut sim_params,
+            &mut res,
+        );
+        descriptor.check_init_result(res)
+    }
+
+    pub fn new_instance(&self) -> Instance {
+        Instance::new(self.clone())
+    }
+}
+
+/// Owns the strings assigned to string parameters. OSDI only stores a pointer (which
+/// `setup_instance` may copy from the model to its instances) so the strings are kept alive
+/// as long as the model or instance data.
+#[derive(Default)]
+pub(crate) struct ParamStrings(RefCell<Vec<CString>>);
+
+impl ParamStrings {
+    /// Writes `val` to `dst`.
+    ///
+    /// # Safety
+    ///
+    /// `dst` must be the pointer returned by the OSDI `access` function for a parameter with the
+    /// type of `val`.
+    pub(crate) unsafe fn write(&self, dst: *mut c_void, val: ParamValue) -> Result<()> {
+        if dst.is_null() {
+            bail!("invalid parameter access")
+        }
+        match val {
+            ParamValue::Real(val) => (dst as *mut f64).write(val),
+            ParamValue::Int(val) => (dst as *mut i32).write(val),
+            ParamValue::Str(val) => {
+                let val = CString::new(val).context("string may not contain null terminators")?;
+                (dst as *mut *const c_char).write(val.as_ptr());
+                self.0.borrow_mut().push(val);
+            }
+        }
+        Ok(())
+    }
+}
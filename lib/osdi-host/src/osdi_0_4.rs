@@ -3,7 +3,7 @@
 use std::os::raw::{c_char, c_void};
 
 pub const OSDI_VERSION_MAJOR_CURR: u32 = 0;
-pub const OSDI_VERSION_MINOR_CURR: u32 = 3;
+pub const OSDI_VERSION_MINOR_CURR: u32 = 4;
 pub const PARA_TY_MASK: u32 = 3;
 pub const PARA_TY_REAL: u32 = 0;
 pub const PARA_TY_INT: u32 = 1;
@@ -49,6 +49,16 @@ pub const LOG_LVL_ERR: u32 = 4;
 pub const LOG_LVL_FATAL: u32 = 5;
 pub const LOG_FMT_ERR: u32 = 16;
 pub const INIT_ERR_OUT_OF_BOUNDS: u32 = 1;
+pub const ATTR_TYPE_REAL: u32 = 0;
+pub const ATTR_TYPE_INT: u32 = 1;
+pub const ATTR_TYPE_STR: u32 = 2;
+pub const NATREF_NONE: u32 = 0;
+pub const NATREF_NATURE: u32 = 1;
+pub const NATREF_DISCIPLINE_FLOW: u32 = 2;
+pub const NATREF_DISCIPLINE_POTENTIAL: u32 = 3;
+pub const DOMAIN_NOT_GIVEN: u32 = 0;
+pub const DOMAIN_DISCRETE: u32 = 1;
+pub const DOMAIN_CONTINUOUS: u32 = 2;
 
 #[repr(C)]
 pub struct OsdiLimFunction {
@@ -124,6 +134,44 @@ pub struct OsdiNoiseSource {
     pub nodes: OsdiNodePair,
 }
 #[repr(C)]
+pub struct OsdiNatureRef {
+    pub ref_type: u32,
+    pub index: u32,
+}
+#[repr(C)]
+pub struct OsdiNature {
+    pub name: *mut c_char,
+    pub parent_type: u32,
+    pub parent: u32,
+    pub ddt: u32,
+    pub idt: u32,
+    pub attr_start: u32,
+    pub num_attr: u32,
+}
+#[repr(C)]
+pub struct OsdiDiscipline {
+    pub name: *mut c_char,
+    pub flow: u32,
+    pub potential: u32,
+    pub domain: u32,
+    pub attr_start: u32,
+    pub num_flow_attr: u32,
+    pub num_potential_attr: u32,
+    pub num_user_attr: u32,
+}
+#[repr(C)]
+pub union OsdiAttributeValue {
+    pub string: *mut c_char,
+    pub integer: i32,
+    pub real: f64,
+}
+#[repr(C)]
+pub struct OsdiAttribute {
+    pub name: *mut c_char,
+    pub value_type: u32,
+    pub value: OsdiAttributeValue,
+}
+#[repr(C)]
 #[non_exhaustive]
 pub struct OsdiDescriptor {
     pub name: *mut c_char,
@@ -163,6 +211,18 @@ pub struct OsdiDescriptor {
     pub load_jacobian_resist: fn(*mut c_void, *mut c_void),
     pub load_jacobian_react: fn(*mut c_void, *mut c_void, f64),
     pub load_jacobian_tran: fn(*mut c_void, *mut c_void, f64),
+    pub given_flag_model: fn(*mut c_void, u32) -> u32,
+    pub given_flag_instance: fn(*mut c_void, u32) -> u32,
+    pub num_resistive_jacobian_entries: u32,
+    pub num_reactive_jacobian_entries: u32,
+    pub write_jacobian_array_resist: fn(*mut c_void, *mut c_void, *mut f64),
+    pub write_jacobian_array_react: fn(*mut c_void, *mut c_void, *mut f64),
+    pub num_inputs: u32,
+    pub inputs: *mut OsdiNodePair,
+    pub load_jacobian_with_offset_resist: fn(*mut c_void, *mut c_void, usize),
+    pub load_jacobian_with_offset_react: fn(*mut c_void, *mut c_void, usize),
+    pub unknown_nature: *mut OsdiNatureRef,
+    pub residual_nature: *mut OsdiNatureRef,
 }
 impl OsdiDescriptor {
     pub fn access(
@@ -253,4 +313,42 @@ impl OsdiDescriptor {
     pub fn load_jacobian_tran(&self, inst: *mut c_void, model: *mut c_void, alpha: f64) {
         (self.load_jacobian_tran)(inst, model, alpha)
     }
+    pub fn given_flag_model(&self, model: *mut c_void, id: u32) -> u32 {
+        (self.given_flag_model)(model, id)
+    }
+    pub fn given_flag_instance(&self, inst: *mut c_void, id: u32) -> u32 {
+        (self.given_flag_instance)(inst, id)
+    }
+    pub fn write_jacobian_array_resist(
+        &self,
+        inst: *mut c_void,
+        model: *mut c_void,
+        destination: *mut f64,
+    ) {
+        (self.write_jacobian_array_resist)(inst, model, destination)
+    }
+    pub fn write_jacobian_array_react(
+        &self,
+        inst: *mut c_void,
+        model: *mut c_void,
+        destination: *mut f64,
+    ) {
+        (self.write_jacobian_array_react)(inst, model, destination)
+    }
+    pub fn load_jacobian_with_offset_resist(
+        &self,
+        inst: *mut c_void,
+        model: *mut c_void,
+        offset: usize,
+    ) {
+        (self.load_jacobian_with_offset_resist)(inst, model, offset)
+    }
+    pub fn load_jacobian_with_offset_react(
+        &self,
+        inst: *mut c_void,
+        model: *mut c_void,
+        offset: usize,
+    ) {
+        (self.load_jacobian_with_offset_react)(inst, model, offset)
+    }
 }
@@ -0,0 +1,111 @@
+use std::fs;
+use std::mem::{size_of, MaybeUninit};
+use std::ptr::addr_of;
+
+use stdx::openvaf_test_data;
+
+use crate::instance::collapse_nodes;
+use crate::osdi_0_3::OsdiNodePair;
+use crate::osdi_0_4;
+
+const GND: u32 = u32::MAX;
+
+fn pair(node_1: u32, node_2: u32) -> OsdiNodePair {
+    OsdiNodePair { node_1, node_2 }
+}
+
+#[test]
+fn collapse_internal_node() {
+    // terminals a, c and internal nodes ai, ci
+    let collapsible = [pair(2, 0), pair(3, 1)];
+    let mut node_mapping = [0; 4];
+    let internal = collapse_nodes(&collapsible, &[true, false], &mut node_mapping, 2);
+    assert_eq!(node_mapping, [0, 1, 0, 2]);
+    assert_eq!(internal, [3]);
+}
+
+#[test]
+fn collapse_to_gnd() {
+    let collapsible = [pair(2, GND), pair(3, 2)];
+    let mut node_mapping = [0; 4];
+    let internal = collapse_nodes(&collapsible, &[true, true], &mut node_mapping, 2);
+    assert_eq!(node_mapping, [0, 1, GND, GND]);
+    assert!(internal.is_empty());
+}
+
+#[test]
+fn terminals_are_not_collapsed() {
+    let collapsible = [pair(0, 1), pair(1, GND)];
+    let mut node_mapping = [0; 3];
+    let internal = collapse_nodes(&collapsible, &[true, true], &mut node_mapping, 2);
+    assert_eq!(node_mapping, [0, 1, 2]);
+    assert_eq!(internal, [2]);
+}
+
+#[test]
+fn unconnected_terminal_is_collapsed() {
+    // only the first terminal is connected so the second one behaves like an internal node
+    let collapsible = [pair(1, 0)];
+    let mut node_mapping = [0; 3];
+    let internal = collapse_nodes(&collapsible, &[true], &mut node_mapping, 1);
+    assert_eq!(node_mapping, [0, 0, 1]);
+    assert_eq!(internal, [2]);
+}
+
+/// Computes the size and field offsets of OSDI structs in the format of
+/// `test_data/osdi/osdi_0_4_layout.txt`.
+macro_rules! layout {
+    ($($ty: ident { $($field: ident),* $(,)? }),*) => {{
+        let mut res = Vec::new();
+        $(
+            res.push(format!("{} {}", stringify!($ty), size_of::<osdi_0_4::$ty>()));
+            let val = MaybeUninit::<osdi_0_4::$ty>::uninit();
+            let base = val.as_ptr();
+            $(
+                // SAFETY: only the address of the field is computed
+                let off = unsafe { addr_of!((*base).$field) as usize - base as usize };
+                res.push(format!("{}.{} {off}", stringify!($ty), stringify!($field)));
+            )*
+        )*
+        res
+    }};
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn osdi_0_4_layout() {
+    let fixture =
+        fs::read_to_string(openvaf_test_data("osdi").join("osdi_0_4_layout.txt")).unwrap();
+    let fixture: Vec<_> = fixture.lines().filter(|line| !line.starts_with('#')).collect();
+    let layout = layout! {
+        OsdiNodePair { node_1, node_2 },
+        OsdiNode {
+            name, units, residual_units, resist_residual_off, react_residual_off,
+            resist_limit_rhs_off, react_limit_rhs_off, is_flow,
+        },
+        OsdiNatureRef { ref_type, index },
+        OsdiNature { name, parent_type, parent, ddt, idt, attr_start, num_attr },
+        OsdiDiscipline {
+            name, flow, potential, domain, attr_start, num_flow_attr, num_potential_attr,
+            num_user_attr,
+        },
+        OsdiAttributeValue { string, integer, real },
+        OsdiAttribute { name, value_type, value },
+        OsdiDescriptor {
+            name, num_nodes, num_terminals, nodes, num_jacobian_entries, jacobian_entries,
+            num_collapsible, collapsible, collapsed_offset, noise_sources, num_noise_src,
+            num_params, num_instance_params, num_opvars, param_opvar, node_mapping_offset,
+            jacobian_ptr_resist_offset, num_states, state_idx_off, bound_step_offset, instance_size,
+            model_size, access, setup_model, setup_instance, eval, load_noise, load_residual_resist,
+            load_residual_react, load_limit_rhs_resist, load_limit_rhs_react, load_spice_rhs_dc,
+            load_spice_rhs_tran, load_jacobian_resist, load_jacobian_react, load_jacobian_tran,
+            given_flag_model, given_flag_instance, num_resistive_jacobian_entries,
+            num_reactive_jacobian_entries, write_jacobian_array_resist, write_jacobian_array_react,
+            num_inputs, inputs, load_jacobian_with_offset_resist, load_jacobian_with_offset_react,
+            unknown_nature, residual_nature,
+        }
+    };
+    for line in layout {
+        assert!(fixture.contains(&&*line), "{line} does not match the OSDI 0.4 header");
+    }
+}
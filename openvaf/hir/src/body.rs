@@ -1,10 +1,13 @@
 use std::sync::Arc;
 
+use basedb::lints::{Lint, LintSrc};
+use basedb::BaseDB;
 use hir_def::db::HirDefDB;
 use hir_def::DefWithBodyId;
 use hir_ty::db::HirTyDB;
 use hir_ty::inference;
 use hir_ty::types::{Signature, Ty};
+use syntax::sourcemap::FileSpan;
 
 pub use hir_def::expr::Event;
 pub use hir_def::{expr::CaseCond, BuiltIn, Case, ExprId, Literal, ParamSysFun, StmtId, Type};
@@ -15,26 +18,39 @@ use crate::{BranchWrite, Function, FunctionArg, NatureAttribute, Parameter, Vari
 
 #[derive(Debug, Clone)]
 pub struct Body {
+    id: DefWithBodyId,
     body: Arc<hir_def::body::Body>,
     infere: Arc<inference::InferenceResult>,
 }
 impl Body {
     pub(crate) fn new(id: DefWithBodyId, db: &CompilationDB) -> Body {
-        Body { body: db.body(id), infere: db.inference_result(id) }
+        Body { id, body: db.body(id), infere: db.inference_result(id) }
     }
 
     pub fn borrow(&self) -> BodyRef<'_> {
-        BodyRef { body: &self.body, infere: &self.infere }
+        BodyRef { id: self.id, body: &self.body, infere: &self.infere }
     }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct BodyRef<'a> {
+    id: DefWithBodyId,
     body: &'a hir_def::body::Body,
     infere: &'a inference::InferenceResult,
 }
 
 impl<'a> BodyRef<'a> {
+    /// Returns a location that uniquely identifies `expr` across all bodies
+    pub fn expr_loc(&self, expr: ExprId) -> ExprLoc {
+        ExprLoc { body: self.id, expr, stmt: None }
+    }
+
+    /// Like [`expr_loc`](Self::expr_loc) but also records the statement `stmt` that contains
+    /// `expr`, which determines the lint levels of the diagnostics reported for the expression.
+    pub fn stmt_expr_loc(&self, stmt: Option<StmtId>, expr: ExprId) -> ExprLoc {
+        ExprLoc { body: self.id, expr, stmt }
+    }
+
     pub fn entry(&self) -> &'a [StmtId] {
         &self.body.entry_stmts
     }
@@ -191,6 +207,40 @@ impl<'a> BodyRef<'a> {
     }
 }
 
+/// Identifies an expression within a specific body.
+/// Allows mapping lowered code back to the source that it was generated from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprLoc {
+    body: DefWithBodyId,
+    expr: ExprId,
+    stmt: Option<StmtId>,
+}
+
+impl ExprLoc {
+    pub fn expr(self) -> ExprId {
+        self.expr
+    }
+
+    /// Returns the span of the expression in the original source files
+    /// (`None` for expressions that were created during desugaring)
+    pub fn file_span(self, db: &CompilationDB) -> Option<FileSpan> {
+        let body_sm = db.body_source_map(self.body);
+        let ptr = body_sm.expr_map_back.get(self.expr)?.as_ref()?;
+        let root_file = self.body.file(db);
+        let parse = db.parse(root_file);
+        Some(parse.to_file_span(ptr.range(), &db.sourcemap(root_file)))
+    }
+
+    /// Returns where the level of `lint` is determined for this expression: the lint attributes
+    /// of the statement that contains the expression.
+    pub fn lint_src(self, db: &CompilationDB, lint: Lint) -> LintSrc {
+        match self.stmt {
+            Some(stmt) => db.body_source_map(self.body).lint_src(stmt, lint),
+            None => LintSrc::GLOBAL,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Copy)]
 pub enum AssignmentLhs {
     Variable(Variable),
@@ -11,7 +11,7 @@ use basedb::{BaseDB, BaseDatabase, FileId, Vfs, VfsPath, VfsStorage, STANDARD_FL
 use hir_def::db::{HirDefDB, HirDefDatabase, InternDatabase};
 use hir_ty::db::HirTyDatabase;
 use parking_lot::RwLock;
-use salsa::ParallelDatabase;
+use salsa::{Database, Durability, ParallelDatabase};
 use stdx::Upcast;
 use typed_index_collections::TiSlice;
 
@@ -69,6 +69,45 @@ impl CompilationDB {
         CompilationUnit { root_file: self.root_file }
     }
 
+    /// All files on disk that were accessed by the compilation: the root file, every file
+    /// reached through `include` and the paths at which an include was looked up but not found.
+    pub fn disk_files(&self) -> Vec<AbsPathBuf> {
+        let vfs = self.vfs.read();
+        (0..vfs.len())
+            .filter_map(|file| Some(vfs.file_path(FileId(file as u16)).as_path()?.to_path_buf()))
+            .collect()
+    }
+
+    /// Replaces the contents of the file at `path` (`None` if the file was deleted).
+    /// Files that were never accessed by the compilation are ignored.
+    ///
+    /// Returns `true` if the contents changed. Only the queries that depend on a changed file
+    /// are recomputed by the next compilation.
+    pub fn update_file(&mut self, path: AbsPathBuf, contents: Option<Vec<u8>>) -> bool {
+        let mut vfs = self.vfs.write();
+        let file = if let Some(file) = vfs.file_id(&path.into()) {
+            file
+        } else {
+            return false;
+        };
+        let changed = vfs.set_file_contents(file, contents.ok_or(io::ErrorKind::NotFound).into());
+        drop(vfs);
+        if changed {
+            // `file_text` reports a synthetic read with low durability
+            self.salsa_runtime_mut().synthetic_write(Durability::LOW);
+        }
+        changed
+    }
+
+    /// Replaces the macros that are defined on the command line. The files that were already
+    /// read are kept, only the queries that depend on the preprocessor are recomputed by the
+    /// next compilation.
+    pub fn set_defines<'a>(&mut self, macro_flags: impl Iterator<Item = &'a str>) {
+        let macro_flags: Arc<[_]> =
+            STANDARD_FLAGS.into_iter().chain(macro_flags).map(Arc::from).collect();
+        self.set_macro_flags(self.root_file, macro_flags);
+    }
+
     pub fn new<'a>(
         root_file: VfsPath,
         contents: Result<Vec<u8>, io::Error>,
@@ -89,9 +128,7 @@ impl CompilationDB {
             once(Ok(VfsPath::new_virtual_path("/std".to_owned()))).chain(include_dirs).collect();
         res.set_include_dirs(root_file, include_dirs?);
 
-        let macro_flags: Arc<[_]> =
-            STANDARD_FLAGS.into_iter().chain(macro_flags).map(Arc::from).collect();
-        res.set_macro_flags(root_file, macro_flags);
+        res.set_defines(macro_flags);
 
         res.set_plugin_lints(&[]);
         let mut overwrites = res.empty_global_lint_overwrites();
@@ -14,7 +14,7 @@ use std::sync::Arc;
 use basedb::diagnostics::sink::Buffer;
 use basedb::diagnostics::ConsoleSink;
 use basedb::BaseDB;
-use basedb::FileId;
+use basedb::{ErasedAstId, FileId};
 use hir_def::db::HirDefDB;
 use hir_def::nameres::{DefMap, LocalScopeId, ScopeDefItem};
 use hir_def::DefWithBodyId;
@@ -23,27 +23,29 @@ use hir_def::LocalFunctionArgId;
 use hir_def::NatureAttrId;
 use hir_def::NatureId;
 use hir_def::{
-    AliasParamId, BlockId, BlockLoc, BranchId, FunctionId, Lookup, ModuleId, ModuleLoc, NodeId,
-    ParamId, VarId,
+    AliasParamId, BlockId, BlockLoc, BranchId, DisciplineAttrId, DisciplineAttrLoc, FunctionId,
+    Intern, Lookup, ModuleId, ModuleLoc, NatureAttrLoc, NodeId, ParamId, VarId,
 };
 use hir_ty::db::HirTyDB as HirDatabase;
 use hir_ty::inference;
 use salsa::InternKey;
 use smol_str::SmolStr;
 use syntax::ast;
+use syntax::sourcemap::FileSpan;
 
 pub use basedb::diagnostics::DiagnosticSink;
 pub use hir_def::body::{ConstraintValue, ParamConstraint};
 pub use hir_def::expr::CaseCond;
 pub use hir_def::nameres::diagnostics::PathResolveError;
-pub use hir_def::{BuiltIn, Case, Literal, ParamSysFun, Path, Type};
+pub use hir_def::{BuiltIn, Case, DisciplineAttrKind, Domain, Literal, ParamSysFun, Path, Type};
 pub use hir_ty::builtin;
 pub use rec_declarations::RecDeclarations;
 pub use syntax::name::Name;
 
 pub use crate::attributes::AstCache;
 pub use crate::body::{
-    AssignmentLhs, Body, BodyRef, ContributeKind, Expr, ExprId, Ref, ResolvedFun, Stmt, StmtId,
+    AssignmentLhs, Body, BodyRef, ContributeKind, Expr, ExprId, ExprLoc, Ref, ResolvedFun, Stmt,
+    StmtId,
 };
 pub use crate::db::CompilationDB;
 
@@ -144,6 +146,12 @@ impl Module {
         self.id.lookup(db)
     }
 
+    /// Returns the span of the declaration in the original source files
+    pub fn file_span(self, db: &CompilationDB) -> FileSpan {
+        let loc = self.lookup(db);
+        decl_file_span(db, loc.scope.root_file, loc.ast_id(db).erased())
+    }
+
     /// list of all child scopes.
     pub fn child_scopes(self, db: &CompilationDB) -> Vec<Scope> {
         Scope::Module(self).children(db)
@@ -445,6 +453,12 @@ impl Variable {
     pub fn get_attr(&self, db: &CompilationDB, ast: &AstCache, name: &str) -> Option<ast::Attr> {
         ast.resolve_attribute(name, self.id.lookup(db).ast_id(db).erased())
     }
+
+    /// Returns the span of the declaration in the original source files
+    pub fn file_span(self, db: &CompilationDB) -> FileSpan {
+        let loc = self.id.lookup(db);
+        decl_file_span(db, loc.scope.root_file, loc.ast_id(db).erased())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -476,6 +490,17 @@ impl Parameter {
     pub fn get_attr(&self, db: &CompilationDB, ast: &AstCache, name: &str) -> Option<ast::Attr> {
         ast.resolve_attribute(name, self.id.lookup(db).ast_id(db).erased())
     }
+
+    /// Returns the span of the declaration in the original source files
+    pub fn file_span(self, db: &CompilationDB) -> FileSpan {
+        let loc = self.id.lookup(db);
+        decl_file_span(db, loc.scope.root_file, loc.ast_id(db).erased())
+    }
+}
+
+fn decl_file_span(db: &CompilationDB, root_file: FileId, ast_id: ErasedAstId) -> FileSpan {
+    let ptr = db.ast_id_map(root_file).get_syntax(ast_id);
+    db.parse(root_file).to_file_span(ptr.range(), &db.sourcemap(root_file))
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
@@ -587,6 +612,44 @@ impl Discipline {
     pub fn flow(&self, db: &CompilationDB) -> Option<Nature> {
         db.discipline_info(self.id).flow.map(|id| Nature { id })
     }
+
+    pub fn domain(self, db: &CompilationDB) -> Option<Domain> {
+        db.discipline_data(self.id).domain
+    }
+
+    /// The attributes declared in this discipline (in declaration order). This includes the
+    /// `potential`, `flow` and `domain` declarations as well as the nature overwrites.
+    pub fn attributes(self, db: &CompilationDB) -> Vec<DisciplineAttribute> {
+        db.discipline_data(self.id)
+            .attrs
+            .keys()
+            .map(|id| DisciplineAttribute {
+                id: DisciplineAttrLoc { discipline: self.id, id }.intern(db),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DisciplineAttribute {
+    id: DisciplineAttrId,
+}
+
+impl DisciplineAttribute {
+    pub fn name(self, db: &CompilationDB) -> String {
+        let loc = self.id.lookup(db);
+        db.discipline_data(loc.discipline).attrs[loc.id].name.to_string()
+    }
+
+    pub fn kind(self, db: &CompilationDB) -> DisciplineAttrKind {
+        let loc = self.id.lookup(db);
+        db.discipline_data(loc.discipline).attrs[loc.id].kind
+    }
+
+    /// The value of this attribute if it is a literal.
+    pub fn literal(self, db: &CompilationDB) -> Option<Literal> {
+        body_literal(self.id.into(), db)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -602,6 +665,32 @@ impl Nature {
     pub fn units(self, db: &CompilationDB) -> String {
         db.nature_data(self.id).units.clone().unwrap_or_default()
     }
+
+    /// The nature this nature is derived from.
+    pub fn parent(self, db: &CompilationDB) -> Option<Nature> {
+        db.nature_info(self.id).parent.map(|id| Nature { id })
+    }
+
+    /// The attributes declared in this nature (in declaration order).
+    pub fn attributes(self, db: &CompilationDB) -> Vec<NatureAttribute> {
+        db.nature_data(self.id)
+            .attrs
+            .keys()
+            .map(|id| NatureAttribute { id: NatureAttrLoc { nature: self.id, id }.intern(db) })
+            .collect()
+    }
+
+    /// The nature of the time derivative of a quantity with this nature
+    /// (the nature itself if it does not declare a `ddt_nature`).
+    pub fn ddt_nature(self, db: &CompilationDB) -> Nature {
+        Nature { id: db.nature_info(self.id).ddt_nature }
+    }
+
+    /// The nature of the time integral of a quantity with this nature
+    /// (the nature itself if it does not declare an `idt_nature`).
+    pub fn idt_nature(self, db: &CompilationDB) -> Nature {
+        Nature { id: db.nature_info(self.id).idt_nature }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -620,6 +709,23 @@ impl NatureAttribute {
         let loc = self.id.lookup(db);
         db.nature_data(loc.nature).attrs[loc.id].name.to_string()
     }
+
+    /// The value of this attribute if it is a literal.
+    pub fn literal(self, db: &CompilationDB) -> Option<Literal> {
+        body_literal(self.id.into(), db)
+    }
+}
+
+/// The value of an attribute body that consists of a single literal.
+fn body_literal(id: DefWithBodyId, db: &CompilationDB) -> Option<Literal> {
+    let body = db.body(id);
+    match body.stmts[*body.entry_stmts.first()?] {
+        hir_def::Stmt::Expr(expr) => match &body.exprs[expr] {
+            hir_def::Expr::Literal(lit) => Some(lit.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 #[non_exhaustive]
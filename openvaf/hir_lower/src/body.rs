@@ -1,5 +1,5 @@
 use hir::Node;
-use hir::{BodyRef, ExprId};
+use hir::{BodyRef, ExprId, ExprLoc, StmtId};
 use mir::builder::InstBuilder;
 use mir::{Block, Value};
 use stdx::iter::zip;
@@ -11,6 +11,8 @@ pub struct BodyLoweringCtx<'a, 'c1, 'c2> {
     pub ctx: &'a mut LoweringCtx<'c1, 'c2>,
     pub body: BodyRef<'a>,
     pub path: &'a str,
+    /// The statement that is currently lowered (`None` for bodies that are just an expression).
+    pub stmt: Option<StmtId>,
 }
 
 impl<'c1, 'c2> BodyLoweringCtx<'_, 'c1, 'c2> {
@@ -20,6 +22,11 @@ impl<'c1, 'c2> BodyLoweringCtx<'_, 'c1, 'c2> {
         }
     }
 
+    /// Returns the location of `expr` within the statement that is currently lowered.
+    pub fn expr_loc(&self, expr: ExprId) -> ExprLoc {
+        self.body.stmt_expr_loc(self.stmt, expr)
+    }
+
     pub fn nodes_from_args(
         &mut self,
         args: &[ExprId],
@@ -47,7 +54,7 @@ impl<'c1, 'c2> BodyLoweringCtx<'_, 'c1, 'c2> {
         mut lower_else_val: impl FnMut(BodyLoweringCtx<'_, 'c1, 'c2>) -> Value,
     ) -> Value {
         self.ctx.make_select(cond, |ctx, branch| {
-            let ctx = BodyLoweringCtx { ctx, body: self.body, path: self.path };
+            let ctx = BodyLoweringCtx { ctx, body: self.body, path: self.path, stmt: self.stmt };
             if branch {
                 lower_then_val(ctx)
             } else {
@@ -62,7 +69,7 @@ impl<'c1, 'c2> BodyLoweringCtx<'_, 'c1, 'c2> {
         mut lower_body: impl FnMut(BodyLoweringCtx<'_, 'c1, 'c2>, bool) -> T,
     ) -> ((Block, T), (Block, T)) {
         self.ctx.make_cond(cond, |ctx, branch| {
-            let ctx = BodyLoweringCtx { ctx, body: self.body, path: self.path };
+            let ctx = BodyLoweringCtx { ctx, body: self.body, path: self.path, stmt: self.stmt };
             lower_body(ctx, branch)
         })
     }
@@ -84,6 +91,6 @@ impl<'c1, 'c2> BodyLoweringCtx<'_, 'c1, 'c2> {
 impl LoweringCtx<'_, '_> {
     /// Lowers a body
     pub fn lower_expr_body(&mut self, body: BodyRef, i: usize) -> Value {
-        BodyLoweringCtx { ctx: self, body, path: "" }.lower_expr(body.get_entry_expr(i))
+        BodyLoweringCtx { ctx: self, body, path: "", stmt: None }.lower_expr(body.get_entry_expr(i))
     }
 }
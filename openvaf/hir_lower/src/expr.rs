@@ -25,7 +25,8 @@ use crate::{
 impl BodyLoweringCtx<'_, '_, '_> {
     pub fn lower_expr(&mut self, expr: ExprId) -> Value {
         let old_loc = self.ctx.get_srcloc();
-        self.ctx.set_srcloc(mir::SourceLoc::new(u32::from(expr) as i32 + 1));
+        let loc = self.ctx.intern.src_loc(self.expr_loc(expr));
+        self.ctx.set_srcloc(loc);
 
         let mut res = match self.body.get_expr(expr) {
             Expr::Read(Ref::Variable(var)) => self.ctx.read_variable(var),
@@ -242,7 +243,8 @@ impl BodyLoweringCtx<'_, '_, '_> {
         self.ctx.def_place(PlaceKind::FunctionReturn(fun), init);
 
         let body = fun.body(self.ctx.db);
-        BodyLoweringCtx { body: body.borrow(), path: self.path, ctx: self.ctx }.lower_entry_stmts();
+        BodyLoweringCtx { body: body.borrow(), path: self.path, stmt: None, ctx: self.ctx }
+            .lower_entry_stmts();
 
         // write outputs back to original (including possibly required cast)
         for (arg, &expr) in args {
@@ -769,6 +771,7 @@ impl BodyLoweringCtx<'_, '_, '_> {
 
     pub fn lower_body(&mut self, body: Body, i: usize) -> Value {
         let expr = body.borrow().get_entry_expr(i);
-        BodyLoweringCtx { ctx: self.ctx, body: body.borrow(), path: self.path }.lower_expr(expr)
+        BodyLoweringCtx { ctx: self.ctx, body: body.borrow(), path: self.path, stmt: None }
+            .lower_expr(expr)
     }
 }
@@ -3,12 +3,15 @@ use std::iter::FilterMap;
 use ahash::{AHashMap, AHashSet};
 use bitset::HybridBitSet;
 use hir::{
-    Branch, BranchWrite, CompilationDB, Module, Node, ParamSysFun, Parameter, Type, Variable,
+    Branch, BranchWrite, CompilationDB, ExprLoc, Module, Node, ParamSysFun, Parameter, Type,
+    Variable,
 };
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use lasso::Rodeo;
 use mir::builder::InstBuilder;
-use mir::{DataFlowGraph, FuncRef, Function, Inst, KnownDerivatives, Param, Unknown, Value};
+use mir::{
+    DataFlowGraph, FuncRef, Function, Inst, KnownDerivatives, Param, SourceLoc, Unknown, Value,
+};
 use mir_build::{FunctionBuilder, FunctionBuilderContext, RetBuilder};
 use stdx::packed_option::PackedOption;
 use stdx::{impl_debug_display, impl_idx_from};
@@ -230,6 +233,8 @@ pub struct HirInterner {
     pub tagged_reads: IndexMap<Value, Variable, ahash::RandomState>,
     pub implicit_equations: TiVec<ImplicitEquation, ImplicitEquationKind>,
     pub lim_state: TiMap<LimitState, Value, Vec<(Value, bool)>>,
+    /// The expressions that the [`SourceLoc`]s of the MIR refer to
+    pub src_locs: IndexSet<ExprLoc, ahash::RandomState>,
 }
 
 pub type LiveParams<'a> = FilterMap<
@@ -238,6 +243,19 @@ pub type LiveParams<'a> = FilterMap<
 >;
 
 impl HirInterner {
+    /// Returns the [`SourceLoc`] that refers to `loc`
+    pub fn src_loc(&mut self, loc: ExprLoc) -> SourceLoc {
+        let (idx, _) = self.src_locs.insert_full(loc);
+        SourceLoc::new(idx as i32 + 1)
+    }
+
+    /// Maps a [`SourceLoc`] back to the expression it was generated from.
+    /// The derivatives of an instruction map to the same expression as the instruction itself.
+    pub fn expr_loc(&self, loc: SourceLoc) -> Option<ExprLoc> {
+        let idx = loc.bits().unsigned_abs().checked_sub(1)?;
+        self.src_locs.get_index(idx as usize).copied()
+    }
+
     fn contains_ddx(
         ddx_calls: &mut AHashMap<FuncRef, (HybridBitSet<Unknown>, HybridBitSet<Unknown>)>,
         func: &Function,
@@ -484,8 +502,12 @@ impl<'a> MirBuilder<'a> {
 
         let mut ctx = LoweringCtx::new(self.db, builder, !self.lower_equations, &mut interner)
             .with_tagged_vars(self.tagged_reads);
-        let mut body_ctx =
-            BodyLoweringCtx { ctx: &mut ctx, body: analog_initial_body.borrow(), path: &path };
+        let mut body_ctx = BodyLoweringCtx {
+            ctx: &mut ctx,
+            body: analog_initial_body.borrow(),
+            path: &path,
+            stmt: None,
+        };
 
         // lower analog initial blocks first
         body_ctx.lower_entry_stmts();
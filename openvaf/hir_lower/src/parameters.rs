@@ -1,10 +1,11 @@
 use std::f64::NEG_INFINITY;
 use std::mem::replace;
 
+use ahash::AHashMap;
 use hir::{CompilationDB, ConstraintValue, ParamConstraint, Parameter, Type};
 use lasso::Rodeo;
 use mir::builder::InstBuilder;
-use mir::{Block, FuncRef, Function, Opcode, Value, FALSE, GRAVESTONE, INFINITY};
+use mir::{Block, Const, FuncRef, Function, Opcode, Value, FALSE, GRAVESTONE, INFINITY};
 use mir_build::{FunctionBuilder, FunctionBuilderContext};
 use stdx::packed_option::ReservedValue;
 use syntax::ast::ConstraintKind;
@@ -45,6 +46,11 @@ impl CmpOps {
 }
 
 impl HirInterner {
+    /// Inserts the initialization (default values and bounds checks) of `params` into `func`.
+    ///
+    /// The value of a parameter in `frozen` is fixed at compile time: the parameter always
+    /// assumes the frozen value and giving any other value is reported as invalid.
+    #[allow(clippy::too_many_arguments)]
     pub fn insert_param_init(
         &mut self,
         db: &CompilationDB,
@@ -53,6 +59,7 @@ impl HirInterner {
         build_min_max: bool,
         build_stores: bool,
         params: &[Parameter],
+        frozen: &AHashMap<Parameter, Const>,
     ) {
         let mut default_vals = if build_stores { vec![GRAVESTONE; params.len()] } else { vec![] };
 
@@ -79,12 +86,14 @@ impl HirInterner {
 
             let ops = CmpOps::from_ty(&ty);
             let invalid = ctx.dec_callback(CallBackKind::ParamInfo(ParamInfoKind::Invalid, param));
+            let frozen = frozen.get(&param).map(|val| ctx.dfg_mut().values.make_const(*val));
 
             let (then_src, else_src) = ctx.make_cond(param_given, |ctx, param_given| {
                 if param_given {
                     if build_stores {
                         let exit = ctx.create_block();
-                        let mut ctx = BodyLoweringCtx { ctx, body: body.borrow(), path: "" };
+                        let mut ctx =
+                            BodyLoweringCtx { ctx, body: body.borrow(), path: "", stmt: None };
                         ctx.check_param(
                             param_val,
                             &bounds,
@@ -105,12 +114,28 @@ impl HirInterner {
                         );
                         ctx.ctx.switch_to_block(exit);
                     }
-                    param_val
+                    if let Some(frozen) = frozen {
+                        let is_frozen = ctx.ins().binary1(ops.eq, param_val, frozen);
+                        let invalid_bb = ctx.create_block();
+                        let exit = ctx.create_block();
+                        ctx.ins().br(is_frozen, exit, invalid_bb);
+                        ctx.switch_to_block(invalid_bb);
+                        ctx.ins().call(invalid, &[]);
+                        ctx.ins().jump(exit);
+                        ctx.switch_to_block(exit);
+                        frozen
+                    } else {
+                        param_val
+                    }
                 } else {
-                    let default_val = ctx.lower_expr_body(body.borrow(), 0);
+                    let default_val = match frozen {
+                        Some(frozen) => frozen,
+                        None => ctx.lower_expr_body(body.borrow(), 0),
+                    };
                     if build_stores {
                         let exit = ctx.create_block();
-                        let mut ctx = BodyLoweringCtx { ctx, body: body.borrow(), path: "" };
+                        let mut ctx =
+                            BodyLoweringCtx { ctx, body: body.borrow(), path: "", stmt: None };
                         ctx.check_param(
                             default_val,
                             &bounds,
@@ -162,7 +187,8 @@ impl HirInterner {
                 let max_exclusive =
                     ctx.dec_callback(CallBackKind::ParamInfo(ParamInfoKind::MaxExclusive, param));
 
-                let mut ctx = BodyLoweringCtx { ctx: &mut ctx, body: body.borrow(), path: "" };
+                let mut ctx =
+                    BodyLoweringCtx { ctx: &mut ctx, body: body.borrow(), path: "", stmt: None };
                 let mut lowered_bounds = None;
                 let precomputed_vals = bounds
                     .iter()
@@ -280,7 +306,8 @@ impl HirInterner {
 
             // first from bounds (here we also get min/max from)
             let exit = ctx.create_block();
-            let mut ctx = BodyLoweringCtx { ctx: &mut ctx, body: body.borrow(), path: "" };
+            let mut ctx =
+                BodyLoweringCtx { ctx: &mut ctx, body: body.borrow(), path: "", stmt: None };
             ctx.check_param(
                 param_val,
                 &bounds,
@@ -7,6 +7,12 @@ use crate::{CallBackKind, CurrentKind, ParamKind, PlaceKind};
 
 impl BodyLoweringCtx<'_, '_, '_> {
     pub(super) fn lower_stmt(&mut self, stmnt: StmtId) {
+        let parent = self.stmt.replace(stmnt);
+        self.lower_stmt_kind(stmnt);
+        self.stmt = parent;
+    }
+
+    fn lower_stmt_kind(&mut self, stmnt: StmtId) {
         // TODO(msrv): let .. else
         let stmnt = if let Some(stmnt) = self.body.get_stmt(stmnt) {
             stmnt
@@ -39,7 +45,8 @@ impl BodyLoweringCtx<'_, '_, '_> {
 
                 self.ctx.make_cond(cond_, |ctx, branch| {
                     let stmt = if branch { then_branch } else { else_branch };
-                    BodyLoweringCtx { body: self.body, path: self.path, ctx }.lower_stmt(stmt);
+                    BodyLoweringCtx { body: self.body, path: self.path, stmt: self.stmt, ctx }
+                        .lower_stmt(stmt);
                 });
             }
             Stmt::ForLoop { init, cond, incr, body } => {
@@ -85,7 +92,8 @@ impl BodyLoweringCtx<'_, '_, '_> {
                 let val_ = self.lower_expr(*val);
 
                 let old_loc = self.ctx.get_srcloc();
-                self.ctx.set_srcloc(mir::SourceLoc::new(u32::from(*val) as i32 + 1));
+                let loc = self.ctx.intern.src_loc(self.expr_loc(*val));
+                self.ctx.set_srcloc(loc);
                 let cond = self.ctx.ins().binary1(discr_op, val_, discr);
                 self.ctx.set_srcloc(old_loc);
 
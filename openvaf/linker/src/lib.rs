@@ -11,34 +11,91 @@ use std::process::{Output, Stdio};
 use std::{ascii, env, io};
 use target::spec::{LinkerFlavor, Target};
 
+/// The kind of artifact produced by [`link`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputKind {
+    /// A shared library (`.so`, `.dylib` or `.dll`).
+    SharedLib,
+    /// A static archive created with `ar` (or `lib.exe` for MSVC).
+    StaticLib,
+    /// A single relocatable object file created with `ld -r`.
+    Object,
+}
+
+impl OutputKind {
+    /// The file extension that openvaf uses for artifacts of this kind.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputKind::SharedLib => "osdi",
+            OutputKind::StaticLib => "a",
+            OutputKind::Object => "o",
+        }
+    }
+}
+
 pub fn link(
     path: Option<Utf8PathBuf>,
     target: &Target,
     out_filename: &Utf8Path,
+    kind: OutputKind,
     add_objects: impl FnOnce(&mut dyn Linker),
 ) -> Result<()> {
-    let mut linker = linker_with_args(path, target, out_filename, add_objects);
+    match kind {
+        OutputKind::StaticLib => return archive(path, target, out_filename, add_objects),
+        OutputKind::Object if target.options.linker_flavor == LinkerFlavor::Msvc => {
+            bail!("relocatable object output is not supported for MSVC targets")
+        }
+        _ => (),
+    }
 
+    let mut linker = linker_with_args(path, target, out_filename, kind, add_objects);
+
+    let import_lib = kind == OutputKind::SharedLib && !target.options.import_lib.is_empty();
     let import_lib_path = out_filename.with_file_name("__openvaf__import.lib");
-    if !target.options.import_lib.is_empty() {
+    if import_lib {
         let mut file = File::create(&import_lib_path).context("failed to create importlib")?;
         file.write_all(target.options.import_lib).context("failed to write importlib")?;
         linker.add_object(&import_lib_path);
     }
     let res = exec_linker(linker.take_cmd(), out_filename);
-    if !target.options.import_lib.is_empty() {
+    if import_lib {
         remove_file(import_lib_path).context("failed to delete importlib")?;
     }
+    check_output(res, "linker", "linking")
+}
+
+fn archive(
+    path: Option<Utf8PathBuf>,
+    target: &Target,
+    out_filename: &Utf8Path,
+    add_objects: impl FnOnce(&mut dyn Linker),
+) -> Result<()> {
+    let mut cmd = get_archiver(path.map(|path| path.into_std_path_buf()), target);
+    disable_localization(cmd.cmd());
+    cmd.cmd().env("ZERO_AR_DATE", "1");
+    cmd.output_filename(out_filename);
+    cmd.set_output_kind(OutputKind::StaticLib);
+    add_objects(&mut *cmd);
+
+    // ar only adds members to an existing archive, so stale objects would survive
+    if out_filename.exists() {
+        remove_file(out_filename).context("failed to delete old archive")?;
+    }
+    let res = exec_linker(cmd.take_cmd(), out_filename);
+    check_output(res, "archiver", "archiving")
+}
+
+fn check_output(res: io::Result<Output>, tool: &str, action: &str) -> Result<()> {
     match res {
         Ok(prog) if !prog.status.success() => {
             let mut output = prog.stderr.clone();
             output.extend_from_slice(&prog.stdout);
             let escaped_output = escape_stdout_stderr_string(&output);
             eprintln!("{}", escaped_output);
-            bail!("linking failed (see linker output for details)")
+            bail!("{action} failed (see {tool} output for details)")
         }
         Ok(_) => Ok(()),
-        Err(err) => bail!("linker not found: {}", err),
+        Err(err) => bail!("{tool} not found: {}", err),
     }
 }
 
@@ -74,6 +131,7 @@ fn linker_with_args<'a>(
     path: Option<Utf8PathBuf>,
     target: &'a Target,
     out_filename: &Utf8Path,
+    kind: OutputKind,
     add_objects: impl FnOnce(&mut dyn Linker),
 ) -> Box<dyn Linker + 'a> {
     let flavor = target.options.linker_flavor;
@@ -91,7 +149,7 @@ fn linker_with_args<'a>(
 
     add_objects(&mut *cmd);
     cmd.output_filename(out_filename);
-    cmd.set_output_kind();
+    cmd.set_output_kind(kind);
 
     cmd.add_post_link_args(target, flavor);
 
@@ -108,38 +166,7 @@ fn get_linker<'a>(
 ) -> Box<dyn Linker + 'a> {
     match flavor {
         LinkerFlavor::Msvc => {
-            let msvc_tool = windows_registry::find_tool(&target.llvm_target, "link.exe");
-            let path = match path {
-                Some(path) => path,
-                None => match msvc_tool {
-                    Some(ref tool) => tool.path().to_owned(),
-                    None => Path::new("link.exe").to_owned(),
-                },
-            };
-            let mut cmd = Command::new(path);
-            let mut new_path = Vec::new();
-            // The compiler's sysroot often has some bundled tools, so add it to the
-            // PATH for the child.
-            let mut msvc_changed_path = false;
-            if let Some(ref tool) = msvc_tool {
-                cmd.args(tool.args());
-                for (k, v) in tool.env() {
-                    if k == "PATH" {
-                        new_path.extend(env::split_paths(v));
-                        msvc_changed_path = true;
-                    } else {
-                        cmd.env(k, v);
-                    }
-                }
-            }
-
-            if !msvc_changed_path {
-                if let Some(path) = env::var_os("PATH") {
-                    new_path.extend(env::split_paths(&path));
-                }
-            }
-            cmd.env("PATH", env::join_paths(new_path).unwrap());
-            Box::new(MsvcLinker { cmd }) as Box<dyn Linker>
+            Box::new(MsvcLinker { cmd: msvc_command(path, target, "link.exe") }) as Box<dyn Linker>
         }
         LinkerFlavor::Ld => {
             Box::new(LdLinker { cmd: Command::new(path.unwrap_or_else(|| "ld".into())), target })
@@ -152,6 +179,53 @@ fn get_linker<'a>(
     }
 }
 
+fn msvc_command(path: Option<PathBuf>, target: &Target, tool: &str) -> Command {
+    let msvc_tool = windows_registry::find_tool(&target.llvm_target, tool);
+    let path = match path {
+        Some(path) => path,
+        None => match msvc_tool {
+            Some(ref tool) => tool.path().to_owned(),
+            None => Path::new(tool).to_owned(),
+        },
+    };
+    let mut cmd = Command::new(path);
+    let mut new_path = Vec::new();
+    // The compiler's sysroot often has some bundled tools, so add it to the
+    // PATH for the child.
+    let mut msvc_changed_path = false;
+    if let Some(ref tool) = msvc_tool {
+        cmd.args(tool.args());
+        for (k, v) in tool.env() {
+            if k == "PATH" {
+                new_path.extend(env::split_paths(v));
+                msvc_changed_path = true;
+            } else {
+                cmd.env(k, v);
+            }
+        }
+    }
+
+    if !msvc_changed_path {
+        if let Some(path) = env::var_os("PATH") {
+            new_path.extend(env::split_paths(&path));
+        }
+    }
+    cmd.env("PATH", env::join_paths(new_path).unwrap());
+    cmd
+}
+
+fn get_archiver(path: Option<PathBuf>, target: &Target) -> Box<dyn Linker + '_> {
+    match target.options.linker_flavor {
+        LinkerFlavor::Msvc => {
+            Box::new(MsvcLinker { cmd: msvc_command(path, target, "lib.exe") }) as Box<dyn Linker>
+        }
+        LinkerFlavor::Ld | LinkerFlavor::Ld64 => {
+            Box::new(ArLinker { cmd: Command::new(path.unwrap_or_else(|| "ar".into())) })
+                as Box<dyn Linker>
+        }
+    }
+}
+
 fn exec_linker(mut cmd: std::process::Command, _out_filename: &Utf8Path) -> io::Result<Output> {
     match cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
         #[allow(clippy::let_and_return)]
@@ -188,7 +262,7 @@ pub trait Linker {
     fn cmd(&mut self) -> &mut Command;
     fn output_filename(&mut self, path: &Utf8Path);
     fn add_object(&mut self, path: &Utf8Path);
-    fn set_output_kind(&mut self);
+    fn set_output_kind(&mut self, kind: OutputKind);
 }
 
 impl dyn Linker + '_ {
@@ -265,8 +339,14 @@ impl<'a> Linker for LdLinker<'a> {
         self.cmd.arg(path.as_str());
     }
 
-    fn set_output_kind(&mut self) {
-        self.build_dylib();
+    fn set_output_kind(&mut self, kind: OutputKind) {
+        match kind {
+            OutputKind::SharedLib => self.build_dylib(),
+            OutputKind::Object => {
+                self.linker_arg("-r");
+            }
+            OutputKind::StaticLib => unreachable!("static libraries are created by the archiver"),
+        }
     }
 }
 
@@ -291,11 +371,35 @@ impl Linker for MsvcLinker {
         self.cmd.arg(path.as_str());
     }
 
-    fn set_output_kind(&mut self) {
-        self.cmd.arg("/DLL");
+    fn set_output_kind(&mut self, kind: OutputKind) {
+        // lib.exe produces a static library by default
+        if kind == OutputKind::SharedLib {
+            self.cmd.arg("/DLL");
+        }
     }
 }
 
+/// Creates static archives with `ar`.
+pub struct ArLinker {
+    cmd: Command,
+}
+
+impl Linker for ArLinker {
+    fn cmd(&mut self) -> &mut Command {
+        &mut self.cmd
+    }
+
+    fn output_filename(&mut self, path: &Utf8Path) {
+        self.cmd.arg("crs").arg(path.as_str());
+    }
+
+    fn add_object(&mut self, path: &Utf8Path) {
+        self.cmd.arg(path.as_str());
+    }
+
+    fn set_output_kind(&mut self, _kind: OutputKind) {}
+}
+
 pub struct Command {
     command: PathBuf,
     args: Vec<OsString>,
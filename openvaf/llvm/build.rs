@@ -136,7 +136,7 @@ fn main() {
         // "avr",
         // "m68k",
         // "mips",
-        // "powerpc",
+        "powerpc",
         // "systemz",
         // "jsbackend",
         // "webassembly",
@@ -144,7 +144,7 @@ fn main() {
         // "sparc",
         // "nvptx",
         // "hexagon",
-        // "riscv",
+        "riscv",
         // "bpf",
     ];
 
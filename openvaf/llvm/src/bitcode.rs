@@ -1,4 +1,4 @@
-use libc::{c_char, size_t};
+use libc::{c_char, c_int, size_t};
 
 use crate::{Bool, Context, MemoryBuffer, Module, Value};
 
@@ -15,5 +15,8 @@ extern "C" {
         dst_module: &mut Option<&'a Module>,
     ) -> Bool;
 
+    /// Writes a module to the specified path. Returns 0 on success.
+    pub fn LLVMWriteBitcodeToFile(module: &Module, path: *const c_char) -> c_int;
+
     pub fn LLVMGetNamedFunction<'a>(module: &'a Module, name: *const c_char) -> Option<&'a Value>;
 }
@@ -0,0 +1,127 @@
+use libc::{c_char, c_uint, size_t};
+
+use crate::{Bool, Builder, Context, DIBuilder, Metadata, Module, Value};
+
+/// Source languages known to DWARF (the values are the indices of `LLVMDWARFSourceLanguage`).
+/// Verilog-A has no DWARF language code so C is used to make debuggers display it sensibly.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DWARFSourceLanguage {
+    C89 = 0,
+    C = 1,
+    C99 = 11,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DWARFEmissionKind {
+    None = 0,
+    Full = 1,
+    LineTablesOnly = 2,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModuleFlagBehavior {
+    Error = 0,
+    Warning = 1,
+    Require = 2,
+    Override = 3,
+    Append = 4,
+    AppendUnique = 5,
+}
+
+pub type DIFlags = c_uint;
+pub const DIFlagZero: DIFlags = 0;
+pub const DIFlagPrototyped: DIFlags = 1 << 8;
+
+// DebugInfo
+extern "C" {
+    pub fn LLVMDebugMetadataVersion() -> c_uint;
+    pub fn LLVMAddModuleFlag(
+        module: &Module,
+        behavior: ModuleFlagBehavior,
+        key: *const c_char,
+        key_len: size_t,
+        val: &Metadata,
+    );
+    pub fn LLVMValueAsMetadata(val: &Value) -> &Metadata;
+    pub fn LLVMMDStringInContext2(cx: &Context, str: *const c_char, len: size_t) -> &Metadata;
+
+    pub fn LLVMCreateDIBuilder(module: &Module) -> &mut DIBuilder<'_>;
+    pub fn LLVMDisposeDIBuilder<'a>(builder: &'a mut DIBuilder<'a>);
+    pub fn LLVMDIBuilderFinalize(builder: &DIBuilder<'_>);
+
+    pub fn LLVMDIBuilderCreateCompileUnit<'a>(
+        builder: &DIBuilder<'a>,
+        lang: DWARFSourceLanguage,
+        file: &'a Metadata,
+        producer: *const c_char,
+        producer_len: size_t,
+        is_optimized: Bool,
+        flags: *const c_char,
+        flags_len: size_t,
+        runtime_ver: c_uint,
+        split_name: *const c_char,
+        split_name_len: size_t,
+        kind: DWARFEmissionKind,
+        dwo_id: c_uint,
+        split_debug_inlining: Bool,
+        debug_info_for_profiling: Bool,
+        sys_root: *const c_char,
+        sys_root_len: size_t,
+        sdk: *const c_char,
+        sdk_len: size_t,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateFile<'a>(
+        builder: &DIBuilder<'a>,
+        filename: *const c_char,
+        filename_len: size_t,
+        directory: *const c_char,
+        directory_len: size_t,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateSubroutineType<'a>(
+        builder: &DIBuilder<'a>,
+        file: &'a Metadata,
+        parameter_types: *const &'a Metadata,
+        num_parameter_types: c_uint,
+        flags: DIFlags,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateFunction<'a>(
+        builder: &DIBuilder<'a>,
+        scope: &'a Metadata,
+        name: *const c_char,
+        name_len: size_t,
+        linkage_name: *const c_char,
+        linkage_name_len: size_t,
+        file: &'a Metadata,
+        line: c_uint,
+        ty: &'a Metadata,
+        is_local_to_unit: Bool,
+        is_definition: Bool,
+        scope_line: c_uint,
+        flags: DIFlags,
+        is_optimized: Bool,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateLexicalBlockFile<'a>(
+        builder: &DIBuilder<'a>,
+        scope: &'a Metadata,
+        file: &'a Metadata,
+        discriminator: c_uint,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateDebugLocation<'a>(
+        ctx: &'a Context,
+        line: c_uint,
+        column: c_uint,
+        scope: &'a Metadata,
+        inlined_at: Option<&'a Metadata>,
+    ) -> &'a Metadata;
+
+    pub fn LLVMSetSubprogram<'a>(fun: &'a Value, subprogram: &'a Metadata);
+    pub fn LLVMSetCurrentDebugLocation2<'a>(builder: &Builder<'a>, loc: Option<&'a Metadata>);
+}
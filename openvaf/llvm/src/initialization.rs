@@ -126,7 +126,7 @@ pub fn initialize_available_targets() {
         } }
     );
 
-    // Currently the only supported targets are x86, arm, aarch64 and riscv
+    // Currently the only supported targets are x86, arm, aarch64, powerpc and riscv
     init_target!(
         llvm_component = "x86",
         LLVMInitializeX86TargetInfo,
@@ -183,14 +183,14 @@ pub fn initialize_available_targets() {
     //     LLVMInitializeMipsAsmPrinter,
     //     LLVMInitializeMipsAsmParser
     // );
-    // init_target!(
-    //     llvm_component = "powerpc",
-    //     LLVMInitializePowerPCTargetInfo,
-    //     LLVMInitializePowerPCTarget,
-    //     LLVMInitializePowerPCTargetMC,
-    //     LLVMInitializePowerPCAsmPrinter,
-    //     LLVMInitializePowerPCAsmParser
-    // );
+    init_target!(
+        llvm_component = "powerpc",
+        LLVMInitializePowerPCTargetInfo,
+        LLVMInitializePowerPCTarget,
+        LLVMInitializePowerPCTargetMC,
+        LLVMInitializePowerPCAsmPrinter,
+        LLVMInitializePowerPCAsmParser
+    );
     // init_target!(
     //     llvm_component = "systemz",
     //     LLVMInitializeSystemZTargetInfo,
@@ -29,6 +29,7 @@ pub mod basic_block;
 pub mod bitcode;
 pub mod builder;
 pub mod context;
+pub mod debuginfo;
 pub mod initialization;
 // pub mod lld;
 pub mod module;
@@ -43,6 +44,7 @@ pub use basic_block::*;
 pub use bitcode::*;
 pub use builder::*;
 pub use context::*;
+pub use debuginfo::*;
 pub use initialization::*;
 pub use module::*;
 pub use pass_manager::*;
@@ -80,6 +82,17 @@ pub struct Builder<'a>(InvariantOpaque<'a>);
 #[repr(C)]
 pub struct PassManager<'a>(InvariantOpaque<'a>);
 
+#[repr(C)]
+pub struct DIBuilder<'a>(InvariantOpaque<'a>);
+
+pub enum Metadata {}
+
+impl fmt::Debug for Metadata {
+    fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
 pub enum Type {}
 
 impl fmt::Debug for Type {
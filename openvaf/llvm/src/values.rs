@@ -1,4 +1,4 @@
-use libc::{c_char, c_double, c_uint, c_ulonglong};
+use libc::{c_char, c_double, c_uint, c_ulonglong, size_t};
 
 use crate::{
     BasicBlock, Bool, CallConv, Context, DLLStorageClass, Linkage, Module, Type, UnnamedAddr,
@@ -12,7 +12,7 @@ extern "C" {
     pub fn LLVMTypeOf(val: &Value) -> &Type;
 
     // pub fn LLVMGetValueName2(val: &'a Value, Length: *mut ::libc::size_t) -> *const ::libc::c_char;
-    // pub fn LLVMSetValueName2(val: &'a Value, Name: *const ::libc::c_char, NameLen: ::libc::size_t);
+    pub fn LLVMSetValueName2(val: &Value, name: *const c_char, name_len: size_t);
 
     // pub fn LLVMDumpValue(Val: &'a Value);
     pub fn LLVMPrintValueToString(val: &Value) -> *mut c_char;
@@ -188,7 +188,7 @@ extern "C" {
     // pub fn LLVMConstSIToFP(ConstantVal: &'a Value, ToType: TypeRef) -> &'a Value;
     // pub fn LLVMConstFPToUI(ConstantVal: &'a Value, ToType: TypeRef) -> &'a Value;
     // pub fn LLVMConstFPToSI(ConstantVal: &'a Value, ToType: TypeRef) -> &'a Value;
-    // pub fn LLVMConstPtrToInt(ConstantVal: &'a Value, ToType: TypeRef) -> &'a Value;
+    pub fn LLVMConstPtrToInt<'a>(const_val: &'a Value, ty: &'a Type) -> &'a Value;
     // pub fn LLVMConstIntToPtr(ConstantVal: &'a Value, ToType: TypeRef) -> &'a Value;
     // pub fn LLVMConstBitCast(ConstantVal: &'a Value, ToType: TypeRef) -> &'a Value;
     // pub fn LLVMConstAddrSpaceCast(ConstantVal: &'a Value, ToType: TypeRef) -> &'a Value;
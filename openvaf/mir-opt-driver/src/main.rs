@@ -0,0 +1,93 @@
+//! `mir-opt` runs a pipeline of MIR passes on textual MIR, similar to LLVM's `opt`.
+//!
+//! The input is read with `mir_reader` (the format written by `openvaf --emit mir`), every
+//! function is transformed by the passes in the order in which they are given and the result
+//! is printed in the same format.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read};
+use std::process::exit;
+
+use anyhow::{anyhow, Context, Result};
+use clap::builder::{PossibleValue, PossibleValuesParser};
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
+
+use crate::passes::Pass;
+
+mod passes;
+
+const INPUT: &str = "input";
+const PASSES: &str = "passes";
+const OUTPUT: &str = "output";
+
+fn main_command() -> Command {
+    Command::new("mir-opt")
+        .about("Run MIR passes on textual MIR.")
+        .long_about("Run MIR passes on textual MIR.\nEvery function of the input is transformed by the passes in the order in which they are given and printed to stdout. The results of optbarrier instructions are the outputs of a function, and calls of functions imported as %ddx_vN are the derivatives of their argument by vN.")
+        .args([
+            Arg::new(PASSES)
+                .long(PASSES)
+                .short('p')
+                .value_name("PASS")
+                .help("The passes to run (comma separated, can be repeated).")
+                .value_parser(PossibleValuesParser::new(
+                    Pass::ALL.map(|pass| PossibleValue::new(pass.name()).help(pass.help())),
+                ))
+                .value_delimiter(',')
+                .action(ArgAction::Append)
+                .required(false),
+            Arg::new(OUTPUT)
+                .long(OUTPUT)
+                .short('o')
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("Write the result to a file instead of stdout.")
+                .required(false),
+            Arg::new(INPUT)
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("The MIR file (- reads from stdin).")
+                .required(true),
+        ])
+}
+
+fn main() {
+    let matches = main_command().get_matches();
+    if let Err(err) = run(&matches) {
+        eprintln!("error: {err:#}");
+        exit(1)
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let passes: Vec<_> = matches
+        .get_many::<String>(PASSES)
+        .map_or_else(Vec::new, |passes| passes.filter_map(|pass| Pass::from_name(pass)).collect());
+
+    let input = matches.get_one::<String>(INPUT).unwrap();
+    let src = if input == "-" {
+        let mut src = String::new();
+        io::stdin().read_to_string(&mut src).context("failed to read stdin")?;
+        src
+    } else {
+        fs::read_to_string(input).with_context(|| format!("failed to read {input}"))?
+    };
+    let (mut funcs, literals) =
+        mir_reader::parse_functions(&src).map_err(|err| anyhow!("{input}:{err}"))?;
+
+    let mut out = String::new();
+    for func in &mut funcs {
+        for pass in &passes {
+            pass.run(func);
+        }
+        let _ = writeln!(out, "{}", func.print(&literals));
+    }
+
+    if let Some(output) = matches.get_one::<String>(OUTPUT) {
+        fs::write(output, out).with_context(|| format!("failed to write {output}"))?;
+    } else {
+        print!("{out}");
+    }
+    Ok(())
+}
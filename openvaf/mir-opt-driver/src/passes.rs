@@ -0,0 +1,149 @@
+//! The passes that can be run by `mir-opt`.
+
+use bitset::{BitSet, HybridBitSet, SparseBitMatrix};
+use mir::{
+    ControlFlowGraph, DominatorTree, FuncRef, Function, KnownDerivatives, Opcode, Unknown, Value,
+    ValueDef,
+};
+use mir_autodiff::auto_diff;
+use mir_opt::{
+    aggressive_dead_code_elimination, dead_code_elimination, inst_combine, simplify_cfg,
+    simplify_cfg_no_phi_merge, sparse_conditional_constant_propagation, GVN,
+};
+use typed_indexmap::TiSet;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    InstCombine,
+    Sccp,
+    SimplifyCfg,
+    SimplifyCfgNoPhiMerge,
+    Gvn,
+    Dce,
+    Adce,
+    AutoDiff,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 8] = [
+        Pass::InstCombine,
+        Pass::Sccp,
+        Pass::SimplifyCfg,
+        Pass::SimplifyCfgNoPhiMerge,
+        Pass::Gvn,
+        Pass::Dce,
+        Pass::Adce,
+        Pass::AutoDiff,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::InstCombine => "inst-combine",
+            Pass::Sccp => "sccp",
+            Pass::SimplifyCfg => "simplify-cfg",
+            Pass::SimplifyCfgNoPhiMerge => "simplify-cfg-no-phi-merge",
+            Pass::Gvn => "gvn",
+            Pass::Dce => "dce",
+            Pass::Adce => "adce",
+            Pass::AutoDiff => "autodiff",
+        }
+    }
+
+    pub fn help(self) -> &'static str {
+        match self {
+            Pass::InstCombine => "simplify instructions with algebraic identities",
+            Pass::Sccp => "sparse conditional constant propagation",
+            Pass::SimplifyCfg => "merge and remove blocks (including trivial phis)",
+            Pass::SimplifyCfgNoPhiMerge => "like simplify-cfg but never merges phis",
+            Pass::Gvn => "global value numbering",
+            Pass::Dce => "remove instructions that do not contribute to an optbarrier",
+            Pass::Adce => "aggressive dead code elimination (also removes dead branches)",
+            Pass::AutoDiff => "replace calls to %ddx_vN with the derivatives by vN",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Pass> {
+        Pass::ALL.into_iter().find(|pass| pass.name() == name)
+    }
+
+    pub fn run(self, func: &mut Function) {
+        let mut cfg = ControlFlowGraph::new();
+        cfg.compute(func);
+        let mut dom_tree = DominatorTree::default();
+        match self {
+            Pass::InstCombine => inst_combine(func),
+            Pass::Sccp => sparse_conditional_constant_propagation(func, &cfg),
+            Pass::SimplifyCfg => simplify_cfg(func, &mut cfg),
+            Pass::SimplifyCfgNoPhiMerge => simplify_cfg_no_phi_merge(func, &mut cfg),
+            Pass::Gvn => {
+                dom_tree.compute(func, &cfg, true, true, false);
+                let mut gvn = GVN::default();
+                gvn.init(func, &dom_tree, num_params(func));
+                gvn.solve(func);
+                gvn.remove_unnecessary_insts(func, &dom_tree);
+                gvn.clear(func);
+            }
+            Pass::Dce => dead_code_elimination(func, &output_values(func)),
+            Pass::Adce => {
+                dom_tree.compute(func, &cfg, true, true, false);
+                let mut control_dep = SparseBitMatrix::new_square(0);
+                dom_tree.compute_postdom_frontiers(&cfg, &mut control_dep);
+                let outputs = output_values(func);
+                aggressive_dead_code_elimination(
+                    func,
+                    &mut cfg,
+                    &|val, _| outputs.contains(val),
+                    &control_dep,
+                );
+            }
+            Pass::AutoDiff => {
+                dom_tree.compute(func, &cfg, true, false, true);
+                let derivatives = known_derivatives(func);
+                auto_diff(&mut *func, &dom_tree, &derivatives, &[]);
+            }
+        }
+    }
+}
+
+fn num_params(func: &Function) -> u32 {
+    func.dfg.values().filter(|&val| matches!(func.dfg.value_def(val), ValueDef::Param(_))).count()
+        as u32
+}
+
+/// The results of all `optbarrier` instructions, which are the outputs of the function in
+/// textual MIR (dead code elimination keeps everything they depend on).
+fn output_values(func: &Function) -> BitSet<Value> {
+    let mut res = BitSet::new_empty(func.dfg.num_values());
+    for block in func.layout.blocks() {
+        for inst in func.layout.block_insts(block) {
+            if func.dfg.insts[inst].opcode() == Opcode::OptBarrier {
+                res.insert(func.dfg.first_result(inst));
+            }
+        }
+    }
+    res
+}
+
+/// A function imported as `%ddx_vN` computes the derivative of its argument by `vN`.
+fn known_derivatives(func: &Function) -> KnownDerivatives {
+    let ddx_calls: Vec<(FuncRef, Value)> = func
+        .dfg
+        .signatures
+        .iter_enumerated()
+        .filter_map(|(func_ref, sig)| {
+            let val: u32 = sig.name.strip_prefix("ddx_v")?.parse().ok()?;
+            Some((func_ref, val.into()))
+        })
+        .collect();
+
+    let unknowns: TiSet<Unknown, Value> = ddx_calls.iter().map(|&(_, val)| val).collect();
+    let ddx_calls = ddx_calls
+        .into_iter()
+        .map(|(func_ref, val)| {
+            let mut unknown = HybridBitSet::new_empty();
+            unknown.insert(unknowns.index(&val).unwrap(), unknowns.len());
+            (func_ref, (unknown, HybridBitSet::new_empty()))
+        })
+        .collect();
+    KnownDerivatives { unknowns, ddx_calls }
+}
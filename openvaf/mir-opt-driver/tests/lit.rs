@@ -0,0 +1,151 @@
+//! File based tests for `mir-opt` in the style of LLVM's lit.
+//!
+//! Every `.mir` file in `openvaf/test_data/mir_opt` is passed to `mir-opt` with the arguments of
+//! its `; RUN:` line. The output must match the `CHECK` directives of the file, a subset of
+//! FileCheck:
+//!
+//! * `; CHECK: text` - a later line of the output contains `text`
+//! * `; CHECK-NEXT: text` - the line after the previous match contains `text`
+//! * `; CHECK-NOT: text` - no line between the previous and the next match contains `text`
+//!
+//! Whitespace is collapsed before the lines are compared.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use mini_harness::{harness, Result};
+use stdx::{ignore_never, openvaf_test_data, project_root};
+
+/// Returns the path of a binary build by cargo
+fn cargo_bin(name: &str) -> PathBuf {
+    let env_var = format!("CARGO_BIN_EXE_{}", name);
+    env::var_os(env_var).map(|p| p.into()).unwrap_or_else(|| {
+        let mut dir = env::current_exe().unwrap();
+        dir.pop();
+        if dir.ends_with("deps") {
+            dir.pop();
+        }
+        dir.join(format!("{}{}", name, env::consts::EXE_SUFFIX))
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CheckKind {
+    Check,
+    Next,
+    Not,
+}
+
+#[derive(Debug)]
+struct Check {
+    kind: CheckKind,
+    pattern: String,
+    /// The line of the directive in the test file.
+    line: usize,
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn parse_directives(src: &str) -> (Option<Vec<String>>, Vec<Check>) {
+    let mut run = None;
+    let mut checks = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        let directive =
+            if let Some(directive) = line.trim().strip_prefix(';') { directive } else { continue };
+        let directive = directive.trim_start();
+        if let Some(args) = directive.strip_prefix("RUN:") {
+            run = Some(args.split_whitespace().map(str::to_owned).collect());
+            continue;
+        }
+        let (kind, pattern) = if let Some(pattern) = directive.strip_prefix("CHECK:") {
+            (CheckKind::Check, pattern)
+        } else if let Some(pattern) = directive.strip_prefix("CHECK-NEXT:") {
+            (CheckKind::Next, pattern)
+        } else if let Some(pattern) = directive.strip_prefix("CHECK-NOT:") {
+            (CheckKind::Not, pattern)
+        } else {
+            continue;
+        };
+        checks.push(Check { kind, pattern: normalize(pattern), line: i + 1 });
+    }
+    (run, checks)
+}
+
+/// Matches the `output` against the `checks`. Returns a message that explains the first
+/// directive that failed.
+fn file_check(output: &str, checks: &[Check]) -> Result<(), String> {
+    let lines: Vec<_> = output.lines().map(normalize).collect();
+    // the first line that has not been matched yet
+    let mut pos = 0;
+    let mut pending_not: Vec<&Check> = Vec::new();
+    let check_not = |pending_not: &mut Vec<&Check>, lines: &[String]| {
+        for not in pending_not.drain(..) {
+            if let Some(line) = lines.iter().find(|line| line.contains(&not.pattern)) {
+                return Err(format!(
+                    "line {}: CHECK-NOT: \"{}\" found in \"{line}\"",
+                    not.line, not.pattern
+                ));
+            }
+        }
+        Ok(())
+    };
+
+    for check in checks {
+        match check.kind {
+            CheckKind::Not => pending_not.push(check),
+            CheckKind::Check => {
+                let found = lines[pos..].iter().position(|line| line.contains(&check.pattern));
+                let found = found.ok_or_else(|| {
+                    format!("line {}: CHECK: \"{}\" not found", check.line, check.pattern)
+                })?;
+                check_not(&mut pending_not, &lines[pos..pos + found])?;
+                pos += found + 1;
+            }
+            CheckKind::Next => {
+                if !pending_not.is_empty() {
+                    return Err(format!("line {}: CHECK-NEXT after CHECK-NOT", check.line));
+                }
+                match lines.get(pos) {
+                    Some(line) if pos != 0 && line.contains(&check.pattern) => pos += 1,
+                    line => {
+                        return Err(format!(
+                            "line {}: CHECK-NEXT: \"{}\" does not match \"{}\"",
+                            check.line,
+                            check.pattern,
+                            line.map_or("<end of output>", String::as_str)
+                        ))
+                    }
+                }
+            }
+        }
+    }
+    check_not(&mut pending_not, &lines[pos..])
+}
+
+fn lit_test(file: &Path) -> Result {
+    let src = fs::read_to_string(file)?;
+    let (args, checks) = parse_directives(&src);
+    let args = args.ok_or_else(|| format!("{} has no RUN line", file.display()))?;
+    if checks.is_empty() {
+        return Err(format!("{} has no CHECK directives", file.display()).into());
+    }
+
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let mir_opt = cargo_bin("mir-opt");
+    let output = xshell::cmd!(sh, "{mir_opt} {args...} {file}").read()?;
+    file_check(&output, &checks)
+        .map_err(|err| format!("{}:{err}\n\noutput:\n{output}", file.display()))?;
+    Ok(())
+}
+
+fn is_mir_file(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "mir")
+}
+
+harness! {
+    Test::from_dir_filtered("lit", &lit_test, &is_mir_file, &ignore_never, &openvaf_test_data("mir_opt"))
+}
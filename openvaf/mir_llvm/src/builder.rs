@@ -13,6 +13,7 @@ use mir::{
 use typed_index_collections::TiVec;
 
 use crate::callbacks::CallbackFun;
+use crate::coverage::Coverage;
 use crate::CodegenCx;
 
 #[derive(Clone)]
@@ -146,6 +147,10 @@ pub struct Builder<'a, 'cx, 'll> {
     pub prepend_pos: &'ll llvm::BasicBlock,
     pub unfinished_phis: Vec<(PhiNode, &'ll llvm::Value)>,
     pub fun: &'ll llvm::Value,
+    /// The debug locations attached to the instructions of `func` (empty without debug info).
+    pub debug_locs: TiVec<Inst, Option<&'ll llvm::Metadata>>,
+    /// Execution counters inserted for `--coverage`.
+    pub coverage: Option<Coverage<'ll>>,
 }
 
 impl Drop for Builder<'_, '_, '_> {
@@ -188,6 +193,8 @@ impl<'a, 'cx, 'll> Builder<'a, 'cx, 'll> {
             fun: llfunc,
             prepend_pos: entry,
             unfinished_phis: Vec::new(),
+            debug_locs: TiVec::new(),
+            coverage: None,
         }
     }
 }
@@ -356,6 +363,7 @@ impl<'ll> Builder<'_, '_, 'll> {
         for bb in po.into_iter().rev() {
             self.build_bb(bb)
         }
+        llvm::LLVMSetCurrentDebugLocation2(self.llbuilder, None);
 
         for (phi, llval) in self.unfinished_phis.iter() {
             let (blocks, vals): (Vec<_>, Vec<_>) = self
@@ -395,13 +403,28 @@ impl<'ll> Builder<'_, '_, 'll> {
     pub unsafe fn build_bb(&mut self, bb: Block) {
         self.select_bb(bb);
 
+        let mut counted = false;
         for inst in self.func.layout.block_insts(bb) {
+            if !self.debug_locs.is_empty() {
+                let loc = self.debug_locs.get(inst).copied().flatten();
+                llvm::LLVMSetCurrentDebugLocation2(self.llbuilder, loc);
+            }
+            // the counter is incremented after the phis
+            if !counted && !matches!(self.func.dfg.insts[inst], mir::InstructionData::PhiNode(_)) {
+                self.count_block(bb);
+                counted = true;
+            }
             let fast_math = self.func.srclocs.get(inst).map_or(false, |loc| loc.0 < 0);
             self.build_inst(
                 inst,
                 if fast_math { FastMathMode::Partial } else { FastMathMode::Disabled },
             )
         }
+        // blocks without any instructions besides phis (like the exit block whose terminator
+        // is built by the caller) are still counted
+        if !counted {
+            self.count_block(bb);
+        }
     }
 
     /// # Safety
@@ -427,9 +450,11 @@ impl<'ll> Builder<'_, '_, 'll> {
             mir::InstructionData::Unary { opcode, ref arg } => (opcode, slice::from_ref(arg)),
             mir::InstructionData::Binary { opcode, ref args } => (opcode, args.as_slice()),
             mir::InstructionData::Branch { cond, then_dst, else_dst, .. } => {
+                let cond = self.values[cond].get(self);
+                self.count_branch(inst, cond);
                 llvm::LLVMBuildCondBr(
                     self.llbuilder,
-                    self.values[cond].get(self),
+                    cond,
                     self.blocks[then_dst].unwrap(),
                     self.blocks[else_dst].unwrap(),
                 );
@@ -0,0 +1,54 @@
+use llvm::{
+    LLVMBuildAdd, LLVMBuildInBoundsGEP2, LLVMBuildLoad2, LLVMBuildSelect, LLVMBuildStore, UNNAMED,
+};
+use mir::{Block, Inst};
+use typed_index_collections::TiVec;
+
+use crate::Builder;
+
+/// Execution counters that are incremented by the code generated for a MIR function.
+/// The counters are 64-bit integers in a global array, they are incremented with a plain
+/// (non-atomic) load and store.
+pub struct Coverage<'ll> {
+    /// Pointer to the global array that holds the counters.
+    pub counters: &'ll llvm::Value,
+    /// The counter that is incremented whenever a block is entered.
+    pub blocks: TiVec<Block, Option<u32>>,
+    /// Conditional branches own two consecutive counters: the first is incremented when the
+    /// `then` destination is taken and the second when the `else` destination is taken.
+    pub branches: TiVec<Inst, Option<u32>>,
+}
+
+impl<'ll> Builder<'_, '_, 'll> {
+    /// # Safety
+    ///
+    /// Must only be called when the builder has been positioned in a block without terminator
+    pub(crate) unsafe fn count_block(&self, bb: Block) {
+        let coverage = if let Some(coverage) = &self.coverage { coverage } else { return };
+        if let Some(counter) = coverage.blocks.get(bb).copied().flatten() {
+            self.increment_counter(coverage.counters, self.cx.const_unsigned_int(counter));
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Must only be called when the builder has been positioned in a block without terminator
+    pub(crate) unsafe fn count_branch(&self, inst: Inst, cond: &'ll llvm::Value) {
+        let coverage = if let Some(coverage) = &self.coverage { coverage } else { return };
+        if let Some(counter) = coverage.branches.get(inst).copied().flatten() {
+            let then_counter = self.cx.const_unsigned_int(counter);
+            let else_counter = self.cx.const_unsigned_int(counter + 1);
+            let idx = LLVMBuildSelect(self.llbuilder, cond, then_counter, else_counter, UNNAMED);
+            self.increment_counter(coverage.counters, idx);
+        }
+    }
+
+    unsafe fn increment_counter(&self, counters: &'ll llvm::Value, idx: &'ll llvm::Value) {
+        let ty = self.cx.ty_aint(64);
+        let ptr = LLVMBuildInBoundsGEP2(self.llbuilder, ty, counters, &idx, 1, UNNAMED);
+        let val = LLVMBuildLoad2(self.llbuilder, ty, ptr, UNNAMED);
+        let val =
+            LLVMBuildAdd(self.llbuilder, val, llvm::LLVMConstInt(ty, 1, llvm::False), UNNAMED);
+        LLVMBuildStore(self.llbuilder, val, ptr);
+    }
+}
@@ -0,0 +1,199 @@
+use std::cell::RefCell;
+
+use ahash::AHashMap;
+use libc::c_uint;
+use llvm::{
+    DIBuilder, DWARFEmissionKind, DWARFSourceLanguage, Metadata, ModuleFlagBehavior, Value,
+};
+
+use crate::CodegenCx;
+
+/// Emits the DWARF debug info for a single LLVM module.
+///
+/// Only line tables are generated: every function built from MIR gets a subprogram
+/// and each instruction is annotated with the Verilog-A file, line and column it
+/// was lowered from.
+pub struct DebugInfo<'ll> {
+    builder: &'ll mut DIBuilder<'ll>,
+    llcx: &'ll llvm::Context,
+    unit: &'ll Metadata,
+    fun_ty: &'ll Metadata,
+    optimized: bool,
+    files: RefCell<AHashMap<String, &'ll Metadata>>,
+    scopes: RefCell<AHashMap<(*const Metadata, *const Metadata), &'ll Metadata>>,
+}
+
+impl<'ll> DebugInfo<'ll> {
+    /// # Safety
+    ///
+    /// This function calls the LLVM-C Api which may not be entirely safe.
+    /// Exercise caution!
+    pub unsafe fn new(cx: &CodegenCx<'_, 'll>, main_file: &str, optimized: bool) -> Self {
+        let dwarf_version = cx.const_unsigned_int(4);
+        let key = "Dwarf Version";
+        llvm::LLVMAddModuleFlag(
+            cx.llmod,
+            ModuleFlagBehavior::Warning,
+            key.as_ptr().cast(),
+            key.len(),
+            llvm::LLVMValueAsMetadata(dwarf_version),
+        );
+        let debug_version = cx.const_unsigned_int(llvm::LLVMDebugMetadataVersion());
+        let key = "Debug Info Version";
+        llvm::LLVMAddModuleFlag(
+            cx.llmod,
+            ModuleFlagBehavior::Warning,
+            key.as_ptr().cast(),
+            key.len(),
+            llvm::LLVMValueAsMetadata(debug_version),
+        );
+
+        let builder = llvm::LLVMCreateDIBuilder(cx.llmod);
+        let (file, dir) = split_path(main_file);
+        let file = llvm::LLVMDIBuilderCreateFile(
+            builder,
+            file.as_ptr().cast(),
+            file.len(),
+            dir.as_ptr().cast(),
+            dir.len(),
+        );
+        let producer = concat!("OpenVAF ", env!("CARGO_PKG_VERSION"));
+        let unit = llvm::LLVMDIBuilderCreateCompileUnit(
+            builder,
+            DWARFSourceLanguage::C,
+            file,
+            producer.as_ptr().cast(),
+            producer.len(),
+            optimized as llvm::Bool,
+            "".as_ptr().cast(),
+            0,
+            0,
+            "".as_ptr().cast(),
+            0,
+            DWARFEmissionKind::LineTablesOnly,
+            0,
+            llvm::False,
+            llvm::False,
+            "".as_ptr().cast(),
+            0,
+            "".as_ptr().cast(),
+            0,
+        );
+        let fun_ty = llvm::LLVMDIBuilderCreateSubroutineType(
+            builder,
+            file,
+            [].as_ptr(),
+            0,
+            llvm::DIFlagZero,
+        );
+
+        let mut files = AHashMap::new();
+        files.insert(main_file.to_owned(), file);
+
+        DebugInfo {
+            builder,
+            llcx: cx.llcx,
+            unit,
+            fun_ty,
+            optimized,
+            files: RefCell::new(files),
+            scopes: RefCell::default(),
+        }
+    }
+
+    /// Returns the debug info file for `path`.
+    pub fn file(&self, path: &str) -> &'ll Metadata {
+        *self.files.borrow_mut().entry(path.to_owned()).or_insert_with(|| {
+            let (file, dir) = split_path(path);
+            unsafe {
+                llvm::LLVMDIBuilderCreateFile(
+                    self.builder,
+                    file.as_ptr().cast(),
+                    file.len(),
+                    dir.as_ptr().cast(),
+                    dir.len(),
+                )
+            }
+        })
+    }
+
+    /// Creates the subprogram for `fun` which is declared at `line` (zero-based) of `file`.
+    /// The returned scope is used to create the debug locations of the instructions in `fun`.
+    ///
+    /// # Safety
+    ///
+    /// This function calls the LLVM-C Api which may not be entirely safe.
+    /// Exercise caution!
+    pub unsafe fn function(
+        &self,
+        fun: &'ll Value,
+        name: &str,
+        file: &'ll Metadata,
+        line: u32,
+    ) -> &'ll Metadata {
+        let subprogram = llvm::LLVMDIBuilderCreateFunction(
+            self.builder,
+            self.unit,
+            name.as_ptr().cast(),
+            name.len(),
+            name.as_ptr().cast(),
+            name.len(),
+            file,
+            line + 1,
+            self.fun_ty,
+            llvm::False,
+            llvm::True,
+            line + 1,
+            llvm::DIFlagPrototyped,
+            self.optimized as llvm::Bool,
+        );
+        llvm::LLVMSetSubprogram(fun, subprogram);
+        self.scopes.borrow_mut().insert((subprogram as *const _, file as *const _), subprogram);
+        subprogram
+    }
+
+    /// Creates a debug location at `line` and `col` (both zero-based) of `file`
+    /// inside the function described by `scope`.
+    pub fn location(
+        &self,
+        scope: &'ll Metadata,
+        file: &'ll Metadata,
+        line: u32,
+        col: u32,
+    ) -> &'ll Metadata {
+        // code from included files must be placed in a scope that refers to that file
+        let key = (scope as *const _, file as *const _);
+        let scope = *self.scopes.borrow_mut().entry(key).or_insert_with(|| unsafe {
+            llvm::LLVMDIBuilderCreateLexicalBlockFile(self.builder, scope, file, 0)
+        });
+        unsafe {
+            llvm::LLVMDIBuilderCreateDebugLocation(
+                self.llcx,
+                line as c_uint + 1,
+                col as c_uint + 1,
+                scope,
+                None,
+            )
+        }
+    }
+
+    /// Resolves all debug info. Must be called once all functions have been build.
+    pub fn finalize(&self) {
+        unsafe { llvm::LLVMDIBuilderFinalize(self.builder) }
+    }
+}
+
+impl Drop for DebugInfo<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            llvm::LLVMDisposeDIBuilder(&mut *(self.builder as *mut _));
+        }
+    }
+}
+
+fn split_path(path: &str) -> (&str, &str) {
+    match path.rfind(['/', '\\']) {
+        Some(pos) => (&path[pos + 1..], &path[..pos]),
+        None => (path, ""),
+    }
+}
@@ -30,6 +30,7 @@ impl<'a, 'll> CodegenCx<'a, 'll> {
 
         ifn!("llvm.pow.f64", fn(t_f64, t_f64) -> t_f64);
         ifn!("llvm.sqrt.f64", fn(t_f64) -> t_f64);
+        ifn!("llvm.fabs.f64", fn(t_f64) -> t_f64);
         ifn!("llvm.sin.f64", fn(t_f64) -> t_f64);
         ifn!("llvm.cos.f64", fn(t_f64) -> t_f64);
         ifn!("llvm.exp.f64", fn(t_f64) -> t_f64);
@@ -16,6 +16,8 @@ use target::spec::Target;
 
 mod builder;
 mod context;
+mod coverage;
+mod debug_info;
 mod declarations;
 mod intrinsics;
 mod types;
@@ -27,6 +29,8 @@ mod tests;
 pub use builder::{Builder, BuilderVal, MemLoc};
 pub use callbacks::CallbackFun;
 pub use context::CodegenCx;
+pub use coverage::Coverage;
+pub use debug_info::DebugInfo;
 
 pub struct LLVMBackend<'t> {
     target: &'t Target,
@@ -124,6 +128,16 @@ impl<'t> LLVMBackend<'t> {
     pub fn target(&self) -> &'t Target {
         self.target
     }
+
+    /// The cpu code is generated for (`native` is resolved to the host cpu).
+    pub fn target_cpu(&self) -> &str {
+        &self.target_cpu
+    }
+
+    /// The comma separated target features code is generated with.
+    pub fn target_features(&self) -> &str {
+        &self.features
+    }
 }
 
 impl Drop for LLVMBackend<'_> {
@@ -169,6 +183,19 @@ impl ModuleLlvm {
         llvm::LLVMSetDataLayout(llmod, data_layout.as_ptr());
         llvm::set_normalized_target(llmod, &target.llvm_target);
 
+        let abi = &target.options.llvm_abiname;
+        if !abi.is_empty() {
+            let key = "target-abi";
+            let val = llvm::LLVMMDStringInContext2(llcx, abi.as_ptr().cast(), abi.len());
+            llvm::LLVMAddModuleFlag(
+                llmod,
+                llvm::ModuleFlagBehavior::Error,
+                key.as_ptr().cast(),
+                key.len(),
+                val,
+            );
+        }
+
         let tm = llvm::create_target(
             &target.llvm_target,
             target_cpu,
@@ -244,6 +271,26 @@ impl ModuleLlvm {
     }
 
     pub fn emit_object(&self, dst: &Path) -> Result<(), LLVMString> {
+        self.emit_file(dst, llvm::CodeGenFileType::ObjectFile)
+    }
+
+    pub fn emit_asm(&self, dst: &Path) -> Result<(), LLVMString> {
+        self.emit_file(dst, llvm::CodeGenFileType::AssemblyFile)
+    }
+
+    pub fn emit_bitcode(&self, dst: &Path) -> Result<(), LLVMString> {
+        let path = CString::new(dst.to_str().unwrap()).unwrap();
+        let return_code = unsafe { llvm::LLVMWriteBitcodeToFile(self.llmod(), path.as_ptr()) };
+        if return_code != 0 {
+            let msg =
+                CString::new(format!("failed to write bitcode to {}", dst.display())).unwrap();
+            return Err(LLVMString::create_from_c_str(&msg));
+        }
+
+        Ok(())
+    }
+
+    fn emit_file(&self, dst: &Path, file_type: llvm::CodeGenFileType) -> Result<(), LLVMString> {
         let path = CString::new(dst.to_str().unwrap()).unwrap();
 
         let mut err_string = MaybeUninit::uninit();
@@ -254,7 +301,7 @@ impl ModuleLlvm {
                 self.tm,
                 self.llmod(),
                 path.as_ptr(),
-                llvm::CodeGenFileType::ObjectFile,
+                file_type,
                 err_string.as_mut_ptr(),
             )
         };
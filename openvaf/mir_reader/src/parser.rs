@@ -201,6 +201,8 @@ impl<'a> Parser<'a> {
     fn token(&mut self) -> Option<Token<'a>> {
         while self.lookahead.is_none() {
             match self.lex.next() {
+                // comments (`; ...`) are only for human readers
+                Some(Ok(LocatedToken { token: Token::Comment(_), .. })) => (),
                 Some(Ok(LocatedToken { token, location })) => {
                     self.lookahead = Some(token);
                     self.loc = location;
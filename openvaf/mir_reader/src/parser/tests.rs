@@ -58,3 +58,22 @@ fn roundtrip() {
     let printed = fun.print(&interner).to_string();
     expected.assert_eq(&printed)
 }
+
+#[test]
+fn comments() {
+    let (fun, interner) = parse_function(
+        "; leading comment
+        function %bar(v4) { ; after the signature
+        block0:
+            v5 = iadd v4, v4 ; trailing comment
+        }",
+    )
+    .unwrap();
+    let expected = expect![[r#"
+        function %bar(v4) {
+        block0:
+            v5 = iadd v4, v4
+        }
+    "#]];
+    expected.assert_eq(&fun.print(&interner).to_string())
+}
@@ -34,13 +34,128 @@ pub fn main_command() -> Command {
             codegen_opts(),
             interface(),
             expand(),
+            describe(),
+            format(),
             dump_json(),
+            debug_info(),
+            sanitize(),
+            sanitize_abort(),
+            coverage(),
+            modelcard(),
+            sensitivity(),
+            emit(),
+            output_kind(),
+            osdi_version(),
+            module(),
+            anonymize_crash_report(),
+            watch(),
             input(),
         ])
+        .subcommand(cache_command())
+        .subcommand(build_command())
+        .subcommand(coverage_command())
+        .subcommand(doc_command())
         .subcommand_required(false)
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
         .arg_required_else_help(true)
 }
 
+fn cache_command() -> Command {
+    Command::new(CACHE)
+        .about("Manage the artifacts stored in batchmode.")
+        .arg(
+            dir_path_arg(CACHE_DIR)
+                .long(CACHE_DIR)
+                .help("Directory where artifacts are stored in batchmode.")
+                .required(false),
+        )
+        .subcommand(Command::new(CACHE_LIST).about("List all cached artifacts."))
+        .subcommand(Command::new(CACHE_CLEAN).about("Remove all cached artifacts."))
+        .subcommand(
+            Command::new(CACHE_PRUNE)
+                .about("Remove the least recently used artifacts.")
+                .arg(max_size()),
+        )
+        .subcommand_required(true)
+}
+
+fn build_command() -> Command {
+    Command::new(BUILD)
+        .about("Compile all models listed in a project manifest.")
+        .long_about("Compile all models listed in a project manifest.\nThe models are compiled in parallel and a summary of all failures is printed at the end.")
+        .arg(
+            input_file_path_arg(MANIFEST)
+                .help("The project manifest. Defaults to openvaf.toml in the current directory.")
+                .required(false),
+        )
+        .arg(dry_run())
+}
+
+fn coverage_command() -> Command {
+    Command::new(COVERAGE)
+        .about("Inspect the execution counts of models compiled with --coverage.")
+        .subcommand(
+            Command::new(COVERAGE_REPORT)
+                .about("Map dumped coverage counters back to the Verilog-A source.")
+                .long_about("Map dumped coverage counters back to the Verilog-A source.\nA counter dump contains the OSDI_COVERAGE_LEN entries of the OSDI_COVERAGE_COUNTERS array exported by the library as whitespace separated integers. The counts of all dumps are summed.")
+                .arg(
+                    input_file_path_arg(COVERAGE_MAP)
+                        .help("The coverage map (.covmap) written next to the library.")
+                        .required(true),
+                )
+                .arg(
+                    input_file_path_arg(COUNTERS)
+                        .help("The dumped counters.")
+                        .required(true)
+                        .num_args(1..),
+                )
+                .arg(flag(LCOV, "lcov").help("Write an lcov tracefile instead of annotated sources."))
+                .arg(
+                    output_file_path_arg(OUTPUT)
+                        .long(OUTPUT)
+                        .short('o')
+                        .help("Write the report to a file instead of stdout.")
+                        .required(false),
+                ),
+        )
+        .subcommand_required(true)
+}
+
+fn doc_command() -> Command {
+    Command::new(DOC)
+        .about("Generate reference pages for the modules of Verilog-A files.")
+        .long_about("Generate reference pages for the modules of Verilog-A files.\nOne page is written per module (and an index of all modules). The pages list the terminals, the parameters grouped by their group attribute with defaults, ranges, units and descriptions and the operating point variables. Every parameter links back to the source line that declares it.")
+        .args([
+            def_arg(),
+            include_dir(),
+            module(),
+            output_dir_path_arg(OUTPUT)
+                .long(OUTPUT)
+                .short('o')
+                .help("The directory the pages are written to.")
+                .default_value("doc"),
+            Arg::new(FORMAT)
+                .long(FORMAT)
+                .value_name("FORMAT")
+                .help("The format of the pages.")
+                .value_parser(["html", "markdown"])
+                .default_value("html")
+                .num_args(1),
+            Arg::new(SOURCE_LINK)
+                .long(SOURCE_LINK)
+                .value_name("TEMPLATE")
+                .help("The link to the declaration of a parameter.")
+                .long_help("The link to the declaration of a parameter. {path} is replaced with the path of the source file relative to the output directory and {line} with the line of the declaration, for example https://github.com/<user>/<repo>/blob/main/doc/{path}#L{line}.")
+                .default_value(openvaf::doc::DEFAULT_SOURCE_LINK)
+                .num_args(1),
+            input_file_path_arg(INPUT)
+                .help("The root Verilog-A files.")
+                .required(true)
+                .num_args(1..),
+        ])
+}
+
 pub const INTERFACE: &str = "interface";
 pub const BATCHMODE: &str = "batchmode";
 pub const DRYRUN: &str = "dry-run";
@@ -57,9 +172,36 @@ pub const OPT_LVL: &str = "opt_lvl";
 pub const DEFINE: &str = "define";
 pub const PRINT_EXPANSION: &str = "print-expansion";
 pub const DUMP_JSON: &str = "dump-json";
+pub const DESCRIBE: &str = "describe";
+pub const FORMAT: &str = "format";
+pub const DOC: &str = "doc";
+pub const SOURCE_LINK: &str = "source-link";
 pub const ALLOW: &str = "allow";
 pub const WARN: &str = "warn";
 pub const DENY: &str = "deny";
+pub const CACHE: &str = "cache";
+pub const CACHE_LIST: &str = "list";
+pub const CACHE_CLEAN: &str = "clean";
+pub const CACHE_PRUNE: &str = "prune";
+pub const MAX_SIZE: &str = "max-size";
+pub const BUILD: &str = "build";
+pub const MANIFEST: &str = "manifest";
+pub const MODULE: &str = "module";
+pub const DEBUG_INFO: &str = "debug-info";
+pub const SANITIZE: &str = "sanitize";
+pub const SANITIZE_ABORT: &str = "sanitize-abort";
+pub const EMIT: &str = "emit";
+pub const COVERAGE: &str = "coverage";
+pub const COVERAGE_REPORT: &str = "report";
+pub const COVERAGE_MAP: &str = "map";
+pub const COUNTERS: &str = "counters";
+pub const LCOV: &str = "lcov";
+pub const OUTPUT_KIND: &str = "output-kind";
+pub const MODELCARD: &str = "modelcard";
+pub const SENSITIVITY: &str = "sensitivity";
+pub const OSDI_VERSION: &str = "osdi-version";
+pub const ANONYMIZE_CRASH_REPORT: &str = "anonymize-crash-report";
+pub const WATCH: &str = "watch";
 
 fn interface() -> Arg {
     Arg::new(INTERFACE)
@@ -144,7 +286,36 @@ fn codegen_opts() -> Arg {
 fn input() -> Arg {
     input_file_path_arg(INPUT)
         .help("The root Verilog-A file.")
+        .long_help("The root Verilog-A file.\nIf multiple root files are passed, the modules of all files are linked into a single library.")
         .required_unless_present_any([LINTS, SUPPORTED_TARGETS])
+        .num_args(1..)
+}
+
+fn module() -> Arg {
+    Arg::new(MODULE)
+        .long(MODULE)
+        .short('m')
+        .value_name("NAME")
+        .help("Only export this module (can be repeated).")
+        .long_help(
+            "Only export this module (can be repeated).\nBy default all modules are exported.",
+        )
+        .required(false)
+        .action(ArgAction::Append)
+}
+
+fn watch() -> Arg {
+    flag(WATCH, WATCH)
+        .short('w')
+        .help("Recompile every time the input files change.")
+        .long_help("Recompile every time the input files change.\nThe root files and all files they include are watched. Diagnostics are printed (or the library is rebuilt) after every change. Only the parts of the compilation that depend on the modified files are repeated.")
+        .conflicts_with_all([PRINT_EXPANSION, DESCRIBE, DUMP_JSON])
+}
+
+fn anonymize_crash_report() -> Arg {
+    flag(ANONYMIZE_CRASH_REPORT, ANONYMIZE_CRASH_REPORT)
+        .help("Replace all identifiers in the reproducer written on a crash.")
+        .long_help("Replace all identifiers in the reproducer written on a crash.\nIf OpenVAF crashes it writes a reproducer that contains the preprocessed source code. With this option all identifiers (except builtin names) are consistently renamed and comments are removed so that the reproducer can be shared without disclosing proprietary names.")
 }
 
 fn include_dir() -> Arg {
@@ -177,6 +348,35 @@ fn cache_dir() -> Arg {
         .requires("batchmode")
 }
 
+fn max_size() -> Arg {
+    let parse = |raw: &str| {
+        let (num, unit) = match raw.find(|c: char| c.is_ascii_alphabetic()) {
+            Some(pos) => raw.split_at(pos),
+            None => (raw, ""),
+        };
+        let factor: u64 = match &*unit.to_ascii_uppercase() {
+            "" | "B" => 1,
+            "K" | "KB" => 1 << 10,
+            "M" | "MB" => 1 << 20,
+            "G" | "GB" => 1 << 30,
+            _ => bail!("unknown unit {unit}"),
+        };
+        match num.trim().parse::<u64>() {
+            Ok(num) => Ok(num.saturating_mul(factor)),
+            Err(err) => bail!("{err}"),
+        }
+    };
+
+    Arg::new(MAX_SIZE)
+        .long(MAX_SIZE)
+        .help("Maximum size of the cache.")
+        .long_help("Maximum size of the cache.\nThe least recently used artifacts are removed until the cache is at most this large.\n\nEXAMPLES: 500M, 2G, 1024K")
+        .value_name("SIZE")
+        .value_hint(ValueHint::Other)
+        .value_parser(ValueParser::new(parse))
+        .required(true)
+}
+
 fn dir_path_arg(name: &'static str) -> Arg {
     let parse = |raw: &str| {
         let path = Utf8Path::new(raw).to_owned();
@@ -194,6 +394,19 @@ fn dir_path_arg(name: &'static str) -> Arg {
         .value_parser(ValueParser::new(parse))
 }
 
+/// A directory that is created if it does not exist yet.
+fn output_dir_path_arg(name: &'static str) -> Arg {
+    let parse = |raw: &str| {
+        let path = Utf8Path::new(raw).to_owned();
+        match fs::metadata(&path) {
+            Ok(info) if !info.is_dir() => bail!("is not a directory"),
+            _ => Ok(path),
+        }
+    };
+
+    Arg::new(name).value_name("DIR").value_hint(ValueHint::DirPath).value_parser(parse)
+}
+
 fn output_file_path_arg(name: &'static str) -> Arg {
     let parse = |raw: &str| {
         let path = Utf8Path::new(raw).to_owned();
@@ -267,6 +480,113 @@ directives (`include) resolved is emitted to stdout.",
         )
 }
 
+fn debug_info() -> Arg {
+    flag(DEBUG_INFO, "debug-info")
+        .short('g')
+        .help("Generate debug info that refers to the Verilog-A sources.")
+        .long_help("Generate DWARF debug info that refers to the Verilog-A sources.\nAllows debuggers and profilers like gdb or perf to show the file and line of the generated code.")
+}
+
+fn sanitize() -> Arg {
+    Arg::new(SANITIZE)
+        .long(SANITIZE)
+        .value_name("SANITIZER")
+        .help("Insert runtime checks into the compiled model (can be repeated).")
+        .long_help("Insert runtime checks into the compiled model (can be repeated).\n\npossible values\n\nfloat - report the first NaN/Inf residual or Jacobian entry computed by eval with osdi_log")
+        .value_parser(["float"])
+        .hide_possible_values(true)
+        .required(false)
+        .action(ArgAction::Append)
+}
+
+fn coverage() -> Arg {
+    flag(COVERAGE, "coverage")
+        .help("Count how often each block and branch of the model is executed.")
+        .long_help("Count how often each block and branch of the model is executed.\nThe counters are exported as OSDI_COVERAGE_COUNTERS (OSDI_COVERAGE_LEN entries) and a coverage map is written next to the library with the extension .covmap. Use `openvaf coverage report` to turn dumped counters into annotated sources or an lcov tracefile.")
+}
+
+fn modelcard() -> Arg {
+    input_file_path_arg(MODELCARD)
+        .long(MODELCARD)
+        .help("Specialise the model on the parameter values of a modelcard.")
+        .long_help("Specialise the model on the parameter values of a modelcard.\nThe modelcard contains one name=value pair per line (# starts a comment). These model parameters are substituted as constants before optimization. The library still lists all parameters but reports any other value of a frozen parameter as invalid.")
+        .required(false)
+}
+
+fn sensitivity() -> Arg {
+    Arg::new(SENSITIVITY)
+        .long(SENSITIVITY)
+        .value_name("PARAM")
+        .help("Generate the derivatives of the residual by this parameter (can be repeated).")
+        .long_help("Generate the derivatives of the residual by this parameter (can be repeated).\nThe derivatives are computed by the eval_sensitivity function that is advertised in the OSDI descriptor extensions. Only real model or instance parameters are supported.")
+        .required(false)
+        .action(ArgAction::Append)
+}
+
+fn sanitize_abort() -> Arg {
+    flag(SANITIZE_ABORT, "sanitize-abort")
+        .help("Abort the simulation when a sanitizer check fails.")
+        .requires(SANITIZE)
+}
+
+fn emit() -> Arg {
+    Arg::new(EMIT)
+        .long(EMIT)
+        .value_name("KIND")
+        .help("Artifacts that are written (comma separated).")
+        .long_help("Artifacts that are written (comma separated).\n\npossible values\n\nlink - the shared library loaded by the simulator\nc - a self-contained C99 source file implementing the same OSDI library, written next to the library with the extension .c\nllvm-ir - the optimized LLVM IR of each generated LLVM module (.ll)\nllvm-bc - the LLVM bitcode of each generated LLVM module (.bc)\nasm - the assembly of each generated LLVM module (.s)\nobj - the object files the library is linked from (.o)\nmir - the MIR of each Verilog-A module in the textual format read by mir_reader (.mir)")
+        .value_parser(["link", "c", "llvm-ir", "llvm-bc", "asm", "obj", "mir"])
+        .hide_possible_values(true)
+        .value_delimiter(',')
+        .default_value("link")
+        .required(false)
+        .action(ArgAction::Append)
+}
+
+fn output_kind() -> Arg {
+    Arg::new(OUTPUT_KIND)
+        .long(OUTPUT_KIND)
+        .value_name("KIND")
+        .help("The kind of library that is linked.")
+        .long_help("The kind of library that is linked.\n\npossible values\n\nshared - a shared library loaded by the simulator at runtime (.osdi)\nstatic - a static archive (.a) for simulators that link models statically\nobject - a single relocatable object (.o)\n\nFor static archives and relocatable objects all OSDI symbols are suffixed with the name of the output file (for example OSDI_DESCRIPTORS_diode) and a C header declaring them is written next to the output with the extension .h")
+        .value_parser(["shared", "static", "object"])
+        .hide_possible_values(true)
+        .default_value("shared")
+        .num_args(1)
+        .required(false)
+}
+
+fn osdi_version() -> Arg {
+    Arg::new(OSDI_VERSION)
+        .long(OSDI_VERSION)
+        .value_name("VERSION")
+        .help("The revision of the OSDI interface implemented by the library.")
+        .long_help("The revision of the OSDI interface implemented by the library.\n\npossible values\n\n0.3 - the OSDI 0.3 descriptor layout supported by all simulators\n0.4 - additionally exports Jacobian loads with offsets, dense Jacobian arrays, parameter-given queries and the natures and disciplines of all nodes")
+        .value_parser(["0.3", "0.4"])
+        .hide_possible_values(true)
+        .default_value("0.3")
+        .num_args(1)
+        .required(false)
+}
+
+fn describe() -> Arg {
+    flag(DESCRIBE, "describe")
+        .help("Print the interface of the compiled modules instead of building a library.")
+        .long_help("Print the interface of the compiled modules instead of building a library.\nThe report lists the parameters (type, default, bounds, units, model or instance), the terminals and internal nodes (and which of them may be collapsed), the operating point variables, the noise sources and the sparsity pattern of the Jacobian exactly as they appear in the OSDI descriptor. Nothing is linked.")
+}
+
+fn format() -> Arg {
+    Arg::new(FORMAT)
+        .long(FORMAT)
+        .value_name("FORMAT")
+        .help("The format of the report printed by --describe.")
+        .value_parser(["table", "json", "markdown"])
+        .default_value("table")
+        .num_args(1)
+        .required(false)
+        .requires(DESCRIBE)
+}
+
 fn dump_json() -> Arg {
     flag(DUMP_JSON, "dump-json").help("Abort after lowering and serialize MIR as json.")
 }
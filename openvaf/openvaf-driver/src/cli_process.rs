@@ -1,17 +1,27 @@
+use std::fs;
 use std::io::Write;
 use std::process::exit;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{bail, Context, Result};
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::ArgMatches;
-use openvaf::{builtin_lints, get_target_names, host_triple, AbsPathBuf, LintLevel, OptLevel};
+use openvaf::coverage::ReportFormat;
+use openvaf::doc::{self, DocFormat};
+use openvaf::{
+    builtin_lints, cache, coverage, get_target_names, host_triple, project, AbsPathBuf,
+    CompilationTermination, EmitKind, FloatSanitizer, LintLevel, OptLevel, OsdiVersion, OutputKind,
+};
 use termcolor::{Color, ColorChoice, ColorSpec, WriteColor};
 
 use crate::cli_def::{
-    ALLOW, BATCHMODE, CACHE_DIR, CODEGEN, DEFINE, DENY, DRYRUN, INCLUDE, INPUT, LINTS, OPT_LVL,
-    OUTPUT, SUPPORTED_TARGETS, TARGET, TARGET_CPU, WARN,
+    ALLOW, BATCHMODE, CACHE_CLEAN, CACHE_DIR, CACHE_LIST, CACHE_PRUNE, CODEGEN, COUNTERS, COVERAGE,
+    COVERAGE_MAP, COVERAGE_REPORT, DEBUG_INFO, DEFINE, DENY, DRYRUN, EMIT, FORMAT, INCLUDE, INPUT,
+    LCOV, LINTS, MANIFEST, MAX_SIZE, MODELCARD, MODULE, OPT_LVL, OSDI_VERSION, OUTPUT, OUTPUT_KIND,
+    SANITIZE, SANITIZE_ABORT, SENSITIVITY, SOURCE_LINK, SUPPORTED_TARGETS, TARGET, TARGET_CPU,
+    WARN,
 };
-use crate::{CompilationDestination, Opts};
+use crate::{CompilationDestination, Opts, DATA_ERROR};
 
 pub fn matches_to_opts(matches: ArgMatches) -> Result<Opts> {
     if matches.get_flag(LINTS) {
@@ -23,7 +33,16 @@ pub fn matches_to_opts(matches: ArgMatches) -> Result<Opts> {
         exit(0)
     }
 
-    let input: Utf8PathBuf = matches.get_one::<Utf8PathBuf>(INPUT).unwrap().clone();
+    let mut inputs = matches.get_many::<Utf8PathBuf>(INPUT).unwrap().cloned();
+    let input = inputs.next().unwrap();
+    let additional_inputs = inputs.collect();
+
+    let modules = matches
+        .get_many::<String>(MODULE)
+        .map_or_else(Vec::new, |values| values.cloned().collect());
+    let sensitivities = matches
+        .get_many::<String>(SENSITIVITY)
+        .map_or_else(Vec::new, |values| values.cloned().collect());
 
     let mut lints = Vec::new();
 
@@ -38,30 +57,23 @@ pub fn matches_to_opts(matches: ArgMatches) -> Result<Opts> {
         lints.extend(deny.map(|lint| (lint.to_owned(), LintLevel::Deny)));
     }
 
+    let output_kind = match &**matches.get_one::<String>(OUTPUT_KIND).unwrap() {
+        "shared" => OutputKind::SharedLib,
+        "static" => OutputKind::StaticLib,
+        "object" => OutputKind::Object,
+        kind => bail!("unknown output kind {kind}"),
+    };
+    let osdi_version: OsdiVersion =
+        matches.get_one::<String>(OSDI_VERSION).unwrap().parse().map_err(anyhow::Error::msg)?;
+
     let output = if matches.get_flag(BATCHMODE) {
-        let cache_dir = if let Some(val) = matches.get_one::<Utf8PathBuf>(CACHE_DIR) {
-            val.clone()
-        } else {
-            let path = directories_next::ProjectDirs::from("com", "semimod", "openvaf")
-                .context(
-                    "failed to find cache directory\nhelp: use --cache-dir to specify it manually",
-                )?
-                .cache_dir()
-                .to_owned();
-            if let Ok(res) = Utf8PathBuf::from_path_buf(path) {
-                res
-            } else {
-                bail!(
-                    "failed to find cache directory\nhelp: use --cache-dir to specify it manually",
-                )
-            }
-        };
+        let cache_dir = cache_dir(&matches)?;
         CompilationDestination::Cache { cache_dir }
     } else {
         let lib_file = if let Some(output) = matches.get_one::<Utf8PathBuf>(OUTPUT) {
             output.clone()
         } else {
-            input.with_extension("osdi")
+            input.with_extension(output_kind.extension())
         };
 
         CompilationDestination::Path { lib_file }
@@ -104,8 +116,34 @@ pub fn matches_to_opts(matches: ArgMatches) -> Result<Opts> {
     let target_cpu: String =
         matches.get_one(TARGET_CPU).cloned().unwrap_or_else(|| default_cpu.to_owned());
 
+    let sanitize_float = matches
+        .get_many::<String>(SANITIZE)
+        .map_or(false, |mut sanitizers| sanitizers.any(|sanitizer| sanitizer == "float"));
+    let sanitize_float = match (sanitize_float, matches.get_flag(SANITIZE_ABORT)) {
+        (false, _) => FloatSanitizer::Disabled,
+        (true, false) => FloatSanitizer::Report,
+        (true, true) => FloatSanitizer::Abort,
+    };
+
+    let emit = matches
+        .get_many::<String>(EMIT)
+        .unwrap()
+        .map(|kind| match &**kind {
+            "link" => Ok(EmitKind::Link),
+            "c" => Ok(EmitKind::CSource),
+            "llvm-ir" => Ok(EmitKind::LlvmIr),
+            "llvm-bc" => Ok(EmitKind::LlvmBc),
+            "asm" => Ok(EmitKind::Asm),
+            "obj" => Ok(EmitKind::Obj),
+            "mir" => Ok(EmitKind::Mir),
+            kind => bail!("unknown emit kind {kind}"),
+        })
+        .collect::<Result<_>>()?;
+
     Ok(Opts {
         input,
+        additional_inputs,
+        modules,
         lints,
         codegen_opts,
         defines,
@@ -115,9 +153,214 @@ pub fn matches_to_opts(matches: ArgMatches) -> Result<Opts> {
         target,
         target_cpu,
         dry_run: matches.get_flag(DRYRUN),
+        debug_info: matches.get_flag(DEBUG_INFO),
+        sanitize_float,
+        coverage: matches.get_flag(COVERAGE),
+        modelcard: matches.get_one::<Utf8PathBuf>(MODELCARD).cloned(),
+        sensitivities,
+        emit,
+        output_kind,
+        osdi_version,
     })
 }
 
+fn cache_dir(matches: &ArgMatches) -> Result<Utf8PathBuf> {
+    if let Some(val) = matches.get_one::<Utf8PathBuf>(CACHE_DIR) {
+        return Ok(val.clone());
+    }
+    let path = directories_next::ProjectDirs::from("com", "semimod", "openvaf")
+        .context("failed to find cache directory\nhelp: use --cache-dir to specify it manually")?
+        .cache_dir()
+        .to_owned();
+    if let Ok(res) = Utf8PathBuf::from_path_buf(path) {
+        Ok(res)
+    } else {
+        bail!("failed to find cache directory\nhelp: use --cache-dir to specify it manually",)
+    }
+}
+
+pub fn cache_command(matches: &ArgMatches) -> Result<i32> {
+    let cache_dir = cache_dir(matches)?;
+    match matches.subcommand() {
+        Some((CACHE_LIST, _)) => print_cache(&cache_dir)?,
+        Some((CACHE_CLEAN, _)) => {
+            let freed = cache::clean(&cache_dir)?;
+            println!("removed {} from {cache_dir}", format_size(freed));
+        }
+        Some((CACHE_PRUNE, matches)) => {
+            let max_size = *matches.get_one::<u64>(MAX_SIZE).unwrap();
+            let freed = cache::prune(&cache_dir, max_size)?;
+            println!("removed {} from {cache_dir}", format_size(freed));
+        }
+        _ => unreachable!("clap requires a subcommand"),
+    }
+    Ok(0)
+}
+
+pub fn coverage_command(matches: &ArgMatches) -> Result<i32> {
+    match matches.subcommand() {
+        Some((COVERAGE_REPORT, matches)) => {
+            let map = matches.get_one::<Utf8PathBuf>(COVERAGE_MAP).unwrap();
+            let counters: Vec<_> =
+                matches.get_many::<Utf8PathBuf>(COUNTERS).unwrap().map(|path| &**path).collect();
+            let format =
+                if matches.get_flag(LCOV) { ReportFormat::Lcov } else { ReportFormat::Annotated };
+            let report = coverage::report(map, &counters, format)?;
+            if let Some(output) = matches.get_one::<Utf8PathBuf>(OUTPUT) {
+                fs::write(output, report).with_context(|| format!("failed to write {output}"))?;
+            } else {
+                print!("{report}");
+            }
+        }
+        _ => unreachable!("clap requires a subcommand"),
+    }
+    Ok(0)
+}
+
+pub fn doc_command(matches: &ArgMatches) -> Result<i32> {
+    let mut inputs = matches.get_many::<Utf8PathBuf>(INPUT).unwrap().cloned();
+    let input = inputs.next().unwrap();
+    let include: Result<_> = matches.get_many::<Utf8PathBuf>(INCLUDE).map_or_else(
+        || Ok(Vec::new()),
+        |include| include.map(|path| Ok(AbsPathBuf::assert(path.canonicalize()?))).collect(),
+    );
+    let format = match &**matches.get_one::<String>(FORMAT).unwrap() {
+        "html" => DocFormat::Html,
+        "markdown" => DocFormat::Markdown,
+        format => bail!("unknown doc format {format}"),
+    };
+    let out_dir = matches.get_one::<Utf8PathBuf>(OUTPUT).unwrap();
+    let source_link = matches.get_one::<String>(SOURCE_LINK).unwrap();
+
+    // the pages only depend on the metadata so the code generation options don't matter
+    let opts = Opts {
+        dry_run: false,
+        defines: matches
+            .get_many::<String>(DEFINE)
+            .map_or_else(Vec::new, |values| values.cloned().collect()),
+        codegen_opts: Vec::new(),
+        lints: Vec::new(),
+        input,
+        additional_inputs: inputs.collect(),
+        modules: matches
+            .get_many::<String>(MODULE)
+            .map_or_else(Vec::new, |values| values.cloned().collect()),
+        output: CompilationDestination::Path { lib_file: Utf8PathBuf::new() },
+        include: include?,
+        opt_lvl: OptLevel::None,
+        target: openvaf::Target::host_target().context("host target is not supported")?,
+        target_cpu: "generic".to_owned(),
+        debug_info: false,
+        sanitize_float: FloatSanitizer::Disabled,
+        coverage: false,
+        modelcard: None,
+        sensitivities: Vec::new(),
+        emit: Vec::new(),
+        output_kind: OutputKind::SharedLib,
+        osdi_version: OsdiVersion::V0_3,
+    };
+    let res = match doc::generate(&opts, out_dir, format, source_link)? {
+        CompilationTermination::Compiled { lib_file } => {
+            println!("{lib_file}");
+            0
+        }
+        CompilationTermination::FatalDiagnostic => DATA_ERROR,
+    };
+    Ok(res)
+}
+
+pub fn build_project(matches: &ArgMatches) -> Result<i32> {
+    let start = Instant::now();
+    let manifest = matches
+        .get_one::<Utf8PathBuf>(MANIFEST)
+        .cloned()
+        .unwrap_or_else(|| project::MANIFEST_NAME.into());
+    let models = project::load(&manifest, matches.get_flag(DRYRUN))?;
+    let results = project::compile_all(&models);
+
+    let mut stderr = termcolor::StandardStream::stderr(ColorChoice::Auto);
+    let mut failed = 0;
+    for (opts, res) in models.iter().zip(results) {
+        let err = match res {
+            Ok(CompilationTermination::Compiled { .. }) => continue,
+            Ok(CompilationTermination::FatalDiagnostic) => {
+                "aborted due to previous errors".to_owned()
+            }
+            Err(err) => err.chain().map(|cause| cause.to_string()).collect::<Vec<_>>().join(": "),
+        };
+        failed += 1;
+        stderr.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+        write!(&mut stderr, "error")?;
+        stderr.set_color(ColorSpec::new().set_bold(true))?;
+        write!(&mut stderr, ":")?;
+        stderr.set_color(&ColorSpec::new())?;
+        writeln!(&mut stderr, " failed to compile {}: {err}", opts.input)?;
+    }
+
+    let seconds = Instant::elapsed(&start).as_secs_f64();
+    let color = if failed == 0 { Color::Green } else { Color::Red };
+    stderr.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+    write!(&mut stderr, "Finished")?;
+    stderr.set_color(&ColorSpec::new())?;
+    writeln!(&mut stderr, " building {} models in {seconds:.2}s ({failed} failed)", models.len())?;
+
+    Ok(if failed == 0 { 0 } else { DATA_ERROR })
+}
+
+fn print_cache(cache_dir: &Utf8Path) -> Result<()> {
+    let entries = cache::list(cache_dir)?;
+    let mut stdout = termcolor::StandardStream::stdout(ColorChoice::Auto);
+
+    stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
+    writeln!(
+        &mut stdout,
+        "{:<30} {:>10} {:>14}  {:<28} INPUT",
+        "ARTIFACT", "SIZE", "LAST USED", "TARGET"
+    )?;
+    stdout.set_color(&ColorSpec::new())?;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let mut total = 0;
+    for entry in &entries {
+        total += entry.size;
+        writeln!(
+            &mut stdout,
+            "{:<30} {:>10} {:>14}  {:<28} {}",
+            entry.file_name,
+            format_size(entry.size),
+            format_age(now.saturating_sub(entry.last_used)),
+            entry.target,
+            entry.input
+        )?;
+    }
+    writeln!(&mut stdout, "{} artifacts, {} in {cache_dir}", entries.len(), format_size(total))?;
+    Ok(())
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size} {}", UNITS[unit])
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => "just now".to_owned(),
+        60..=3599 => format!("{} min ago", secs / 60),
+        3600..=86399 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
 fn print_lints() {
     let mut stdout = termcolor::StandardStream::stdout(ColorChoice::Auto);
 
@@ -1,17 +1,36 @@
-//! This module prints backtraces in case of panics
+//! This module prints backtraces in case of panics and writes a reproducer
+//! (see [`openvaf::reproducer`]) for the compilation that crashed.
 //! Adapted from https://github.com/rust-cli/human-panic/blob/0ebcb91b29e3f23b3559ea49d931a493ed7c8139
 //! under MIT license
 
 use std::error::Error;
 use std::fmt::Write as FmtWrite;
 use std::panic::PanicInfo;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{env, fs::File, io::Write, path::Path, path::PathBuf};
 use std::{io, mem, panic};
 
 use backtrace::Backtrace;
 use backtrace_ext::short_frames_strict;
+use camino::Utf8PathBuf;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
+static ANONYMIZE_REPRODUCER: AtomicBool = AtomicBool::new(false);
+
+/// Replace all identifiers in the reproducer written on a crash.
+pub fn anonymize_reproducer(anonymize: bool) {
+    ANONYMIZE_REPRODUCER.store(anonymize, Ordering::Relaxed)
+}
+
+/// The path of a file in the temporary directory that is named after the current time.
+fn crash_file(extension: &str) -> PathBuf {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    env::temp_dir().join(format!("openvaf-crash-{timestamp}.{extension}"))
+}
+
 // Utility function which will handle dumping information to disk
 pub fn handle_dump(panic_info: &PanicInfo) -> Option<PathBuf> {
     let mut expl = String::new();
@@ -66,6 +85,47 @@ pub fn install_panic_handler() {
     }));
 }
 
+/// Writes a reproducer for the compilation that crashed once the panic has unwound.
+/// This can not happen inside the panic hook because a second panic (preprocessing the sources
+/// again may itself panic) would abort the process there.
+pub fn report_crash() {
+    if cfg!(debug_assertions) {
+        return;
+    }
+
+    // the crash has already been reported
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| ()));
+    let anonymize = ANONYMIZE_REPRODUCER.load(Ordering::Relaxed);
+    let reproducer = panic::catch_unwind(|| write_reproducer(anonymize));
+    panic::set_hook(hook);
+
+    match reproducer {
+        Ok(Some(reproducer)) => print_reproducer_msg(&reproducer, anonymize)
+            .expect("printing error message to console failed"),
+        Ok(None) => (),
+        Err(_) => eprintln!("failed to write reproducer: OpenVAF crashed again"),
+    }
+}
+
+/// Writes a reproducer for the compilation that crashed (if any) to disk.
+fn write_reproducer(anonymize: bool) -> Option<PathBuf> {
+    let opts = super::ARGS.lock().ok()?.clone()?;
+    let file_path = Utf8PathBuf::from_path_buf(crash_file("tar")).ok()?;
+    match openvaf::reproducer::write_reproducer(
+        &opts,
+        env!("CARGO_PKG_VERSION"),
+        anonymize,
+        &file_path,
+    ) {
+        Ok(()) => Some(file_path.into()),
+        Err(err) => {
+            eprintln!("failed to write reproducer: {err:#}");
+            None
+        }
+    }
+}
+
 pub fn print_msg<P: AsRef<Path>>(file_path: Option<P>) -> io::Result<()> {
     use std::io::Write as _;
 
@@ -87,6 +147,26 @@ If possible please also attach the source file that OpenVAF was compiling.",
     Ok(())
 }
 
+pub fn print_reproducer_msg(file_path: &Path, anonymized: bool) -> io::Result<()> {
+    let mut stderr = StandardStream::stderr(ColorChoice::Auto);
+    writeln!(
+        stderr,
+        "
+A reproducer has been generated at \"{}\".
+It contains the preprocessed source code and the compiler options. Attaching it allows us to
+reproduce the problem without your model files.",
+        file_path.display()
+    )?;
+    if !anonymized {
+        writeln!(
+            stderr,
+            "If the source code can not be shared, run OpenVAF again with --anonymize-crash-report
+to replace all identifiers in the reproducer."
+        )?;
+    }
+    Ok(())
+}
+
 /// Contains metadata about the crash like the backtrace and
 /// information about the crate and operating system. Can
 /// be used to be serialized and persisted or printed as
@@ -158,13 +238,7 @@ impl Report {
 
     /// Write a file to disk.
     pub fn persist(&self) -> Result<PathBuf, Box<dyn Error + 'static>> {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        let tmp_dir = env::temp_dir();
-        let file_name = format!("openvaf-crash-{timestamp}.log");
-        let file_path = Path::new(&tmp_dir).join(file_name);
+        let file_path = crash_file("log");
         let mut file = File::create(&file_path)?;
         file.write_all(self.0.as_bytes())?;
         // TODO: include log
@@ -1,4 +1,5 @@
 use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
 use std::process::exit;
 use std::sync::Mutex;
 
@@ -9,10 +10,17 @@ use mimalloc::MiMalloc;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 use cli_def::{main_command, INPUT};
+use openvaf::describe::{describe, DescribeFormat};
+use openvaf::watch::watch;
 use openvaf::{compile, expand, CompilationDestination, CompilationTermination, Opts};
 
-use crate::cli_def::{DUMP_JSON, PRINT_EXPANSION};
-use crate::cli_process::matches_to_opts;
+use crate::cli_def::{
+    ANONYMIZE_CRASH_REPORT, BUILD, CACHE, COVERAGE, DESCRIBE, DOC, DUMP_JSON, FORMAT,
+    PRINT_EXPANSION, WATCH,
+};
+use crate::cli_process::{
+    build_project, cache_command, coverage_command, doc_command, matches_to_opts,
+};
 
 mod cli_def;
 mod cli_process;
@@ -25,7 +33,10 @@ static GLOBAL: MiMalloc = MiMalloc;
 pub fn main() {
     let matches = main_command().get_matches();
     crash_report::install_panic_handler();
-    let input: Utf8PathBuf = matches.get_one(INPUT).cloned().unwrap_or_else(Utf8PathBuf::new);
+    let input: Utf8PathBuf = matches
+        .get_many(INPUT)
+        .and_then(|mut inputs| inputs.next().cloned())
+        .unwrap_or_else(Utf8PathBuf::new);
     let env = env_logger::Env::default().filter("OPENVAF_LOG").write_style("OPENVAF_LOG_STYLE");
     env_logger::Builder::new()
         .format_timestamp(None)
@@ -33,7 +44,14 @@ pub fn main() {
         .filter_level(log::LevelFilter::Off)
         .parse_env(env)
         .init();
-    match wrapped_main(matches) {
+    let res = match panic::catch_unwind(AssertUnwindSafe(|| wrapped_main(matches))) {
+        Ok(res) => res,
+        Err(payload) => {
+            crash_report::report_crash();
+            panic::resume_unwind(payload)
+        }
+    };
+    match res {
         Ok(err_code) => exit(err_code),
         Err(err) => {
             let mut stderr = StandardStream::stderr(ColorChoice::Auto);
@@ -60,8 +78,28 @@ pub fn main() {
 pub const DATA_ERROR: i32 = 65;
 
 fn wrapped_main(matches: ArgMatches) -> Result<i32> {
+    match matches.subcommand() {
+        Some((CACHE, matches)) => return cache_command(matches),
+        Some((BUILD, matches)) => return build_project(matches),
+        Some((COVERAGE, matches)) => return coverage_command(matches),
+        Some((DOC, matches)) => return doc_command(matches),
+        _ => (),
+    }
+    crash_report::anonymize_reproducer(matches.get_flag(ANONYMIZE_CRASH_REPORT));
     let print_expansion = matches.get_flag(PRINT_EXPANSION);
     let dump_json_ = matches.get_flag(DUMP_JSON);
+    let watch_ = matches.get_flag(WATCH);
+    let describe_format = if matches.get_flag(DESCRIBE) {
+        let format = match matches.get_one::<String>(FORMAT).unwrap().as_str() {
+            "table" => DescribeFormat::Table,
+            "json" => DescribeFormat::Json,
+            "markdown" => DescribeFormat::Markdown,
+            format => bail!("unknown report format {format}"),
+        };
+        Some(format)
+    } else {
+        None
+    };
     let opts = matches_to_opts(matches)?;
     *ARGS.lock().unwrap() = Some(opts.clone());
     if print_expansion {
@@ -71,6 +109,13 @@ fn wrapped_main(matches: ArgMatches) -> Result<i32> {
         };
         return Ok(res);
     }
+    if let Some(format) = describe_format {
+        let res = match describe(&opts, format)? {
+            CompilationTermination::Compiled { .. } => 0,
+            CompilationTermination::FatalDiagnostic => DATA_ERROR,
+        };
+        return Ok(res);
+    }
     if dump_json_ {
         bail!("currently unimplemented");
         // let res = match dump_json(&opts)? {
@@ -79,6 +124,10 @@ fn wrapped_main(matches: ArgMatches) -> Result<i32> {
         // };
         // return Ok(res);
     }
+    if watch_ {
+        watch(&opts)?;
+        return Ok(0);
+    }
 
     let res = match compile(&opts)? {
         CompilationTermination::Compiled { lib_file } => {
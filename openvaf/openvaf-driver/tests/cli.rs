@@ -1,7 +1,11 @@
+use std::borrow::Cow;
 use std::env;
 use std::path::PathBuf;
 
+use gimli::{EndianSlice, RunTimeEndian};
 use mini_harness::{harness, Result};
+use object::read::archive::ArchiveFile;
+use object::{BinaryFormat, Object, ObjectKind, ObjectSection, ObjectSymbol};
 use stdx::{ignore_never, project_root};
 
 // Adapted from
@@ -45,8 +49,421 @@ fn link_diode() -> Result {
     Ok(())
 }
 
+fn cache_commands() -> Result {
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    let cache_dir = sh.create_temp_dir()?;
+    let cache_dir = cache_dir.path();
+    let mut artifacts = Vec::new();
+    for kind in ["shared", "static"] {
+        let artifact = xshell::cmd!(
+            sh,
+            "{openvaf} -O 0 --batch --cache-dir {cache_dir} --output-kind {kind} integration_tests/DIODE/diode.va"
+        )
+        .read()?;
+        assert!(sh.path_exists(&artifact), "{artifact}");
+        artifacts.push(artifact);
+    }
+
+    // every artifact is listed with the file it was compiled from
+    let list = xshell::cmd!(sh, "{openvaf} cache --cache-dir {cache_dir} list").read()?;
+    let entries: Vec<_> = list.lines().skip(1).filter(|line| line.contains("diode.va")).collect();
+    assert_eq!(entries.len(), 2, "{list}");
+    for artifact in &artifacts {
+        let file_name = PathBuf::from(artifact).file_name().unwrap().to_str().unwrap().to_owned();
+        assert!(entries.iter().any(|entry| entry.contains(&file_name)), "{file_name}: {list}");
+    }
+
+    // both artifacts fit into the cache
+    xshell::cmd!(sh, "{openvaf} cache --cache-dir {cache_dir} prune --max-size 1G").run()?;
+    assert!(artifacts.iter().all(|artifact| sh.path_exists(artifact)));
+
+    xshell::cmd!(sh, "{openvaf} cache --cache-dir {cache_dir} clean").run()?;
+    // the headers of static libraries are removed with them, only the locks and manifest remain
+    let leftover: Vec<_> = sh
+        .read_dir(cache_dir)?
+        .into_iter()
+        .filter(|file| file.extension().map_or(false, |ext| ext != "lock"))
+        .collect();
+    assert!(leftover.is_empty(), "{leftover:?}");
+    let list = xshell::cmd!(sh, "{openvaf} cache --cache-dir {cache_dir} list").read()?;
+    assert!(!list.contains("diode.va"), "{list}");
+    Ok(())
+}
+
+fn build_project() -> Result {
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    let dir = sh.create_temp_dir()?;
+    let manifest = dir.path().join("openvaf.toml");
+    let diode = project_root().join("integration_tests/DIODE/diode.va");
+    let resistor = project_root().join("integration_tests/RESISTOR/resistor.va");
+    sh.write_file(
+        &manifest,
+        format!(
+            "[settings]\nopt_lvl = 0\noutput_dir = \"build/osdi\"\n\n[[model]]\ninput = {diode:?}\n\n[[model]]\ninput = {resistor:?}\n"
+        ),
+    )?;
+    xshell::cmd!(sh, "{openvaf} build --dry-run {manifest}").run()?;
+    let output_dir = dir.path().join("build/osdi");
+    assert!(!sh.path_exists(&output_dir));
+
+    // the output directory is created and both libraries are linked into it
+    xshell::cmd!(sh, "{openvaf} build {manifest}").run()?;
+    for lib in ["diode.osdi", "resistor.osdi"] {
+        let data = sh.read_binary_file(output_dir.join(lib))?;
+        let obj = object::File::parse(&*data)?;
+        assert_eq!(obj.kind(), ObjectKind::Dynamic, "{lib}");
+        let exports = obj.exports()?;
+        let exported = |name: &[u8]| exports.iter().any(|export| export.name().ends_with(name));
+        assert!(exported(b"OSDI_DESCRIPTORS"), "{lib} does not export OSDI_DESCRIPTORS");
+    }
+
+    // models that share a root file reuse its database but are compiled with their own defines
+    sh.write_file(
+        dir.path().join("switch.va"),
+        "`include \"disciplines.vams\"\n`ifdef WIDE\nmodule wide(a, c);\n`else\nmodule narrow(a, c);\n`endif\n    inout a, c;\n    electrical a, c;\n    parameter real r = 1.0;\n    analog I(a, c) <+ V(a, c) / r;\nendmodule\n",
+    )?;
+    sh.write_file(
+        &manifest,
+        "[settings]\nopt_lvl = 0\n\n[[model]]\ninput = \"switch.va\"\noutput = \"wide.osdi\"\ndefines = [\"WIDE\"]\n\n[[model]]\ninput = \"switch.va\"\noutput = \"narrow.osdi\"\n",
+    )?;
+    xshell::cmd!(sh, "{openvaf} build {manifest}").run()?;
+    for (lib, module, other) in [("wide.osdi", "wide", "narrow"), ("narrow.osdi", "narrow", "wide")]
+    {
+        let data = sh.read_binary_file(dir.path().join(lib))?;
+        let contains = |name: &str| {
+            let name = format!("{name}\0");
+            data.windows(name.len()).any(|window| window == name.as_bytes())
+        };
+        assert!(contains(module), "{lib} does not contain the module {module}");
+        assert!(!contains(other), "{lib} contains the module {other}");
+    }
+    Ok(())
+}
+
+fn link_multiple_files() -> Result {
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    let dir = sh.create_temp_dir()?;
+    let lib_file = dir.path().join("models.osdi");
+    xshell::cmd!(
+        sh,
+        "{openvaf} -O 0 -o {lib_file} integration_tests/DIODE/diode.va integration_tests/RESISTOR/resistor.va"
+    )
+    .run()?;
+    xshell::cmd!(
+        sh,
+        "{openvaf} --dry-run --module resistor_va integration_tests/DIODE/diode.va integration_tests/RESISTOR/resistor.va"
+    )
+    .run()?;
+    let output = xshell::cmd!(
+        sh,
+        "{openvaf} --dry-run --module bjt_va integration_tests/DIODE/diode.va integration_tests/RESISTOR/resistor.va"
+    )
+    .ignore_status()
+    .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("module bjt_va was not found in integration_tests/DIODE/diode.va, integration_tests/RESISTOR/resistor.va"),
+        "{stderr}"
+    );
+    let expansion = xshell::cmd!(
+        sh,
+        "{openvaf} --print-expansion integration_tests/DIODE/diode.va integration_tests/RESISTOR/resistor.va"
+    )
+    .read()?;
+    assert!(expansion.contains("module diode_va"), "{expansion}");
+    assert!(expansion.contains("module resistor_va"), "{expansion}");
+
+    // duplicate modules are reported with the file that declares them
+    let module = "module dup(a);\n    inout a;\n    electrical a;\nendmodule\n";
+    sh.write_file(dir.path().join("dup.vams"), module)?;
+    sh.write_file(
+        dir.path().join("a.va"),
+        "`include \"disciplines.vams\"\n`include \"dup.vams\"\n",
+    )?;
+    sh.write_file(dir.path().join("b.va"), format!("`include \"disciplines.vams\"\n{module}"))?;
+    let (a, b) = (dir.path().join("a.va"), dir.path().join("b.va"));
+    let output = xshell::cmd!(sh, "{openvaf} --dry-run {a} {b}").ignore_status().output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("dup.vams") && stderr.contains("b.va"), "{stderr}");
+    Ok(())
+}
+
+fn emit_c_source() -> Result {
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    let dir = sh.create_temp_dir()?;
+    let lib_file = dir.path().join("diode.osdi");
+    xshell::cmd!(sh, "{openvaf} --emit c -o {lib_file} integration_tests/DIODE/diode.va").run()?;
+    let src = sh.read_file(dir.path().join("diode.c"))?;
+    assert!(src.contains("OSDI_DESCRIPTOR_EXTENSIONS[1]"));
+    assert!(!sh.path_exists(&lib_file));
+    Ok(())
+}
+
+fn emit_intermediates() -> Result {
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    let dir = sh.create_temp_dir()?;
+    let lib_file = dir.path().join("diode.osdi");
+    xshell::cmd!(
+        sh,
+        "{openvaf} -O 0 --emit link,llvm-ir,llvm-bc,asm,obj,mir -o {lib_file} integration_tests/DIODE/diode.va"
+    )
+    .run()?;
+    assert!(sh.path_exists(&lib_file));
+    let files = sh.read_dir(dir.path())?;
+    for extension in ["ll", "bc", "s", "o", "mir"] {
+        assert!(
+            files.iter().any(|file| file.extension().map_or(false, |ext| ext == extension)),
+            "no .{extension} file was emitted"
+        );
+    }
+    Ok(())
+}
+
+fn debug_line_table() -> Result {
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    let dir = sh.create_temp_dir()?;
+    let lib_file = dir.path().join("diode.osdi");
+    xshell::cmd!(sh, "{openvaf} -O 0 -g --emit obj -o {lib_file} integration_tests/DIODE/diode.va")
+        .run()?;
+    // the model functions are split into multiple codegen units (and object files)
+    let mut lines = Vec::new();
+    for file in sh.read_dir(dir.path())? {
+        if file.extension().map_or(true, |ext| ext != "o") {
+            continue;
+        }
+        let data = sh.read_binary_file(&file)?;
+        let obj = object::File::parse(&*data)?;
+        if obj.section_by_name(".debug_line").is_none() {
+            continue;
+        }
+        line_table(&obj, &mut lines)?;
+    }
+    // the contribution of the diode current is on line 77
+    assert!(lines.contains(&("diode.va".to_owned(), 77)), "{lines:?}");
+    Ok(())
+}
+
+/// Collects the file name and line of every row in the DWARF line tables of `obj`.
+fn line_table(obj: &object::File, lines: &mut Vec<(String, u64)>) -> Result {
+    let endian = if obj.is_little_endian() { RunTimeEndian::Little } else { RunTimeEndian::Big };
+    let dwarf = gimli::Dwarf::load(|id| -> std::result::Result<_, gimli::Error> {
+        let data = obj.section_by_name(id.name()).map(|section| section.uncompressed_data());
+        Ok(data.transpose().unwrap().unwrap_or(Cow::Borrowed(&[])))
+    })?;
+    let dwarf = dwarf.borrow(|section| EndianSlice::new(section, endian));
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut rows = unit.line_program.clone().expect("no line program").rows();
+        while let Some((header, row)) = rows.next_row()? {
+            let file = row.file(header).expect("invalid file index");
+            let file = dwarf.attr_string(&unit, file.path_name())?.to_string_lossy().into_owned();
+            lines.push((file, row.line().map_or(0, |line| line.get())));
+        }
+    }
+    Ok(())
+}
+
+/// Only the object files are emitted for targets other than the host because they can't be linked.
+fn cross_compile_objects() -> Result {
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    for target in ["riscv64gc-unknown-linux", "powerpc64le-unknown-linux", "x86_64-unknown-freebsd"]
+    {
+        let dir = sh.create_temp_dir()?;
+        let lib_file = dir.path().join("diode.osdi");
+        xshell::cmd!(
+            sh,
+            "{openvaf} -O 0 --target {target} --emit obj -o {lib_file} integration_tests/DIODE/diode.va"
+        )
+        .run()?;
+        let files = sh.read_dir(dir.path())?;
+        assert!(
+            files.iter().any(|file| file.extension().map_or(false, |ext| ext == "o")),
+            "no object file was emitted for {target}"
+        );
+        assert!(!sh.path_exists(&lib_file), "{target} should not be linked");
+    }
+    Ok(())
+}
+
+fn static_and_object_output() -> Result {
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    let dir = sh.create_temp_dir()?;
+    for (kind, extension) in [("static", "a"), ("object", "o")] {
+        let lib_file = dir.path().join(format!("diode.{extension}"));
+        xshell::cmd!(
+            sh,
+            "{openvaf} --output-kind {kind} -o {lib_file} integration_tests/DIODE/diode.va"
+        )
+        .run()?;
+        assert!(sh.path_exists(&lib_file));
+        let header = sh.read_file(dir.path().join("diode.h"))?;
+        assert!(header.contains("OSDI_DESCRIPTORS_diode[]"), "{header}");
+        assert!(header.contains("OSDI_NUM_DESCRIPTORS_diode;"), "{header}");
+        assert!(header.contains("OSDI_DESCRIPTOR_EXTENSIONS_diode[]"), "{header}");
+
+        // the symbols are renamed in the artifact itself, not just in the header
+        let symbols = defined_symbols(&sh.read_binary_file(&lib_file)?, kind == "static");
+        for sym in ["OSDI_DESCRIPTORS", "OSDI_NUM_DESCRIPTORS", "OSDI_DESCRIPTOR_EXTENSIONS"] {
+            assert!(symbols.contains(&format!("{sym}_diode")), "{sym}_diode: {symbols:?}");
+            assert!(!symbols.iter().any(|other| other == sym), "{sym}: {symbols:?}");
+        }
+
+        // cached artifacts of different kinds are kept apart
+        let cache_dir = dir.path().join("cache");
+        sh.create_dir(&cache_dir)?;
+        let cached = xshell::cmd!(
+            sh,
+            "{openvaf} --batch --cache-dir {cache_dir} --output-kind {kind} integration_tests/DIODE/diode.va"
+        )
+        .read()?;
+        assert!(cached.ends_with(&format!(".{extension}")), "{cached}");
+    }
+    Ok(())
+}
+
+/// The global symbols defined by an object file (or by the members of a static `archive`),
+/// without the leading underscore that Mach-O adds.
+fn defined_symbols(data: &[u8], archive: bool) -> Vec<String> {
+    let objects: Vec<&[u8]> = if archive {
+        let archive = ArchiveFile::parse(data).unwrap();
+        archive.members().map(|member| member.unwrap().data(data).unwrap()).collect()
+    } else {
+        vec![data]
+    };
+    let mut symbols = Vec::new();
+    for obj in objects {
+        let obj = object::File::parse(obj).unwrap();
+        for sym in obj.symbols() {
+            if sym.is_global() && !sym.is_undefined() {
+                let name = sym.name().unwrap();
+                let name = if obj.format() == BinaryFormat::MachO {
+                    name.strip_prefix('_').unwrap_or(name)
+                } else {
+                    name
+                };
+                symbols.push(name.to_owned());
+            }
+        }
+    }
+    symbols
+}
+
+fn coverage_report() -> Result {
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    let dir = sh.create_temp_dir()?;
+    let lib_file = dir.path().join("diode.osdi");
+    xshell::cmd!(sh, "{openvaf} --coverage -o {lib_file} integration_tests/DIODE/diode.va")
+        .run()?;
+    let map = dir.path().join("diode.covmap");
+    let num_counters: usize = sh
+        .read_file(&map)?
+        .lines()
+        .find_map(|line| line.strip_prefix("counters "))
+        .expect("coverage map without counters")
+        .parse()?;
+    assert_ne!(num_counters, 0);
+
+    // a dump in which only the first block was executed
+    let counters = dir.path().join("counters.txt");
+    let mut dump = vec!["0"; num_counters];
+    dump[0] = "1";
+    sh.write_file(&counters, dump.join("\n"))?;
+
+    let lcov = xshell::cmd!(sh, "{openvaf} coverage report --lcov {map} {counters}").read()?;
+    assert!(lcov.contains("SF:") && lcov.contains("DA:") && lcov.contains("end_of_record"));
+    let annotated = xshell::cmd!(sh, "{openvaf} coverage report {map} {counters}").read()?;
+    assert!(annotated.contains("#####"), "{annotated}");
+    Ok(())
+}
+
+fn describe_diode() -> Result {
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    let table = xshell::cmd!(sh, "{openvaf} --describe integration_tests/DIODE/diode.va").read()?;
+    assert!(table.starts_with("module diode_va"), "{table}");
+    assert!(table.contains("from [0:inf]"), "{table}");
+    let json =
+        xshell::cmd!(sh, "{openvaf} --describe --format json integration_tests/DIODE/diode.va")
+            .read()?;
+    assert!(json.contains(r#""name": "is""#), "{json}");
+    assert!(json.contains(r#""default": "1e-14""#), "{json}");
+    assert!(json.contains(r#""kind": "terminal""#), "{json}");
+    let markdown =
+        xshell::cmd!(sh, "{openvaf} --describe --format markdown integration_tests/DIODE/diode.va")
+            .read()?;
+    assert!(markdown.contains("### Parameters"), "{markdown}");
+    assert!(markdown.contains("### Jacobian\n\n"), "{markdown}");
+    assert!(markdown.contains("```\nA "), "{markdown}");
+    Ok(())
+}
+
+fn doc_pages() -> Result {
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    let dir = sh.create_temp_dir()?;
+    let out_dir = dir.path();
+    xshell::cmd!(sh, "{openvaf} doc -o {out_dir} integration_tests/DIODE/diode.va").run()?;
+    let page = sh.read_file(out_dir.join("diode_va.html"))?;
+    assert!(page.contains("<h1>diode_va</h1>"), "{page}");
+    assert!(page.contains("<code>1e-14</code>"), "{page}");
+    assert!(page.contains("diode.va#L"), "{page}");
+    assert!(sh.path_exists(out_dir.join("index.html")));
+
+    xshell::cmd!(
+        sh,
+        "{openvaf} doc --format markdown -o {out_dir} integration_tests/DIODE/diode.va"
+    )
+    .run()?;
+    let page = sh.read_file(out_dir.join("diode_va.md"))?;
+    assert!(page.contains("## Parameters"), "{page}");
+    assert!(page.contains("[diode.va:"), "{page}");
+
+    let link = "https://example.com/{path}?line={line}";
+    xshell::cmd!(
+        sh,
+        "{openvaf} doc --source-link {link} -o {out_dir} integration_tests/DIODE/diode.va"
+    )
+    .run()?;
+    let page = sh.read_file(out_dir.join("diode_va.html"))?;
+    assert!(page.contains("href=\"https://example.com/"), "{page}");
+    assert!(page.contains("diode.va?line="), "{page}");
+    Ok(())
+}
+
 harness! {
     Test::new("cli::link_diode", &link_diode),
+    Test::new("cli::emit_c_source", &emit_c_source),
+    Test::new("cli::emit_intermediates", &emit_intermediates),
+    Test::new("cli::debug_line_table", &debug_line_table),
+    Test::new("cli::cross_compile_objects", &cross_compile_objects),
+    Test::new("cli::static_and_object_output", &static_and_object_output),
+    Test::new("cli::coverage_report", &coverage_report),
+    Test::new("cli::describe_diode", &describe_diode),
+    Test::new("cli::doc_pages", &doc_pages),
+    Test::new("cli::link_multiple_files", &link_multiple_files),
+    Test::new("cli::cache_commands", &cache_commands),
+    Test::new("cli::build_project", &build_project),
     Test::from_list(
         "cli::smoke_test",
          &smoke_test,
@@ -58,6 +475,18 @@ harness! {
              "--supported-targets",
              "--batch",
              "--batch --cache-dir sourcegen",
+             "--module diode_va",
+             "-g",
+             "--sanitize float",
+             "--sanitize float --sanitize-abort",
+             "--coverage",
+             "--sensitivity is --sensitivity cj0",
+             "--emit c",
+             "--emit link,c",
+             "--emit llvm-ir,llvm-bc,asm,obj,mir",
+             "--output-kind static",
+             "--output-kind object",
+             "--osdi-version 0.4",
              "-O 0",
              "-O 1",
              "-O 2",
@@ -65,6 +494,9 @@ harness! {
              "-I sourcegen",
              "-D foo",
              "--print-expansion",
+             "--describe",
+             "--describe --format json",
+             "--describe --format markdown",
              "--supported-targets",
              "--lints",
              "-D all",
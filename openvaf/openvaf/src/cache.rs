@@ -1,55 +1,327 @@
 use core::slice;
+use std::cmp::Reverse;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
 use std::mem::{size_of, size_of_val};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use anyhow::{Context, Result};
 use basedb::lints::LintLevel;
 use basedb::{BaseDB, VfsStorage};
+use camino::{Utf8Path, Utf8PathBuf};
+use fs4::FileExt;
 use hir::CompilationDB;
+use mir_llvm::LLVMBackend;
 
 use crate::Opts;
 
+/// Lists all artifacts in the cache together with the settings they were compiled with.
+const MANIFEST: &str = "manifest";
+/// Serializes all accesses to the manifest.
+const MANIFEST_LOCK: &str = "manifest.lock";
+/// Held shared by compilations and exclusively while artifacts are removed.
+const CACHE_LOCK: &str = "cache.lock";
+
 // TODO: use high level hir API instead of low leve database API
-fn hash(db: &CompilationDB, defines: &[String]) -> md5::Digest {
+fn hash(dbs: &[CompilationDB], opts: &Opts, back: &LLVMBackend) -> md5::Digest {
     let mut hash_builder = md5::Context::new();
-    let cu = db.compilation_unit();
 
     // hash settings
-    hash_builder.consume(cu.root_file().0.to_ne_bytes());
+    hash_builder.consume(opts.defines.len().to_ne_bytes());
+    for def in &opts.defines {
+        consume_str(&mut hash_builder, def)
+    }
+
+    hash_builder.consume(opts.include.len().to_ne_bytes());
+    for dir in &opts.include {
+        let dir: &Path = dir.as_ref();
+        consume_str(&mut hash_builder, dir.to_string_lossy().as_bytes())
+    }
 
-    hash_builder.consume(defines.len().to_ne_bytes());
-    for def in defines {
-        hash_builder.consume(def)
+    hash_builder.consume(opts.modules.len().to_ne_bytes());
+    for module in &opts.modules {
+        consume_str(&mut hash_builder, module)
     }
 
+    hash_builder.consume(opts.emit.len().to_ne_bytes());
+    for emit in &opts.emit {
+        hash_builder.consume([*emit as u8])
+    }
+    hash_builder.consume([opts.output_kind as u8]);
+
     hash_builder.consume(env!("CARGO_PKG_VERSION"));
-    let lints = db.global_lint_overwrites(cu.root_file());
-    if cfg!(debug_assertions) && !lints.is_empty() {
-        assert_eq!(size_of::<Option<LintLevel>>(), size_of_val(&lints.raw[0]));
-    }
-    let lints = unsafe {
-        slice::from_raw_parts(
-            lints.as_ptr() as *const u8,
-            size_of::<Option<LintLevel>>() * lints.len(),
-        )
-    };
-    hash_builder.consume(lints);
-
-    // Hash the full preprocessor result
-    let preprocess = cu.preprocess(db);
-    let vfs = db.vfs().read();
-    for token in &*preprocess.ts {
-        if !token.kind.is_trivia() {
-            let filespan = token.span.to_file_span(&preprocess.sm);
-            let src = vfs.file_contents_unchecked(filespan.file);
-            hash_builder.consume(&src[filespan.range]);
-            hash_builder.consume(" ");
+
+    // hash codegen settings, the cpu and features are taken from the backend
+    // so that `native` is resolved to the cpu of the current machine
+    consume_str(&mut hash_builder, &opts.target.llvm_target);
+    consume_str(&mut hash_builder, &opts.target.data_layout);
+    hash_builder.consume([
+        opts.opt_lvl as u8,
+        opts.debug_info as u8,
+        opts.sanitize_float as u8,
+        opts.coverage as u8,
+        opts.osdi_version as u8,
+    ]);
+    consume_str(&mut hash_builder, back.target_cpu());
+    consume_str(&mut hash_builder, back.target_features());
+    // the modelcard was already read successfully by `compile`
+    if let Some(modelcard) = &opts.modelcard {
+        consume_str(&mut hash_builder, fs::read(modelcard).unwrap_or_default());
+    }
+    hash_builder.consume(opts.sensitivities.len().to_ne_bytes());
+    for param in &opts.sensitivities {
+        consume_str(&mut hash_builder, param)
+    }
+    hash_builder.consume(opts.codegen_opts.len().to_ne_bytes());
+    for opt in &opts.codegen_opts {
+        consume_str(&mut hash_builder, opt)
+    }
+
+    hash_builder.consume(dbs.len().to_ne_bytes());
+    for db in dbs {
+        let cu = db.compilation_unit();
+        hash_builder.consume(cu.root_file().0.to_ne_bytes());
+
+        let lints = db.global_lint_overwrites(cu.root_file());
+        if cfg!(debug_assertions) && !lints.is_empty() {
+            assert_eq!(size_of::<Option<LintLevel>>(), size_of_val(&lints.raw[0]));
+        }
+        let lints = unsafe {
+            slice::from_raw_parts(
+                lints.as_ptr() as *const u8,
+                size_of::<Option<LintLevel>>() * lints.len(),
+            )
+        };
+        hash_builder.consume(lints);
+
+        // Hash the full preprocessor result
+        let preprocess = cu.preprocess(db);
+        let vfs = db.vfs().read();
+        for token in &*preprocess.ts {
+            if !token.kind.is_trivia() {
+                let filespan = token.span.to_file_span(&preprocess.sm);
+                let src = vfs.file_contents_unchecked(filespan.file);
+                hash_builder.consume(&src[filespan.range]);
+                hash_builder.consume(" ");
+            }
         }
     }
 
     hash_builder.compute()
 }
 
-pub fn file_name(db: &CompilationDB, opts: &Opts) -> String {
-    let hash = u128::from_ne_bytes(*hash(db, &opts.defines));
+/// Hashes `data` prefixed with its length so that consecutive strings can't
+/// produce the same key when the boundary between them moves.
+fn consume_str(hash_builder: &mut md5::Context, data: impl AsRef<[u8]>) {
+    let data = data.as_ref();
+    hash_builder.consume(data.len().to_ne_bytes());
+    hash_builder.consume(data);
+}
+
+pub fn file_name(dbs: &[CompilationDB], opts: &Opts, back: &LLVMBackend) -> String {
+    let hash = u128::from_ne_bytes(*hash(dbs, opts, back));
     let hash = base_n::encode(hash, base_n::CASE_INSENSITIVE);
-    format!("{}.osdi", hash)
+    format!("{}.{}", hash, opts.output_kind.extension())
+}
+
+/// An artifact stored in a cache directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub file_name: String,
+    /// The root file the artifact was compiled from.
+    pub input: Utf8PathBuf,
+    pub target: String,
+    /// The size of the artifact in bytes.
+    pub size: u64,
+    /// Seconds since the unix epoch at which the artifact was last compiled or reused.
+    pub last_used: u64,
+}
+
+impl CacheEntry {
+    fn parse(line: &str) -> Option<CacheEntry> {
+        let mut fields = line.splitn(5, '\t');
+        let file_name = fields.next()?.to_owned();
+        let last_used = fields.next()?.parse().ok()?;
+        let size = fields.next()?.parse().ok()?;
+        let target = fields.next()?.to_owned();
+        let input = fields.next()?.into();
+        Some(CacheEntry { file_name, input, target, size, last_used })
+    }
+}
+
+/// Prevents other processes from removing or compiling a cache entry
+/// while it is compiled or reused by this process.
+pub struct EntryLock {
+    _cache: File,
+    _entry: File,
+}
+
+/// Locks the cache entry `file_name`. Blocks while another process compiles
+/// the same entry or removes artifacts from the cache.
+pub fn lock_entry(cache_dir: &Utf8Path, file_name: &str) -> Result<EntryLock> {
+    let cache = lock_file(&cache_dir.join(CACHE_LOCK), true)?;
+    let entry = lock_file(&cache_dir.join(file_name).with_extension("lock"), false)?;
+    Ok(EntryLock { _cache: cache, _entry: entry })
+}
+
+/// Records in the manifest that the cache entry `file_name` was just compiled or reused.
+pub fn record_entry(cache_dir: &Utf8Path, file_name: &str, opts: &Opts) -> Result<()> {
+    let mut manifest = Manifest::open(cache_dir)?;
+    let size = fs::metadata(cache_dir.join(file_name)).map_or(0, |meta| meta.len());
+    let entry = CacheEntry {
+        file_name: file_name.to_owned(),
+        input: opts.input.clone(),
+        target: opts.target.llvm_target.clone(),
+        size,
+        last_used: now(),
+    };
+    if let Some(old) = manifest.entries.iter_mut().find(|old| old.file_name == file_name) {
+        *old = entry
+    } else {
+        manifest.entries.push(entry)
+    }
+    manifest.save()
+}
+
+/// Returns all artifacts in `cache_dir` with the most recently used artifact first.
+pub fn list(cache_dir: &Utf8Path) -> Result<Vec<CacheEntry>> {
+    let mut entries = Manifest::open(cache_dir)?.entries;
+    entries.sort_by_key(|entry| Reverse(entry.last_used));
+    Ok(entries)
+}
+
+/// Removes all artifacts from `cache_dir`. Returns the number of bytes that were freed.
+pub fn clean(cache_dir: &Utf8Path) -> Result<u64> {
+    let _lock = lock_file(&cache_dir.join(CACHE_LOCK), false)?;
+    let mut manifest = Manifest::open(cache_dir)?;
+    let mut freed = 0;
+    for entry in manifest.entries.drain(..) {
+        let path = cache_dir.join(&entry.file_name);
+        if path.exists() {
+            remove_artifact(&path)?;
+            freed += entry.size;
+        }
+    }
+    manifest.save()?;
+
+    // also remove artifacts that are missing from the manifest
+    // (for example because they were created before the manifest existed)
+    let dir = cache_dir.read_dir_utf8().context("failed to read cache directory")?;
+    for file in dir {
+        let file = file.context("failed to read cache directory")?;
+        let path = file.path();
+        let is_artifact = match path.extension() {
+            Some("osdi" | "a" | "o" | "h") => true,
+            Some("lock") => !matches!(path.file_name(), Some(CACHE_LOCK | MANIFEST_LOCK)),
+            _ => false,
+        };
+        if is_artifact {
+            freed += file.metadata().map_or(0, |meta| meta.len());
+            fs::remove_file(path).with_context(|| format!("failed to remove {path}"))?;
+        }
+    }
+    Ok(freed)
+}
+
+/// Removes the least recently used artifacts from `cache_dir` until its
+/// size is at most `max_size` bytes. Returns the number of bytes that were freed.
+pub fn prune(cache_dir: &Utf8Path, max_size: u64) -> Result<u64> {
+    let _lock = lock_file(&cache_dir.join(CACHE_LOCK), false)?;
+    let mut manifest = Manifest::open(cache_dir)?;
+    manifest.entries.sort_by_key(|entry| Reverse(entry.last_used));
+
+    let mut size = 0;
+    let mut freed = 0;
+    let mut keep = Vec::with_capacity(manifest.entries.len());
+    for entry in manifest.entries.drain(..) {
+        if size + entry.size <= max_size {
+            size += entry.size;
+            keep.push(entry);
+            continue;
+        }
+        remove_artifact(&cache_dir.join(&entry.file_name))?;
+        freed += entry.size;
+    }
+    manifest.entries = keep;
+    manifest.save()?;
+    Ok(freed)
+}
+
+/// Removes a cached artifact together with its lock file and (for static libraries and object
+/// files) its header. The cache lock must be held exclusively.
+fn remove_artifact(path: &Utf8Path) -> Result<()> {
+    fs::remove_file(path).with_context(|| format!("failed to remove {path}"))?;
+    // no other process can wait for the lock while the cache lock is held exclusively
+    let _ = fs::remove_file(path.with_extension("lock"));
+    let _ = fs::remove_file(path.with_extension("h"));
+    Ok(())
+}
+
+/// The (locked) manifest of a cache directory.
+struct Manifest<'a> {
+    dir: &'a Utf8Path,
+    entries: Vec<CacheEntry>,
+    _lock: File,
+}
+
+impl<'a> Manifest<'a> {
+    fn open(dir: &'a Utf8Path) -> Result<Manifest<'a>> {
+        fs::create_dir_all(dir).context("failed to create cache directory")?;
+        let lock = lock_file(&dir.join(MANIFEST_LOCK), false)?;
+
+        let mut entries = Vec::new();
+        match File::open(dir.join(MANIFEST)) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line.context("failed to read cache manifest")?;
+                    // artifacts may have been deleted by hand
+                    if let Some(entry) = CacheEntry::parse(&line) {
+                        if dir.join(&entry.file_name).exists() {
+                            entries.push(entry)
+                        }
+                    }
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => return Err(err).context("failed to read cache manifest"),
+        }
+
+        Ok(Manifest { dir, entries, _lock: lock })
+    }
+
+    fn save(&self) -> Result<()> {
+        // write to a temporary file first so that the manifest is never left half written
+        let tmp = self.dir.join(MANIFEST).with_extension("tmp");
+        let file = File::create(&tmp).context("failed to write cache manifest")?;
+        let mut file = BufWriter::new(file);
+        for entry in &self.entries {
+            writeln!(
+                file,
+                "{}\t{}\t{}\t{}\t{}",
+                entry.file_name, entry.last_used, entry.size, entry.target, entry.input
+            )
+            .context("failed to write cache manifest")?;
+        }
+        file.flush().context("failed to write cache manifest")?;
+        drop(file);
+        fs::rename(tmp, self.dir.join(MANIFEST)).context("failed to write cache manifest")?;
+        Ok(())
+    }
+}
+
+fn lock_file(path: &Utf8Path, shared: bool) -> Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(path)
+        .with_context(|| format!("failed to open {path}"))?;
+    let res = if shared { file.lock_shared() } else { file.lock_exclusive() };
+    res.with_context(|| format!("failed to lock {path}"))?;
+    Ok(file)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
 }
@@ -0,0 +1,135 @@
+//! Reports for libraries compiled with `--coverage`.
+//!
+//! The simulator (or test bench) dumps the `OSDI_COVERAGE_LEN` entries of the exported
+//! `OSDI_COVERAGE_COUNTERS` array as whitespace separated decimal integers. [`report`] sums
+//! any number of such dumps (e.g. one per simulation of a regression) and maps the counters
+//! back to the Verilog-A source with the `.covmap` file written next to the library.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+
+use anyhow::{anyhow, bail, Context, Result};
+use camino::Utf8Path;
+use osdi::{CoverageMap, RegionKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// The sources annotated with execution counts (similar to `gcov`).
+    Annotated,
+    /// The tracefile format of `lcov`/`genhtml`.
+    Lcov,
+}
+
+/// Creates a report for the counter `dumps` of a library compiled with the coverage `map`.
+pub fn report(map: &Utf8Path, dumps: &[&Utf8Path], format: ReportFormat) -> Result<String> {
+    let src = fs::read_to_string(map).with_context(|| format!("failed to read {map}"))?;
+    let map =
+        CoverageMap::parse(&src).map_err(|err| anyhow!("invalid coverage map {map}: {err}"))?;
+    let counters = read_counters(&map, dumps)?;
+    let files = collect(&map, &counters);
+    match format {
+        ReportFormat::Annotated => annotate(&map, &files),
+        ReportFormat::Lcov => Ok(lcov(&map, &files)),
+    }
+}
+
+fn read_counters(map: &CoverageMap, dumps: &[&Utf8Path]) -> Result<Vec<u64>> {
+    let mut counters = vec![0u64; map.num_counters as usize];
+    for &dump in dumps {
+        let src = fs::read_to_string(dump).with_context(|| format!("failed to read {dump}"))?;
+        let vals: Vec<u64> = src
+            .split_whitespace()
+            .map(|val| val.parse().with_context(|| format!("invalid counter {val} in {dump}")))
+            .collect::<Result<_>>()?;
+        if vals.len() != counters.len() {
+            bail!("{dump} contains {} counters but the library has {}", vals.len(), counters.len());
+        }
+        for (counter, val) in counters.iter_mut().zip(vals) {
+            *counter = counter.saturating_add(val);
+        }
+    }
+    Ok(counters)
+}
+
+/// The counts of one source file.
+#[derive(Default)]
+struct FileCoverage {
+    /// How often each line was executed (the maximum of all blocks that start on the line).
+    lines: BTreeMap<u32, u64>,
+    /// How often each branch on a line was taken and not taken.
+    branches: BTreeMap<u32, Vec<[u64; 2]>>,
+}
+
+fn collect(map: &CoverageMap, counters: &[u64]) -> Vec<FileCoverage> {
+    let mut files: Vec<FileCoverage> = map.files.iter().map(|_| FileCoverage::default()).collect();
+    for region in &map.regions {
+        let pos = if let Some(pos) = region.pos { pos } else { continue };
+        let file = &mut files[pos.file as usize];
+        let counter = region.counter as usize;
+        match region.kind {
+            RegionKind::Block => {
+                let count = file.lines.entry(pos.line).or_default();
+                *count = (*count).max(counters[counter]);
+            }
+            RegionKind::Branch => {
+                let taken = [counters[counter], counters[counter + 1]];
+                file.branches.entry(pos.line).or_default().push(taken);
+                // the condition itself was executed
+                let count = file.lines.entry(pos.line).or_default();
+                *count = (*count).max(taken[0] + taken[1]);
+            }
+        }
+    }
+    files
+}
+
+fn lcov(map: &CoverageMap, files: &[FileCoverage]) -> String {
+    let mut out = String::new();
+    for (path, file) in map.files.iter().zip(files) {
+        let _ = writeln!(out, "TN:\nSF:{path}");
+        let (mut found, mut hit) = (0, 0);
+        for (block, (line, branches)) in file.branches.iter().enumerate() {
+            for (i, taken) in branches.iter().flatten().enumerate() {
+                found += 1;
+                // lcov uses `-` for branches whose condition was never evaluated
+                let reached = branches[i / 2] != [0, 0];
+                if reached {
+                    hit += (*taken != 0) as u32;
+                    let _ = writeln!(out, "BRDA:{line},{block},{i},{taken}");
+                } else {
+                    let _ = writeln!(out, "BRDA:{line},{block},{i},-");
+                }
+            }
+        }
+        let _ = writeln!(out, "BRF:{found}\nBRH:{hit}");
+        for (line, count) in &file.lines {
+            let _ = writeln!(out, "DA:{line},{count}");
+        }
+        let hit = file.lines.values().filter(|count| **count != 0).count();
+        let _ = writeln!(out, "LF:{}\nLH:{hit}\nend_of_record", file.lines.len());
+    }
+    out
+}
+
+fn annotate(map: &CoverageMap, files: &[FileCoverage]) -> Result<String> {
+    let mut out = String::new();
+    for (path, file) in map.files.iter().zip(files) {
+        let src = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+        let _ = writeln!(out, "{:>9}:{:>5}:Source:{path}", "-", 0);
+        for (i, text) in src.lines().enumerate() {
+            let line = i as u32 + 1;
+            let count = match file.lines.get(&line) {
+                Some(0) => "#####".to_owned(),
+                Some(count) => count.to_string(),
+                None => "-".to_owned(),
+            };
+            let _ = writeln!(out, "{count:>9}:{line:>5}:{text}");
+            let branches = file.branches.get(&line).into_iter().flatten().flatten();
+            for (branch, taken) in branches.enumerate() {
+                let _ = writeln!(out, "branch {branch:>2} taken {taken}");
+            }
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,402 @@
+//! Reports the interface of the compiled modules (`--describe`).
+//!
+//! The report is generated from the same metadata as the OSDI descriptor (see
+//! [`osdi::describe`]) so it matches what a simulator sees after loading the library, but no
+//! code is generated or linked.
+
+use std::fmt::Write;
+use std::iter::zip;
+
+use anyhow::Result;
+use basedb::diagnostics::ConsoleSink;
+use camino::Utf8PathBuf;
+use mir_llvm::LLVMBackend;
+use osdi::{ModuleDescription, ParamDescription};
+use serde::Serialize;
+use sim_back::collect_modules;
+
+use crate::doc::{self, Block, Cell};
+use crate::{check_module_names, open_db, CompilationTermination, Opts};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescribeFormat {
+    /// Aligned plain text tables.
+    Table,
+    /// A JSON document for scripts and simulator frontends.
+    Json,
+    /// Markdown tables that can be pasted into documentation.
+    Markdown,
+}
+
+/// Prints the interface of all modules of the root files of `opts` (filtered by `--module`)
+/// to stdout.
+pub fn describe(opts: &Opts, format: DescribeFormat) -> Result<CompilationTermination> {
+    let modules = if let Some(modules) = describe_modules(opts)? {
+        modules
+    } else {
+        return Ok(CompilationTermination::FatalDiagnostic);
+    };
+    print!("{}", render(&modules, format));
+    Ok(CompilationTermination::Compiled { lib_file: Utf8PathBuf::default() })
+}
+
+/// Describes all modules of the root files of `opts` (filtered by `--module`). Returns `None`
+/// if the modules could not be compiled because of errors (which are printed to stderr).
+pub fn describe_modules(opts: &Opts) -> Result<Option<Vec<ModuleDescription>>> {
+    let dbs: Vec<_> = opts.inputs().map(|input| open_db(opts, input)).collect::<Result<_>>()?;
+    let mut modules = Vec::with_capacity(dbs.len());
+    for db in &dbs {
+        let mut file_modules =
+            if let Some(modules) = collect_modules(db, false, &mut ConsoleSink::new(db)) {
+                modules
+            } else {
+                return Ok(None);
+            };
+        if !opts.modules.is_empty() {
+            file_modules.retain(|module| opts.modules.contains(&module.module.name(db)));
+        }
+        modules.push(file_modules);
+    }
+    check_module_names(opts, &dbs, &modules)?;
+
+    let back = LLVMBackend::new(&opts.codegen_opts, &opts.target, opts.target_cpu.clone(), &[]);
+    let inputs: Vec<_> = zip(&dbs, &modules).map(|(db, modules)| (db, &modules[..])).collect();
+    Ok(Some(osdi::describe(&inputs, &opts.target, &back)?))
+}
+
+pub fn render(modules: &[ModuleDescription], format: DescribeFormat) -> String {
+    match format {
+        DescribeFormat::Table => table(modules),
+        DescribeFormat::Json => json(modules),
+        DescribeFormat::Markdown => markdown(modules),
+    }
+}
+
+fn node_kind(module: &ModuleDescription, node: u32) -> &'static str {
+    if node < module.num_terminals {
+        "terminal"
+    } else {
+        "internal"
+    }
+}
+
+fn node_name(module: &ModuleDescription, node: Option<u32>) -> &str {
+    node.map_or("gnd", |node| &module.nodes[node as usize].name)
+}
+
+pub(crate) fn scope(param: &ParamDescription) -> &'static str {
+    if param.is_instance {
+        "instance"
+    } else {
+        "model"
+    }
+}
+
+pub(crate) fn ty(param: &ParamDescription) -> String {
+    if param.len == 0 {
+        param.ty.name().to_owned()
+    } else {
+        format!("{}[{}]", param.ty.name(), param.len)
+    }
+}
+
+fn yes_no(val: bool) -> String {
+    let res = if val { "yes" } else { "no" };
+    res.to_owned()
+}
+
+/// The sparsity pattern of the Jacobian with one row per node: `R` marks a resistive entry,
+/// `C` a reactive entry, `X` both and `.` an entry that is not part of the matrix.
+fn sparsity(module: &ModuleDescription) -> Vec<String> {
+    let n = module.nodes.len();
+    let mut rows = vec![vec!['.'; n]; n];
+    for entry in &module.jacobian_entries {
+        rows[entry.row as usize][entry.col as usize] = match (entry.resist, entry.react) {
+            (true, true) => 'X',
+            (true, false) => 'R',
+            (false, true) => 'C',
+            (false, false) => '.',
+        };
+    }
+    let width = module.nodes.iter().map(|node| node.name.len()).max().unwrap_or(0);
+    zip(&module.nodes, rows)
+        .map(|(node, row)| {
+            let row: Vec<_> = row.into_iter().map(String::from).collect();
+            format!("{:width$} {}", node.name, row.join(" "))
+        })
+        .collect()
+}
+
+fn node_rows(module: &ModuleDescription) -> Vec<Vec<String>> {
+    module
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| {
+            let i = i as u32;
+            vec![
+                node.name.clone(),
+                node_kind(module, i).to_owned(),
+                if node.is_flow { "flow" } else { "potential" }.to_owned(),
+                node.units.clone(),
+                yes_no(module.is_collapsible(i)),
+            ]
+        })
+        .collect()
+}
+
+fn param_rows(params: &[ParamDescription]) -> Vec<Vec<String>> {
+    params
+        .iter()
+        .map(|param| {
+            let mut name = param.name.clone();
+            if !param.aliases.is_empty() {
+                let _ = write!(name, " ({})", param.aliases.join(", "));
+            }
+            vec![
+                name,
+                ty(param),
+                scope(param).to_owned(),
+                param.default.clone().unwrap_or_default(),
+                param.bounds.join(", "),
+                param.units.clone(),
+                param.description.clone(),
+            ]
+        })
+        .collect()
+}
+
+fn opvar_rows(opvars: &[ParamDescription]) -> Vec<Vec<String>> {
+    opvars
+        .iter()
+        .map(|opvar| {
+            vec![opvar.name.clone(), ty(opvar), opvar.units.clone(), opvar.description.clone()]
+        })
+        .collect()
+}
+
+fn noise_rows(module: &ModuleDescription) -> Vec<Vec<String>> {
+    module
+        .noise_sources
+        .iter()
+        .map(|source| {
+            vec![
+                source.name.clone(),
+                node_name(module, Some(source.hi)).to_owned(),
+                node_name(module, source.lo).to_owned(),
+            ]
+        })
+        .collect()
+}
+
+const NODE_HEADER: [&str; 5] = ["name", "kind", "unknown", "units", "collapsible"];
+const PARAM_HEADER: [&str; 7] =
+    ["name", "type", "scope", "default", "bounds", "units", "description"];
+const OPVAR_HEADER: [&str; 4] = ["name", "type", "units", "description"];
+const NOISE_HEADER: [&str; 3] = ["name", "hi", "lo"];
+
+fn table(modules: &[ModuleDescription]) -> String {
+    fn write_table(out: &mut String, title: &str, header: &[&str], rows: Vec<Vec<String>>) {
+        let _ = writeln!(out, "\n{title} ({})", rows.len());
+        if rows.is_empty() {
+            return;
+        }
+        let mut widths: Vec<_> = header.iter().map(|col| col.len()).collect();
+        for row in &rows {
+            for (width, col) in zip(&mut widths, row) {
+                *width = (*width).max(col.chars().count());
+            }
+        }
+        let header: Vec<_> = header.iter().map(|col| col.to_string()).collect();
+        for row in Some(header).into_iter().chain(rows) {
+            let mut line = String::from(" ");
+            for (&width, col) in zip(&widths, &row) {
+                let _ = write!(line, " {col:width$}");
+            }
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+    }
+
+    let mut out = String::new();
+    for (i, module) in modules.iter().enumerate() {
+        if i != 0 {
+            out.push('\n');
+        }
+        let _ = writeln!(out, "module {}", module.name);
+        write_table(&mut out, "nodes", &NODE_HEADER, node_rows(module));
+        write_table(&mut out, "parameters", &PARAM_HEADER, param_rows(&module.params));
+        write_table(&mut out, "opvars", &OPVAR_HEADER, opvar_rows(&module.opvars));
+        write_table(&mut out, "noise sources", &NOISE_HEADER, noise_rows(module));
+        let _ = writeln!(out, "\njacobian ({} entries)", module.jacobian_entries.len());
+        for row in sparsity(module) {
+            let _ = writeln!(out, "  {row}");
+        }
+    }
+    out
+}
+
+fn markdown(modules: &[ModuleDescription]) -> String {
+    fn push_table(
+        blocks: &mut Vec<Block>,
+        title: &str,
+        header: &'static [&'static str],
+        rows: Vec<Vec<String>>,
+    ) {
+        if rows.is_empty() {
+            return;
+        }
+        blocks.push(Block::Heading(3, title.to_owned()));
+        let rows = rows.into_iter().map(|row| row.into_iter().map(Cell::Text).collect()).collect();
+        blocks.push(Block::Table(header, rows));
+    }
+
+    let mut blocks = Vec::new();
+    for module in modules {
+        blocks.push(Block::Heading(2, module.name.clone()));
+        push_table(&mut blocks, "Nodes", &NODE_HEADER, node_rows(module));
+        push_table(&mut blocks, "Parameters", &PARAM_HEADER, param_rows(&module.params));
+        push_table(
+            &mut blocks,
+            "Operating point variables",
+            &OPVAR_HEADER,
+            opvar_rows(&module.opvars),
+        );
+        push_table(&mut blocks, "Noise sources", &NOISE_HEADER, noise_rows(module));
+        blocks.push(Block::Heading(3, "Jacobian".to_owned()));
+        blocks.push(Block::Paragraph(format!(
+            "{} entries (`R` resistive, `C` reactive, `X` both):",
+            module.jacobian_entries.len()
+        )));
+        blocks.push(Block::Code(sparsity(module).join("\n")));
+    }
+    doc::markdown(&blocks)
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    modules: Vec<JsonModule<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonModule<'a> {
+    name: &'a str,
+    num_terminals: u32,
+    nodes: Vec<JsonNode<'a>>,
+    collapsible: &'a [(u32, Option<u32>)],
+    parameters: Vec<JsonParam<'a>>,
+    opvars: Vec<JsonParam<'a>>,
+    noise_sources: Vec<JsonNoiseSource<'a>>,
+    jacobian: Vec<JsonJacobianEntry>,
+}
+
+#[derive(Serialize)]
+struct JsonNode<'a> {
+    name: &'a str,
+    kind: &'static str,
+    units: &'a str,
+    is_flow: bool,
+    collapsible: bool,
+}
+
+#[derive(Serialize)]
+struct JsonParam<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    ty: &'static str,
+    len: u32,
+    units: &'a str,
+    description: &'a str,
+    /// Only reported for parameters (not opvars).
+    #[serde(flatten)]
+    decl: Option<JsonParamDecl<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonParamDecl<'a> {
+    aliases: &'a [String],
+    scope: &'static str,
+    default: Option<&'a str>,
+    bounds: &'a [String],
+    group: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonNoiseSource<'a> {
+    name: &'a str,
+    hi: u32,
+    lo: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct JsonJacobianEntry {
+    row: u32,
+    col: u32,
+    resist: bool,
+    react: bool,
+    resist_const: bool,
+    react_const: bool,
+}
+
+impl<'a> JsonParam<'a> {
+    fn new(param: &'a ParamDescription, is_opvar: bool) -> JsonParam<'a> {
+        let decl = (!is_opvar).then(|| JsonParamDecl {
+            aliases: &param.aliases,
+            scope: scope(param),
+            default: param.default.as_deref(),
+            bounds: &param.bounds,
+            group: &param.group,
+        });
+        JsonParam {
+            name: &param.name,
+            ty: param.ty.name(),
+            len: param.len,
+            units: &param.units,
+            description: &param.description,
+            decl,
+        }
+    }
+}
+
+fn json(modules: &[ModuleDescription]) -> String {
+    let modules = modules
+        .iter()
+        .map(|module| JsonModule {
+            name: &module.name,
+            num_terminals: module.num_terminals,
+            nodes: (0..)
+                .zip(&module.nodes)
+                .map(|(i, node)| JsonNode {
+                    name: &node.name,
+                    kind: node_kind(module, i),
+                    units: &node.units,
+                    is_flow: node.is_flow,
+                    collapsible: module.is_collapsible(i),
+                })
+                .collect(),
+            collapsible: &module.collapsible,
+            parameters: module.params.iter().map(|param| JsonParam::new(param, false)).collect(),
+            opvars: module.opvars.iter().map(|opvar| JsonParam::new(opvar, true)).collect(),
+            noise_sources: module
+                .noise_sources
+                .iter()
+                .map(|source| JsonNoiseSource { name: &source.name, hi: source.hi, lo: source.lo })
+                .collect(),
+            jacobian: module
+                .jacobian_entries
+                .iter()
+                .map(|entry| JsonJacobianEntry {
+                    row: entry.row,
+                    col: entry.col,
+                    resist: entry.resist,
+                    react: entry.react,
+                    resist_const: entry.resist_const,
+                    react_const: entry.react_const,
+                })
+                .collect(),
+        })
+        .collect();
+    let mut res = serde_json::to_string_pretty(&JsonReport { modules })
+        .expect("serializing the description can not fail");
+    res.push('\n');
+    res
+}
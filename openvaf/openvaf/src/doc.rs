@@ -0,0 +1,318 @@
+//! Reference pages for the compiled modules (`openvaf doc`).
+//!
+//! One page is written per module (plus an index of all modules). It lists the terminals, the
+//! parameters grouped by their `group` attribute and the operating point variables. Each
+//! parameter and opvar links back to the line of the source file in which it is declared.
+//!
+//! The pages are built from [`Block`]s, whose markdown renderer is shared with `--describe`.
+
+use std::fmt::Write;
+use std::fs::{self, create_dir_all};
+use std::iter::zip;
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use osdi::{ModuleDescription, ParamDescription, SourceLine};
+
+use crate::describe::{describe_modules, scope, ty};
+use crate::{CompilationTermination, Opts};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    /// Standalone HTML pages.
+    Html,
+    /// Markdown pages (with the links in a form that most forges render).
+    Markdown,
+}
+
+impl DocFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            DocFormat::Html => "html",
+            DocFormat::Markdown => "md",
+        }
+    }
+}
+
+/// The default link to the declaration of a parameter, which works for the source views of
+/// most forges. `{path}` is replaced with the path of the source file relative to the output
+/// directory and `{line}` with the line of the declaration.
+pub const DEFAULT_SOURCE_LINK: &str = "{path}#L{line}";
+
+/// Writes the reference pages of all modules of the root files of `opts` (filtered by
+/// `--module`) to `out_dir`. The pages are named after the modules. The declarations are linked
+/// with the `source_link` template (see [`DEFAULT_SOURCE_LINK`]).
+pub fn generate(
+    opts: &Opts,
+    out_dir: &Utf8Path,
+    format: DocFormat,
+    source_link: &str,
+) -> Result<CompilationTermination> {
+    let modules = if let Some(modules) = describe_modules(opts)? {
+        modules
+    } else {
+        return Ok(CompilationTermination::FatalDiagnostic);
+    };
+
+    create_dir_all(out_dir).with_context(|| format!("failed to create {out_dir}"))?;
+    let abs_out_dir =
+        out_dir.canonicalize_utf8().with_context(|| format!("failed to resolve {out_dir}"))?;
+
+    let mut index = vec![Block::Heading(1, "Modules".to_owned())];
+    let mut rows = Vec::new();
+    for module in &modules {
+        let file_name = format!("{}.{}", module.name, format.extension());
+        let blocks = module_blocks(module, &abs_out_dir, source_link);
+        let page = render(&module.name, &blocks, format);
+        let path = out_dir.join(&file_name);
+        fs::write(&path, page).with_context(|| format!("failed to write {path}"))?;
+
+        let num_params = module.params.iter().filter(|param| param.src.is_some()).count();
+        rows.push(vec![
+            Cell::Link { text: module.name.clone(), href: file_name },
+            Cell::Text(module.terminals().len().to_string()),
+            Cell::Text(num_params.to_string()),
+            Cell::Text(module.opvars.len().to_string()),
+        ]);
+    }
+    index.push(Block::Table(&["module", "terminals", "parameters", "opvars"], rows));
+    let path = out_dir.join(format!("index.{}", format.extension()));
+    fs::write(&path, render("Modules", &index, format))
+        .with_context(|| format!("failed to write {path}"))?;
+
+    Ok(CompilationTermination::Compiled { lib_file: path })
+}
+
+pub(crate) enum Cell {
+    Text(String),
+    Code(String),
+    Link { text: String, href: String },
+}
+
+pub(crate) enum Block {
+    Heading(u32, String),
+    Paragraph(String),
+    /// A preformatted block of text.
+    Code(String),
+    Table(&'static [&'static str], Vec<Vec<Cell>>),
+}
+
+fn module_blocks(module: &ModuleDescription, out_dir: &Utf8Path, source_link: &str) -> Vec<Block> {
+    let mut res = vec![Block::Heading(1, module.name.clone())];
+
+    res.push(Block::Heading(2, "Terminals".to_owned()));
+    let rows = module
+        .terminals()
+        .iter()
+        .map(|node| vec![Cell::Code(node.name.clone()), Cell::Text(node.units.clone())])
+        .collect();
+    res.push(Block::Table(&["name", "units"], rows));
+
+    // groups are listed in the order in which they are first used
+    let mut groups: Vec<(&str, Vec<&ParamDescription>)> = Vec::new();
+    for param in &module.params {
+        match groups.iter_mut().find(|(group, _)| *group == param.group) {
+            Some((_, params)) => params.push(param),
+            None => groups.push((param.group.as_str(), vec![param])),
+        }
+    }
+    res.push(Block::Heading(2, "Parameters".to_owned()));
+    if groups.is_empty() {
+        res.push(Block::Paragraph("The module has no parameters.".to_owned()));
+    }
+    let grouped = groups.iter().any(|(group, _)| !group.is_empty());
+    for (group, params) in groups {
+        if grouped {
+            let title = if group.is_empty() { "Other" } else { group };
+            res.push(Block::Heading(3, title.to_owned()));
+        }
+        let rows = params
+            .into_iter()
+            .map(|param| {
+                let mut name = param.name.clone();
+                for alias in &param.aliases {
+                    let _ = write!(name, ", {alias}");
+                }
+                vec![
+                    Cell::Code(name),
+                    Cell::Text(ty(param)),
+                    Cell::Text(scope(param).to_owned()),
+                    Cell::Code(param.default.clone().unwrap_or_default()),
+                    Cell::Code(param.bounds.join(", ")),
+                    Cell::Text(param.units.clone()),
+                    Cell::Text(param.description.clone()),
+                    src_cell(param.src.as_ref(), out_dir, source_link),
+                ]
+            })
+            .collect();
+        res.push(Block::Table(
+            &["name", "type", "scope", "default", "range", "units", "description", "source"],
+            rows,
+        ));
+    }
+
+    if !module.opvars.is_empty() {
+        res.push(Block::Heading(2, "Operating point variables".to_owned()));
+        let rows = module
+            .opvars
+            .iter()
+            .map(|opvar| {
+                vec![
+                    Cell::Code(opvar.name.clone()),
+                    Cell::Text(ty(opvar)),
+                    Cell::Text(opvar.units.clone()),
+                    Cell::Text(opvar.description.clone()),
+                    src_cell(opvar.src.as_ref(), out_dir, source_link),
+                ]
+            })
+            .collect();
+        res.push(Block::Table(&["name", "type", "units", "description", "source"], rows));
+    }
+
+    res
+}
+
+fn src_cell(src: Option<&SourceLine>, out_dir: &Utf8Path, source_link: &str) -> Cell {
+    let src = if let Some(src) = src { src } else { return Cell::Text(String::new()) };
+    let path = Utf8Path::new(&src.path);
+    let text = format!("{}:{}", path.file_name().unwrap_or(&src.path), src.line);
+    let href = source_link
+        .replace("{path}", &relative_path(out_dir, path))
+        .replace("{line}", &src.line.to_string());
+    Cell::Link { text, href }
+}
+
+/// The path of `path` relative to the directory `base` (both absolute) with `/` separators
+/// so that it can be used as a link.
+fn relative_path(base: &Utf8Path, path: &Utf8Path) -> String {
+    if !path.is_absolute() {
+        return path.as_str().replace('\\', "/");
+    }
+    let base: Vec<_> = base.components().collect();
+    let path: Vec<_> = path.components().collect();
+    let common = zip(&base, &path).take_while(|(c1, c2)| c1 == c2).count();
+    let mut res = Utf8PathBuf::new();
+    for _ in common..base.len() {
+        res.push("..");
+    }
+    for component in &path[common..] {
+        res.push(component);
+    }
+    res.as_str().replace('\\', "/")
+}
+
+fn render(title: &str, blocks: &[Block], format: DocFormat) -> String {
+    match format {
+        DocFormat::Html => html(title, blocks),
+        DocFormat::Markdown => markdown(blocks),
+    }
+}
+
+fn html(title: &str, blocks: &[Block]) -> String {
+    fn escape(text: &str) -> String {
+        let mut res = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '<' => res.push_str("&lt;"),
+                '>' => res.push_str("&gt;"),
+                '&' => res.push_str("&amp;"),
+                '"' => res.push_str("&quot;"),
+                c => res.push(c),
+            }
+        }
+        res
+    }
+
+    fn cell(cell: &Cell) -> String {
+        match cell {
+            Cell::Text(text) => escape(text),
+            Cell::Code(code) if code.is_empty() => String::new(),
+            Cell::Code(code) => format!("<code>{}</code>", escape(code)),
+            Cell::Link { text, href } => {
+                format!("<a href=\"{}\">{}</a>", escape(href), escape(text))
+            }
+        }
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">");
+    let _ = writeln!(out, "<title>{}</title>", escape(title));
+    let _ = writeln!(
+        out,
+        "<style>\nbody {{ font-family: sans-serif; margin: 2em; }}\ntable {{ border-collapse: collapse; margin-bottom: 1em; }}\nth, td {{ border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }}\nth {{ background: #eee; }}\n</style>"
+    );
+    let _ = writeln!(out, "</head>\n<body>");
+    for block in blocks {
+        match block {
+            Block::Heading(level, title) => {
+                let _ = writeln!(out, "<h{level}>{}</h{level}>", escape(title));
+            }
+            Block::Paragraph(text) => {
+                let _ = writeln!(out, "<p>{}</p>", escape(text));
+            }
+            Block::Code(text) => {
+                let _ = writeln!(out, "<pre>{}</pre>", escape(text));
+            }
+            Block::Table(header, rows) => {
+                let _ = writeln!(out, "<table>\n<tr>");
+                for col in header.iter() {
+                    let _ = writeln!(out, "<th>{col}</th>");
+                }
+                let _ = writeln!(out, "</tr>");
+                for row in rows {
+                    let _ = writeln!(out, "<tr>");
+                    for col in row {
+                        let _ = writeln!(out, "<td>{}</td>", cell(col));
+                    }
+                    let _ = writeln!(out, "</tr>");
+                }
+                let _ = writeln!(out, "</table>");
+            }
+        }
+    }
+    let _ = writeln!(out, "</body>\n</html>");
+    out
+}
+
+pub(crate) fn markdown(blocks: &[Block]) -> String {
+    fn escape(text: &str) -> String {
+        text.replace('|', "\\|").replace('\n', " ")
+    }
+
+    fn cell(cell: &Cell) -> String {
+        match cell {
+            Cell::Text(text) => escape(text),
+            Cell::Code(code) if code.is_empty() => String::new(),
+            // double backticks allow single backticks (macros) inside the code span
+            Cell::Code(code) => format!("`` {} ``", escape(code)),
+            Cell::Link { text, href } => format!("[{}]({href})", escape(text)),
+        }
+    }
+
+    let mut out = String::new();
+    for (i, block) in blocks.iter().enumerate() {
+        if i != 0 {
+            out.push('\n');
+        }
+        match block {
+            Block::Heading(level, title) => {
+                let _ = writeln!(out, "{} {title}", "#".repeat(*level as usize));
+            }
+            Block::Paragraph(text) => {
+                let _ = writeln!(out, "{text}");
+            }
+            Block::Code(text) => {
+                let _ = writeln!(out, "```\n{text}\n```");
+            }
+            Block::Table(header, rows) => {
+                let _ = writeln!(out, "| {} |", header.join(" | "));
+                let _ = writeln!(out, "|{}", " --- |".repeat(header.len()));
+                for row in rows {
+                    let row: Vec<_> = row.iter().map(cell).collect();
+                    let _ = writeln!(out, "| {} |", row.join(" | "));
+                }
+            }
+        }
+    }
+    out
+}
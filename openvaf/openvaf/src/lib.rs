@@ -1,26 +1,38 @@
-use std::fs::{create_dir_all, remove_file};
+use std::collections::HashMap;
+use std::fs::{self, create_dir_all, remove_file};
 use std::io::Write;
+use std::iter::{once, zip};
 use std::time::Instant;
 
 use anyhow::Context;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use basedb::diagnostics::{ConsoleSink, DiagnosticSink};
 use basedb::BaseDB;
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use hir::CompilationDB;
 use linker::link;
 use mir_llvm::LLVMBackend;
-use sim_back::collect_modules;
+use sim_back::{check_numerical_hazards, collect_modules, ModuleInfo};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 pub use basedb::lints::builtin as builtin_lints;
 pub use basedb::lints::LintLevel;
+pub use linker::OutputKind;
 pub use llvm::OptLevel;
+pub use osdi::{FloatSanitizer, OsdiVersion};
 pub use paths::AbsPathBuf;
 pub use target::host_triple;
 pub use target::spec::{get_target_names, Target};
 
-mod cache;
+pub mod cache;
+pub mod coverage;
+pub mod describe;
+pub mod doc;
+pub mod modelcard;
+pub mod project;
+pub mod reproducer;
+pub mod sensitivity;
+pub mod watch;
 
 #[derive(Debug, Clone)]
 pub enum CompilationDestination {
@@ -28,6 +40,26 @@ pub enum CompilationDestination {
     Cache { cache_dir: Utf8PathBuf },
 }
 
+/// The artifacts produced by [`compile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmitKind {
+    /// The shared library that is loaded by the simulator.
+    Link,
+    /// A self-contained C99 translation unit that implements the same OSDI library.
+    /// It is placed next to the library with the extension `.c`.
+    CSource,
+    /// The (optimized) LLVM IR of every generated LLVM module.
+    LlvmIr,
+    /// The LLVM bitcode of every generated LLVM module.
+    LlvmBc,
+    /// The assembly of every generated LLVM module.
+    Asm,
+    /// The object files that are linked into the library.
+    Obj,
+    /// The MIR of every Verilog-A module in the textual format read by `mir_reader`.
+    Mir,
+}
+
 pub enum CompilationTermination {
     Compiled { lib_file: Utf8PathBuf },
     FatalDiagnostic,
@@ -40,11 +72,42 @@ pub struct Opts {
     pub codegen_opts: Vec<String>,
     pub lints: Vec<(String, LintLevel)>,
     pub input: Utf8PathBuf,
+    /// Further root files whose modules are linked into the same library.
+    pub additional_inputs: Vec<Utf8PathBuf>,
+    /// Only the modules with these names are exported (all modules if empty).
+    pub modules: Vec<String>,
     pub output: CompilationDestination,
     pub include: Vec<AbsPathBuf>,
     pub opt_lvl: OptLevel,
     pub target: Target,
     pub target_cpu: String,
+    /// Emit DWARF debug info that refers to the Verilog-A sources.
+    pub debug_info: bool,
+    /// Check the residuals and Jacobian entries computed by `eval` for NaN/Inf at runtime.
+    pub sanitize_float: FloatSanitizer,
+    /// Count how often each block and branch of the model is executed, see [`coverage`].
+    pub coverage: bool,
+    /// Specialise the compiled modules on the model parameters of this modelcard, see
+    /// [`modelcard`].
+    pub modelcard: Option<Utf8PathBuf>,
+    /// The parameters for which the derivatives of the residual are generated, see
+    /// [`sensitivity`].
+    pub sensitivities: Vec<String>,
+    /// The revision of the OSDI interface implemented by the library.
+    pub osdi_version: OsdiVersion,
+    /// The artifacts written by [`compile`].
+    pub emit: Vec<EmitKind>,
+    /// The kind of library that is linked. For static libraries and relocatable objects the
+    /// exported OSDI symbols are suffixed with the name of the library (for example
+    /// `OSDI_DESCRIPTORS_diode`) and a C header that declares them is placed next to the library.
+    pub output_kind: OutputKind,
+}
+
+impl Opts {
+    /// All root files that are compiled.
+    pub fn inputs(&self) -> impl Iterator<Item = &Utf8Path> {
+        once(&*self.input).chain(self.additional_inputs.iter().map(|input| &**input))
+    }
 }
 // pub fn dump_json(opts: &Opts) -> Result<CompilationTermination> {
 //     let input =
@@ -107,24 +170,25 @@ pub struct Opts {
 pub fn expand(opts: &Opts) -> Result<CompilationTermination> {
     let start = Instant::now();
 
-    let input =
-        opts.input.canonicalize().with_context(|| format!("failed to resolve {}", opts.input))?;
-    let input = AbsPathBuf::assert(input);
-    let db = CompilationDB::new_fs(input, &opts.include, &opts.defines, &opts.lints)?;
-    let cu = db.compilation_unit();
-
-    let preprocess = cu.preprocess(&db);
-    for token in preprocess.ts.iter() {
-        let span = token.span.to_file_span(&preprocess.sm);
-        let text = db.file_text(span.file).unwrap();
-        print!("{}", &text[span.range]);
-    }
-    println!();
+    let mut failed = false;
+    for input in opts.inputs() {
+        let db = open_db(opts, input)?;
+        let cu = db.compilation_unit();
 
-    let mut sink = ConsoleSink::new(&db);
-    sink.add_diagnostics(&*preprocess.diagnostics, cu.root_file(), &db);
+        let preprocess = cu.preprocess(&db);
+        for token in preprocess.ts.iter() {
+            let span = token.span.to_file_span(&preprocess.sm);
+            let text = db.file_text(span.file).unwrap();
+            print!("{}", &text[span.range]);
+        }
+        println!();
+
+        let mut sink = ConsoleSink::new(&db);
+        sink.add_diagnostics(&*preprocess.diagnostics, cu.root_file(), &db);
+        failed |= sink.summary(&input.file_name().unwrap());
+    }
 
-    if sink.summary(&opts.input.file_name().unwrap()) {
+    if failed {
         return Ok(CompilationTermination::FatalDiagnostic);
     }
 
@@ -139,54 +203,205 @@ pub fn expand(opts: &Opts) -> Result<CompilationTermination> {
 }
 
 pub fn compile(opts: &Opts) -> Result<CompilationTermination> {
+    let dbs: Vec<_> = opts.inputs().map(|input| open_db(opts, input)).collect::<Result<_>>()?;
+    compile_dbs(opts, &dbs, &mut StandardStream::stderr(ColorChoice::Auto))
+}
+
+/// Compiles the root files of `opts` which have already been loaded into `dbs`.
+/// The diagnostics and progress messages are written to `dst` (for example a buffer when
+/// multiple models are compiled in parallel). The databases can be reused for further
+/// compilations (see [`watch`] and [`project::compile_all`]).
+fn compile_dbs(
+    opts: &Opts,
+    dbs: &[CompilationDB],
+    dst: &mut dyn WriteColor,
+) -> Result<CompilationTermination> {
     let start = Instant::now();
 
-    let input =
-        opts.input.canonicalize().with_context(|| format!("failed to resolve {}", opts.input))?;
-    let input = AbsPathBuf::assert(input);
-    let db = CompilationDB::new_fs(input, &opts.include, &opts.defines, &opts.lints)?;
+    let modelcard = opts.modelcard.as_deref().map(modelcard::read).transpose()?;
+
+    let back = LLVMBackend::new(&opts.codegen_opts, &opts.target, opts.target_cpu.clone(), &[]);
 
+    let mut cache_entry = None;
     let lib_file = match &opts.output {
         CompilationDestination::Cache { cache_dir } => {
-            let file_name = cache::file_name(&db, opts);
-            let lib_file = cache_dir.join(file_name);
-            if cfg!(not(debug_assertions)) && lib_file.exists() {
-                return Ok(CompilationTermination::Compiled { lib_file });
+            let file_name = cache::file_name(dbs, opts, &back);
+            let lib_file = cache_dir.join(&file_name);
+            if !opts.dry_run {
+                create_dir_all(cache_dir).context("failed to create cache directory")?;
+                // held until the artifact is written so that concurrent
+                // compilations of the same model do not race each other
+                let lock = cache::lock_entry(cache_dir, &file_name)?;
+                if cfg!(not(debug_assertions)) && lib_file.exists() {
+                    cache::record_entry(cache_dir, &file_name, opts)?;
+                    return Ok(CompilationTermination::Compiled { lib_file });
+                }
+                cache_entry = Some((cache_dir, file_name, lock));
             }
-            create_dir_all(cache_dir).context("failed to create cache directory")?;
             lib_file
         }
         CompilationDestination::Path { lib_file } => lib_file.clone(),
     };
 
-    let modules = if let Some(modules) = collect_modules(&db, false, &mut ConsoleSink::new(&db)) {
-        modules
-    } else {
-        return Ok(CompilationTermination::FatalDiagnostic);
-    };
+    let mut modules = Vec::with_capacity(dbs.len());
+    for db in dbs {
+        let mut file_modules = if let Some(modules) =
+            collect_modules(db, false, &mut ConsoleSink::new_with(db, Box::new(&mut *dst)))
+        {
+            modules
+        } else {
+            return Ok(CompilationTermination::FatalDiagnostic);
+        };
+        if !opts.modules.is_empty() {
+            file_modules.retain(|module| opts.modules.contains(&module.module.name(db)));
+        }
+        modules.push(file_modules);
+    }
+    check_module_names(opts, dbs, &modules)?;
+    if let Some(modelcard) = &modelcard {
+        modelcard::freeze_params(modelcard, dbs, &mut modules)?;
+    }
+    sensitivity::select_params(&opts.sensitivities, dbs, &mut modules)?;
+
+    let inputs: Vec<_> = zip(dbs, &modules).map(|(db, modules)| (db, &modules[..])).collect();
+    let lowered = osdi::lower_modules(&inputs);
+    for (i, (db, input)) in zip(dbs, opts.inputs()).enumerate() {
+        let mut sink = ConsoleSink::new_with(db, Box::new(&mut *dst));
+        for (_, module) in lowered.modules.iter().filter(|(file, _)| *file == i) {
+            check_numerical_hazards(db, module, &mut sink);
+        }
+        if sink.summary(&input.file_name().unwrap()) {
+            return Ok(CompilationTermination::FatalDiagnostic);
+        }
+    }
 
-    let back = LLVMBackend::new(&opts.codegen_opts, &opts.target, opts.target_cpu.clone(), &[]);
     if opts.dry_run {
         return Ok(CompilationTermination::Compiled { lib_file });
     }
-    let paths = osdi::compile(&db, &modules, &lib_file, &opts.target, &back, true, opts.opt_lvl);
-    // TODO configure linker
-    link(None, &opts.target, lib_file.as_ref(), |linker| {
-        for path in &paths {
-            linker.add_object(path);
+    if opts.emit.contains(&EmitKind::CSource) {
+        if opts.sanitize_float != FloatSanitizer::Disabled {
+            bail!("sanitizers are not supported when emitting C sources");
+        }
+        if opts.coverage {
+            bail!("coverage instrumentation is not supported when emitting C sources");
+        }
+        if !opts.sensitivities.is_empty() {
+            bail!("sensitivities are not supported when emitting C sources");
+        }
+        if opts.osdi_version != OsdiVersion::V0_3 {
+            bail!("only OSDI 0.3 is supported when emitting C sources");
+        }
+        let c_file = lib_file.with_extension("c");
+        let src = osdi::c_source(&inputs, &lowered, &back)?;
+        fs::write(&c_file, src).with_context(|| format!("failed to write {c_file}"))?;
+    }
+
+    let sym_suffix = match opts.output_kind {
+        OutputKind::SharedLib => None,
+        OutputKind::StaticLib | OutputKind::Object => Some(symbol_suffix(&lib_file)),
+    };
+    let link_lib = opts.emit.contains(&EmitKind::Link);
+    let keep_obj = opts.emit.contains(&EmitKind::Obj);
+    let emit = osdi::Emit {
+        obj: link_lib || keep_obj,
+        llvm_ir: opts.emit.contains(&EmitKind::LlvmIr),
+        llvm_bc: opts.emit.contains(&EmitKind::LlvmBc),
+        asm: opts.emit.contains(&EmitKind::Asm),
+        mir: opts.emit.contains(&EmitKind::Mir),
+    };
+    if emit != osdi::Emit::default() {
+        let compile_opts = osdi::CompileOpts {
+            target: &opts.target,
+            emit,
+            opt_lvl: opts.opt_lvl,
+            debug_info: opts.debug_info,
+            float_sanitizer: opts.sanitize_float,
+            coverage: opts.coverage,
+            sym_suffix: sym_suffix.as_deref(),
+            version: opts.osdi_version,
+        };
+        let paths = osdi::compile(&inputs, &lowered, &lib_file, &back, &compile_opts)?;
+        // the main object is named after the library so the relocatable object
+        // is linked to a temporary file (the linker can't overwrite its input)
+        let out_file = if opts.output_kind == OutputKind::Object {
+            lib_file.with_extension("o.tmp")
+        } else {
+            lib_file.clone()
+        };
+        if link_lib {
+            // TODO configure linker
+            link(None, &opts.target, &out_file, opts.output_kind, |linker| {
+                for path in &paths {
+                    linker.add_object(path);
+                }
+            })?;
+        }
+
+        // the objects are only written if they are linked or kept
+        if link_lib && !keep_obj {
+            for obj_file in paths {
+                remove_file(obj_file).context("failed to delete intermediate compile artifact")?;
+            }
+        }
+        if link_lib && out_file != lib_file {
+            fs::rename(&out_file, &lib_file)
+                .with_context(|| format!("failed to move {out_file} to {lib_file}"))?;
         }
-    })?;
+    }
 
-    for obj_file in paths {
-        remove_file(obj_file).context("failed to delete intermediate compile artifact")?;
+    if let Some((cache_dir, file_name, _lock)) = cache_entry {
+        cache::record_entry(cache_dir, &file_name, opts)?;
     }
 
     let seconds = Instant::elapsed(&start).as_secs_f64();
-    let mut stderr = StandardStream::stderr(ColorChoice::Auto);
-    stderr.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
-    write!(&mut stderr, "Finished")?;
-    stderr.set_color(&ColorSpec::new())?;
-    writeln!(&mut stderr, " building {} in {:.2}s", opts.input.file_name().unwrap(), seconds)?;
+    dst.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+    write!(dst, "Finished")?;
+    dst.set_color(&ColorSpec::new())?;
+    writeln!(dst, " building {} in {:.2}s", opts.input.file_name().unwrap(), seconds)?;
 
     Ok(CompilationTermination::Compiled { lib_file })
 }
+
+/// The suffix appended to the OSDI symbols of a static library or relocatable object:
+/// the file stem of the library with all characters that are invalid in C identifiers replaced.
+fn symbol_suffix(lib_file: &Utf8Path) -> String {
+    let stem = lib_file.file_stem().unwrap_or_default();
+    stem.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+fn open_db(opts: &Opts, input: &Utf8Path) -> Result<CompilationDB> {
+    let input = input.canonicalize().with_context(|| format!("failed to resolve {input}"))?;
+    let input = AbsPathBuf::assert(input);
+    CompilationDB::new_fs(input, &opts.include, &opts.defines, &opts.lints)
+}
+
+/// Ensures that every module selected with `--module` exists and that
+/// no two exported modules share a name (the simulator looks them up by name).
+fn check_module_names(
+    opts: &Opts,
+    dbs: &[CompilationDB],
+    modules: &[Vec<ModuleInfo>],
+) -> Result<()> {
+    let mut defined: HashMap<String, String> = HashMap::new();
+    for (db, modules) in zip(dbs, modules) {
+        for module in modules {
+            let name = module.module.name(db);
+            // the module may be declared in a file included by the root file
+            let file = module.module.file_span(db).file;
+            let file = db.file_path(file).to_string();
+            if let Some(other) = defined.get(&name) {
+                bail!("module {name} is defined in both {other} and {file}");
+            }
+            defined.insert(name, file);
+        }
+    }
+
+    for name in &opts.modules {
+        if !defined.contains_key(name) {
+            let inputs: Vec<_> = opts.inputs().map(Utf8Path::as_str).collect();
+            bail!("module {name} was not found in {}", inputs.join(", "));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,147 @@
+//! Compile-time specialisation of the compiled modules on a fixed modelcard (`--modelcard`).
+//!
+//! A modelcard is a text file with one `name=value` pair per line. Empty lines and everything
+//! after a `#` are ignored. The named model parameters are frozen: the compiled code is
+//! specialised on their values and `setup_model` reports any other value as invalid. The
+//! descriptor still lists all parameters. Numbers may use the SPICE scale factors (`1k`, `10n`).
+
+use std::fs;
+
+use anyhow::{bail, Context, Result};
+use camino::Utf8Path;
+use hir::{CompilationDB, Parameter, Type};
+use sim_back::{FrozenParam, ModuleInfo, ParamInfo};
+
+/// Reads the `name=value` pairs of the modelcard at `path`.
+pub fn read(path: &Utf8Path) -> Result<Vec<(String, String)>> {
+    let src = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    parse(&src).with_context(|| format!("invalid modelcard {path}"))
+}
+
+fn parse(src: &str) -> Result<Vec<(String, String)>> {
+    let mut params: Vec<(String, String)> = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, val) = if let Some(pair) = line.split_once('=') {
+            pair
+        } else {
+            bail!("line {}: expected name=value but found \"{line}\"", i + 1)
+        };
+        let (name, val) = (name.trim(), val.trim());
+        if name.is_empty() || val.is_empty() {
+            bail!("line {}: expected name=value but found \"{line}\"", i + 1)
+        }
+        if params.iter().any(|(other, _)| name.eq_ignore_ascii_case(other)) {
+            bail!("line {}: parameter {name} is given multiple times", i + 1)
+        }
+        params.push((name.to_owned(), val.to_owned()));
+    }
+    Ok(params)
+}
+
+/// Freezes the parameters of the `modelcard` in all `modules` (of the root files compiled with
+/// `dbs`) that declare them. Parameter names are matched case-insensitively (like in SPICE
+/// netlists) against the names and aliases of the parameters.
+pub fn freeze_params(
+    modelcard: &[(String, String)],
+    dbs: &[CompilationDB],
+    modules: &mut [Vec<ModuleInfo>],
+) -> Result<()> {
+    for (name, val) in modelcard {
+        let mut found = false;
+        for (db, modules) in dbs.iter().zip(modules.iter_mut()) {
+            for module in modules.iter_mut() {
+                let (param, info) =
+                    if let Some(param) = find_param(module, name) { param } else { continue };
+                found = true;
+
+                let module_name = module.module.name(db);
+                if info.is_instance {
+                    bail!("{module_name}: {name} is an instance parameter and can not be frozen");
+                }
+                let frozen = match param.ty(db) {
+                    Type::Real => parse_number(val).map(FrozenParam::Real),
+                    Type::Integer => parse_int(val).map(FrozenParam::Integer),
+                    Type::String => Some(FrozenParam::String(val.trim_matches('"').to_owned())),
+                    ty => bail!("{module_name}: parameter {name} of type {ty} can not be frozen"),
+                };
+                let frozen = if let Some(frozen) = frozen {
+                    frozen
+                } else {
+                    bail!("{module_name}: invalid value {val} for parameter {name}")
+                };
+                if frozen.violates_bounds(db, param) {
+                    bail!("{module_name}: the value {val} of parameter {name} is out of bounds")
+                }
+                module.frozen_params.insert(param, frozen);
+            }
+        }
+        if !found {
+            bail!("the modelcard sets the unknown parameter {name}");
+        }
+    }
+    Ok(())
+}
+
+/// Parses a number with an optional SPICE scale factor (`1k`, `10n`, `2.2meg`). Like in SPICE,
+/// scale factors are case-insensitive and any letters that follow (units like in `10pF`) are
+/// ignored.
+fn parse_number(val: &str) -> Option<f64> {
+    let (num, suffix) = (1..=val.len())
+        .rev()
+        .filter(|&i| val.is_char_boundary(i))
+        .find_map(|i| Some((val[..i].parse::<f64>().ok()?, &val[i..])))?;
+    if !suffix.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let suffix = suffix.to_ascii_lowercase();
+    let scale = if suffix.starts_with("meg") {
+        1e6
+    } else if suffix.starts_with("mil") {
+        25.4e-6
+    } else {
+        match suffix.as_bytes().first() {
+            Some(b't') => 1e12,
+            Some(b'g') => 1e9,
+            Some(b'k') => 1e3,
+            Some(b'm') => 1e-3,
+            Some(b'u') => 1e-6,
+            Some(b'n') => 1e-9,
+            Some(b'p') => 1e-12,
+            Some(b'f') => 1e-15,
+            Some(b'a') => 1e-18,
+            _ => 1.0,
+        }
+    };
+    Some(num * scale)
+}
+
+/// Parses an integer that may also be written with a SPICE scale factor (`2k`).
+fn parse_int(val: &str) -> Option<i32> {
+    if let Ok(val) = val.parse() {
+        return Some(val);
+    }
+    let val = parse_number(val)?;
+    let in_range = val.fract() == 0.0 && i32::MIN as f64 <= val && val <= i32::MAX as f64;
+    in_range.then_some(val as i32)
+}
+
+/// Finds the parameter of `module` that is called `name` (or has `name` as an alias). Like in
+/// SPICE netlists, the comparison is case-insensitive.
+pub(crate) fn find_param<'a>(
+    module: &'a ModuleInfo,
+    name: &str,
+) -> Option<(Parameter, &'a ParamInfo)> {
+    module
+        .params
+        .iter()
+        .find(|(_, info)| {
+            info.name.eq_ignore_ascii_case(name)
+                || info.alias.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+        })
+        .map(|(&param, info)| (param, info))
+}
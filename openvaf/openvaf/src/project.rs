@@ -0,0 +1,288 @@
+//! Compilation of many models described by a project manifest (`openvaf.toml`).
+//!
+//! ```toml
+//! [settings]
+//! include = ["include"]
+//! defines = ["FOO=1"]
+//! output_dir = "build"
+//! lints = { trivial_probe = "allow" }
+//!
+//! [[model]]
+//! input = "bsim4/bsim4.va"
+//! output = "bsim4_v48.osdi"
+//! defines = ["VERSION=48"]
+//!
+//! [[model]]
+//! input = "bsim4/bsim4.va"
+//! output = "bsim4_nmos_tt.osdi"
+//! modelcard = "bsim4/nmos_tt.mc"
+//! ```
+//!
+//! Paths are relative to the directory of the manifest. The settings of a model
+//! extend (defines, include, lints) or replace (everything else) the shared settings.
+
+use std::collections::BTreeMap;
+use std::fs::create_dir_all;
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use hir::CompilationDB;
+use paths::AbsPathBuf;
+use serde::Deserialize;
+use termcolor::{BufferWriter, ColorChoice};
+
+use crate::{
+    compile_dbs, host_triple, open_db, CompilationDestination, CompilationTermination, EmitKind,
+    FloatSanitizer, LintLevel, OptLevel, Opts, OsdiVersion, OutputKind, Target,
+};
+
+pub const MANIFEST_NAME: &str = "openvaf.toml";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default)]
+    settings: Settings,
+    #[serde(default, rename = "model")]
+    models: Vec<Model>,
+}
+
+/// Settings shared by all models.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    #[serde(default)]
+    defines: Vec<String>,
+    #[serde(default)]
+    include: Vec<Utf8PathBuf>,
+    #[serde(default)]
+    lints: BTreeMap<String, String>,
+    target: Option<String>,
+    target_cpu: Option<String>,
+    opt_lvl: Option<u8>,
+    osdi_version: Option<String>,
+    /// LLVM is only configured once per process so
+    /// codegen options can not be set for individual models.
+    #[serde(default)]
+    codegen: Vec<String>,
+    output_dir: Option<Utf8PathBuf>,
+    #[serde(default)]
+    debug_info: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Model {
+    input: Utf8PathBuf,
+    output: Option<String>,
+    #[serde(default)]
+    defines: Vec<String>,
+    #[serde(default)]
+    include: Vec<Utf8PathBuf>,
+    #[serde(default)]
+    lints: BTreeMap<String, String>,
+    target: Option<String>,
+    target_cpu: Option<String>,
+    opt_lvl: Option<u8>,
+    osdi_version: Option<String>,
+    /// Only these modules are exported (all modules if empty).
+    #[serde(default)]
+    modules: Vec<String>,
+    /// Specialise the model on the parameters of this modelcard, see [`crate::modelcard`].
+    modelcard: Option<Utf8PathBuf>,
+    /// Generate the derivatives of the residual by these parameters, see [`crate::sensitivity`].
+    #[serde(default)]
+    sensitivities: Vec<String>,
+}
+
+/// Reads the manifest at `path` and returns the options used to compile each model.
+pub fn load(path: &Utf8Path, dry_run: bool) -> Result<Vec<Opts>> {
+    let src = std::fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    let manifest: Manifest =
+        toml::from_str(&src).with_context(|| format!("failed to parse {path}"))?;
+    let root = path.parent().unwrap_or_else(|| Utf8Path::new("."));
+    let settings = &manifest.settings;
+
+    let mut res: Vec<Opts> = Vec::with_capacity(manifest.models.len());
+    for model in &manifest.models {
+        let input = root.join(&model.input);
+        let opts = model_opts(root, settings, model, dry_run)
+            .with_context(|| format!("invalid settings for {input}"))?;
+        if let Some(other) = res.iter().find(|other| same_output(other, &opts)) {
+            bail!("{} and {} are compiled to the same file", other.input, opts.input);
+        }
+        res.push(opts);
+    }
+    Ok(res)
+}
+
+fn model_opts(root: &Utf8Path, settings: &Settings, model: &Model, dry_run: bool) -> Result<Opts> {
+    let input = root.join(&model.input);
+
+    let include: Result<Vec<_>> = settings
+        .include
+        .iter()
+        .chain(&model.include)
+        .map(|dir| {
+            let dir = root.join(dir);
+            let dir = dir.canonicalize().with_context(|| format!("failed to resolve {dir}"))?;
+            Ok(AbsPathBuf::assert(dir))
+        })
+        .collect();
+
+    let mut lints = Vec::new();
+    for (name, lvl) in settings.lints.iter().chain(&model.lints) {
+        let lvl = match &**lvl {
+            "allow" => LintLevel::Allow,
+            "warn" => LintLevel::Warn,
+            "deny" => LintLevel::Deny,
+            _ => bail!("unknown lint level {lvl} for {name} (expected allow, warn or deny)"),
+        };
+        lints.push((name.clone(), lvl));
+    }
+
+    let host = host_triple();
+    let target = model.target.as_deref().or(settings.target.as_deref()).unwrap_or(host);
+    let default_cpu = if host != target { "generic" } else { "native" };
+    let target_cpu = model.target_cpu.as_ref().or(settings.target_cpu.as_ref());
+    let target_cpu = target_cpu.map_or(default_cpu, String::as_str).to_owned();
+    let target = match Target::search(target) {
+        Some(target) => target,
+        None => bail!("the target {target} is not supported by this binary"),
+    };
+
+    let opt_lvl = match model.opt_lvl.or(settings.opt_lvl).unwrap_or(3) {
+        0 => OptLevel::None,
+        1 => OptLevel::Less,
+        2 => OptLevel::Default,
+        3 => OptLevel::Aggressive,
+        lvl => bail!("unknown opt lvl {lvl}"),
+    };
+
+    let osdi_version = model.osdi_version.as_ref().or(settings.osdi_version.as_ref());
+    let osdi_version: OsdiVersion = match osdi_version {
+        Some(version) => version.parse().map_err(anyhow::Error::msg)?,
+        None => OsdiVersion::default(),
+    };
+
+    let lib_file = match (&settings.output_dir, &model.output) {
+        (Some(dir), Some(output)) => root.join(dir).join(output),
+        (Some(dir), None) => {
+            let name = input.with_extension("osdi");
+            root.join(dir).join(name.file_name().unwrap())
+        }
+        (None, Some(output)) => input.with_file_name(output),
+        (None, None) => input.with_extension("osdi"),
+    };
+
+    Ok(Opts {
+        dry_run,
+        defines: settings.defines.iter().chain(&model.defines).cloned().collect(),
+        codegen_opts: settings.codegen.clone(),
+        lints,
+        input,
+        additional_inputs: Vec::new(),
+        modules: model.modules.clone(),
+        output: CompilationDestination::Path { lib_file },
+        include: include?,
+        opt_lvl,
+        target,
+        target_cpu,
+        debug_info: settings.debug_info,
+        sanitize_float: FloatSanitizer::Disabled,
+        coverage: false,
+        modelcard: model.modelcard.as_ref().map(|modelcard| root.join(modelcard)),
+        sensitivities: model.sensitivities.clone(),
+        osdi_version,
+        emit: vec![EmitKind::Link],
+        output_kind: OutputKind::SharedLib,
+    })
+}
+
+fn same_output(opts1: &Opts, opts2: &Opts) -> bool {
+    match (&opts1.output, &opts2.output) {
+        (
+            CompilationDestination::Path { lib_file: file1 },
+            CompilationDestination::Path { lib_file: file2 },
+        ) => file1 == file2,
+        _ => false,
+    }
+}
+
+/// Compiles all `models` in parallel. The diagnostics of each model are buffered and printed at
+/// once when it is finished. The results are returned in the same order as `models`.
+///
+/// Models that only differ in their defines and codegen settings (for example different
+/// versions of a PDK model) are compiled one after another by the same task, which reuses the
+/// compilation databases (and therefore the files that were already read and the queries that
+/// don't depend on the preprocessor).
+pub fn compile_all(models: &[Opts]) -> Vec<Result<CompilationTermination>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (i, opts) in models.iter().enumerate() {
+        match groups.iter_mut().find(|group| same_sources(&models[group[0]], opts)) {
+            Some(group) => group.push(i),
+            None => groups.push(vec![i]),
+        }
+    }
+
+    let stderr = BufferWriter::stderr(ColorChoice::Auto);
+    let results: Vec<_> = models.iter().map(|_| Mutex::new(None)).collect();
+    rayon_core::scope(|scope| {
+        let stderr = &stderr;
+        let results = &results;
+        for group in groups {
+            scope.spawn(move |_| {
+                let mut dbs = None;
+                for i in group {
+                    let opts = &models[i];
+                    let mut diagnostics = stderr.buffer();
+                    let compiled = create_output_dir(opts).and_then(|_| {
+                        let dbs = reuse_dbs(&mut dbs, opts)?;
+                        compile_dbs(opts, dbs, &mut diagnostics)
+                    });
+                    // a broken stderr is no reason to fail the compilation
+                    let _ = stderr.print(&diagnostics);
+                    *results[i].lock().unwrap() = Some(compiled);
+                }
+            })
+        }
+    });
+    results.into_iter().map(|res| res.into_inner().unwrap().unwrap()).collect()
+}
+
+/// Whether the compilation databases of `opts1` can be reused for `opts2`.
+fn same_sources(opts1: &Opts, opts2: &Opts) -> bool {
+    opts1.input == opts2.input
+        && opts1.additional_inputs == opts2.additional_inputs
+        && opts1.include == opts2.include
+        && opts1.lints == opts2.lints
+}
+
+/// Returns the compilation databases of the root files of `opts`. The databases of the previous
+/// model of the group are reused (with the defines of `opts`) if there are any.
+fn reuse_dbs<'a>(
+    dbs: &'a mut Option<Vec<CompilationDB>>,
+    opts: &Opts,
+) -> Result<&'a [CompilationDB]> {
+    if let Some(dbs) = dbs.as_mut() {
+        for db in dbs {
+            db.set_defines(opts.defines.iter().map(String::as_str));
+        }
+    } else {
+        let opened = opts.inputs().map(|input| open_db(opts, input)).collect::<Result<_>>()?;
+        *dbs = Some(opened);
+    }
+    Ok(dbs.as_deref().unwrap())
+}
+
+/// Creates the directory the library of `opts` is written to
+/// (the `output_dir` of the manifest usually doesn't exist before the first build).
+fn create_output_dir(opts: &Opts) -> Result<()> {
+    if let (false, CompilationDestination::Path { lib_file }) = (opts.dry_run, &opts.output) {
+        if let Some(dir) = lib_file.parent() {
+            create_dir_all(dir).with_context(|| format!("failed to create {dir}"))?;
+        }
+    }
+    Ok(())
+}
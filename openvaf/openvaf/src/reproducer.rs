@@ -0,0 +1,273 @@
+//! Self-contained reproducers for internal compiler errors.
+//!
+//! A reproducer is a tar archive that contains the fully preprocessed token stream of every root
+//! file, the modelcard, the version of OpenVAF and the [`Opts`] as an `openvaf` command line
+//! (`opts.txt`). All includes and macros are already expanded, so the crash can be reproduced
+//! without the original files, include directories or defines. The paths in the command line
+//! refer to the files inside the archive.
+//!
+//! Optionally all identifiers can be anonymised. Every identifier is consistently replaced with a
+//! generic name (`anon0`, `anon1`, ...) and comments are removed. Reserved names (like builtin
+//! functions and nature attributes), system functions, string literals and attributes are kept as
+//! they may change how the model is compiled.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+
+use anyhow::{Context, Result};
+use basedb::BaseDB;
+use camino::{Utf8Path, Utf8PathBuf};
+use hir::CompilationDB;
+use syntax::name::kw;
+use syntax::{SyntaxKind, T};
+
+use crate::{
+    get_target_names, modelcard, open_db, CompilationDestination, EmitKind, FloatSanitizer,
+    LintLevel, Opts, OutputKind, Target,
+};
+
+/// Consistently replaces identifiers with generic names.
+#[derive(Debug, Default)]
+pub struct Anonymizer {
+    names: HashMap<String, String>,
+}
+
+impl Anonymizer {
+    pub fn new() -> Anonymizer {
+        Anonymizer::default()
+    }
+
+    /// Returns the replacement of the identifier `ident` (which may be escaped).
+    pub fn name(&mut self, ident: &str) -> &str {
+        let ident = ident.strip_prefix('\\').unwrap_or(ident).trim_end();
+        let num_names = self.names.len();
+        self.names.entry(ident.to_owned()).or_insert_with(|| format!("anon{num_names}"))
+    }
+
+    /// The replacement of an identifier that is passed to the compiler outside of the source code
+    /// (for example the name of a module or parameter in the `Opts`).
+    fn opt_name(&mut self, ident: &str) -> String {
+        if kw::is_reserved(ident) {
+            ident.to_owned()
+        } else {
+            self.name(ident).to_owned()
+        }
+    }
+
+    /// The replacement of a parameter name in a modelcard, which is matched case-insensitively.
+    fn modelcard_name(&mut self, name: &str) -> String {
+        if self.names.contains_key(name) {
+            return self.opt_name(name);
+        }
+        let ident = self.names.keys().find(|ident| ident.eq_ignore_ascii_case(name)).cloned();
+        self.opt_name(ident.as_deref().unwrap_or(name))
+    }
+}
+
+/// Returns the fully preprocessed source of the compilation unit of `db`.
+/// All identifiers are replaced if an `anonymizer` is provided.
+pub fn preprocessed_source(db: &CompilationDB, mut anonymizer: Option<&mut Anonymizer>) -> String {
+    let preprocess = db.compilation_unit().preprocess(db);
+    let mut dst = String::new();
+    let mut in_attr = false;
+    for token in preprocess.ts.iter() {
+        let span = token.span.to_file_span(&preprocess.sm);
+        let text = db.file_text(span.file).unwrap();
+        let text = &text[span.range];
+        match (token.kind, &mut anonymizer) {
+            (T!["(*"], _) => in_attr = true,
+            (T!["*)"], _) => in_attr = false,
+            (SyntaxKind::COMMENT, Some(_)) => {
+                dst.push(' ');
+                continue;
+            }
+            (SyntaxKind::IDENT, Some(anonymizer)) if !in_attr && !kw::is_reserved(text) => {
+                dst.push_str(anonymizer.name(text));
+                continue;
+            }
+            _ => (),
+        }
+        dst.push_str(text);
+    }
+    dst
+}
+
+/// The name of the `i`th root file inside the archive.
+fn root_file_name(i: usize) -> String {
+    format!("root{i}.va")
+}
+
+/// The name of the modelcard inside the archive.
+const MODELCARD: &str = "modelcard.mc";
+
+/// Quotes `arg` for a POSIX shell if it contains any special characters.
+fn shell_quote(arg: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_.,=/:+".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_plain) {
+        arg.to_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Returns the `openvaf` invocation that compiles with the options `opts`.
+fn command_line(opts: &Opts) -> String {
+    let mut args: Vec<String> = vec!["openvaf".to_owned()];
+    let mut arg = |flag: &str, val: &str| {
+        args.push(flag.to_owned());
+        args.push(shell_quote(val));
+    };
+
+    for define in &opts.defines {
+        arg("-D", define);
+    }
+    for include in &opts.include {
+        arg("-I", &include.display().to_string());
+    }
+    for module in &opts.modules {
+        arg("--module", module);
+    }
+    for (lint, lvl) in &opts.lints {
+        let flag = match lvl {
+            LintLevel::Allow => "--allow",
+            LintLevel::Warn => "--warn",
+            LintLevel::Deny => "--deny",
+        };
+        arg(flag, lint);
+    }
+    for codegen_opt in &opts.codegen_opts {
+        arg("-C", codegen_opt);
+    }
+    for param in &opts.sensitivities {
+        arg("--sensitivity", param);
+    }
+    if let Some(modelcard) = &opts.modelcard {
+        arg("--modelcard", modelcard.as_str());
+    }
+    // the target triples of the CLI are not always the LLVM triples
+    let target = get_target_names()
+        .find(|name| {
+            Target::search(name)
+                .map_or(false, |target| target.llvm_target == opts.target.llvm_target)
+        })
+        .unwrap_or(&opts.target.llvm_target);
+    arg("--target", target);
+    arg("--target_cpu", &opts.target_cpu);
+    arg("-O", &(opts.opt_lvl as u32).to_string());
+    arg("--osdi-version", &opts.osdi_version.to_string());
+    let output_kind = match opts.output_kind {
+        OutputKind::SharedLib => "shared",
+        OutputKind::StaticLib => "static",
+        OutputKind::Object => "object",
+    };
+    arg("--output-kind", output_kind);
+    let emit: Vec<_> = opts
+        .emit
+        .iter()
+        .map(|kind| match kind {
+            EmitKind::Link => "link",
+            EmitKind::CSource => "c",
+            EmitKind::LlvmIr => "llvm-ir",
+            EmitKind::LlvmBc => "llvm-bc",
+            EmitKind::Asm => "asm",
+            EmitKind::Obj => "obj",
+            EmitKind::Mir => "mir",
+        })
+        .collect();
+    arg("--emit", &emit.join(","));
+    match &opts.output {
+        CompilationDestination::Path { lib_file } => arg("-o", lib_file.as_str()),
+        CompilationDestination::Cache { cache_dir } => {
+            arg("--cache-dir", cache_dir.as_str());
+            args.push("--batch".to_owned());
+        }
+    }
+    if opts.sanitize_float != FloatSanitizer::Disabled {
+        args.push("--sanitize".to_owned());
+        args.push("float".to_owned());
+    }
+    if opts.sanitize_float == FloatSanitizer::Abort {
+        args.push("--sanitize-abort".to_owned());
+    }
+    let flags =
+        [("-g", opts.debug_info), ("--coverage", opts.coverage), ("--dry-run", opts.dry_run)];
+    for (flag, enabled) in flags {
+        if enabled {
+            args.push(flag.to_owned());
+        }
+    }
+    args.extend(opts.inputs().map(|input| shell_quote(input.as_str())));
+    args.join(" ")
+}
+
+/// Writes a reproducer for the compilation described by `opts` to `dst`.
+/// `version` is recorded in the archive to identify the compiler that crashed.
+pub fn write_reproducer(opts: &Opts, version: &str, anonymize: bool, dst: &Utf8Path) -> Result<()> {
+    let mut anonymizer = anonymize.then(Anonymizer::new);
+    let mut sources = Vec::new();
+    for input in opts.inputs() {
+        let db = open_db(opts, input)?;
+        sources.push(preprocessed_source(&db, anonymizer.as_mut()));
+    }
+
+    let mut modelcard = opts.modelcard.as_deref().map(modelcard::read).transpose()?;
+
+    let mut opts = opts.clone();
+    // includes and defines have already been applied by the preprocessor
+    opts.include.clear();
+    opts.defines.clear();
+    opts.input = Utf8PathBuf::from(root_file_name(0));
+    opts.additional_inputs =
+        (1..sources.len()).map(|i| Utf8PathBuf::from(root_file_name(i))).collect();
+    let lib_file =
+        Utf8PathBuf::from(root_file_name(0)).with_extension(opts.output_kind.extension());
+    opts.output = CompilationDestination::Path { lib_file };
+    if modelcard.is_some() {
+        opts.modelcard = Some(Utf8PathBuf::from(MODELCARD));
+    }
+    if let Some(anonymizer) = &mut anonymizer {
+        opts.modules = opts.modules.iter().map(|module| anonymizer.opt_name(module)).collect();
+        opts.sensitivities =
+            opts.sensitivities.iter().map(|param| anonymizer.opt_name(param)).collect();
+        if let Some(modelcard) = &mut modelcard {
+            for (name, _) in modelcard {
+                *name = anonymizer.modelcard_name(name);
+            }
+        }
+    }
+
+    let mut readme = format!("OpenVAF {version}\n\nThe crash can be reproduced by compiling");
+    for i in 0..sources.len() {
+        let _ = write!(readme, " {}", root_file_name(i));
+    }
+    let _ = writeln!(readme, " with the command in opts.txt (run it in this directory).");
+    if modelcard.is_some() {
+        let _ = writeln!(readme, "The parameters of {MODELCARD} are frozen.");
+    }
+
+    let file = File::create(dst).with_context(|| format!("failed to create {dst}"))?;
+    let mut archive = tar::Builder::new(file);
+    let mut append = |path: &str, data: &str| -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        archive.append_data(&mut header, path, data.as_bytes())?;
+        Ok(())
+    };
+    append("README", &readme)?;
+    append("VERSION", &format!("{version}\n"))?;
+    append("opts.txt", &format!("{}\n", command_line(&opts)))?;
+    for (i, source) in sources.iter().enumerate() {
+        append(&root_file_name(i), source)?;
+    }
+    if let Some(modelcard) = &modelcard {
+        let mut src = String::new();
+        for (name, val) in modelcard {
+            let _ = writeln!(src, "{name}={val}");
+        }
+        append(MODELCARD, &src)?;
+    }
+    archive.into_inner().and_then(|file| file.sync_all()).context("failed to write reproducer")?;
+    Ok(())
+}
@@ -0,0 +1,48 @@
+//! Parameter sensitivities (`--sensitivity`).
+//!
+//! For each selected real parameter `p` the derivatives of the residual dR/dp are generated
+//! by automatic differentiation, just like the Jacobian entries. They are computed by the
+//! `eval_sensitivity` entry point that is advertised in the `OsdiDescriptorExtensions` of the
+//! module (see `osdi/header/openvaf_ext.h`).
+
+use anyhow::{bail, Result};
+use hir::{CompilationDB, Type};
+use sim_back::ModuleInfo;
+
+use crate::modelcard::find_param;
+
+/// Selects the parameters called `names` for sensitivity analysis in all `modules` (of the
+/// root files compiled with `dbs`) that declare them. Names are matched like the parameters
+/// of a modelcard.
+pub fn select_params(
+    names: &[String],
+    dbs: &[CompilationDB],
+    modules: &mut [Vec<ModuleInfo>],
+) -> Result<()> {
+    for name in names {
+        let mut found = false;
+        for (db, modules) in dbs.iter().zip(modules.iter_mut()) {
+            for module in modules.iter_mut() {
+                let param =
+                    if let Some((param, _)) = find_param(module, name) { param } else { continue };
+                found = true;
+
+                let module_name = module.module.name(db);
+                let ty = param.ty(db);
+                if ty != Type::Real {
+                    bail!("{module_name}: parameter {name} of type {ty} has no sensitivity");
+                }
+                if module.frozen_params.contains_key(&param) {
+                    bail!(
+                        "{module_name}: {name} is frozen by the modelcard and has no sensitivity"
+                    );
+                }
+                module.sensitivity_params.insert(param);
+            }
+        }
+        if !found {
+            bail!("sensitivities requested for the unknown parameter {name}");
+        }
+    }
+    Ok(())
+}
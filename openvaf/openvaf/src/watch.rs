@@ -0,0 +1,106 @@
+//! Recompiles the root files whenever they (or one of their includes) change (`openvaf --watch`).
+//!
+//! The compilation databases are kept alive between compilations. Changed files are pushed
+//! into the databases so salsa only recomputes the queries that depend on them.
+
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use hir::CompilationDB;
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use vfs::loader::{Config, Entry, Handle, Message, PollHandle};
+use vfs::AbsPathBuf;
+
+use crate::{compile_dbs, open_db, Opts};
+
+/// Editors often save a file in multiple steps, all changes that arrive within this duration of
+/// each other are handled by a single compilation.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Compiles the root files of `opts` and recompiles them every time a file they depend on
+/// changes. Only returns if an error occurs while watching.
+pub fn watch(opts: &Opts) -> Result<()> {
+    let mut dbs: Vec<_> = opts.inputs().map(|input| open_db(opts, input)).collect::<Result<_>>()?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut handle = PollHandle::spawn(Box::new(move |msg| {
+        let _ = sender.send(msg);
+    }));
+    let mut config_version = 0;
+    let mut watched = Vec::new();
+
+    loop {
+        // errors (for example a failing linker) are reported but don't end the session
+        if let Err(err) = compile_dbs(opts, &dbs, &mut StandardStream::stderr(ColorChoice::Auto)) {
+            print_error(&err)?;
+        }
+
+        // includes may have been added or removed
+        let files = watched_files(&dbs);
+        if files != watched {
+            config_version += 1;
+            handle.set_config(Config {
+                version: config_version,
+                load: vec![Entry::Files(files.clone())],
+                watch: vec![0],
+            });
+            watched = files;
+        }
+
+        let mut stderr = StandardStream::stderr(ColorChoice::Auto);
+        stderr.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)).set_bold(true))?;
+        write!(&mut stderr, "Watching")?;
+        stderr.set_color(&ColorSpec::new())?;
+        writeln!(&mut stderr, " {} files for changes", watched.len())?;
+
+        wait_for_changes(&receiver, &mut dbs)?;
+    }
+}
+
+/// The files on disk that the root files of `dbs` depend on.
+fn watched_files(dbs: &[CompilationDB]) -> Vec<AbsPathBuf> {
+    let mut files: Vec<_> = dbs.iter().flat_map(CompilationDB::disk_files).collect();
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Blocks until the contents of a file that one of the `dbs` depends on has changed and
+/// updates the `dbs` accordingly.
+fn wait_for_changes(receiver: &Receiver<Message>, dbs: &mut [CompilationDB]) -> Result<()> {
+    let mut changed = false;
+    loop {
+        let msg = if changed {
+            match receiver.recv_timeout(DEBOUNCE) {
+                Ok(msg) => msg,
+                Err(RecvTimeoutError::Timeout) => return Ok(()),
+                Err(RecvTimeoutError::Disconnected) => bail!("file watcher stopped unexpectedly"),
+            }
+        } else {
+            receiver.recv().context("file watcher stopped unexpectedly")?
+        };
+
+        if let Message::Loaded { files } = msg {
+            for (path, contents) in files {
+                for db in dbs.iter_mut() {
+                    changed |= db.update_file(path.clone(), contents.clone());
+                }
+            }
+        }
+    }
+}
+
+fn print_error(err: &anyhow::Error) -> Result<()> {
+    let mut stderr = StandardStream::stderr(ColorChoice::Auto);
+    for cause in err.chain() {
+        stderr.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+        write!(&mut stderr, "error")?;
+        stderr.set_color(ColorSpec::new().set_bold(true))?;
+        write!(&mut stderr, ":")?;
+        stderr.set_color(&ColorSpec::new())?;
+        writeln!(&mut stderr, " {cause}")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,509 @@
+//! Differential tests of the MIR optimizations and the LLVM backend.
+//!
+//! Every model in `integration_tests` is evaluated for randomised (but valid) parameters and
+//! biases in two ways: by interpreting its unoptimized MIR (`sim_interpret`) and by calling the
+//! compiled OSDI library. The residuals, Jacobian entries and operating point variables must
+//! agree within a tolerance. Only the small models in [`DEFAULT_MODELS`] are tested by default,
+//! all other models are tested when `RUN_DEV_TESTS` is set.
+//!
+//! A discrepancy is minimised to the MIR pass that introduced it. The number of optimization
+//! passes that sim_back runs is bisected (see [`OptBisect`]) until the first pass whose result no
+//! longer matches the unoptimized MIR is found. If the fully optimized MIR still agrees with the
+//! unoptimized MIR, the LLVM backend is at fault.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use hir::diagnostics::ConsoleSink;
+use hir::{CompilationDB, Parameter};
+use indexmap::IndexMap;
+use lasso::Rodeo;
+use llvm::OptLevel;
+use mini_harness::{harness, Result};
+use openvaf::{
+    AbsPathBuf, CompilationDestination, CompilationTermination, EmitKind, FloatSanitizer,
+    OsdiVersion, OutputKind,
+};
+use osdi_host::{Descriptor, EvalFlags, ParamTy, SimInfo};
+use sim_back::{collect_modules, CompiledModule, ModuleInfo, OptBisect, SimUnknownKind};
+use sim_interpret::{Analysis, InterpretedModule, ParamValue, ParamValues, SimParams};
+use stdx::{ignore_dev_tests, project_root};
+use target::spec::Target;
+use xshell::Shell;
+
+/// The seed of the random number generator, fixed so that failures are reproducible.
+const SEED: u64 = 0x5eed_f00d_cafe_d00d;
+/// The number of random parameter sets and biases that are compared for each model.
+const NUM_SAMPLES: usize = 8;
+/// The models that are small enough to be tested by default.
+const DEFAULT_MODELS: [&str; 2] = ["DIODE", "RESISTOR"];
+/// The probability that a real or integer parameter is changed from its default.
+const PERTURB_PROBABILITY: f64 = 0.3;
+const TEMPERATURE: f64 = 300.15;
+const REL_TOL: f64 = 1e-6;
+const ABS_TOL: f64 = 1e-12;
+
+/// A small (SplitMix64) random number generator, the quality requirements are low here.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A random number in `[lo, hi)`.
+    fn uniform(&mut self, lo: f64, hi: f64) -> f64 {
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        lo + (hi - lo) * unit
+    }
+}
+
+/// The inputs of a single comparison.
+struct Sample {
+    params: Vec<(Parameter, ParamValue)>,
+    /// The voltage (or other unknown) of each node of the optimized module. Collapsed nodes have
+    /// the same value (zero if they are collapsed into ground).
+    prev_solve: Vec<f64>,
+}
+
+/// The results of an evaluation keyed by a readable name (the residual and Jacobian entries
+/// are named after the unknowns of the optimized module).
+type Results = IndexMap<String, f64>;
+
+fn differential_test(dir: &Path) -> Result {
+    let name = dir.file_name().unwrap().to_str().unwrap().to_lowercase();
+    let root_file: Utf8PathBuf = dir.join(format!("{name}.va")).try_into().unwrap();
+    // the library stays loaded until the end of the test, so the directory must outlive it
+    let out_dir = Shell::new()?.create_temp_dir()?;
+    let lib_file: Utf8PathBuf = out_dir.path().join(format!("{name}.osdi")).try_into().unwrap();
+    let lib = compile_osdi(&root_file, lib_file);
+
+    let root_file = AbsPathBuf::assert(root_file.canonicalize()?);
+    let db = CompilationDB::new_fs(root_file, &[], &[], &[])?;
+    let modules = collect_modules(&db, false, &mut ConsoleSink::new(&db))
+        .ok_or_else(|| format!("failed to compile {name}"))?;
+    assert_eq!(modules.len(), lib.len());
+
+    let mut rng = Rng(SEED);
+    let mut failures = String::new();
+    for (info, &desc) in modules.iter().zip(&lib) {
+        assert_eq!(desc.name(), info.module.name(&db));
+        let differential = Differential::new(&db, info, desc);
+        for i in 0..NUM_SAMPLES {
+            let sample = if let Some(sample) = differential.sample(&mut rng) {
+                sample
+            } else {
+                continue;
+            };
+            let expected = differential.interpret(Some(0), &sample);
+            let found = differential.eval_osdi(&sample);
+            let (expected, found) = match (expected, found) {
+                (Some(expected), Some(found)) => (expected, found),
+                // setup or eval failed (consistently), nothing to compare
+                (None, None) => continue,
+                (expected, _) => {
+                    let _ = writeln!(
+                        failures,
+                        "{} (sample {i}): setup {} for the unoptimized MIR but not for OSDI",
+                        desc.name(),
+                        if expected.is_some() { "succeeded" } else { "failed" },
+                    );
+                    continue;
+                }
+            };
+
+            let mismatches = compare(&expected, &found);
+            if !mismatches.is_empty() {
+                let _ = writeln!(
+                    failures,
+                    "{} (sample {i}): {}\n{}",
+                    desc.name(),
+                    differential.minimise(&sample, &expected),
+                    mismatches.join("\n")
+                );
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.into())
+    }
+}
+
+fn compile_osdi(root_file: &Utf8Path, lib_file: Utf8PathBuf) -> Vec<Descriptor> {
+    let opts = openvaf::Opts {
+        defines: Vec::new(),
+        codegen_opts: Vec::new(),
+        lints: Vec::new(),
+        input: root_file.to_path_buf(),
+        additional_inputs: Vec::new(),
+        modules: Vec::new(),
+        output: CompilationDestination::Path { lib_file },
+        include: Vec::new(),
+        opt_lvl: OptLevel::Aggressive,
+        target: Target::host_target().unwrap(),
+        target_cpu: "native".to_owned(),
+        dry_run: false,
+        debug_info: false,
+        sanitize_float: FloatSanitizer::Disabled,
+        coverage: false,
+        modelcard: None,
+        sensitivities: Vec::new(),
+        osdi_version: OsdiVersion::V0_3,
+        emit: vec![EmitKind::Link],
+        output_kind: OutputKind::SharedLib,
+    };
+    let lib_file = match openvaf::compile(&opts).unwrap() {
+        CompilationTermination::Compiled { lib_file } => lib_file,
+        CompilationTermination::FatalDiagnostic => {
+            panic!("openvaf: compilation of {root_file} failed");
+        }
+    };
+    let lib = unsafe { osdi_host::Library::load(&lib_file).unwrap() };
+    lib.descriptors().to_vec()
+}
+
+struct Differential<'a> {
+    db: &'a CompilationDB,
+    info: &'a ModuleInfo,
+    desc: Descriptor,
+    /// The fully optimized module (the same MIR the OSDI library was generated from).
+    optimized: CompiledModule<'a>,
+    /// All MIR passes run for the optimized module.
+    passes: Vec<String>,
+}
+
+impl<'a> Differential<'a> {
+    fn new(db: &'a CompilationDB, info: &'a ModuleInfo, desc: Descriptor) -> Differential<'a> {
+        let mut opt_bisect = OptBisect::new(None);
+        let optimized =
+            CompiledModule::with_opt_bisect(db, info, &mut Rodeo::new(), &mut opt_bisect);
+        Differential { db, info, desc, optimized, passes: opt_bisect.passes().to_vec() }
+    }
+
+    /// Creates random parameters (that are accepted by the model) and random biases.
+    fn sample(&self, rng: &mut Rng) -> Option<Sample> {
+        let mut literals = Rodeo::new();
+        let module = CompiledModule::with_opt_bisect(
+            self.db,
+            self.info,
+            &mut literals,
+            &mut OptBisect::new(Some(0)),
+        );
+        let interpreted = InterpretedModule::new(self.db, &module, &literals);
+        let sim_params = SimParams::default();
+        let (defaults, report) = interpreted.setup_model(&ParamValues::default(), &sim_params);
+        if !report.invalid_params.is_empty() || report.fatal {
+            return None;
+        }
+
+        let mut params: Vec<_> = self
+            .info
+            .params
+            .keys()
+            .filter_map(|param| {
+                let val = match *defaults.params.get(param)? {
+                    ParamValue::Real(val) if val != 0.0 => {
+                        ParamValue::Real(val * rng.uniform(0.8, 1.2))
+                    }
+                    // integer parameters are often switches, so only step to a neighbouring value
+                    ParamValue::Integer(val) => {
+                        let step = if rng.uniform(0.0, 1.0) < 0.5 { 1 } else { -1 };
+                        ParamValue::Integer(val + step)
+                    }
+                    _ => return None,
+                };
+                (rng.uniform(0.0, 1.0) < PERTURB_PROBABILITY).then_some((*param, val))
+            })
+            .collect();
+        // parameters that are out of bounds keep their default
+        let model = loop {
+            let (model, report) = interpreted.setup_model(&param_values(&params), &sim_params);
+            if report.invalid_params.is_empty() {
+                break model;
+            }
+            let num_params = params.len();
+            params.retain(|(param, _)| !report.invalid_params.contains(param));
+            if params.len() == num_params {
+                return None;
+            }
+        };
+
+        // collapsed nodes must have the same voltage
+        let (instance, _) = interpreted.setup_instance(
+            &model,
+            &ParamValues::default(),
+            TEMPERATURE,
+            self.desc.num_terminals(),
+            &sim_params,
+        );
+        let unknowns = &module.dae_system.unknowns;
+        let num_nodes = self.optimized.dae_system.unknowns.len();
+        // union find, the last element represents ground
+        let mut parents: Vec<_> = (0..=num_nodes).collect();
+        fn root(parents: &[usize], mut node: usize) -> usize {
+            while parents[node] != node {
+                node = parents[node];
+            }
+            node
+        }
+        for (hi, lo) in interpreted.collapsed_nodes(&instance) {
+            let hi = self.node(&unknowns[hi]);
+            let lo = lo.map_or(Some(num_nodes), |lo| self.node(&unknowns[lo]));
+            if let (Some(hi), Some(lo)) = (hi, lo) {
+                let (hi, lo) = (root(&parents, hi), root(&parents, lo));
+                // ground always remains the root
+                if hi == num_nodes {
+                    parents[lo] = hi;
+                } else {
+                    parents[hi] = lo;
+                }
+            }
+        }
+        let voltages: Vec<_> = (0..num_nodes).map(|_| rng.uniform(-1.0, 1.0)).collect();
+        let prev_solve = (0..num_nodes).map(|node| match root(&parents, node) {
+            root if root == num_nodes => 0.0,
+            root => voltages[root],
+        });
+        Some(Sample { params, prev_solve: prev_solve.collect() })
+    }
+
+    /// Interprets the MIR produced by running the first `limit` optimization passes (all passes
+    /// for `None`). Returns `None` if the setup or the evaluation failed.
+    fn interpret(&self, limit: Option<usize>, sample: &Sample) -> Option<Results> {
+        let mut literals = Rodeo::new();
+        let module = CompiledModule::with_opt_bisect(
+            self.db,
+            self.info,
+            &mut literals,
+            &mut OptBisect::new(limit),
+        );
+        let interpreted = InterpretedModule::new(self.db, &module, &literals);
+        let sim_params = SimParams::default();
+        let (model, report) = interpreted.setup_model(&param_values(&sample.params), &sim_params);
+        if !report.invalid_params.is_empty() || report.fatal {
+            return None;
+        }
+        let (instance, report) = interpreted.setup_instance(
+            &model,
+            &ParamValues::default(),
+            TEMPERATURE,
+            self.desc.num_terminals(),
+            &sim_params,
+        );
+        if !report.invalid_params.is_empty() || report.fatal {
+            return None;
+        }
+
+        let unknowns = &module.dae_system.unknowns;
+        let prev_solve: Vec<_> = unknowns
+            .iter()
+            .map(|unknown| self.node(unknown).map_or(0.0, |node| sample.prev_solve[node]))
+            .collect();
+        let sim = sim_interpret::SimInfo {
+            analysis: Analysis::Tran,
+            abstime: 0.0,
+            prev_solve: &prev_solve,
+            prev_state: &vec![0.0; module.intern.lim_state.len()],
+            enable_lim: false,
+            sim_params: &sim_params,
+        };
+        let res = interpreted.eval(&instance, &model, &sim);
+        if res.fatal {
+            return None;
+        }
+
+        // unknowns removed by the optimizations (for example branches that turned out not to be
+        // voltage sources) do not exist in the OSDI library, their entries are not compared
+        let mut results = Results::new();
+        for (unknown, kind) in unknowns.iter_enumerated() {
+            if self.node(kind).is_none() {
+                continue;
+            }
+            let name = self.unknown_name(kind);
+            results.insert(format!("resist({name})"), res.residual_resist[unknown]);
+            results.insert(format!("react({name})"), res.residual_react[unknown]);
+        }
+        for (id, entry) in module.dae_system.jacobian.iter_enumerated() {
+            if self.node(&unknowns[entry.row]).is_none()
+                || self.node(&unknowns[entry.col]).is_none()
+            {
+                continue;
+            }
+            let row = self.unknown_name(&unknowns[entry.row]);
+            let col = self.unknown_name(&unknowns[entry.col]);
+            results.insert(format!("resist({row}, {col})"), res.jacobian_resist[id]);
+            results.insert(format!("react({row}, {col})"), res.jacobian_react[id]);
+        }
+        for (var, val) in &res.opvars {
+            let val = match *val {
+                ParamValue::Real(val) => val,
+                ParamValue::Integer(val) => val as f64,
+                ParamValue::String(_) => continue,
+            };
+            results.insert(format!("opvar({})", var.name(self.db)), val);
+        }
+        Some(results)
+    }
+
+    /// Evaluates the OSDI library. Returns `None` if the setup or the evaluation failed.
+    fn eval_osdi(&self, sample: &Sample) -> Option<Results> {
+        let desc = self.desc;
+        let model = desc.new_model();
+        for (param, val) in &sample.params {
+            let name = &self.info.params[param].name;
+            let res = match val {
+                ParamValue::Real(val) => model.set_param(name, *val),
+                ParamValue::Integer(val) => model.set_param(name, *val),
+                ParamValue::String(val) => model.set_param(name, val.as_str()),
+            };
+            res.unwrap();
+        }
+        model.process_params().ok()?;
+        let mut instance = model.new_instance();
+        let internal_nodes = instance.process_params(TEMPERATURE, desc.num_terminals()).ok()?;
+
+        // unknown 0 is ground
+        instance.map_nodes(|node| if node == u32::MAX { 0 } else { node + 1 });
+        let num_unknowns = 1 + desc.num_terminals() as usize + internal_nodes.len();
+        let mut prev_solve = vec![0.0; num_unknowns];
+        for (node, &unknown) in instance.node_mapping().iter().enumerate() {
+            if unknown != 0 {
+                prev_solve[unknown as usize] = sample.prev_solve[node];
+            }
+        }
+
+        // every entry is bound to its own element so that collapsing does not sum entries
+        let num_entries = desc.jacobian_entries().len();
+        let mut jacobian_resist = vec![0.0; num_entries];
+        let mut jacobian_react = vec![0.0; num_entries];
+        for i in 0..num_entries {
+            // SAFETY: the vectors are not resized and outlive the instance
+            unsafe {
+                instance.bind_jacobian(i, &mut jacobian_resist[i], &mut jacobian_react[i]);
+            }
+        }
+
+        let num_states = desc.num_states() as usize;
+        let mut next_state = vec![0.0; num_states];
+        let mut sim = SimInfo {
+            abstime: 0.0,
+            prev_solve: &prev_solve,
+            prev_state: &vec![0.0; num_states],
+            next_state: &mut next_state,
+            flags: EvalFlags::TRAN | EvalFlags::CALC_OP,
+        };
+        if instance.eval(&mut sim).is_fatal() {
+            return None;
+        }
+        // SAFETY: all entries were bound above
+        unsafe {
+            instance.load_jacobian_resist();
+            instance.load_jacobian_react(1.0);
+        }
+
+        let mut results = Results::new();
+        let unknowns = &self.optimized.dae_system.unknowns;
+        for (node, kind) in unknowns.iter().enumerate() {
+            let name = self.unknown_name(kind);
+            results.insert(format!("resist({name})"), instance.residual_resist(node as u32));
+            results.insert(format!("react({name})"), instance.residual_react(node as u32));
+        }
+        for (i, entry) in self.optimized.dae_system.jacobian.iter().enumerate() {
+            let row = self.unknown_name(&unknowns[entry.row]);
+            let col = self.unknown_name(&unknowns[entry.col]);
+            results.insert(format!("resist({row}, {col})"), jacobian_resist[i]);
+            results.insert(format!("react({row}, {col})"), jacobian_react[i]);
+        }
+        for opvar in desc.opvars() {
+            if opvar.len() != 0 || opvar.ty() == ParamTy::Str {
+                continue;
+            }
+            results.insert(format!("opvar({})", opvar.name()), instance.read_opvar(opvar.id).ok()?);
+        }
+        Some(results)
+    }
+
+    /// Finds the MIR pass that introduced a discrepancy between the unoptimized MIR (which
+    /// produced `expected`) and the OSDI library.
+    fn minimise(&self, sample: &Sample, expected: &Results) -> String {
+        let agrees = |limit| match self.interpret(limit, sample) {
+            Some(found) => compare(expected, &found).is_empty(),
+            None => false,
+        };
+        if agrees(None) {
+            return "the optimized MIR matches the unoptimized MIR, the discrepancy was \
+                    introduced by the LLVM backend"
+                .to_owned();
+        }
+
+        // invariant: the first `lo` passes preserve the results, the first `hi` passes do not
+        let (mut lo, mut hi) = (0, self.passes.len());
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if agrees(Some(mid)) {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        format!("introduced by MIR pass #{hi} ({})", self.passes[hi - 1])
+    }
+
+    /// The index of `unknown` in the optimized module (which is the OSDI node index).
+    fn node(&self, unknown: &SimUnknownKind) -> Option<usize> {
+        self.optimized.dae_system.unknowns.index(unknown).map(usize::from)
+    }
+
+    fn unknown_name(&self, unknown: &SimUnknownKind) -> String {
+        match self.node(unknown) {
+            Some(node) => self.desc.node_name(node as u32).to_owned(),
+            None => format!("{unknown:?}"),
+        }
+    }
+}
+
+fn param_values(params: &[(Parameter, ParamValue)]) -> ParamValues {
+    let mut values = ParamValues::default();
+    for (param, val) in params {
+        values.set(*param, val.clone());
+    }
+    values
+}
+
+fn is_close(expected: f64, found: f64) -> bool {
+    if expected.is_nan() || found.is_nan() {
+        return expected.is_nan() && found.is_nan();
+    }
+    expected == found
+        || (expected - found).abs() <= ABS_TOL + REL_TOL * expected.abs().max(found.abs())
+}
+
+/// Lists all results that differ (missing entries are zero).
+fn compare(expected: &Results, found: &Results) -> Vec<String> {
+    let names = expected.keys().chain(found.keys().filter(|name| !expected.contains_key(*name)));
+    names
+        .filter_map(|name| {
+            let expected_val = expected.get(name).copied().unwrap_or(0.0);
+            let found_val = found.get(name).copied().unwrap_or(0.0);
+            (!is_close(expected_val, found_val))
+                .then(|| format!("  {name}: expected {expected_val:e}, found {found_val:e}"))
+        })
+        .collect()
+}
+
+fn ignore_differential_test(dir: &Path) -> bool {
+    let name = dir.file_name().unwrap().to_str().unwrap();
+    !DEFAULT_MODELS.contains(&name) && ignore_dev_tests(dir)
+}
+
+harness! {
+    Test::from_dir("differential", &differential_test, &ignore_differential_test, &project_root().join("integration_tests"))
+}
@@ -1,34 +1,69 @@
+use std::env;
 use std::f64::consts;
+use std::fs::{self, File};
 use std::path::Path;
+use std::process::Command;
+use std::slice;
 
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use expect_test::expect_file;
 use float_cmp::assert_approx_eq;
 use llvm::OptLevel;
-use mini_harness::{harness, Result};
-use openvaf::{CompilationDestination, CompilationTermination};
+use mini_harness::{harness, Result, Test};
+use openvaf::coverage::ReportFormat;
+use openvaf::describe::DescribeFormat;
+use openvaf::{
+    CompilationDestination, CompilationTermination, EmitKind, FloatSanitizer, OsdiVersion,
+    OutputKind,
+};
+use osdi::{CoverageMap, RegionKind};
+use osdi_host::osdi_0_4::{
+    ATTR_TYPE_REAL, ATTR_TYPE_STR, DOMAIN_NOT_GIVEN, NATREF_DISCIPLINE_FLOW,
+    NATREF_DISCIPLINE_POTENTIAL,
+};
+use osdi_host::osdi_str;
 use stdx::{ignore_dev_tests, openvaf_test_data, project_root};
 use target::spec::Target;
+use xshell::{Shell, TempDir};
 
-use crate::load::{load_osdi_lib, EvalFlags, OsdiDescriptor};
+use crate::load::{
+    load_descriptor_extensions, load_osdi_lib, Descriptor, EvalFlags, EvalRetFlags, Instance,
+    Model, OsdiNoiseCorrelation,
+};
 use crate::mock_sim::{MockSimulation, ALPHA};
 
 mod load;
 mod mock_sim;
 
-fn compile_and_load(root_file: &Utf8Path) -> &'static OsdiDescriptor {
-    let openvaf_opts = openvaf::Opts {
-        defines: Vec::new(),
-        codegen_opts: Vec::new(),
-        lints: Vec::new(),
-        input: root_file.to_path_buf(),
-        output: CompilationDestination::Path { lib_file: root_file.with_extension("osdi") },
-        include: Vec::new(),
-        opt_lvl: OptLevel::Aggressive,
-        target: Target::host_target().unwrap(),
-        target_cpu: "native".to_owned(),
-        dry_run: false,
+const KB: f64 = 1.3806488e-23;
+const Q: f64 = 1.602176565e-19;
+const VT: f64 = KB * 300.0 / Q;
+
+/// The parameters of the resistor and the diode of `batch.va`.
+const R: f64 = 50.0;
+const IS: f64 = 1e-14;
+/// The voltage across `batch.va` in the tests that use it.
+const VD: f64 = 0.6;
+
+fn compile_and_load(root_file: &Utf8Path) -> Descriptor {
+    compile_and_load_variant(root_file, None, |_| ())
+}
+
+/// Compiles `root_file` with the options modified by `configure`. The library of each `variant`
+/// is placed next to (and does not replace) the library compiled with the default options.
+fn compile_and_load_variant(
+    root_file: &Utf8Path,
+    variant: Option<&str>,
+    configure: impl FnOnce(&mut openvaf::Opts),
+) -> Descriptor {
+    let lib_file = match variant {
+        Some(variant) => {
+            root_file.with_file_name(format!("{}_{variant}.osdi", root_file.file_stem().unwrap()))
+        }
+        None => root_file.with_extension("osdi"),
     };
+    let mut openvaf_opts = default_opts(root_file, lib_file);
+    configure(&mut openvaf_opts);
 
     let res = openvaf::compile(&openvaf_opts).unwrap();
     let lib_file = match res {
@@ -39,7 +74,69 @@ fn compile_and_load(root_file: &Utf8Path) -> &'static OsdiDescriptor {
     };
     let libs = unsafe { load_osdi_lib(&lib_file).unwrap() };
     assert_eq!(libs.len(), 1);
-    &libs[0]
+    libs[0]
+}
+
+fn default_opts(root_file: &Utf8Path, lib_file: Utf8PathBuf) -> openvaf::Opts {
+    openvaf::Opts {
+        defines: Vec::new(),
+        codegen_opts: Vec::new(),
+        lints: Vec::new(),
+        input: root_file.to_path_buf(),
+        additional_inputs: Vec::new(),
+        modules: Vec::new(),
+        output: CompilationDestination::Path { lib_file },
+        include: Vec::new(),
+        opt_lvl: OptLevel::Aggressive,
+        target: Target::host_target().unwrap(),
+        target_cpu: "native".to_owned(),
+        dry_run: false,
+        debug_info: false,
+        sanitize_float: FloatSanitizer::Disabled,
+        coverage: false,
+        modelcard: None,
+        sensitivities: Vec::new(),
+        osdi_version: OsdiVersion::V0_3,
+        emit: vec![EmitKind::Link],
+        output_kind: OutputKind::SharedLib,
+    }
+}
+
+/// Creates an empty temporary directory that is unique to this test run.
+/// The directory is removed when the returned handle is dropped.
+fn temp_dir() -> Result<(TempDir, Utf8PathBuf)> {
+    let dir = Shell::new()?.create_temp_dir()?;
+    let path = dir.path().to_owned().try_into().unwrap();
+    Ok((dir, path))
+}
+
+/// A test that loads the compiled library into the test process, which is not possible in CI
+/// on Windows.
+fn host_test<'a>(name: &str, runner: &'a dyn Fn() -> Result) -> Test<'a> {
+    let test = Test::new(name, runner);
+    if stdx::SKIP_HOST_TESTS {
+        test.ignored()
+    } else {
+        test
+    }
+}
+
+/// Creates a model of `batch.va` with the parameters `R` and `IS`.
+fn batch_model(desc: Descriptor) -> Result<Model> {
+    let model = desc.new_model();
+    model.set_param_by_id(1, R)?;
+    model.set_param_by_id(2, IS)?;
+    model.process_params()?;
+    Ok(model)
+}
+
+/// Creates an instance of the `batch.va` `model` together with a simulation that contains only
+/// this instance and biases it with `VD` at 300K.
+fn biased_batch_instance(desc: Descriptor, model: &Model) -> Result<(Instance, MockSimulation)> {
+    let mut instance = model.new_instance();
+    let mut sim = MockSimulation::new(&mut instance, desc.num_terminals(), 300.0)?;
+    sim.set_voltage("a", VD);
+    Ok((instance, sim))
 }
 
 // fn integration_test(dir: &str) -> Result {
@@ -58,7 +155,7 @@ fn integration_test(dir: &Path) -> Result {
     Ok(())
 }
 
-fn test_descriptor(main_file: &Path) -> Result<&'static OsdiDescriptor> {
+fn test_descriptor(main_file: &Path) -> Result<Descriptor> {
     let main_file: &Utf8Path = main_file.try_into().unwrap();
     let name = main_file.file_stem().unwrap();
     let desc = compile_and_load(main_file);
@@ -68,7 +165,7 @@ fn test_descriptor(main_file: &Path) -> Result<&'static OsdiDescriptor> {
     let default_model = desc.new_model();
     default_model.process_params()?;
     let mut instance = default_model.new_instance();
-    instance.process_params(&default_model, desc.num_terminals, 300.0)?;
+    instance.process_params(300.0, desc.num_terminals())?;
     Ok(desc)
 }
 
@@ -142,26 +239,26 @@ fn test_limit() -> Result<()> {
     // compile model and setup simulation
     let desc = test_descriptor(&openvaf_test_data("osdi").join("diode_lim.va"))?;
     let model = desc.new_model();
-    model.set_real_param(1, IS);
-    model.set_real_param(5, CJ0);
+    model.set_param_by_id(1, IS)?;
+    model.set_param_by_id(5, CJ0)?;
     model.process_params()?;
     let mut instance = model.new_instance();
-    let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+    let mut sim = MockSimulation::new(&mut instance, desc.num_terminals(), 300.0)?;
 
-    instance.eval(&model, &mut sim, EvalFlags::INIT_LIM | EvalFlags::ENABLE_LIM);
-    instance.load_dae(&model, &mut sim);
+    sim.eval(&mut instance, EvalFlags::INIT_LIM | EvalFlags::ENABLE_LIM);
+    sim.load_dae(&instance);
     check_dae_equations(&sim, vcrit, 0.0);
     sim.clear();
-    instance.load_spice(&model, &mut sim);
+    sim.load_spice(&instance);
     check_spice_equations(&sim, vcrit, 0.0);
 
     sim.next_iter();
     sim.set_voltage("A", 2.0 * vcrit);
-    instance.eval(&model, &mut sim, EvalFlags::ENABLE_LIM);
-    instance.load_dae(&model, &mut sim);
+    sim.eval(&mut instance, EvalFlags::ENABLE_LIM);
+    sim.load_dae(&instance);
     check_dae_equations(&sim, 1.5 * vcrit, 2.0 * vcrit);
     sim.clear();
-    instance.load_spice(&model, &mut sim);
+    sim.load_spice(&instance);
     check_spice_equations(&sim, 1.5 * vcrit, 2.0 * vcrit);
     Ok(())
 }
@@ -191,18 +288,18 @@ fn test_noise() -> Result<()> {
     // compile model and setup simulation
     let desc = test_descriptor(&openvaf_test_data("osdi").join("noise.va"))?;
     let model = desc.new_model();
-    model.set_real_param(0, MFACTOR);
-    model.set_real_param(1, PWR);
-    model.set_real_param(2, EXP);
+    model.set_param_by_id(0, MFACTOR)?;
+    model.set_param_by_id(1, PWR)?;
+    model.set_param_by_id(2, EXP)?;
     model.process_params()?;
     let mut instance = model.new_instance();
-    let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+    let mut sim = MockSimulation::new(&mut instance, desc.num_terminals(), 300.0)?;
 
     sim.set_voltage("a", V_AC);
-    instance.eval(&model, &mut sim, EvalFlags::empty());
+    sim.eval(&mut instance, EvalFlags::empty());
     for freq in 1..10 {
         let freq = freq as f64;
-        instance.load_noise(&model, &mut sim, freq);
+        sim.load_noise(&instance, freq);
         let white_noise = MFACTOR * PWR * V_AC;
         assert_approx_eq!(sim.read_noise(0), white_noise);
         assert_approx_eq!(sim.read_noise(1), white_noise);
@@ -212,8 +309,537 @@ fn test_noise() -> Result<()> {
     Ok(())
 }
 
+fn test_correlated_noise() -> Result<()> {
+    if stdx::IS_CI && cfg!(windows) {
+        return Ok(());
+    }
+
+    let root_file = openvaf_test_data("osdi").join("correlated_noise.va");
+    let root_file: &Utf8Path = root_file.as_path().try_into().unwrap();
+    let desc = compile_and_load(root_file);
+    let ext = unsafe { load_descriptor_extensions(&root_file.with_extension("osdi"))? };
+
+    // all "shot" sources are driven by the same noise process, "thermal" is independent
+    let shot: Vec<_> = desc
+        .noise_sources()
+        .iter()
+        .enumerate()
+        .filter(|(_, src)| unsafe { osdi_str(src.name) } == "shot")
+        .map(|(i, _)| i as u32)
+        .collect();
+    assert_eq!(desc.noise_sources().len(), 4);
+    assert_eq!(shot.len(), 3);
+    let correlation = |source_1, source_2, coefficient| OsdiNoiseCorrelation {
+        source_1: shot[source_1],
+        source_2: shot[source_2],
+        coefficient,
+    };
+    assert_eq!(
+        ext[0].noise_correlations(),
+        &[correlation(0, 1, 1.0), correlation(0, 2, -1.0), correlation(1, 2, -1.0)]
+    );
+
+    // the cross-spectral density follows from the power spectral densities of both sources
+    const MFACTOR: f64 = 2.0;
+    const IB: f64 = 3e-6;
+    const IC: f64 = 5e-4;
+    const Q: f64 = 1.602176462e-19;
+    let model = desc.new_model();
+    model.set_param_by_id(0, MFACTOR)?;
+    model.set_param_by_id(1, IB)?;
+    model.set_param_by_id(2, IC)?;
+    model.process_params()?;
+    let mut instance = model.new_instance();
+    let mut sim = MockSimulation::new(&mut instance, desc.num_terminals(), 300.0)?;
+    sim.eval(&mut instance, EvalFlags::empty());
+    sim.load_noise(&instance, 1.0);
+    let cross_spectral_density = |correlation: &OsdiNoiseCorrelation| {
+        let pwr1 = sim.read_noise(correlation.source_1 as usize);
+        let pwr2 = sim.read_noise(correlation.source_2 as usize);
+        correlation.coefficient * (pwr1 * pwr2).abs().sqrt()
+    };
+    let [base_collector, base_voltage, collector_voltage] = match ext[0].noise_correlations() {
+        [a, b, c] => [a, b, c].map(cross_spectral_density),
+        correlations => unreachable!("{correlations:?}"),
+    };
+    let assert_rel_eq = |val: f64, expected: f64| {
+        assert!((val / expected - 1.0).abs() < 1e-12, "{val} != {expected}");
+    };
+    assert_rel_eq(base_collector, 2.0 * Q * MFACTOR * (IB * IC).sqrt());
+    assert_rel_eq(base_voltage, -2.0 * Q * IB * MFACTOR.sqrt());
+    assert_rel_eq(collector_voltage, -2.0 * Q * (IB * IC * MFACTOR).sqrt());
+
+    // sources with unique names are never correlated
+    let noise_file = openvaf_test_data("osdi").join("noise.va");
+    let noise_file: &Utf8Path = noise_file.as_path().try_into().unwrap();
+    compile_and_load(noise_file);
+    let ext = unsafe { load_descriptor_extensions(&noise_file.with_extension("osdi"))? };
+    assert!(ext[0].noise_correlations().is_empty());
+    Ok(())
+}
+
+fn test_eval_batch() -> Result<()> {
+    let root_file = openvaf_test_data("osdi").join("batch.va");
+    let root_file: &Utf8Path = root_file.as_path().try_into().unwrap();
+    let desc = compile_and_load(root_file);
+    let ext = unsafe { load_descriptor_extensions(&root_file.with_extension("osdi"))? };
+    assert_eq!(ext.len(), 1);
+
+    let model = batch_model(desc)?;
+    let (instances, mut sims): (Vec<_>, Vec<_>) = (0..5)
+        .map(|_| biased_batch_instance(desc, &model))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+
+    // all instances are evaluated with the sim info (and therefore the solution vector) of the
+    // first simulation
+    let flags = EvalFlags::CALC_RESIST_JACOBIAN | EvalFlags::CALC_RESIST_RESIDUAL;
+    let instance_refs: Vec<_> = instances.iter().collect();
+    let (ret_flags, instance_flags) = sims[0].eval_batch(&model, ext[0], &instance_refs, flags);
+    assert_eq!(ret_flags, EvalRetFlags::empty());
+    assert_eq!(instance_flags, vec![EvalRetFlags::empty(); instances.len()]);
+
+    let id = VD / R + IS * (f64::exp(VD / VT) - 1.0);
+    let gd = 1.0 / R + IS / VT * f64::exp(VD / VT);
+    for (instance, sim) in instances.iter().zip(&mut sims) {
+        sim.load_dae(instance);
+        assert_approx_eq!(sim.read_residual("a").0, id);
+        assert_approx_eq!(sim.read_residual("c").0, -id);
+        assert_approx_eq!(sim.read_jacobian("a", "a").0, gd);
+        assert_approx_eq!(sim.read_jacobian("a", "c").0, -gd);
+    }
+
+    // evaluating no instances is a noop
+    let (ret_flags, instance_flags) = sims[0].eval_batch(&model, ext[0], &[], flags);
+    assert_eq!(ret_flags, EvalRetFlags::empty());
+    assert!(instance_flags.is_empty());
+    Ok(())
+}
+
+fn test_modelcard() -> Result<()> {
+    let test_dir = openvaf_test_data("osdi");
+    let root_file = test_dir.join("batch.va");
+    let root_file: &Utf8Path = root_file.as_path().try_into().unwrap();
+    let modelcard = test_dir.join("batch_specialised.mc");
+    let modelcard: &Utf8Path = modelcard.as_path().try_into().unwrap();
+    let desc = compile_and_load_variant(root_file, Some("specialised"), |opts| {
+        opts.modelcard = Some(modelcard.to_path_buf())
+    });
+    // frozen parameters are still part of the interface
+    assert_eq!(desc.params().len(), 3);
+
+    // the frozen values (R and IS) are used without setting any parameter
+    let model = desc.new_model();
+    model.process_params()?;
+    let (mut instance, mut sim) = biased_batch_instance(desc, &model)?;
+    sim.eval(&mut instance, EvalFlags::empty());
+    sim.load_dae(&instance);
+    let id = VD / R + IS * (f64::exp(VD / VT) - 1.0);
+    assert_approx_eq!(sim.read_residual("a").0, id);
+
+    // setting a frozen parameter to its frozen value is allowed but any other value is rejected
+    let model = desc.new_model();
+    model.set_param_by_id(1, R)?;
+    model.process_params()?;
+    let model = desc.new_model();
+    model.set_param_by_id(1, 2.0 * R)?;
+    assert!(model.process_params().is_err());
+
+    // values outside of the parameter bounds are rejected at compile time
+    let (_tmp, out_dir) = temp_dir()?;
+    let invalid_modelcard = out_dir.join("invalid.mc");
+    fs::write(&invalid_modelcard, "r = -1k\n")?;
+    let lib_file = invalid_modelcard.with_extension("osdi");
+    let mut opts = default_opts(root_file, lib_file);
+    opts.modelcard = Some(invalid_modelcard);
+    let err = match openvaf::compile(&opts) {
+        Ok(_) => panic!("the out of bounds value in {} was accepted", opts.input),
+        Err(err) => err,
+    };
+    assert!(format!("{err:#}").contains("out of bounds"), "{err:?}");
+    Ok(())
+}
+
+fn test_sensitivity() -> Result<()> {
+    let root_file = openvaf_test_data("osdi").join("batch.va");
+    let root_file: &Utf8Path = root_file.as_path().try_into().unwrap();
+    let desc = compile_and_load_variant(root_file, Some("sensitivity"), |opts| {
+        opts.sensitivities = vec!["IS".to_owned(), "r".to_owned()]
+    });
+    let lib_file = root_file.with_file_name("batch_sensitivity.osdi");
+    let ext = unsafe { load_descriptor_extensions(&lib_file)? };
+    // the parameters are listed in the order of --sensitivity
+    assert_eq!(ext[0].sensitivity_params(), &[2, 1]);
+
+    let model = batch_model(desc)?;
+    let (instance, mut sim) = biased_batch_instance(desc, &model)?;
+
+    let (ret_flags, sens_resist, sens_react) =
+        sim.eval_sensitivity(&instance, ext[0], EvalFlags::CALC_RESIST_RESIDUAL);
+    assert_eq!(ret_flags, EvalRetFlags::empty());
+    let did_dis = f64::exp(VD / VT) - 1.0;
+    let did_dr = -VD / (R * R);
+    // sens[i * num_nodes + j] with the nodes (a, c)
+    assert_eq!(sens_resist.len(), 4);
+    assert_approx_eq!(sens_resist[0], did_dis);
+    assert_approx_eq!(sens_resist[1], -did_dis);
+    assert_approx_eq!(sens_resist[2], did_dr);
+    assert_approx_eq!(sens_resist[3], -did_dr);
+    assert_eq!(sens_react, vec![0.0; 4]);
+
+    // eval_sensitivity still performs a regular evaluation
+    sim.load_dae(&instance);
+    let id = VD / R + IS * did_dis;
+    assert_approx_eq!(sim.read_residual("a").0, id);
+
+    // libraries compiled without --sensitivity do not provide eval_sensitivity
+    let ext = unsafe { load_descriptor_extensions(&root_file.with_extension("osdi"))? };
+    assert!(ext[0].sensitivity_params().is_empty());
+    assert!(ext[0].eval_sensitivity.is_none());
+    Ok(())
+}
+
+fn test_osdi_0_4() -> Result<()> {
+    let root_file = openvaf_test_data("osdi").join("batch.va");
+    let root_file: &Utf8Path = root_file.as_path().try_into().unwrap();
+    // the OSDI 0.3 interface of the descriptor is unchanged
+    let desc = compile_and_load_variant(root_file, Some("osdi_0_4"), |opts| {
+        opts.osdi_version = OsdiVersion::V0_4
+    });
+    let desc_0_4 = desc.v0_4().unwrap();
+
+    // the natures and disciplines are shared by all modules of the library
+    let lib_file = root_file.with_file_name("batch_osdi_0_4.osdi");
+    let lib = unsafe { osdi_host::Library::load(&lib_file)? };
+    let (natures, attributes) = (lib.natures(), lib.attributes());
+    let nature_attr = |nature: u32, name: &str| {
+        let nature = &natures[nature as usize];
+        attributes[nature.attr_start as usize..][..nature.num_attr as usize]
+            .iter()
+            .find(|attr| unsafe { osdi_str(attr.name) } == name)
+            .unwrap()
+    };
+    assert_eq!(lib.disciplines().len(), 1);
+    let electrical = &lib.disciplines()[0];
+    unsafe {
+        assert_eq!(osdi_str(electrical.name), "electrical");
+        assert_eq!(electrical.domain, DOMAIN_NOT_GIVEN);
+        assert_eq!(osdi_str(natures[electrical.potential as usize].name), "Voltage");
+        assert_eq!(osdi_str(natures[electrical.flow as usize].name), "Current");
+        let units = nature_attr(electrical.potential, "units");
+        assert_eq!(units.value_type, ATTR_TYPE_STR);
+        assert_eq!(osdi_str(units.value.string), "V");
+        let abstol = nature_attr(electrical.flow, "abstol");
+        assert_eq!(abstol.value_type, ATTR_TYPE_REAL);
+        assert_eq!(abstol.value.real, 1e-12);
+    }
+    let charge = &natures[natures[electrical.flow as usize].idt as usize];
+    assert_eq!(unsafe { osdi_str(charge.name) }, "Charge");
+    assert_eq!(charge.ddt, electrical.flow);
+
+    // both nodes are electrical: the unknowns are potentials and the residuals flows
+    for (unknown, residual) in desc.unknown_natures().iter().zip(desc.residual_natures()) {
+        assert_eq!((unknown.ref_type, unknown.index), (NATREF_DISCIPLINE_POTENTIAL, 0));
+        assert_eq!((residual.ref_type, residual.index), (NATREF_DISCIPLINE_FLOW, 0));
+    }
+    let inputs: Vec<_> = desc
+        .inputs()
+        .iter()
+        .map(|pair| (desc.node_name(pair.node_1), desc.node_name(pair.node_2)))
+        .collect();
+    assert_eq!(inputs, [("a", "c")]);
+
+    let model = desc.new_model();
+    model.set_param_by_id(1, R)?;
+    assert_eq!(desc_0_4.given_flag_model(model.data(), 1), 1);
+    assert_eq!(desc_0_4.given_flag_model(model.data(), 2), 0);
+
+    let model = batch_model(desc)?;
+    let (mut instance, mut sim) = biased_batch_instance(desc, &model)?;
+    assert_eq!(desc_0_4.given_flag_instance(instance.data(), 0), 0);
+    sim.eval(&mut instance, EvalFlags::empty());
+
+    let gd = 1.0 / R + IS / VT * f64::exp(VD / VT);
+    assert_eq!(desc_0_4.num_resistive_jacobian_entries, 4);
+    assert_eq!(desc_0_4.num_reactive_jacobian_entries, 0);
+    let mut jacobian = vec![f64::NAN; 4];
+    desc_0_4.write_jacobian_array_resist(instance.data(), model.data(), jacobian.as_mut_ptr());
+    for (entry, val) in desc.jacobian_entries().iter().zip(jacobian) {
+        let expected = if entry.nodes.node_1 == entry.nodes.node_2 { gd } else { -gd };
+        assert_approx_eq!(val, expected);
+    }
+
+    desc_0_4.load_jacobian_with_offset_resist(instance.data(), model.data(), 0);
+    assert_approx_eq!(sim.read_jacobian("a", "a").0, gd);
+    assert_approx_eq!(sim.read_jacobian("a", "c").0, -gd);
+    Ok(())
+}
+
+/// The C backend emits portable C99 that behaves like the library compiled by LLVM.
+fn test_c_source() -> Result<()> {
+    if stdx::IS_CI && cfg!(windows) {
+        return Ok(());
+    }
+
+    let root_file = project_root().join("integration_tests").join("DIODE").join("diode.va");
+    let root_file: Utf8PathBuf = root_file.try_into().unwrap();
+    let (_tmp, dir) = temp_dir()?;
+
+    let llvm_lib = dir.join("diode_llvm.osdi");
+    openvaf::compile(&default_opts(&root_file, llvm_lib.clone()))?;
+    let c_lib = dir.join("diode_c.osdi");
+    let mut opts = default_opts(&root_file, c_lib.clone());
+    opts.emit = vec![EmitKind::CSource];
+    openvaf::compile(&opts)?;
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+    let status = Command::new(cc)
+        .args(["-std=c99", "-pedantic-errors", "-O1", "-shared", "-fPIC", "-o"])
+        .args([c_lib.as_str(), c_lib.with_extension("c").as_str(), "-lm"])
+        .status()?;
+    assert!(status.success(), "failed to compile the generated C source");
+
+    let eval = |lib_file: &Utf8Path| -> Result<_> {
+        let desc = unsafe { load_osdi_lib(lib_file)? }[0];
+        let model = desc.new_model();
+        model.set_param("rs", 10.0)?;
+        model.set_param("cj0", 1e-12)?;
+        model.set_param("rth", 100.0)?;
+        model.process_params()?;
+        let mut instance = model.new_instance();
+        let mut sim = MockSimulation::new(&mut instance, desc.num_terminals(), 300.0)?;
+        sim.set_voltage("A", 0.7);
+        sim.set_voltage("dT", 1.0);
+        let flags = sim.eval(&mut instance, EvalFlags::empty());
+        sim.load_dae(&instance);
+        sim.load_noise(&instance, 1e3);
+        let jacobian: Vec<_> = sim
+            .jacobian_info
+            .iter()
+            .map(|(hi, lo)| sim.read_jacobian(sim.nodes[*hi as usize], sim.nodes[*lo as usize]))
+            .collect();
+        Ok((flags, sim.residual_resist, sim.residual_react, jacobian, sim.noise_dense))
+    };
+    let (llvm_flags, llvm_resist, llvm_react, llvm_jacobian, llvm_noise) = eval(&llvm_lib)?;
+    let (c_flags, c_resist, c_react, c_jacobian, c_noise) = eval(&c_lib)?;
+
+    assert_eq!(llvm_flags, c_flags);
+    assert!(llvm_resist.iter().any(|&val| val != 0.0));
+    let assert_close = |llvm: &[f64], c: &[f64]| {
+        assert_eq!(llvm.len(), c.len());
+        for (llvm, c) in llvm.iter().zip(c) {
+            assert!((llvm - c).abs() <= 1e-12 * llvm.abs().max(1e-12), "{llvm} != {c}");
+        }
+    };
+    assert_close(&llvm_resist, &c_resist);
+    assert_close(&llvm_react, &c_react);
+    let (llvm_jacobian_resist, llvm_jacobian_react): (Vec<_>, Vec<_>) =
+        llvm_jacobian.into_iter().unzip();
+    let (c_jacobian_resist, c_jacobian_react): (Vec<_>, Vec<_>) = c_jacobian.into_iter().unzip();
+    assert_close(&llvm_jacobian_resist, &c_jacobian_resist);
+    assert_close(&llvm_jacobian_react, &c_jacobian_react);
+    assert_close(&llvm_noise, &c_noise);
+    Ok(())
+}
+
+fn test_write_errors() -> Result<()> {
+    if stdx::IS_CI && cfg!(windows) {
+        return Ok(());
+    }
+
+    let root_file = project_root().join("integration_tests").join("DIODE").join("diode.va");
+    let root_file: Utf8PathBuf = root_file.try_into().unwrap();
+    let (_tmp, dir) = temp_dir()?;
+    // the outputs can not be written because their directory is a file
+    let not_a_dir = dir.join("not_a_dir");
+    fs::write(&not_a_dir, "")?;
+
+    for emit in [EmitKind::LlvmIr, EmitKind::LlvmBc, EmitKind::Asm, EmitKind::Obj, EmitKind::Mir] {
+        let mut opts = default_opts(&root_file, not_a_dir.join("diode.osdi"));
+        opts.emit = vec![emit];
+        match openvaf::compile(&opts) {
+            Ok(_) => panic!("writing {emit:?} into {not_a_dir} succeeded"),
+            Err(err) => assert!(format!("{err:#}").contains("not_a_dir"), "{err:#}"),
+        }
+    }
+    Ok(())
+}
+
+fn test_coverage() -> Result<()> {
+    if stdx::IS_CI && cfg!(windows) {
+        return Ok(());
+    }
+    const NUM_EVALS: u64 = 3;
+
+    let root_file = project_root().join("integration_tests").join("DIODE").join("diode.va");
+    let root_file: Utf8PathBuf = root_file.try_into().unwrap();
+    let (_tmp, dir) = temp_dir()?;
+    let lib_file = dir.join("diode.osdi");
+    let mut opts = default_opts(&root_file, lib_file.clone());
+    opts.coverage = true;
+    openvaf::compile(&opts)?;
+
+    let desc = unsafe { load_osdi_lib(&lib_file)? }[0];
+    let model = desc.new_model();
+    model.process_params()?;
+    let mut instance = model.new_instance();
+    let mut sim = MockSimulation::new(&mut instance, desc.num_terminals(), 300.0)?;
+    sim.set_voltage("A", 0.7);
+    for _ in 0..NUM_EVALS {
+        sim.eval(&mut instance, EvalFlags::empty());
+    }
+
+    // dump the counters like a simulator would
+    let counters = unsafe {
+        let lib = libloading::Library::new(&lib_file)?;
+        let counters: *const u64 = *lib.get(b"OSDI_COVERAGE_COUNTERS\0")?;
+        let len: &u32 = *lib.get(b"OSDI_COVERAGE_LEN\0")?;
+        slice::from_raw_parts(counters, *len as usize).to_vec()
+    };
+    let map_file = lib_file.with_extension("covmap");
+    let map = CoverageMap::parse(&fs::read_to_string(&map_file)?).unwrap();
+    assert_eq!(counters.len(), map.num_counters as usize);
+
+    // the entry block of eval is executed once per call
+    let entry = map
+        .regions
+        .iter()
+        .find(|region| region.function == "eval" && region.kind == RegionKind::Block)
+        .unwrap();
+    assert_eq!(counters[entry.counter as usize], NUM_EVALS);
+    // setup_model ran once and ends in an (empty) exit block, which is laid out last
+    let exit = map
+        .regions
+        .iter()
+        .rev()
+        .find(|region| region.function == "setup_model" && region.kind == RegionKind::Block)
+        .unwrap();
+    assert_eq!(counters[exit.counter as usize], 1);
+
+    let dump = dir.join("diode.counters");
+    let vals: Vec<_> = counters.iter().map(u64::to_string).collect();
+    fs::write(&dump, vals.join("\n"))?;
+    let lcov = openvaf::coverage::report(&map_file, &[&dump], ReportFormat::Lcov)?;
+    if let Some(pos) = entry.pos {
+        assert!(lcov.contains(&format!("DA:{},{NUM_EVALS}\n", pos.line)), "{lcov}");
+    }
+    let total = |prefix: &str| -> usize {
+        let vals = lcov.lines().filter_map(|line| line.strip_prefix(prefix));
+        vals.map(|val| val.parse::<usize>().unwrap()).sum()
+    };
+    let (lines_hit, lines_found) = (total("LH:"), total("LF:"));
+    // the limiting and breakdown branches of the diode are not reached at 0.7V
+    assert!(0 < lines_hit && lines_hit < lines_found, "{lcov}");
+    Ok(())
+}
+
+fn test_float_sanitizer() -> Result<()> {
+    let (_tmp, dir) = temp_dir()?;
+    let root_file = dir.join("nan.va");
+    fs::write(
+        &root_file,
+        r#"`include "disciplines.vams"
+module nan(a, c);
+    inout a, c;
+    electrical a, c;
+    (*type="instance"*) parameter real scale = 1.0;
+    parameter real r = 1.0;
+    parameter integer n = 1;
+    parameter real unused = 1.0;
+    analog I(a, c) <+ n * scale * sqrt(V(a, c)) / r;
+endmodule
+"#,
+    )?;
+    let mut opts = default_opts(&root_file, dir.join("nan.osdi"));
+    opts.sanitize_float = FloatSanitizer::Report;
+    // no objects are written (or removed)
+    opts.emit = vec![EmitKind::LlvmIr];
+    openvaf::compile(&opts)?;
+
+    let mut ir = String::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        if name.starts_with("nan.eval_") && name.ends_with(".ll") {
+            ir = fs::read_to_string(&path)?;
+        }
+    }
+    // the parameters the residual depends on are printed with their values
+    let msg = "nan: non-finite value %g in the resistive residual of a";
+    let line = ir.lines().find(|line| line.contains(msg)).expect("missing sanitizer message");
+    assert!(
+        line.contains("(instance parameters: scale=%g; model parameters: n=%d, r=%g)"),
+        "{line}"
+    );
+    assert!(!line.contains("unused"), "{line}");
+    Ok(())
+}
+
+fn test_describe() -> Result<()> {
+    let root_file = project_root().join("integration_tests").join("DIODE").join("diode.va");
+    let root_file: Utf8PathBuf = root_file.try_into().unwrap();
+    let opts = default_opts(&root_file, root_file.with_extension("osdi"));
+    let modules = openvaf::describe::describe_modules(&opts)?.expect("compilation failed");
+    let json = openvaf::describe::render(&modules, DescribeFormat::Json);
+    expect_file![openvaf_test_data("osdi").join("diode_describe.json")].assert_eq(&json);
+    Ok(())
+}
+
+fn test_reproducer() -> Result<()> {
+    let root_file = project_root().join("integration_tests").join("DIODE").join("diode.va");
+    let root_file: Utf8PathBuf = root_file.try_into().unwrap();
+    let (_tmp, dir) = temp_dir()?;
+    let archive = dir.join("diode.tar");
+    let modelcard = dir.join("diode.mc");
+    // modelcards are matched case-insensitively
+    fs::write(&modelcard, "IS=10f\n")?;
+    let mut opts = default_opts(&root_file, root_file.with_extension("osdi"));
+    opts.modelcard = Some(modelcard);
+    opts.modules = vec!["diode_va".to_owned()];
+    opts.defines = vec!["DIODE".to_owned()];
+    openvaf::reproducer::write_reproducer(&opts, "test", true, &archive)?;
+    tar::Archive::new(File::open(&archive)?).unpack(&dir)?;
+
+    assert_eq!(fs::read_to_string(dir.join("VERSION"))?, "test\n");
+    // opts.txt is an openvaf invocation that refers to the files in the archive
+    let opts = fs::read_to_string(dir.join("opts.txt"))?;
+    assert!(opts.starts_with("openvaf ") && opts.ends_with(" root0.va\n"), "{opts}");
+    assert!(
+        opts.contains(" --modelcard modelcard.mc ") && opts.contains(" -o root0.osdi "),
+        "{opts}"
+    );
+    assert!(opts.contains(" --module anon") && !opts.contains("diode_va"), "{opts}");
+    assert!(!opts.contains("DIODE") && !opts.contains("diode.mc"), "{opts}");
+    let source = fs::read_to_string(dir.join("root0.va"))?;
+    assert!(!source.contains("diode_va") && !source.contains("`include"));
+    let modelcard = fs::read_to_string(dir.join("modelcard.mc"))?;
+    assert!(modelcard.starts_with("anon") && modelcard.ends_with("=10f\n"), "{modelcard}");
+
+    // the anonymised source still compiles to the same interface
+    let desc = compile_and_load_variant(&dir.join("root0.va"), None, |opts| {
+        opts.modelcard = Some(dir.join("modelcard.mc"));
+    });
+    assert!(desc.name().starts_with("anon"));
+    assert_eq!(desc.num_terminals(), 3);
+    Ok(())
+}
+
 harness! {
     // TODO: run this in CI, somehow this test is flakey tough regarding the linker invocation (and really slow)
     Test::from_dir("integration", &integration_test, &ignore_dev_tests, &project_root().join("integration_tests")),
-    [Test::new("$limit", &test_limit),Test::new("noise", &test_noise)]
+    [
+        Test::new("$limit", &test_limit),
+        Test::new("noise", &test_noise),
+        Test::new("correlated_noise", &test_correlated_noise),
+        host_test("eval_batch", &test_eval_batch),
+        host_test("modelcard", &test_modelcard),
+        host_test("sensitivity", &test_sensitivity),
+        host_test("osdi_0_4", &test_osdi_0_4),
+        Test::new("c_source", &test_c_source),
+        Test::new("write_errors", &test_write_errors),
+        Test::new("coverage", &test_coverage),
+        Test::new("float_sanitizer", &test_float_sanitizer),
+        Test::new("describe", &test_describe),
+        host_test("reproducer", &test_reproducer),
+    ]
 }
@@ -1,16 +1,17 @@
 use std::cell::UnsafeCell;
+use std::ffi::c_void;
 use std::mem::swap;
 use std::ptr;
 
 use anyhow::Result;
 use indexmap::IndexSet;
-use libc::c_void;
-use stdx::iter::zip;
+use osdi_host::osdi_0_3::OsdiSimParas;
+use osdi_host::SimInfo;
 
 pub const ALPHA: f64 = 0.172;
 
 use crate::load::{
-    osdi_str, EvalFlags, EvalRetFlags, OsdiInstance, OsdiModel, OsdiSimInfo, OsdiSimParas,
+    EvalFlags, EvalRetFlags, Instance, Model, OsdiDescriptorExtensions, OsdiSimInfo,
 };
 
 #[derive(Debug, Default)]
@@ -27,8 +28,15 @@ pub struct MockSimulation {
     pub noise_dense: Vec<f64>,
 }
 impl MockSimulation {
-    fn new() -> MockSimulation {
-        MockSimulation {
+    /// Sets up `instance` and creates a simulation that only contains this instance.
+    pub fn new(
+        instance: &mut Instance,
+        connected_terminals: u32,
+        temp: f64,
+    ) -> Result<MockSimulation> {
+        let mut internal_nodes = instance.process_params(temp, connected_terminals)?;
+        let descriptor = instance.descriptor();
+        let mut sim = MockSimulation {
             nodes: {
                 let mut set = IndexSet::new();
                 set.insert("gnd");
@@ -47,7 +55,54 @@ impl MockSimulation {
             state_1: Vec::new(),
             state_2: Vec::new(),
             noise_dense: Vec::new(),
+        };
+        // create internal nodes
+        let terminals: Vec<_> = (0..connected_terminals)
+            .map(|node| sim.register_node(descriptor.node_name(node)))
+            .collect();
+
+        for node_idx in &mut internal_nodes {
+            *node_idx = sim.register_node(descriptor.node_name(*node_idx));
+        }
+
+        instance.map_nodes(|idx| {
+            if let Some(&terminal) = terminals.get(idx as usize) {
+                terminal
+            } else if idx == u32::MAX {
+                0
+            } else {
+                internal_nodes[idx as usize - terminals.len()]
+            }
+        });
+
+        // create jacobian
+        let node_mapping = instance.node_mapping();
+        for entry in descriptor.jacobian_entries() {
+            let column = node_mapping[entry.nodes.node_1 as usize];
+            let row = node_mapping[entry.nodes.node_2 as usize];
+            sim.register_jacobian_entry(row, column);
+        }
+        sim.build_jacobian();
+
+        // populate matrix ptrs
+        for (i, entry) in descriptor.jacobian_entries().iter().enumerate() {
+            let node_mapping = instance.node_mapping();
+            let column = node_mapping[entry.nodes.node_1 as usize];
+            let row = node_mapping[entry.nodes.node_2 as usize];
+            let dst = sim.get_jacobian_entry(row, column);
+            // SAFETY: the matrix is leaked and therefore outlives the instance
+            unsafe {
+                instance.bind_jacobian(
+                    i,
+                    sim.jacobian_resist[dst].get(),
+                    sim.jacobian_react[dst].get(),
+                )
+            };
         }
+        sim.state_1.resize(descriptor.num_states() as usize, 0.0);
+        sim.state_2.resize(descriptor.num_states() as usize, 0.0);
+        sim.noise_dense.resize(descriptor.noise_sources().len(), 0.0);
+        Ok(sim)
     }
 
     fn register_node(&mut self, name: &'static str) -> u32 {
@@ -117,141 +172,107 @@ impl MockSimulation {
         swap(&mut self.state_1, &mut self.state_2);
         self.clear();
     }
-}
-
-impl OsdiInstance {
-    pub(super) fn mock_simulation(
-        &mut self,
-        model: &OsdiModel,
-        connected_terminals: u32,
-        temp: f64,
-    ) -> Result<MockSimulation> {
-        let mut internal_nodes = self.process_params(model, connected_terminals, temp)?;
-        let mut sim = MockSimulation::new();
-        // create internal nodes
-        let terminals: Vec<_> = self.descriptor.nodes()[..connected_terminals as usize]
-            .iter()
-            .map(|node| unsafe { sim.register_node(osdi_str(node.name)) })
-            .collect();
-
-        for node_idx in &mut internal_nodes {
-            let node = &self.descriptor.nodes()[*node_idx as usize];
-            *node_idx = unsafe { sim.register_node(osdi_str(node.name)) };
-        }
-
-        let node_mapping = self.node_mapping();
-        for node in node_mapping {
-            let idx = node.get();
-            if let Some(&terminal) = terminals.get(idx as usize) {
-                node.set(terminal)
-            } else if idx == u32::MAX {
-                node.set(0)
-            } else {
-                node.set(internal_nodes[idx as usize - terminals.len()])
-            }
-        }
-
-        // create jacobian
-        for entry in self.descriptor.matrix_entries() {
-            let column = node_mapping[entry.nodes.node_1 as usize].get();
-            let row = node_mapping[entry.nodes.node_2 as usize].get();
-            sim.register_jacobian_entry(row, column);
-        }
-        sim.build_jacobian();
 
-        // populate matrix ptrs
-        for (entry, ptr_resist) in zip(self.descriptor.matrix_entries(), self.matrix_ptrs_resist())
-        {
-            let column = node_mapping[entry.nodes.node_1 as usize].get();
-            let row = node_mapping[entry.nodes.node_2 as usize].get();
-            let i = sim.get_jacobian_entry(row, column);
-            ptr_resist.set(sim.jacobian_resist[i].get());
-            if entry.react_ptr_off != u32::MAX {
-                let data = self.data as *mut u8;
-                unsafe {
-                    let react_ptr_ptr: *mut *mut f64 =
-                        data.add(entry.react_ptr_off as usize).cast();
-                    *react_ptr_ptr = sim.jacobian_react[i].get();
-                }
-            }
+    fn raw_sim_info(&mut self, flags: EvalFlags) -> OsdiSimInfo {
+        let sim_params = OsdiSimParas {
+            names: &mut ptr::null_mut(),
+            vals: ptr::null_mut(),
+            names_str: &mut ptr::null_mut(),
+            vals_str: ptr::null_mut(),
+        };
+        OsdiSimInfo {
+            paras: sim_params,
+            abstime: 0.0,
+            prev_solve: self.solve.as_ptr() as *mut f64,
+            prev_state: self.state_1.as_mut_ptr(),
+            next_state: self.state_2.as_mut_ptr(),
+            flags: flags.bits(),
         }
-        sim.state_1.resize(self.descriptor.num_states as usize, 0.0);
-        sim.state_2.resize(self.descriptor.num_states as usize, 0.0);
-        sim.noise_dense.resize(self.descriptor.num_noise_src as usize, 0.0);
-        Ok(sim)
     }
 
-    pub fn load_spice(&self, model: &OsdiModel, sim: &mut MockSimulation) {
-        self.descriptor.load_spice_rhs_tran(
-            self.data,
-            model.data,
-            sim.residual_resist.as_mut_ptr(),
-            sim.solve.as_mut_ptr(),
-            ALPHA,
-        );
-        self.descriptor.load_jacobian_tran(self.data, self.data, ALPHA);
+    pub fn load_spice(&mut self, instance: &Instance) {
+        instance.load_spice_rhs_tran(&mut self.residual_resist, &self.solve, ALPHA);
+        // SAFETY: all entries were bound in `new`
+        unsafe { instance.load_jacobian_tran(ALPHA) };
     }
 
-    pub fn load_noise(&self, model: &OsdiModel, sim: &mut MockSimulation, freq: f64) {
-        self.descriptor.load_noise(self.data, model.data, freq, sim.noise_dense.as_mut_ptr())
+    pub fn load_noise(&mut self, instance: &Instance, freq: f64) {
+        instance.load_noise(freq, &mut self.noise_dense)
     }
 
-    pub fn load_dae(&self, model: &OsdiModel, sim: &mut MockSimulation) {
-        self.descriptor.load_residual_resist(
-            self.data,
-            model.data,
-            sim.residual_resist.as_mut_ptr(),
-        );
-        self.descriptor.load_limit_rhs_resist(
-            self.data,
-            model.data,
-            sim.residual_resist.as_mut_ptr(),
-        );
-
-        self.descriptor.load_residual_react(self.data, model.data, sim.residual_react.as_mut_ptr());
-        self.descriptor.load_limit_rhs_react(
-            self.data,
-            model.data,
-            sim.residual_react.as_mut_ptr(),
-        );
-
-        self.descriptor.load_jacobian_resist(self.data, model.data);
-        self.descriptor.load_jacobian_react(self.data, model.data, 1.0);
+    pub fn load_dae(&mut self, instance: &Instance) {
+        instance.load_residual_resist(&mut self.residual_resist);
+        instance.load_limit_rhs_resist(&mut self.residual_resist);
+        instance.load_residual_react(&mut self.residual_react);
+        instance.load_limit_rhs_react(&mut self.residual_react);
+        // SAFETY: all entries were bound in `new`
+        unsafe {
+            instance.load_jacobian_resist();
+            instance.load_jacobian_react(1.0);
+        }
     }
-    pub fn eval(
-        &self,
-        model: &OsdiModel,
-        sim: &mut MockSimulation,
-        mut flags: EvalFlags,
-    ) -> EvalRetFlags {
+
+    pub fn eval(&mut self, instance: &mut Instance, mut flags: EvalFlags) -> EvalRetFlags {
         // always calculate everything
-        flags |= EvalFlags::CALC_RESIST_JACOBIAN
-            | EvalFlags::CALC_RESIST_RESIDUAL
-            | EvalFlags::CALC_RESIST_LIM_RHS
-            | EvalFlags::CALC_REACT_JACOBIAN
-            | EvalFlags::CALC_REACT_RESIDUAL
-            | EvalFlags::CALC_REACT_LIM_RHS
-            | EvalFlags::CALC_NOISE;
-        let sim_params = OsdiSimParas {
-            names: &mut ptr::null_mut(),
-            vals: ptr::null_mut(),
-            names_str: &mut ptr::null_mut(),
-            vals_str: ptr::null_mut(),
-        };
-        let mut sim_info = OsdiSimInfo {
-            paras: sim_params,
+        flags |= EvalFlags::CALC_RESIST | EvalFlags::CALC_REACT | EvalFlags::CALC_NOISE;
+        let mut sim_info = SimInfo {
             abstime: 0.0,
-            prev_solve: sim.solve.as_ptr() as *mut f64,
-            prev_state: sim.state_1.as_mut_ptr(),
-            next_state: sim.state_2.as_mut_ptr(),
-            flags: flags.bits(),
+            prev_solve: &self.solve,
+            prev_state: &self.state_1,
+            next_state: &mut self.state_2,
+            flags,
         };
-        let flags = self.descriptor.eval(
-            b"foo\0".as_ptr() as *mut c_void,
-            self.data,
-            model.data,
+        instance.eval(&mut sim_info)
+    }
+
+    /// Evaluates `instance` with `eval_sensitivity`. Returns the flags and the resistive
+    /// and reactive derivatives of the residual by the sensitivity parameters.
+    pub fn eval_sensitivity(
+        &mut self,
+        instance: &Instance,
+        ext: &OsdiDescriptorExtensions,
+        flags: EvalFlags,
+    ) -> (EvalRetFlags, Vec<f64>, Vec<f64>) {
+        let mut sim_info = self.raw_sim_info(flags);
+        let len = ext.num_sensitivity_params as usize * instance.descriptor().nodes().len();
+        // NaN ensures that every entry is written
+        let mut sens_resist = vec![f64::NAN; len];
+        let mut sens_react = vec![f64::NAN; len];
+        let eval_sensitivity = ext.eval_sensitivity.expect("eval_sensitivity is missing");
+        let flags = eval_sensitivity(
+            instance.handle(),
+            instance.data(),
+            instance.model().data(),
+            &mut sim_info,
+            sens_resist.as_mut_ptr(),
+            sens_react.as_mut_ptr(),
+        );
+        (EvalRetFlags::from_bits(flags).unwrap(), sens_resist, sens_react)
+    }
+
+    /// Evaluates all `instances` of `model` with a single call to `eval_batch`. Returns the
+    /// combined flags and the flags of each instance.
+    pub fn eval_batch(
+        &mut self,
+        model: &Model,
+        ext: &OsdiDescriptorExtensions,
+        instances: &[&Instance],
+        flags: EvalFlags,
+    ) -> (EvalRetFlags, Vec<EvalRetFlags>) {
+        let mut sim_info = self.raw_sim_info(flags);
+        let mut handles: Vec<*mut c_void> = instances.iter().map(|inst| inst.handle()).collect();
+        let mut data: Vec<_> = instances.iter().map(|inst| inst.data()).collect();
+        let mut ret_flags = vec![u32::MAX; instances.len()];
+        let flags = (ext.eval_batch)(
+            handles.as_mut_ptr(),
+            data.as_mut_ptr(),
+            model.data(),
             &mut sim_info,
+            instances.len() as u32,
+            ret_flags.as_mut_ptr(),
         );
-        EvalRetFlags::from_bits(flags).unwrap()
+        let ret_flags =
+            ret_flags.into_iter().map(|flags| EvalRetFlags::from_bits(flags).unwrap()).collect();
+        (EvalRetFlags::from_bits(flags).unwrap(), ret_flags)
     }
 }
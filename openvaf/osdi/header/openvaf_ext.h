@@ -0,0 +1,66 @@
+#pragma once
+
+/* OpenVAF specific extensions of the OSDI interface.
+ *
+ * Libraries compiled by OpenVAF export the array
+ *
+ *     const OsdiDescriptorExtensions *OSDI_DESCRIPTOR_EXTENSIONS[OSDI_NUM_DESCRIPTORS];
+ *
+ * next to OSDI_DESCRIPTORS: the i-th entry extends the i-th descriptor. Simulators that do
+ * not know about the extensions can simply ignore the symbol.
+ *
+ * New fields are only ever appended to OsdiDescriptorExtensions. A field is only present
+ * if it lies within the first `size` bytes of the struct, so simulators must check `size`
+ * before accessing a field (see OSDI_EXT_HAS_FIELD).
+ */
+
+#include "osdi_0_3.h"
+
+#include <stddef.h>
+
+#define OSDI_EXT_HAS_FIELD(ext, field)                                                   \
+  ((ext)->size >= offsetof(OsdiDescriptorExtensions, field) + sizeof((ext)->field))
+
+/* Two noise sources (indices into the noise_sources array of the descriptor) that are driven
+ * by the same noise process. The cross-spectral density of such a pair is
+ * coefficient * sqrt(|S_1 * S_2|) where S_1 and S_2 are the power spectral densities computed
+ * by `load_noise`. Both are oriented like the node pairs of the sources, so the coefficient is
+ * -1 if the noise process enters the two sources with opposite signs and 1 otherwise. */
+typedef struct OsdiNoiseCorrelation {
+  uint32_t source_1;
+  uint32_t source_2;
+  double coefficient;
+} OsdiNoiseCorrelation;
+
+typedef struct OsdiDescriptorExtensions {
+  /* sizeof(OsdiDescriptorExtensions) of the library */
+  uint32_t size;
+
+  /* Equivalent to calling `eval` for the first `num_instances` entries of `handles` and
+   * `instances` (which must all belong to `model`). The flags returned for instance i are
+   * stored in `ret_flags[i]`, the bitwise or of all flags is returned. The instances are
+   * evaluated one after another (there is no SIMD vectorization across instances), the batch
+   * only saves the simulator an indirect call per instance. */
+  uint32_t (*eval_batch)(void **handles, void **instances, void *model, OsdiSimInfo *info,
+                         uint32_t num_instances, uint32_t *ret_flags);
+
+  /* The parameters (indices into the param_opvar array of the descriptor) selected with
+   * --sensitivity. */
+  uint32_t num_sensitivity_params;
+  const uint32_t *sensitivity_params;
+
+  /* Equivalent to `eval` but additionally stores the derivatives of the residual by the
+   * sensitivity parameters (dR/dp) in `sens_resist` and `sens_react`. Both arrays hold
+   * num_sensitivity_params * num_nodes entries: the derivative of the residual of node j
+   * (indexed like the nodes of the descriptor) by the i-th sensitivity parameter is stored at
+   * index i * num_nodes + j. All entries are written, independent of the flags in `info`.
+   * NULL if no sensitivity parameters were selected. */
+  uint32_t (*eval_sensitivity)(void *handle, void *inst, void *model, OsdiSimInfo *info,
+                               double *sens_resist, double *sens_react);
+
+  /* The nonzero off-diagonal entries of the noise correlation matrix: identically named noise
+   * sources (for example shot noise contributed to several branches) are correlated. Each pair
+   * is listed once with source_1 < source_2, all other sources are uncorrelated. */
+  uint32_t num_noise_correlations;
+  const OsdiNoiseCorrelation *noise_correlations;
+} OsdiDescriptorExtensions;
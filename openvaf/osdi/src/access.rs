@@ -1,8 +1,8 @@
 use llvm::IntPredicate::IntNE;
 use llvm::{
     LLVMAddCase, LLVMAppendBasicBlockInContext, LLVMBuildAnd, LLVMBuildBr, LLVMBuildCondBr,
-    LLVMBuildICmp, LLVMBuildRet, LLVMBuildSwitch, LLVMCreateBuilderInContext, LLVMDisposeBuilder,
-    LLVMGetParam, LLVMPositionBuilderAtEnd, UNNAMED,
+    LLVMBuildICmp, LLVMBuildIntCast2, LLVMBuildRet, LLVMBuildSwitch, LLVMCreateBuilderInContext,
+    LLVMDisposeBuilder, LLVMGetParam, LLVMPositionBuilderAtEnd, UNNAMED,
 };
 
 use crate::compilation_unit::OsdiCompilationUnit;
@@ -155,4 +155,84 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
 
         llfunc
     }
+
+    /// `given_flag_model(model, id)` of OSDI 0.4: returns 1 if the parameter `id` was set on
+    /// `model` and 0 otherwise (also for unknown ids and operating point variables).
+    pub fn given_flag_model(&self) -> &'ll llvm::Value {
+        let OsdiCompilationUnit { inst_data, model_data, cx, module, .. } = self;
+        let fun_ty = cx.ty_func(&[cx.ty_ptr(), cx.ty_int()], cx.ty_int());
+        let name = &format!("given_flag_model_{}", module.sym);
+        let llfunc = cx.declare_int_c_fn(name, fun_ty);
+
+        unsafe {
+            let entry = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+            let not_given = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+            let llbuilder = LLVMCreateBuilderInContext(cx.llcx);
+
+            LLVMPositionBuilderAtEnd(llbuilder, entry);
+            let model = LLVMGetParam(llfunc, 0);
+            let param_id = LLVMGetParam(llfunc, 1);
+            let num_params = inst_data.params.len() + model_data.params.len();
+            let switch = LLVMBuildSwitch(llbuilder, param_id, not_given, num_params as u32);
+
+            for param_idx in 0..num_params {
+                let bb = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+                LLVMPositionBuilderAtEnd(llbuilder, bb);
+                LLVMAddCase(switch, cx.const_unsigned_int(param_idx as u32), bb);
+
+                // instance parameters are followed by the model parameters (see access)
+                let given = match param_idx.checked_sub(inst_data.params.len()) {
+                    Some(pos) => model_data.is_nth_param_given(cx, pos as u32, model, llbuilder),
+                    None => {
+                        model_data.is_nth_inst_param_given(cx, param_idx as u32, model, llbuilder)
+                    }
+                };
+                let given = LLVMBuildIntCast2(llbuilder, given, cx.ty_int(), llvm::False, UNNAMED);
+                LLVMBuildRet(llbuilder, given);
+            }
+
+            LLVMPositionBuilderAtEnd(llbuilder, not_given);
+            LLVMBuildRet(llbuilder, cx.const_unsigned_int(0));
+            LLVMDisposeBuilder(llbuilder);
+        }
+
+        llfunc
+    }
+
+    /// `given_flag_instance(inst, id)` of OSDI 0.4: returns 1 if the instance parameter `id`
+    /// was set on `inst` and 0 otherwise.
+    pub fn given_flag_instance(&self) -> &'ll llvm::Value {
+        let OsdiCompilationUnit { inst_data, cx, module, .. } = self;
+        let fun_ty = cx.ty_func(&[cx.ty_ptr(), cx.ty_int()], cx.ty_int());
+        let name = &format!("given_flag_instance_{}", module.sym);
+        let llfunc = cx.declare_int_c_fn(name, fun_ty);
+
+        unsafe {
+            let entry = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+            let not_given = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+            let llbuilder = LLVMCreateBuilderInContext(cx.llcx);
+
+            LLVMPositionBuilderAtEnd(llbuilder, entry);
+            let inst = LLVMGetParam(llfunc, 0);
+            let param_id = LLVMGetParam(llfunc, 1);
+            let switch =
+                LLVMBuildSwitch(llbuilder, param_id, not_given, inst_data.params.len() as u32);
+
+            for param_idx in 0..inst_data.params.len() {
+                let bb = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+                LLVMPositionBuilderAtEnd(llbuilder, bb);
+                LLVMAddCase(switch, cx.const_unsigned_int(param_idx as u32), bb);
+
+                let given = inst_data.is_nth_param_given(cx, param_idx as u32, inst, llbuilder);
+                let given = LLVMBuildIntCast2(llbuilder, given, cx.ty_int(), llvm::False, UNNAMED);
+                LLVMBuildRet(llbuilder, given);
+            }
+
+            LLVMPositionBuilderAtEnd(llbuilder, not_given);
+            LLVMBuildRet(llbuilder, cx.const_unsigned_int(0));
+            LLVMDisposeBuilder(llbuilder);
+        }
+
+        llfunc
+    }
 }
@@ -12,13 +12,13 @@ type Word = u32;
 const WORD_BYTES: u32 = size_of::<Word>() as u32;
 const WORD_BITS: u32 = WORD_BYTES * 8;
 
-fn word_index_and_mask(pos: u32) -> (u32, u32) {
+pub fn word_index_and_mask(pos: u32) -> (u32, u32) {
     let word_index = pos / WORD_BITS;
     let mask = 1 << (pos % WORD_BITS);
     (word_index, mask)
 }
 
-fn word_cnt(len: u32) -> u32 {
+pub fn word_cnt(len: u32) -> u32 {
     (len + WORD_BITS - 1) / WORD_BITS
 }
 
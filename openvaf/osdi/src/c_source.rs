@@ -0,0 +1,752 @@
+//! Emits the compiled modules as a single C99 translation unit that implements the OSDI
+//! interface. The generated code mirrors the functions the LLVM backend produces, the layout of
+//! the instance and model data is determined by the C compiler instead (all offsets in the
+//! descriptors are computed with `offsetof`).
+
+use std::ffi::CString;
+use std::fmt::{self, Display, Write};
+
+use hir::{CompilationDB, Type};
+use hir_lower::fmt::{DisplayKind, FmtArg, FmtArgKind};
+use hir_lower::{CallBackKind, HirInterner, ParamKind};
+use lasso::Rodeo;
+use llvm::{LLVMDisposeTargetData, OptLevel};
+use mir::FuncRef;
+use mir_llvm::LLVMBackend;
+use sim_back::dae::NoiseSourceKind;
+use sim_back::ModuleInfo;
+use typed_index_collections::TiVec;
+use typed_indexmap::TiSet;
+
+use crate::bitfield::{word_cnt, word_index_and_mask};
+use crate::compilation_unit::{new_codegen, OsdiCompilationUnit, OsdiModule};
+use crate::inst_data::{EvalOutput, EvalOutputSlot, OsdiInstanceParam};
+use crate::load::JacobianLoadType;
+use crate::metadata::osdi_0_3::OsdiTys;
+use crate::metadata::{sim_unknown_info, OsdiLimFunction};
+use crate::{osdi_modules, ty_len, LoweredModules, OsdiLimId, OsdiVersion};
+
+use self::function::{real_lit, str_lit, CCallback, CTy};
+
+mod eval;
+mod function;
+mod load;
+mod setup;
+
+const HEADER: &str = include_str!("../header/osdi_0_3.h");
+const EXT_HEADER: &str = include_str!("../header/openvaf_ext.h");
+const STDLIB: &str = include_str!("../stdlib.c");
+
+/// The C backend only implements the descriptor layout of OSDI 0.3.
+const OSDI_VERSION: (u32, u32) = OsdiVersion::V0_3.numbers();
+
+/// Label at the end of each translated MIR function.
+const EXIT_LABEL: &str = "end";
+
+/// A construct used by a module that the C backend can not translate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedFeature {
+    pub module: String,
+    pub feature: String,
+}
+
+impl Display for UnsupportedFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "module {} uses {} which is not supported when emitting C sources",
+            self.module, self.feature
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedFeature {}
+
+fn check_supported(
+    db: &CompilationDB,
+    module: &OsdiModule,
+    literals: &Rodeo,
+) -> Result<(), UnsupportedFeature> {
+    let unsupported =
+        |feature| Err(UnsupportedFeature { module: module.info.module.name(db), feature });
+    for kind in module.intern.params.raw.keys() {
+        if let ParamKind::HiddenState(var) = *kind {
+            if module.intern.is_param_live(module.eval, kind) {
+                return unsupported(format!(
+                    "the value of `{}` from a previous evaluation",
+                    var.name(db)
+                ));
+            }
+        }
+    }
+    for src in &module.dae_system.noise_sources {
+        if let NoiseSourceKind::NoiseTable { .. } = src.kind {
+            let name = literals.resolve(&src.name);
+            return unsupported(format!("the noise table \"{name}\""));
+        }
+    }
+    Ok(())
+}
+
+/// Generates C source code that implements the `lowered` modules of all root files.
+/// The result is portable C99 and can be compiled into an OSDI library with any C99 compiler,
+/// the target is only determined by the C compiler.
+pub fn c_source(
+    inputs: &[(&CompilationDB, &[ModuleInfo])],
+    lowered: &LoweredModules,
+    back: &LLVMBackend,
+) -> anyhow::Result<String> {
+    let mut literals = lowered.literals.clone();
+    let lim_table = &lowered.lim_table;
+    let modules = osdi_modules(inputs, &lowered.modules, lim_table, &mut literals)?;
+    for (file, module) in &modules {
+        check_supported(inputs[*file].0, module, &literals)?;
+    }
+
+    // the slot assignment is shared with the LLVM backend so an LLVM context is still required,
+    // the layout of the data is determined by the C compiler so the default data layout is used
+    let target_data = unsafe {
+        let src = CString::new("").unwrap();
+        llvm::LLVMCreateTargetData(src.as_ptr())
+    };
+
+    let mut out = String::new();
+    let names: Vec<_> =
+        modules.iter().map(|(file, module)| module.info.module.name(inputs[*file].0)).collect();
+    write_prelude(&mut out, &names, lim_table, &literals);
+
+    let mut descriptors = Vec::with_capacity(modules.len());
+    for (file, module) in &modules {
+        let db = inputs[*file].0;
+        let llmod = unsafe { back.new_module(&module.sym, OptLevel::None).unwrap() };
+        let cx = new_codegen(back, &llmod, &literals, OsdiVersion::V0_3);
+        let tys = OsdiTys::new(&cx, target_data);
+        let unit = OsdiCompilationUnit::new(db, module, &cx, &tys, false);
+        let mut cmodule = CModule::new(unit, &literals);
+        cmodule.write_module();
+        descriptors.push(cmodule.descriptor());
+        out.push_str(&cmodule.out);
+    }
+
+    let _ = writeln!(
+        out,
+        "OSDI_EXPORT const OsdiDescriptor OSDI_DESCRIPTORS[{}] = {{",
+        descriptors.len()
+    );
+    for descriptor in &descriptors {
+        let _ = writeln!(out, "{descriptor},");
+    }
+    out.push_str("};\n\n");
+    let _ = writeln!(
+        out,
+        "OSDI_EXPORT const OsdiDescriptorExtensions *const OSDI_DESCRIPTOR_EXTENSIONS[{}] = {{",
+        modules.len()
+    );
+    for (_, module) in &modules {
+        let _ = writeln!(out, "&extensions_{},", module.sym);
+    }
+    out.push_str("};\n\n");
+    let _ = writeln!(out, "OSDI_EXPORT const uint32_t OSDI_NUM_DESCRIPTORS = {};", modules.len());
+    let _ = writeln!(out, "OSDI_EXPORT const uint32_t OSDI_VERSION_MAJOR = {};", OSDI_VERSION.0);
+    let _ = writeln!(out, "OSDI_EXPORT const uint32_t OSDI_VERSION_MINOR = {};", OSDI_VERSION.1);
+
+    unsafe { LLVMDisposeTargetData(target_data) };
+    Ok(out)
+}
+
+fn write_prelude(
+    out: &mut String,
+    modules: &[String],
+    lim_table: &TiSet<OsdiLimId, OsdiLimFunction>,
+    literals: &Rodeo,
+) {
+    let _ = write!(
+        out,
+        "\
+/* Generated by OpenVAF from the Verilog-A module(s) {}.
+ * Implements the OSDI {}.{} interface, build with:
+ *
+ *     cc -std=c99 -O2 -shared -fPIC -fvisibility=hidden <file>.c -o <file>.osdi -lm
+ */
+
+#include <math.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#if defined(_WIN32)
+#define OSDI_EXPORT __declspec(dllexport)
+#elif defined(__GNUC__)
+#define OSDI_EXPORT __attribute__((visibility(\"default\")))
+#else
+#define OSDI_EXPORT
+#endif
+
+",
+        modules.join(", "),
+        OSDI_VERSION.0,
+        OSDI_VERSION.1
+    );
+
+    out.push_str(HEADER.trim_start().trim_start_matches("#pragma once"));
+    let ext_header = EXT_HEADER.trim_start().trim_start_matches("#pragma once");
+    out.push_str(&ext_header.replace("#include \"osdi_0_3.h\"\n", ""));
+    out.push_str("\n#define OSDI_0_3\n");
+    out.push_str(STDLIB);
+    out.push_str("\nOSDI_EXPORT osdi_log_ptr osdi_log = NULL;\n\n");
+
+    if !lim_table.is_empty() {
+        let _ =
+            writeln!(out, "OSDI_EXPORT OsdiLimFunction OSDI_LIM_TABLE[{}] = {{", lim_table.len());
+        for entry in lim_table.iter() {
+            let name = str_lit(literals.resolve(&entry.name));
+            let _ = writeln!(out, "  {{{name}, {}, NULL}},", entry.num_args);
+        }
+        out.push_str("};\n");
+        let _ =
+            writeln!(out, "OSDI_EXPORT const uint32_t OSDI_LIM_TABLE_LEN = {};\n", lim_table.len());
+    }
+
+    out.push_str(
+        "\
+static int32_t osdi_clog2(int32_t val) {
+  uint32_t bits = (uint32_t)val;
+  int32_t res = 0;
+  while (bits != 0) {
+    res++;
+    bits >>= 1;
+  }
+  return res;
+}
+
+",
+    );
+}
+
+/// C does not allow zero length arrays.
+fn arr_len(len: u32) -> u32 {
+    len.max(1)
+}
+
+fn field_decl(ty: &Type, name: &str) -> String {
+    let decl = CTy::new(ty).decl(name);
+    match ty_len(ty) {
+        Some(len) => format!("{decl}[{}]", arr_len(len)),
+        None => decl,
+    }
+}
+
+fn u32_lit(val: u32) -> String {
+    if val == u32::MAX {
+        "UINT32_MAX".to_owned()
+    } else {
+        format!("{val}u")
+    }
+}
+
+struct CModule<'a, 'b, 'll> {
+    unit: OsdiCompilationUnit<'a, 'b, 'll>,
+    literals: &'a Rodeo,
+    sym: String,
+    slot_tys: TiVec<EvalOutputSlot, CTy>,
+    num_print: u32,
+    out: String,
+}
+
+impl<'a, 'b, 'll> CModule<'a, 'b, 'll> {
+    fn new(unit: OsdiCompilationUnit<'a, 'b, 'll>, literals: &'a Rodeo) -> Self {
+        let inst_data = &unit.inst_data;
+        let mut slot_tys: TiVec<_, _> = vec![CTy::Real; inst_data.eval_outputs.len()].into();
+        for (var, output) in &inst_data.opvars {
+            let slot = match *output {
+                EvalOutput::Calculated(slot) => slot,
+                EvalOutput::Const(_, slot) => match slot.expand() {
+                    Some(slot) => slot,
+                    None => continue,
+                },
+                EvalOutput::Param(_) | EvalOutput::Cache(_) => continue,
+            };
+            slot_tys[slot] = CTy::new(&var.ty(unit.db));
+        }
+
+        CModule {
+            sym: unit.module.sym.clone(),
+            unit,
+            literals,
+            slot_tys,
+            num_print: 0,
+            out: String::new(),
+        }
+    }
+
+    fn inst_ty(&self) -> String {
+        format!("osdi_inst_data_{}", self.sym)
+    }
+
+    fn model_ty(&self) -> String {
+        format!("osdi_model_data_{}", self.sym)
+    }
+
+    /// Declares the `inst` and `model` pointers at the start of a function.
+    fn cast_inst_model(&mut self) {
+        let (inst_ty, model_ty) = (self.inst_ty(), self.model_ty());
+        let _ = writeln!(self.out, "  {inst_ty} *inst = ({inst_ty} *)inst_;");
+        let _ = writeln!(self.out, "  {model_ty} *model = ({model_ty} *)model_;");
+    }
+
+    fn inst_param_ty(&self, param: &OsdiInstanceParam) -> Type {
+        match param {
+            OsdiInstanceParam::Builtin(_) => Type::Real,
+            OsdiInstanceParam::User(param) => param.ty(self.unit.db),
+        }
+    }
+
+    fn write_module(&mut self) {
+        self.write_structs();
+        self.write_collapse();
+        self.write_access();
+        self.write_setup_model();
+        self.write_setup_instance();
+        self.write_eval();
+        self.write_load_noise();
+        for reactive in [false, true] {
+            self.write_load_residual(reactive);
+            self.write_load_lim_rhs(reactive);
+        }
+        for tran in [false, true] {
+            self.write_load_spice_rhs(tran);
+        }
+        for kind in [JacobianLoadType::Resist, JacobianLoadType::React, JacobianLoadType::Tran] {
+            self.write_load_jacobian(kind);
+        }
+    }
+
+    fn write_structs(&mut self) {
+        let OsdiCompilationUnit { inst_data, model_data, module, db, .. } = &self.unit;
+        let mut out = String::new();
+
+        let inst_ty = self.inst_ty();
+        let num_react = inst_data.jacobian.iter().filter(|entry| entry.react_off.is_some()).count();
+        let _ = writeln!(out, "typedef struct {inst_ty} {{");
+        let _ = writeln!(
+            out,
+            "  uint32_t param_given[{}];",
+            arr_len(word_cnt(inst_data.params.len() as u32))
+        );
+        let _ = writeln!(
+            out,
+            "  double *jacobian_ptr_resist[{}];",
+            arr_len(module.dae_system.jacobian.len() as u32)
+        );
+        let _ = writeln!(out, "  double *jacobian_ptr_react[{}];", arr_len(num_react as u32));
+        let _ = writeln!(
+            out,
+            "  uint32_t node_mapping[{}];",
+            arr_len(module.dae_system.unknowns.len() as u32)
+        );
+        let _ = writeln!(out, "  bool collapsed[{}];", arr_len(module.node_collapse.num_pairs()));
+        out.push_str("  double temperature;\n  uint32_t connected_ports;\n");
+        let _ = writeln!(
+            out,
+            "  uint32_t state_idx[{}];",
+            arr_len(module.intern.lim_state.len() as u32)
+        );
+        for (i, param) in inst_data.params.keys().enumerate() {
+            let decl = field_decl(&self.inst_param_ty(param), &format!("param_{i}"));
+            let name = match param {
+                OsdiInstanceParam::Builtin(builtin) => format!("${builtin:?}"),
+                OsdiInstanceParam::User(param) => module.info.params[param].name.to_string(),
+            };
+            let _ = writeln!(out, "  {decl}; /* {name} */");
+        }
+        for (i, ty) in module.init.cache_slots.raw.values().enumerate() {
+            let _ = writeln!(out, "  {};", field_decl(ty, &format!("cache_{i}")));
+        }
+        for (slot, ty) in self.slot_tys.iter_enumerated() {
+            let _ = writeln!(out, "  {};", ty.decl(&format!("out_{}", u32::from(slot))));
+        }
+        let _ = writeln!(out, "}} {inst_ty};\n");
+
+        let model_ty = self.model_ty();
+        let _ = writeln!(out, "typedef struct {model_ty} {{");
+        let _ = writeln!(
+            out,
+            "  uint32_t param_given[{}];",
+            arr_len(word_cnt((model_data.params.len() + inst_data.params.len()) as u32))
+        );
+        for (i, param) in model_data.params.keys().enumerate() {
+            let decl = field_decl(&param.ty(db), &format!("param_{i}"));
+            let _ = writeln!(out, "  {decl}; /* {} */", module.info.params[param].name);
+        }
+        for (i, param) in inst_data.params.keys().enumerate() {
+            let _ = writeln!(
+                out,
+                "  {};",
+                field_decl(&self.inst_param_ty(param), &format!("inst_param_{i}"))
+            );
+        }
+        let _ = writeln!(out, "}} {model_ty};\n");
+
+        self.out.push_str(&out);
+    }
+
+    /// Expression that checks whether the `pos`-th bit of the `param_given` bitfield of `ptr` is set.
+    fn is_given(ptr: &str, pos: usize) -> String {
+        let (word, mask) = word_index_and_mask(pos as u32);
+        format!("(({ptr}->param_given[{word}] & {mask}u) != 0)")
+    }
+
+    fn set_given(ptr: &str, pos: usize) -> String {
+        let (word, mask) = word_index_and_mask(pos as u32);
+        format!("{ptr}->param_given[{word}] |= {mask}u;")
+    }
+
+    fn is_inst_param_given(&self, pos: usize) -> String {
+        Self::is_given("model", self.unit.model_data.params.len() + pos)
+    }
+
+    fn slot(slot: EvalOutputSlot) -> String {
+        format!("inst->out_{}", u32::from(slot))
+    }
+
+    /// The instance or model field that stores the eval parameter `param`.
+    fn param_field(&self, param: mir::Param) -> String {
+        let OsdiCompilationUnit { inst_data, model_data, module, .. } = &self.unit;
+        let (kind, _) = module.intern.params.get_index(param).unwrap();
+        match *kind {
+            ParamKind::Param(param) => {
+                match inst_data.params.get_index_of(&OsdiInstanceParam::User(param)) {
+                    Some(i) => format!("inst->param_{i}"),
+                    None => {
+                        format!("model->param_{}", model_data.params.get_index_of(&param).unwrap())
+                    }
+                }
+            }
+            ParamKind::Temperature => "inst->temperature".to_owned(),
+            ParamKind::ParamSysFun(func) => {
+                let i = inst_data.params.get_index_of(&OsdiInstanceParam::Builtin(func)).unwrap();
+                format!("inst->param_{i}")
+            }
+            ParamKind::HiddenState(_) => unreachable!("rejected by check_supported"),
+            ParamKind::Voltage { .. }
+            | ParamKind::Current(_)
+            | ParamKind::PortConnected { .. }
+            | ParamKind::ParamGiven { .. }
+            | ParamKind::Abstime
+            | ParamKind::EnableIntegration
+            | ParamKind::EnableLim
+            | ParamKind::PrevState(_)
+            | ParamKind::NewState(_)
+            | ParamKind::ImplicitUnknown(_) => unreachable!(),
+        }
+    }
+
+    /// Expression that reads `output` after `eval` was called.
+    fn eval_output(&self, output: EvalOutput) -> String {
+        match output {
+            EvalOutput::Calculated(slot) => Self::slot(slot),
+            EvalOutput::Const(val, _) => function::const_val(&val, self.literals),
+            EvalOutput::Param(param) => self.param_field(param),
+            EvalOutput::Cache(slot) => format!("inst->cache_{}", u32::from(slot)),
+        }
+    }
+
+    fn general_callbacks(
+        &mut self,
+        intern: &HirInterner,
+        handle: &str,
+        simparam: &str,
+    ) -> TiVec<FuncRef, Option<CCallback>> {
+        let state = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+        intern
+            .callbacks
+            .raw
+            .iter()
+            .map(|call| {
+                let cb = match call {
+                    CallBackKind::SimParam => CCallback::call(
+                        "simparam",
+                        state(&[simparam, handle, "&ret_flags"]),
+                        Some(CTy::Real),
+                    ),
+                    CallBackKind::SimParamOpt => {
+                        CCallback::call("simparam_opt", state(&[simparam]), Some(CTy::Real))
+                    }
+                    CallBackKind::SimParamStr => CCallback::call(
+                        "simparam_str",
+                        state(&[simparam, handle, "&ret_flags"]),
+                        Some(CTy::Str),
+                    ),
+                    // If these derivative were non zero they would have been removed
+                    CallBackKind::Derivative(_) | CallBackKind::NodeDerivative(_) => {
+                        CCallback::Const { val: real_lit(0.0), ty: CTy::Real }
+                    }
+                    CallBackKind::ParamInfo(_, _)
+                    | CallBackKind::CollapseHint(_, _)
+                    | CallBackKind::BuiltinLimit { .. }
+                    | CallBackKind::StoreLimit(_)
+                    | CallBackKind::LimDiscontinuity
+                    | CallBackKind::Analysis
+                    | CallBackKind::NoiseTable(_)
+                    | CallBackKind::WhiteNoise { .. }
+                    | CallBackKind::FlickerNoise { .. }
+                    | CallBackKind::TimeDerivative => return None,
+
+                    CallBackKind::Print { kind, arg_tys } => {
+                        let fun = self.print_callback(*kind, arg_tys);
+                        CCallback::call(fun, state(&[handle]), None)
+                    }
+                };
+                Some(cb)
+            })
+            .collect()
+    }
+
+    /// Writes a function that formats its arguments and passes the result to `osdi_log`.
+    fn print_callback(&mut self, kind: DisplayKind, arg_tys: &[FmtArg]) -> String {
+        let name = format!("print_{}_{}", self.sym, self.num_print);
+        self.num_print += 1;
+
+        let mut params = vec!["void *handle".to_owned(), "char *fmt".to_owned()];
+        let mut prepare = String::new();
+        let mut free = String::new();
+        let mut args = String::new();
+        for (i, arg) in arg_tys.iter().enumerate() {
+            params.push(CTy::new(&arg.ty).decl(&format!("arg{i}")));
+            match arg.kind {
+                FmtArgKind::Binary => {
+                    let _ = writeln!(prepare, "  char *bin{i} = fmt_binary(arg{i});");
+                    let _ = writeln!(free, "  free(bin{i});");
+                    let _ = write!(args, ", bin{i}");
+                }
+                FmtArgKind::EngineerReal => {
+                    let _ = writeln!(prepare, "  int idx{i} = fmt_char_idx(arg{i});");
+                    let _ = write!(args, ", arg{i} * EXP[idx{i}], FMT_CHARS[idx{i}]");
+                }
+                FmtArgKind::Other => {
+                    let _ = write!(args, ", arg{i}");
+                }
+            }
+        }
+
+        let lvl = match kind {
+            DisplayKind::Debug => "LOG_LVL_DEBUG",
+            DisplayKind::Display | DisplayKind::Monitor => "LOG_LVL_DISPLAY",
+            DisplayKind::Info => "LOG_LVL_INFO",
+            DisplayKind::Warn => "LOG_LVL_WARN",
+            DisplayKind::Error => "LOG_LVL_ERR",
+            DisplayKind::Fatal => "LOG_LVL_FATAL",
+        };
+
+        let _ = write!(
+            self.out,
+            "\
+static void {name}({}) {{
+{prepare}  uint32_t lvl = {lvl};
+  char *msg = NULL;
+  int len = snprintf(NULL, 0, fmt{args});
+  if (len >= 0) {{
+    msg = malloc(len + 1);
+    if (msg != NULL && snprintf(msg, len + 1, fmt{args}) < 0) {{
+      free(msg);
+      msg = NULL;
+    }}
+  }}
+{free}  if (msg == NULL) {{
+    msg = fmt;
+    lvl |= LOG_FMT_ERR;
+  }}
+  osdi_log(handle, msg, lvl);
+}}
+
+",
+            params.join(", ")
+        );
+
+        name
+    }
+
+    /// Writes the static tables referenced by the descriptor and returns its initializer.
+    fn descriptor(&mut self) -> String {
+        let OsdiCompilationUnit { inst_data, module, db, .. } = &self.unit;
+        let sym = &self.sym;
+        let inst_ty = self.inst_ty();
+        let offset = |slot: Option<EvalOutputSlot>| match slot {
+            Some(slot) => format!("offsetof({inst_ty}, out_{})", u32::from(slot)),
+            None => "UINT32_MAX".to_owned(),
+        };
+        let mut out = String::new();
+
+        let nodes: Vec<_> = module
+            .dae_system
+            .unknowns
+            .iter_enumerated()
+            .map(|(id, unknown)| {
+                let (name, units, is_flow) = sim_unknown_info(*unknown, db);
+                let residual = &inst_data.residual[id];
+                format!(
+                    "{{.name = {}, .units = {}, .residual_units = \"\", \
+                     .resist_residual_off = {}, .react_residual_off = {}, \
+                     .resist_limit_rhs_off = {}, .react_limit_rhs_off = {}, .is_flow = {is_flow}}}",
+                    str_lit(&name),
+                    str_lit(&units),
+                    offset(residual.resist.expand()),
+                    offset(residual.react.expand()),
+                    offset(residual.resist_lim_rhs.expand()),
+                    offset(residual.react_lim_rhs.expand()),
+                )
+            })
+            .collect();
+        let nodes = write_table(&mut out, "OsdiNode", &format!("nodes_{sym}"), &nodes);
+
+        let jacobian_entries: Vec<_> = module
+            .dae_system
+            .jacobian
+            .iter_enumerated()
+            .map(|(id, entry)| {
+                let react_ptr_off = match inst_data.jacobian[id].react_off.expand() {
+                    Some(off) => {
+                        format!("offsetof({inst_ty}, jacobian_ptr_react[{}])", u32::from(off))
+                    }
+                    None => "UINT32_MAX".to_owned(),
+                };
+                format!(
+                    "{{.nodes = {{{}u, {}u}}, .react_ptr_off = {react_ptr_off}, .flags = {}u}}",
+                    u32::from(entry.row),
+                    u32::from(entry.col),
+                    self.unit.jacobian_entry_flags(entry)
+                )
+            })
+            .collect();
+        let jacobian_entries = write_table(
+            &mut out,
+            "OsdiJacobianEntry",
+            &format!("jacobian_entries_{sym}"),
+            &jacobian_entries,
+        );
+
+        let collapsible: Vec<_> = self
+            .unit
+            .collapsible()
+            .iter()
+            .map(|pair| format!("{{{}, {}}}", u32_lit(pair.node_1), u32_lit(pair.node_2)))
+            .collect();
+        let collapsible_table =
+            write_table(&mut out, "OsdiNodePair", &format!("collapsible_{sym}"), &collapsible);
+
+        let noise_sources: Vec<_> = module
+            .dae_system
+            .noise_sources
+            .iter()
+            .map(|source| {
+                let name = str_lit(self.literals.resolve(&source.name));
+                let node_2 = source.lo.map_or(u32::MAX, u32::from);
+                format!("{{{name}, {{{}u, {}}}}}", u32::from(source.hi), u32_lit(node_2))
+            })
+            .collect();
+        let noise_table = write_table(
+            &mut out,
+            "OsdiNoiseSource",
+            &format!("noise_sources_{sym}"),
+            &noise_sources,
+        );
+
+        let param_opvar = self.unit.param_opvar();
+        let names: Vec<_> = param_opvar
+            .iter()
+            .flat_map(|param| param.name.iter().map(|name| str_lit(name)))
+            .collect();
+        let names_table = format!("param_opvar_names_{sym}");
+        write_table(&mut out, "char *", &names_table, &names);
+        let mut name_off = 0;
+        let param_opvar: Vec<_> = param_opvar
+            .iter()
+            .map(|param| {
+                let res = format!(
+                    "{{.name = &{names_table}[{name_off}], .num_alias = {}u, .description = {}, \
+                     .units = {}, .flags = {}u, .len = {}u}}",
+                    param.num_alias,
+                    str_lit(&param.description),
+                    str_lit(&param.units),
+                    param.flags,
+                    param.len
+                );
+                name_off += param.name.len();
+                res
+            })
+            .collect();
+        let param_opvar =
+            write_table(&mut out, "OsdiParamOpvar", &format!("param_opvar_{sym}"), &param_opvar);
+
+        let bound_step_offset = offset(inst_data.bound_step);
+        let model_ty = self.model_ty();
+        let fields = [
+            ("name", str_lit(&module.info.module.name(db))),
+            ("num_nodes", format!("{}u", module.dae_system.unknowns.len())),
+            ("num_terminals", format!("{}u", module.info.module.ports(db).len())),
+            ("nodes", nodes),
+            ("num_jacobian_entries", format!("{}u", module.dae_system.jacobian.len())),
+            ("jacobian_entries", jacobian_entries),
+            ("num_collapsible", format!("{}u", collapsible.len())),
+            ("collapsible", collapsible_table),
+            ("collapsed_offset", format!("offsetof({inst_ty}, collapsed)")),
+            ("noise_sources", noise_table),
+            ("num_noise_src", format!("{}u", noise_sources.len())),
+            (
+                "num_params",
+                format!("{}u", inst_data.params.len() + self.unit.model_data.params.len()),
+            ),
+            ("num_instance_params", format!("{}u", inst_data.params.len())),
+            ("num_opvars", format!("{}u", inst_data.opvars.len())),
+            ("param_opvar", param_opvar),
+            ("node_mapping_offset", format!("offsetof({inst_ty}, node_mapping)")),
+            ("jacobian_ptr_resist_offset", format!("offsetof({inst_ty}, jacobian_ptr_resist)")),
+            ("num_states", format!("{}u", module.intern.lim_state.len())),
+            ("state_idx_off", format!("offsetof({inst_ty}, state_idx)")),
+            ("bound_step_offset", bound_step_offset),
+            ("instance_size", format!("sizeof({inst_ty})")),
+            ("model_size", format!("sizeof({model_ty})")),
+            ("access", format!("access_{sym}")),
+            ("setup_model", format!("setup_model_{sym}")),
+            ("setup_instance", format!("setup_instance_{sym}")),
+            ("eval", format!("eval_{sym}")),
+            ("load_noise", format!("load_noise_{sym}")),
+            ("load_residual_resist", format!("load_residual_resist_{sym}")),
+            ("load_residual_react", format!("load_residual_react_{sym}")),
+            ("load_limit_rhs_resist", format!("load_lim_rhs_resist_{sym}")),
+            ("load_limit_rhs_react", format!("load_lim_rhs_react_{sym}")),
+            ("load_spice_rhs_dc", format!("load_spice_rhs_dc_{sym}")),
+            ("load_spice_rhs_tran", format!("load_spice_rhs_tran_{sym}")),
+            ("load_jacobian_resist", format!("load_jacobian_resist_{sym}")),
+            ("load_jacobian_react", format!("load_jacobian_react_{sym}")),
+            ("load_jacobian_tran", format!("load_jacobian_tran_{sym}")),
+        ];
+
+        self.out.push_str(&out);
+
+        let mut descriptor = "  {\n".to_owned();
+        for (field, val) in fields {
+            let _ = writeln!(descriptor, "    .{field} = {val},");
+        }
+        descriptor.push_str("  }");
+        descriptor
+    }
+}
+
+/// Writes a static array with the `entries` and returns an expression that points to it.
+fn write_table(out: &mut String, ty: &str, name: &str, entries: &[String]) -> String {
+    if entries.is_empty() {
+        return "NULL".to_owned();
+    }
+
+    let sep = if ty.ends_with('*') { "" } else { " " };
+    let _ = writeln!(out, "static {ty}{sep}{name}[{}] = {{", entries.len());
+    for entry in entries {
+        let _ = writeln!(out, "  {entry},");
+    }
+    out.push_str("};\n\n");
+    name.to_owned()
+}
@@ -0,0 +1,312 @@
+use std::fmt::Write;
+
+use ahash::AHashSet;
+use hir_lower::{CallBackKind, CurrentKind, ParamKind};
+use sim_back::SimUnknownKind;
+
+use crate::c_source::function::{real_lit, CCallback, CFunction, CTy};
+use crate::c_source::{write_table, CModule, EXIT_LABEL};
+use crate::compilation_unit::OsdiCompilationUnit;
+use crate::inst_data::{EvalOutput, OsdiInstanceParam};
+use crate::metadata::OsdiLimFunction;
+use crate::OsdiLimId;
+
+impl CModule<'_, '_, '_> {
+    pub(super) fn write_eval(&mut self) {
+        let module = self.unit.module;
+        let func = module.eval;
+        let intern = module.intern;
+
+        let mut callbacks = self.general_callbacks(intern, "handle", "simparam");
+        let mut lim_funcs = AHashSet::default();
+        for (func_ref, kind) in intern.callbacks.iter_enumerated() {
+            let cb = match *kind {
+                CallBackKind::BuiltinLimit { name, num_args } => {
+                    let id = module
+                        .lim_table
+                        .unwrap_index(&OsdiLimFunction { name, num_args: num_args - 2 });
+                    if lim_funcs.insert(id) {
+                        self.write_lim_func(id, num_args - 2);
+                    }
+                    let state = vec!["sim_info".to_owned(), "&ret_flags".to_owned()];
+                    CCallback::call(format!("lim_{}_{id}", self.sym), state, Some(CTy::Real))
+                }
+                CallBackKind::StoreLimit(state) => {
+                    let state = vec![
+                        "sim_info".to_owned(),
+                        format!("inst->state_idx[{}]", u32::from(state)),
+                    ];
+                    CCallback::call("store_lim", state, Some(CTy::Real))
+                }
+                CallBackKind::LimDiscontinuity => {
+                    CCallback::call("lim_discontinuity", vec!["(int *)&ret_flags".to_owned()], None)
+                }
+                CallBackKind::Analysis => {
+                    CCallback::call("analysis", vec!["sim_info".to_owned()], Some(CTy::Int))
+                }
+                _ => continue,
+            };
+            callbacks[func_ref] = Some(cb);
+        }
+
+        let OsdiCompilationUnit { inst_data, model_data, db, .. } = &self.unit;
+        let num_params = intern.params.len() + module.init.cache_slots.len();
+        let mut cfunc = CFunction::new(func, self.literals, num_params, intern.callbacks.len());
+        cfunc.callbacks = callbacks;
+
+        let prev_solve = |node| match module.dae_system.unknowns.index(&node) {
+            Some(node) => format!("sim_info->prev_solve[inst->node_mapping[{}]]", u32::from(node)),
+            None => "0.0".to_owned(),
+        };
+
+        for (param, (kind, val)) in intern.params.iter_enumerated() {
+            if func.dfg.value_dead(*val) && !inst_data.eval_outputs.contains_key(val) {
+                continue;
+            }
+
+            let (expr, ty) = match *kind {
+                ParamKind::Param(param_) => {
+                    let field =
+                        match inst_data.params.get_index_of(&OsdiInstanceParam::User(param_)) {
+                            Some(i) => format!("inst->param_{i}"),
+                            None => format!(
+                                "model->param_{}",
+                                model_data.params.get_index_of(&param_).unwrap()
+                            ),
+                        };
+                    cfunc.load_param(param, field, CTy::new(&param_.ty(db)));
+                    continue;
+                }
+                ParamKind::Voltage { hi, lo } => {
+                    let hi = prev_solve(SimUnknownKind::KirchoffLaw(hi));
+                    let val = if let Some(lo) = lo {
+                        let lo = prev_solve(SimUnknownKind::KirchoffLaw(lo));
+                        format!("{hi} - {lo}")
+                    } else {
+                        hi
+                    };
+                    (val, CTy::Real)
+                }
+                // TODO support abstime
+                ParamKind::Current(CurrentKind::Port(_)) => ("0.0".to_owned(), CTy::Real),
+                ParamKind::Abstime => {
+                    cfunc.load_param(param, "sim_info->abstime".to_owned(), CTy::Real);
+                    continue;
+                }
+                ParamKind::Current(kind) => (prev_solve(SimUnknownKind::Current(kind)), CTy::Real),
+                ParamKind::ImplicitUnknown(equation) => {
+                    (prev_solve(SimUnknownKind::Implicit(equation)), CTy::Real)
+                }
+                ParamKind::Temperature => {
+                    cfunc.load_param(param, "inst->temperature".to_owned(), CTy::Real);
+                    continue;
+                }
+                ParamKind::ParamGiven { param: param_ } => {
+                    let expr = match inst_data.params.get_index_of(&OsdiInstanceParam::User(param_))
+                    {
+                        Some(i) => format!(
+                            "{} || {}",
+                            Self::is_given("inst", i),
+                            self.is_inst_param_given(i)
+                        ),
+                        None => Self::is_given(
+                            "model",
+                            model_data.params.get_index_of(&param_).unwrap(),
+                        ),
+                    };
+                    (expr, CTy::Bool)
+                }
+                ParamKind::PortConnected { port } => {
+                    let id =
+                        module.dae_system.unknowns.unwrap_index(&SimUnknownKind::KirchoffLaw(port));
+                    (format!("{}u < inst->connected_ports", u32::from(id)), CTy::Bool)
+                }
+                ParamKind::ParamSysFun(param_) => {
+                    let i =
+                        inst_data.params.get_index_of(&OsdiInstanceParam::Builtin(param_)).unwrap();
+                    (format!("inst->param_{i}"), CTy::Real)
+                }
+                ParamKind::HiddenState(_) => unreachable!(), // TODO  hidden state
+                ParamKind::EnableIntegration => (
+                    "(sim_info->flags & CALC_REACT_JACOBIAN) != 0 && \
+                     (sim_info->flags & ANALYSIS_IC) == 0"
+                        .to_owned(),
+                    CTy::Bool,
+                ),
+                ParamKind::PrevState(state) => {
+                    let loc =
+                        format!("sim_info->prev_state[inst->state_idx[{}]]", u32::from(state));
+                    cfunc.load_param(param, loc, CTy::Real);
+                    continue;
+                }
+                ParamKind::NewState(state) => {
+                    let loc =
+                        format!("sim_info->next_state[inst->state_idx[{}]]", u32::from(state));
+                    cfunc.load_param(param, loc, CTy::Real);
+                    continue;
+                }
+                ParamKind::EnableLim => {
+                    ("(sim_info->flags & ENABLE_LIM) != 0".to_owned(), CTy::Bool)
+                }
+            };
+            cfunc.eager_param(param, expr, ty);
+        }
+
+        for (i, ty) in module.init.cache_slots.raw.values().enumerate() {
+            let param = (intern.params.len() + i).into();
+            cfunc.eager_param(param, format!("inst->cache_{i}"), CTy::new(ty));
+        }
+
+        let store_slot = |out: &mut String, slot| {
+            let val = *inst_data.eval_outputs.get_index(slot).unwrap().0;
+            let _ = writeln!(out, "    {} = {};", Self::slot(slot), cfunc.val(val));
+        };
+
+        let mut epilogue = String::new();
+        for reactive in [false, true] {
+            let (jacobian_flag, residual_flag, lim_rhs_flag) = if reactive {
+                ("CALC_REACT_JACOBIAN", "CALC_REACT_RESIDUAL", "CALC_REACT_LIM_RHS")
+            } else {
+                ("CALC_RESIST_JACOBIAN", "CALC_RESIST_RESIDUAL", "CALC_RESIST_LIM_RHS")
+            };
+
+            let _ = writeln!(epilogue, "  if (sim_info->flags & {jacobian_flag}) {{");
+            for entry in &inst_data.jacobian {
+                let dst = if reactive { entry.react } else { entry.resist };
+                if let Some(EvalOutput::Calculated(slot)) = dst {
+                    store_slot(&mut epilogue, slot);
+                }
+            }
+            epilogue.push_str("  }\n");
+
+            let _ = writeln!(epilogue, "  if (sim_info->flags & {residual_flag}) {{");
+            for residual in &inst_data.residual {
+                let slot = if reactive { residual.react } else { residual.resist };
+                if let Some(slot) = slot.expand() {
+                    store_slot(&mut epilogue, slot);
+                }
+            }
+            epilogue.push_str("  }\n");
+
+            let _ = writeln!(epilogue, "  if (sim_info->flags & {lim_rhs_flag}) {{");
+            for residual in &inst_data.residual {
+                let slot = if reactive { residual.react_lim_rhs } else { residual.resist_lim_rhs };
+                if let Some(slot) = slot.expand() {
+                    store_slot(&mut epilogue, slot);
+                }
+            }
+            epilogue.push_str("  }\n");
+        }
+
+        epilogue.push_str("  if (sim_info->flags & CALC_OP) {\n");
+        for &output in inst_data.opvars.values() {
+            if let EvalOutput::Calculated(slot) = output {
+                store_slot(&mut epilogue, slot);
+            }
+        }
+        epilogue.push_str("  }\n");
+
+        epilogue.push_str("  if (sim_info->flags & CALC_NOISE) {\n");
+        for source in &inst_data.noise {
+            for output in source.eval_outputs() {
+                if let EvalOutput::Calculated(slot) = output {
+                    store_slot(&mut epilogue, slot);
+                }
+            }
+        }
+        epilogue.push_str("  }\n");
+
+        if let Some(slot) = inst_data.bound_step {
+            store_slot(&mut epilogue, slot);
+        }
+
+        let sym = &self.sym;
+        let _ = writeln!(
+            self.out,
+            "static uint32_t eval_{sym}(void *handle, void *inst_, void *model_, \
+             OsdiSimInfo *sim_info) {{"
+        );
+        self.cast_inst_model();
+        self.out.push_str("  OsdiSimParas *simparam = &sim_info->paras;\n");
+        self.out.push_str("  uint32_t ret_flags = 0;\n");
+        cfunc.write(&mut self.out, EXIT_LABEL);
+        let _ = writeln!(self.out, "{EXIT_LABEL}:;\n{epilogue}  return ret_flags;\n}}\n");
+        self.write_eval_batch();
+    }
+
+    /// Writes `eval_batch` and the `OsdiDescriptorExtensions` that advertise it.
+    fn write_eval_batch(&mut self) {
+        let sym = &self.sym;
+        let correlations: Vec<_> = self
+            .unit
+            .module
+            .dae_system
+            .noise_correlations
+            .iter()
+            .map(|correlation| {
+                format!(
+                    "{{{}u, {}u, {}}}",
+                    correlation.source_1,
+                    correlation.source_2,
+                    real_lit(correlation.coefficient)
+                )
+            })
+            .collect();
+        let correlation_table = write_table(
+            &mut self.out,
+            "OsdiNoiseCorrelation",
+            &format!("noise_correlations_{sym}"),
+            &correlations,
+        );
+        let _ = writeln!(
+            self.out,
+            "\
+static uint32_t eval_batch_{sym}(void **handles, void **instances, void *model, \
+             OsdiSimInfo *sim_info, uint32_t num_instances, uint32_t *ret_flags) {{
+  uint32_t res = 0;
+  for (uint32_t i = 0; i < num_instances; i++) {{
+    ret_flags[i] = eval_{sym}(handles[i], instances[i], model, sim_info);
+    res |= ret_flags[i];
+  }}
+  return res;
+}}
+
+static const OsdiDescriptorExtensions extensions_{sym} = {{
+  .size = sizeof(OsdiDescriptorExtensions),
+  .eval_batch = eval_batch_{sym},
+  .num_noise_correlations = {}u,
+  .noise_correlations = {correlation_table},
+}};
+",
+            correlations.len()
+        );
+    }
+
+    /// Writes a wrapper that calls the `$limit` function `id` (provided by the simulator in
+    /// `OSDI_LIM_TABLE`) and marks the result as limited if the function changed the value.
+    fn write_lim_func(&mut self, id: OsdiLimId, num_args: u32) {
+        let sym = &self.sym;
+        let args: Vec<_> = (0..num_args + 2).map(|i| format!("arg{i}")).collect();
+        let params: Vec<_> = args.iter().map(|arg| format!("double {arg}")).collect();
+        let arg_tys = vec!["double"; args.len()].join(", ");
+        let _ = write!(
+            self.out,
+            "\
+static double lim_{sym}_{id}(OsdiSimInfo *sim_info, uint32_t *ret_flags, {}) {{
+  typedef double (*lim_fn)(bool, bool *, {arg_tys});
+  bool val_changed = false;
+  lim_fn fun = (lim_fn)OSDI_LIM_TABLE[{}].func_ptr;
+  double res = fun((sim_info->flags & INIT_LIM) != 0, &val_changed, {});
+  if (val_changed) {{
+    *ret_flags |= EVAL_RET_FLAG_LIM;
+  }}
+  return res;
+}}
+
+",
+            params.join(", "),
+            u32::from(id),
+            args.join(", ")
+        );
+    }
+}
@@ -0,0 +1,554 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use ahash::{AHashMap, AHashSet};
+use hir::Type;
+use lasso::Rodeo;
+use mir::{
+    Block, Const, ControlFlowGraph, FuncRef, Function, InstructionData, Opcode, Param, Value,
+    ValueDef,
+};
+use typed_index_collections::TiVec;
+
+/// The C type of a MIR value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CTy {
+    Real,
+    Int,
+    Str,
+    Bool,
+}
+
+impl CTy {
+    pub fn new(ty: &Type) -> CTy {
+        match ty.base_type() {
+            Type::Real => CTy::Real,
+            Type::Integer => CTy::Int,
+            Type::String => CTy::Str,
+            Type::Bool => CTy::Bool,
+            ty => unreachable!("values of type {ty:?} can not be represented in C"),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CTy::Real => "double",
+            CTy::Int => "int32_t",
+            CTy::Str => "char *",
+            CTy::Bool => "bool",
+        }
+    }
+
+    /// Declaration of a variable/field with name `name` and this type.
+    pub fn decl(self, name: &str) -> String {
+        match self {
+            CTy::Str => format!("char *{name}"),
+            _ => format!("{} {name}", self.name()),
+        }
+    }
+}
+
+pub struct CParam {
+    expr: String,
+    ty: CTy,
+    /// eager parameters are computed once at the start of the function,
+    /// all other parameters are lvalues that are reread each time they are used
+    eager: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum CCallback {
+    Call { fun: String, state: Vec<String>, num_state: usize, ret: Option<CTy> },
+    Const { val: String, ty: CTy },
+}
+
+impl CCallback {
+    pub fn call(fun: impl Into<String>, state: Vec<String>, ret: Option<CTy>) -> CCallback {
+        CCallback::Call { fun: fun.into(), state, num_state: 0, ret }
+    }
+}
+
+/// Translates the body of a MIR function into C. Each SSA value becomes a local variable and
+/// each block a label, phis are lowered to copies on the incoming edges.
+pub struct CFunction<'a> {
+    pub func: &'a Function,
+    literals: &'a Rodeo,
+    pub params: TiVec<Param, Option<CParam>>,
+    pub callbacks: TiVec<FuncRef, Option<CCallback>>,
+    before_terminator: AHashMap<Block, Vec<String>>,
+}
+
+impl<'a> CFunction<'a> {
+    pub fn new(
+        func: &'a Function,
+        literals: &'a Rodeo,
+        num_params: usize,
+        num_callbacks: usize,
+    ) -> CFunction<'a> {
+        CFunction {
+            func,
+            literals,
+            params: (0..num_params).map(|_| None).collect(),
+            callbacks: vec![None; num_callbacks].into(),
+            before_terminator: AHashMap::default(),
+        }
+    }
+
+    /// Defines `param` as an lvalue that is read each time the parameter is used.
+    pub fn load_param(&mut self, param: Param, lvalue: String, ty: CTy) {
+        self.params[param] = Some(CParam { expr: lvalue, ty, eager: false });
+    }
+
+    /// Defines `param` as an expression that is evaluated once at the start of the function.
+    pub fn eager_param(&mut self, param: Param, expr: String, ty: CTy) {
+        self.params[param] = Some(CParam { expr, ty, eager: true });
+    }
+
+    /// Adds `stmt` to the end of `bb` (before its terminator).
+    pub fn store_before_terminator(&mut self, bb: Block, stmt: String) {
+        self.before_terminator.entry(bb).or_default().push(stmt);
+    }
+
+    /// The C expression that evaluates to `val`.
+    pub fn val(&self, val: Value) -> String {
+        match self.func.dfg.value_def(val) {
+            ValueDef::Result(_, _) => val.to_string(),
+            ValueDef::Param(param) => {
+                let param_ = self.params[param]
+                    .as_ref()
+                    .unwrap_or_else(|| unreachable!("attempted to read undefined value"));
+                if param_.eager {
+                    param.to_string()
+                } else {
+                    param_.expr.clone()
+                }
+            }
+            ValueDef::Const(val) => const_val(&val, self.literals),
+            ValueDef::Invalid => unreachable!(),
+        }
+    }
+
+    fn operand(&self, val: Value) -> String {
+        operand(&self.val(val)).into_owned()
+    }
+
+    fn infer_types(&self) -> TiVec<Value, Option<CTy>> {
+        let dfg = &self.func.dfg;
+        let mut tys: TiVec<Value, Option<CTy>> = dfg
+            .values()
+            .map(|val| match dfg.value_def(val) {
+                ValueDef::Const(val) => Some(const_ty(&val)),
+                ValueDef::Param(param) => {
+                    self.params.get(param).and_then(Option::as_ref).map(|param| param.ty)
+                }
+                ValueDef::Result(_, _) | ValueDef::Invalid => None,
+            })
+            .collect();
+
+        // the type of phis (and optbarriers) depends on values which may be defined later
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for bb in self.func.layout.blocks() {
+                for inst in self.func.layout.block_insts(bb) {
+                    let ty = match dfg.insts[inst] {
+                        InstructionData::Unary { opcode: Opcode::OptBarrier, arg } => tys[arg],
+                        InstructionData::Unary { opcode, .. }
+                        | InstructionData::Binary { opcode, .. } => Some(opcode_ty(opcode)),
+                        InstructionData::PhiNode(ref phi) => {
+                            dfg.phi_edges(phi).find_map(|(_, val)| tys[val])
+                        }
+                        InstructionData::Call { func_ref, .. } => match self.callbacks[func_ref] {
+                            Some(CCallback::Call { ret, .. }) => ret,
+                            Some(CCallback::Const { ty, .. }) => Some(ty),
+                            None => None,
+                        },
+                        InstructionData::Branch { .. } | InstructionData::Jump { .. } => None,
+                    };
+
+                    if let Some(ty) = ty {
+                        for &res in dfg.inst_results(inst) {
+                            if tys[res].is_none() {
+                                tys[res] = Some(ty);
+                                changed = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        tys
+    }
+
+    /// Writes the declarations and statements of the function body to `out`.
+    /// The block that exits the function jumps to `exit_label` which must be defined by the caller.
+    pub fn write(&self, out: &mut String, exit_label: &str) {
+        let func = self.func;
+        let tys = self.infer_types();
+
+        for (param, def) in self.params.iter_enumerated() {
+            if let Some(CParam { expr, ty, eager: true }) = def {
+                let _ = writeln!(out, "  {} = {expr};", ty.decl(&param.to_string()));
+            }
+        }
+
+        let mut cfg = ControlFlowGraph::new();
+        cfg.compute(func);
+        let mut blocks: Vec<_> = cfg.postorder(func).collect();
+        blocks.reverse();
+
+        let mut jump_targets = AHashSet::default();
+        for &bb in &blocks {
+            if let Some(term) = func.layout.last_inst(bb) {
+                match func.dfg.insts[term] {
+                    InstructionData::Jump { destination } => {
+                        jump_targets.insert(destination);
+                    }
+                    InstructionData::Branch { then_dst, else_dst, .. } => {
+                        jump_targets.insert(then_dst);
+                        jump_targets.insert(else_dst);
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        for &bb in &blocks {
+            for inst in func.layout.block_insts(bb) {
+                let phi = matches!(func.dfg.insts[inst], InstructionData::PhiNode(_));
+                for &res in func.dfg.inst_results(inst) {
+                    if let Some(ty) = tys[res] {
+                        let _ = writeln!(out, "  {};", ty.decl(&res.to_string()));
+                        if phi {
+                            let _ = writeln!(out, "  {};", ty.decl(&format!("{res}_in")));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (i, &bb) in blocks.iter().enumerate() {
+            let next = blocks.get(i + 1).copied();
+            if jump_targets.contains(&bb) {
+                let _ = writeln!(out, "{bb}:;");
+            }
+
+            let mut terminated = false;
+            for inst in func.layout.block_insts(bb) {
+                match func.dfg.insts[inst] {
+                    InstructionData::PhiNode(_) => {
+                        let res = func.dfg.first_result(inst);
+                        if tys[res].is_some() {
+                            let _ = writeln!(out, "  {res} = {res}_in;");
+                        }
+                    }
+                    InstructionData::Jump { destination } => {
+                        self.write_before_terminator(out, bb);
+                        self.write_jump(out, bb, destination, next, "  ");
+                        terminated = true;
+                    }
+                    InstructionData::Branch { cond, then_dst, else_dst, .. } => {
+                        self.write_before_terminator(out, bb);
+                        let _ = writeln!(out, "  if ({}) {{", self.val(cond));
+                        self.write_jump(out, bb, then_dst, None, "    ");
+                        let _ = writeln!(out, "  }} else {{");
+                        self.write_jump(out, bb, else_dst, None, "    ");
+                        let _ = writeln!(out, "  }}");
+                        terminated = true;
+                    }
+                    InstructionData::Call { func_ref, ref args } => {
+                        let args = args.as_slice(&func.dfg.insts.value_lists);
+                        self.write_call(out, func_ref, args, func.dfg.inst_results(inst));
+                    }
+                    InstructionData::Unary { opcode, arg } => {
+                        let res = func.dfg.first_result(inst);
+                        let expr = self.inst_expr(opcode, &[arg]);
+                        let _ = writeln!(out, "  {res} = {expr};");
+                    }
+                    InstructionData::Binary { opcode, args } => {
+                        let res = func.dfg.first_result(inst);
+                        let expr = self.inst_expr(opcode, &args);
+                        let _ = writeln!(out, "  {res} = {expr};");
+                    }
+                }
+            }
+
+            if !terminated {
+                self.write_before_terminator(out, bb);
+                let _ = writeln!(out, "  goto {exit_label};");
+            }
+        }
+    }
+
+    fn write_before_terminator(&self, out: &mut String, bb: Block) {
+        for stmt in self.before_terminator.get(&bb).into_iter().flatten() {
+            let _ = writeln!(out, "  {stmt}");
+        }
+    }
+
+    fn write_jump(
+        &self,
+        out: &mut String,
+        src: Block,
+        dst: Block,
+        next: Option<Block>,
+        indent: &str,
+    ) {
+        let dfg = &self.func.dfg;
+        for inst in self.func.layout.block_insts(dst) {
+            if let InstructionData::PhiNode(ref phi) = dfg.insts[inst] {
+                let res = dfg.first_result(inst);
+                if let Some(val) = dfg.phi_edge_val(phi, src) {
+                    let _ = writeln!(out, "{indent}{res}_in = {};", self.val(val));
+                }
+            } else {
+                break;
+            }
+        }
+
+        if next != Some(dst) {
+            let _ = writeln!(out, "{indent}goto {dst};");
+        }
+    }
+
+    fn write_call(&self, out: &mut String, func_ref: FuncRef, args: &[Value], results: &[Value]) {
+        let (fun, state, num_state, ret) = match &self.callbacks[func_ref] {
+            Some(CCallback::Call { fun, state, num_state, ret }) => (fun, state, *num_state, ret),
+            Some(CCallback::Const { val, .. }) => {
+                for res in results {
+                    let _ = writeln!(out, "  {res} = {val};");
+                }
+                return;
+            }
+            None => return, // assume noop
+        };
+
+        let args: Vec<_> = args.iter().map(|arg| self.val(*arg)).collect();
+        if num_state != 0 {
+            debug_assert!(results.is_empty());
+            for state in state.chunks(num_state) {
+                let operands: Vec<_> = state.iter().chain(&args).map(String::as_str).collect();
+                let _ = writeln!(out, "  {fun}({});", operands.join(", "));
+            }
+            return;
+        }
+
+        let operands: Vec<_> = state.iter().chain(&args).map(String::as_str).collect();
+        let call = format!("{fun}({})", operands.join(", "));
+        match results {
+            [] => {
+                let _ = writeln!(out, "  {call};");
+            }
+            [res] if ret.is_some() => {
+                let _ = writeln!(out, "  {res} = {call};");
+            }
+            _ => unreachable!("callback {fun} does not return {} values", results.len()),
+        }
+    }
+
+    fn inst_expr(&self, opcode: Opcode, args: &[Value]) -> String {
+        let a = || self.operand(args[0]);
+        let b = || self.operand(args[1]);
+        let call = |fun: &str| {
+            let args: Vec<_> = args.iter().map(|arg| self.val(*arg)).collect();
+            format!("{fun}({})", args.join(", "))
+        };
+        let wrapping = |op: &str| format!("(int32_t)((uint32_t){} {op} (uint32_t){})", a(), b());
+
+        match opcode {
+            Opcode::Inot => format!("~{}", a()),
+            Opcode::Bnot => format!("!{}", a()),
+            Opcode::Fneg => format!("-{}", a()),
+            Opcode::Ineg => format!("(int32_t)(0u - (uint32_t){})", a()),
+            Opcode::FIcast => format!("(int32_t)lround({})", self.val(args[0])),
+            Opcode::IFcast | Opcode::BFcast => format!("(double){}", a()),
+            Opcode::BIcast => format!("(int32_t){}", a()),
+            Opcode::IBcast => format!("{} != 0", a()),
+            Opcode::FBcast => format!("islessgreater({}, 0.0)", self.val(args[0])),
+            Opcode::OptBarrier => self.val(args[0]),
+            Opcode::Sqrt => call("sqrt"),
+            Opcode::Exp => call("exp"),
+            Opcode::Ln => call("log"),
+            Opcode::Log => call("log10"),
+            Opcode::Clog2 => call("osdi_clog2"),
+            Opcode::Floor => call("floor"),
+            Opcode::Ceil => call("ceil"),
+            Opcode::Sin => call("sin"),
+            Opcode::Cos => call("cos"),
+            Opcode::Tan => call("tan"),
+            Opcode::Asin => call("asin"),
+            Opcode::Acos => call("acos"),
+            Opcode::Atan => call("atan"),
+            Opcode::Sinh => call("sinh"),
+            Opcode::Cosh => call("cosh"),
+            Opcode::Tanh => call("tanh"),
+            Opcode::Asinh => call("asinh"),
+            Opcode::Acosh => call("acosh"),
+            Opcode::Atanh => call("atanh"),
+            Opcode::Hypot => call("hypot"),
+            Opcode::Atan2 => call("atan2"),
+            Opcode::Pow => call("pow"),
+            Opcode::Frem => call("fmod"),
+            Opcode::Fne => call("islessgreater"),
+            // LLVM integer arithmetic wraps on overflow while signed overflow is UB in C
+            Opcode::Iadd => wrapping("+"),
+            Opcode::Isub => wrapping("-"),
+            Opcode::Imul => wrapping("*"),
+            Opcode::Ishl => format!("(int32_t)((uint32_t){} << {})", a(), b()),
+            Opcode::Ishr => format!("(int32_t)((uint32_t){} >> {})", a(), b()),
+            Opcode::Idiv | Opcode::Fdiv => format!("{} / {}", a(), b()),
+            Opcode::Irem => format!("{} % {}", a(), b()),
+            Opcode::Ixor => format!("{} ^ {}", a(), b()),
+            Opcode::Iand => format!("{} & {}", a(), b()),
+            Opcode::Ior => format!("{} | {}", a(), b()),
+            Opcode::Fadd => format!("{} + {}", a(), b()),
+            Opcode::Fsub => format!("{} - {}", a(), b()),
+            Opcode::Fmul => format!("{} * {}", a(), b()),
+            Opcode::Ilt | Opcode::Flt => format!("{} < {}", a(), b()),
+            Opcode::Igt | Opcode::Fgt => format!("{} > {}", a(), b()),
+            Opcode::Ile | Opcode::Fle => format!("{} <= {}", a(), b()),
+            Opcode::Ige | Opcode::Fge => format!("{} >= {}", a(), b()),
+            Opcode::Ieq | Opcode::Feq | Opcode::Beq => format!("{} == {}", a(), b()),
+            Opcode::Ine | Opcode::Bne => format!("{} != {}", a(), b()),
+            Opcode::Seq => format!("strcmp({}, {}) == 0", self.val(args[0]), self.val(args[1])),
+            Opcode::Sne => format!("strcmp({}, {}) != 0", self.val(args[0]), self.val(args[1])),
+            Opcode::Br | Opcode::Jmp | Opcode::Call | Opcode::Phi => unreachable!(),
+        }
+    }
+}
+
+fn opcode_ty(opcode: Opcode) -> CTy {
+    match opcode {
+        Opcode::Inot
+        | Opcode::Ineg
+        | Opcode::FIcast
+        | Opcode::BIcast
+        | Opcode::Clog2
+        | Opcode::Iadd
+        | Opcode::Isub
+        | Opcode::Imul
+        | Opcode::Idiv
+        | Opcode::Irem
+        | Opcode::Ishl
+        | Opcode::Ishr
+        | Opcode::Ixor
+        | Opcode::Iand
+        | Opcode::Ior => CTy::Int,
+
+        Opcode::Bnot
+        | Opcode::IBcast
+        | Opcode::FBcast
+        | Opcode::Ilt
+        | Opcode::Igt
+        | Opcode::Ige
+        | Opcode::Ile
+        | Opcode::Flt
+        | Opcode::Fgt
+        | Opcode::Fge
+        | Opcode::Fle
+        | Opcode::Ieq
+        | Opcode::Feq
+        | Opcode::Seq
+        | Opcode::Beq
+        | Opcode::Ine
+        | Opcode::Fne
+        | Opcode::Sne
+        | Opcode::Bne => CTy::Bool,
+
+        Opcode::Fneg
+        | Opcode::IFcast
+        | Opcode::BFcast
+        | Opcode::Sqrt
+        | Opcode::Exp
+        | Opcode::Ln
+        | Opcode::Log
+        | Opcode::Floor
+        | Opcode::Ceil
+        | Opcode::Sin
+        | Opcode::Cos
+        | Opcode::Tan
+        | Opcode::Asin
+        | Opcode::Acos
+        | Opcode::Atan
+        | Opcode::Sinh
+        | Opcode::Cosh
+        | Opcode::Tanh
+        | Opcode::Asinh
+        | Opcode::Acosh
+        | Opcode::Atanh
+        | Opcode::Fadd
+        | Opcode::Fsub
+        | Opcode::Fmul
+        | Opcode::Fdiv
+        | Opcode::Frem
+        | Opcode::Hypot
+        | Opcode::Atan2
+        | Opcode::Pow => CTy::Real,
+
+        Opcode::OptBarrier | Opcode::Br | Opcode::Jmp | Opcode::Call | Opcode::Phi => {
+            unreachable!()
+        }
+    }
+}
+
+fn const_ty(val: &Const) -> CTy {
+    match val {
+        Const::Float(_) => CTy::Real,
+        Const::Int(_) => CTy::Int,
+        Const::Str(_) => CTy::Str,
+        Const::Bool(_) => CTy::Bool,
+    }
+}
+
+pub fn const_val(val: &Const, literals: &Rodeo) -> String {
+    match *val {
+        Const::Float(val) => real_lit(val.into()),
+        Const::Int(i32::MIN) => "(-2147483647 - 1)".to_owned(),
+        Const::Int(val) => val.to_string(),
+        Const::Str(val) => str_lit(literals.resolve(&val)),
+        Const::Bool(val) => val.to_string(),
+    }
+}
+
+/// A C literal that evaluates to exactly `val`.
+pub fn real_lit(val: f64) -> String {
+    if val.is_nan() {
+        "NAN".to_owned()
+    } else if val.is_infinite() {
+        if val > 0.0 { "INFINITY" } else { "-INFINITY" }.to_owned()
+    } else {
+        // rust prints the shortest representation that roundtrips
+        format!("{val:e}")
+    }
+}
+
+pub fn str_lit(val: &str) -> String {
+    let mut res = String::with_capacity(val.len() + 2);
+    res.push('"');
+    for byte in val.bytes() {
+        match byte {
+            b'"' => res.push_str("\\\""),
+            b'\\' => res.push_str("\\\\"),
+            // avoid trigraphs
+            b'?' => res.push_str("\\?"),
+            b'\n' => res.push_str("\\n"),
+            b'\t' => res.push_str("\\t"),
+            b' '..=b'~' => res.push(byte as char),
+            _ => {
+                let _ = write!(res, "\\{byte:03o}");
+            }
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// Parenthesizes `expr` unless it is a single token (or postfix expression).
+pub fn operand(expr: &str) -> Cow<'_, str> {
+    if expr.starts_with('-') || expr.contains(' ') {
+        Cow::Owned(format!("({expr})"))
+    } else {
+        Cow::Borrowed(expr)
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt::Write;
+
+use sim_back::dae::NoiseSourceKind;
+use stdx::iter::zip;
+use typed_index_collections::TiVec;
+
+use crate::c_source::function::operand;
+use crate::c_source::CModule;
+use crate::compilation_unit::OsdiCompilationUnit;
+use crate::load::JacobianLoadType;
+
+impl CModule<'_, '_, '_> {
+    fn load_fn(&mut self, name: &str, params: &str, body: &str) {
+        let sym = &self.sym;
+        let _ =
+            writeln!(self.out, "static void {name}_{sym}(void *inst_, void *model_{params}) {{");
+        self.cast_inst_model();
+        let _ = writeln!(self.out, "{body}}}\n");
+    }
+
+    pub(super) fn write_load_noise(&mut self) {
+        let OsdiCompilationUnit { inst_data, module, .. } = &self.unit;
+        let mut body = String::new();
+        for (i, (src, eval_outputs)) in
+            zip(&module.dae_system.noise_sources, &inst_data.noise).enumerate()
+        {
+            let fac = self.eval_output(eval_outputs.factor);
+            let pwr = match src.kind {
+                NoiseSourceKind::WhiteNoise { .. } => self.eval_output(eval_outputs.args[0]),
+                NoiseSourceKind::FlickerNoise { .. } => {
+                    let pwr = self.eval_output(eval_outputs.args[0]);
+                    let exp = self.eval_output(eval_outputs.args[1]);
+                    format!("({} / pow(freq, {exp}))", operand(&pwr))
+                }
+                NoiseSourceKind::NoiseTable { .. } => unreachable!("rejected by check_supported"),
+            };
+            let _ = writeln!(body, "  dst[{i}] = {} * {};", operand(&pwr), operand(&fac));
+        }
+        self.load_fn("load_noise", ", double freq, double *dst", &body);
+    }
+
+    pub(super) fn write_load_residual(&mut self, reactive: bool) {
+        let OsdiCompilationUnit { inst_data, .. } = &self.unit;
+        let mut body = String::new();
+        for (node, residual) in inst_data.residual.iter_enumerated() {
+            let slot = if reactive { residual.react } else { residual.resist };
+            if let Some(slot) = slot.expand() {
+                let _ = writeln!(
+                    body,
+                    "  dst[inst->node_mapping[{}]] += {};",
+                    u32::from(node),
+                    Self::slot(slot)
+                );
+            }
+        }
+        let name = format!("load_residual_{}", if reactive { "react" } else { "resist" });
+        self.load_fn(&name, ", double *dst", &body);
+    }
+
+    pub(super) fn write_load_lim_rhs(&mut self, reactive: bool) {
+        let OsdiCompilationUnit { inst_data, .. } = &self.unit;
+        let mut body = String::new();
+        for (node, residual) in inst_data.residual.iter_enumerated() {
+            let slot = if reactive { residual.react_lim_rhs } else { residual.resist_lim_rhs };
+            if let Some(slot) = slot.expand() {
+                let _ = writeln!(
+                    body,
+                    "  dst[inst->node_mapping[{}]] -= {};",
+                    u32::from(node),
+                    Self::slot(slot)
+                );
+            }
+        }
+        let name = format!("load_lim_rhs_{}", if reactive { "react" } else { "resist" });
+        self.load_fn(&name, ", double *dst", &body);
+    }
+
+    fn load_spice_rhs_(&self, tran: bool, body: &mut String) {
+        let OsdiCompilationUnit { inst_data, module, .. } = &self.unit;
+        let dae_system = &module.dae_system;
+        let mut node_derivatives = TiVec::from(vec![Vec::new(); dae_system.unknowns.len()]);
+        for (id, entry) in dae_system.jacobian.iter_enumerated() {
+            node_derivatives[entry.row].push(id)
+        }
+
+        for node in dae_system.unknowns.indices() {
+            let mut terms = Vec::new();
+            for &entry in &node_derivatives[node] {
+                let col = dae_system.jacobian[entry].col;
+                let entry = &inst_data.jacobian[entry];
+                let ddx = if tran { entry.react } else { entry.resist };
+                if let Some(ddx) = ddx {
+                    let ddx = self.eval_output(ddx);
+                    terms.push(format!(
+                        "{} * prev_solve[inst->node_mapping[{}]]",
+                        operand(&ddx),
+                        u32::from(col)
+                    ));
+                }
+            }
+
+            let residual = &inst_data.residual[node];
+            if !tran {
+                if let Some(slot) = residual.resist.expand() {
+                    if terms.is_empty() {
+                        terms.push("0.0".to_owned());
+                    }
+                    terms.push(format!("-{}", Self::slot(slot)));
+                }
+            }
+
+            if terms.is_empty() {
+                continue;
+            }
+
+            let lim_rhs = if tran { residual.react_lim_rhs } else { residual.resist_lim_rhs };
+            if let Some(slot) = lim_rhs.expand() {
+                terms.push(Self::slot(slot));
+            }
+
+            let mut res = terms.join(" + ").replace("+ -", "- ");
+            if tran {
+                res = format!("({res}) * alpha");
+            }
+            let _ = writeln!(body, "  dst[inst->node_mapping[{}]] += {res};", u32::from(node));
+        }
+    }
+
+    pub(super) fn write_load_spice_rhs(&mut self, tran: bool) {
+        let mut body = String::new();
+        self.load_spice_rhs_(false, &mut body);
+        if tran {
+            self.load_spice_rhs_(true, &mut body);
+        }
+        let (name, params) = if tran {
+            ("load_spice_rhs_tran", ", double *dst, double *prev_solve, double alpha")
+        } else {
+            ("load_spice_rhs_dc", ", double *dst, double *prev_solve")
+        };
+        self.load_fn(name, params, &body);
+    }
+
+    pub(super) fn write_load_jacobian(&mut self, kind: JacobianLoadType) {
+        let OsdiCompilationUnit { inst_data, .. } = &self.unit;
+        let mut body = String::new();
+        for (id, entry) in inst_data.jacobian.iter_enumerated() {
+            let mut res = None;
+            if kind.read_resistive() {
+                res = entry.resist.map(|val| self.eval_output(val));
+            }
+
+            if kind.read_reactive() {
+                if let Some(val) = entry.react {
+                    let val = format!("{} * alpha", operand(&self.eval_output(val)));
+                    res = match res {
+                        Some(resist) => Some(format!("{resist} + {val}")),
+                        None => Some(val),
+                    };
+                }
+            }
+
+            if let Some(res) = res {
+                let dst = if kind.dst_reactive() {
+                    format!("jacobian_ptr_react[{}]", u32::from(entry.react_off.unwrap_unchecked()))
+                } else {
+                    format!("jacobian_ptr_resist[{}]", u32::from(id))
+                };
+                let _ = writeln!(body, "  *inst->{dst} += {res};");
+            }
+        }
+
+        let name = format!("load_jacobian_{}", kind.name());
+        let params = if kind.read_reactive() { ", double alpha" } else { "" };
+        self.load_fn(&name, params, &body);
+    }
+}
@@ -0,0 +1,332 @@
+use std::fmt::Write;
+
+use hir_lower::{CallBackKind, ParamInfoKind, ParamKind, PlaceKind};
+use sim_back::SimUnknownKind;
+
+use crate::c_source::function::{const_val, real_lit, CCallback, CFunction, CTy};
+use crate::c_source::{CModule, EXIT_LABEL};
+use crate::compilation_unit::OsdiCompilationUnit;
+use crate::inst_data::{EvalOutput, OsdiInstanceParam};
+
+impl CModule<'_, '_, '_> {
+    pub(super) fn write_collapse(&mut self) {
+        let sym = &self.sym;
+        let inst_ty = self.inst_ty();
+        let _ = write!(
+            self.out,
+            "\
+static void collapse_{sym}({inst_ty} *inst, uint32_t pair) {{
+  inst->collapsed[pair] = true;
+}}
+
+"
+        );
+    }
+
+    pub(super) fn write_access(&mut self) {
+        let OsdiCompilationUnit { inst_data, model_data, .. } = &self.unit;
+        let inst_len = inst_data.params.len();
+        let model_len = model_data.params.len();
+
+        let mut body = String::new();
+        body.push_str("  if (flags & ACCESS_FLAG_INSTANCE) {\n    switch (id) {\n");
+        for i in 0..inst_len {
+            let _ = write!(
+                body,
+                "    case {i}:\n      if (flags & ACCESS_FLAG_SET) {{\n        {}\n      }}\n      \
+                 return &inst->param_{i};\n",
+                Self::set_given("inst", i)
+            );
+        }
+        body.push_str("    default:\n      break;\n    }\n  } else {\n    switch (id) {\n");
+
+        // inst param model default values
+        for i in 0..inst_len {
+            let _ = write!(
+                body,
+                "    case {i}:\n      if (flags & ACCESS_FLAG_SET) {{\n        {}\n      }}\n      \
+                 return &model->inst_param_{i};\n",
+                Self::set_given("model", model_len + i)
+            );
+        }
+
+        for j in 0..model_len {
+            let _ = write!(
+                body,
+                "    case {}:\n      if (flags & ACCESS_FLAG_SET) {{\n        {}\n      }}\n      \
+                 return &model->param_{j};\n",
+                inst_len + j,
+                Self::set_given("model", j)
+            );
+        }
+        body.push_str("    default:\n      break;\n    }\n  }\n\n  switch (id) {\n");
+
+        for (k, output) in inst_data.opvars.values().enumerate() {
+            let _ = writeln!(body, "  case {}:", model_len + inst_len + k);
+            match *output {
+                EvalOutput::Const(val, slot) => {
+                    let slot = Self::slot(slot.unwrap());
+                    let _ = writeln!(body, "    {slot} = {};", const_val(&val, self.literals));
+                    let _ = writeln!(body, "    return &{slot};");
+                }
+                output => {
+                    let _ = writeln!(body, "    return &{};", self.eval_output(output));
+                }
+            }
+        }
+        body.push_str("  default:\n    return NULL;\n  }\n");
+
+        let sym = &self.sym;
+        let _ = writeln!(
+            self.out,
+            "static void *access_{sym}(void *inst_, void *model_, uint32_t id, uint32_t flags) {{"
+        );
+        self.cast_inst_model();
+        let _ = writeln!(self.out, "\n{body}}}\n");
+    }
+
+    fn init_res(&mut self) {
+        self.out.push_str(
+            "  uint32_t ret_flags = 0;
+  uint32_t err_cap = 0;
+  res->flags = 0;
+  res->num_errors = 0;
+  res->errors = NULL;
+",
+        );
+    }
+
+    fn invalid_param_err(id: usize) -> CCallback {
+        let state = vec![
+            "(void **)&res->errors".to_owned(),
+            "&res->num_errors".to_owned(),
+            "&err_cap".to_owned(),
+            format!("{id}u"),
+        ];
+        CCallback::call("push_invalid_param_err", state, None)
+    }
+
+    pub(super) fn write_setup_model(&mut self) {
+        let module = self.unit.module;
+        let func = module.model_param_setup;
+        let intern = module.model_param_intern;
+
+        let callbacks = self.general_callbacks(intern, "handle", "simparam");
+        let OsdiCompilationUnit { inst_data, model_data, db, .. } = &self.unit;
+        let mut cfunc =
+            CFunction::new(func, self.literals, intern.params.len(), intern.callbacks.len());
+        cfunc.callbacks = callbacks;
+
+        for (j, param) in model_data.params.keys().copied().enumerate() {
+            let dst = intern.params.unwrap_index(&ParamKind::Param(param));
+            cfunc.load_param(dst, format!("model->param_{j}"), CTy::new(&param.ty(db)));
+
+            let dst = intern.params.unwrap_index(&ParamKind::ParamGiven { param });
+            cfunc.eager_param(dst, Self::is_given("model", j), CTy::Bool);
+        }
+
+        for (i, param) in inst_data.params.keys().enumerate() {
+            let is_given = self.is_inst_param_given(i);
+            let val = format!("model->inst_param_{i}");
+
+            match *param {
+                OsdiInstanceParam::Builtin(builtin) => {
+                    if let Some(dst) = intern.params.index(&ParamKind::ParamSysFun(builtin)) {
+                        let default_val = real_lit(builtin.default_value());
+                        let val = format!("{is_given} ? {val} : {default_val}");
+                        cfunc.eager_param(dst, val, CTy::Real);
+                    }
+                }
+                OsdiInstanceParam::User(param) => {
+                    let dst = intern.params.unwrap_index(&ParamKind::Param(param));
+                    cfunc.eager_param(dst, val, CTy::new(&param.ty(db)));
+                    let dst = intern.params.unwrap_index(&ParamKind::ParamGiven { param });
+                    cfunc.eager_param(dst, is_given, CTy::Bool);
+                }
+            }
+        }
+
+        for (call_id, call) in intern.callbacks.iter_enumerated() {
+            if let CallBackKind::ParamInfo(ParamInfoKind::Invalid, param) = call {
+                if !module.info.params[param].is_instance {
+                    let id =
+                        model_data.params.get_index_of(param).unwrap() + inst_data.params.len();
+                    cfunc.callbacks[call_id] = Some(Self::invalid_param_err(id));
+                }
+            }
+        }
+
+        // store parameters
+        for (j, param) in model_data.params.keys().enumerate() {
+            let val = intern.outputs[&PlaceKind::Param(*param)].unwrap_unchecked();
+            let inst = func.dfg.value_def(val).unwrap_inst();
+            let bb = func.layout.inst_block(inst).unwrap();
+            let stmt = format!("model->param_{j} = {};", cfunc.val(val));
+            cfunc.store_before_terminator(bb, stmt);
+        }
+
+        let sym = &self.sym;
+        let _ = writeln!(
+            self.out,
+            "static void setup_model_{sym}(void *handle, void *model_, OsdiSimParas *simparam, \
+             OsdiInitInfo *res) {{"
+        );
+        let model_ty = self.model_ty();
+        let _ = writeln!(self.out, "  {model_ty} *model = ({model_ty} *)model_;");
+        self.init_res();
+        cfunc.write(&mut self.out, EXIT_LABEL);
+        let _ = writeln!(self.out, "{EXIT_LABEL}:\n  return;\n}}\n");
+    }
+
+    pub(super) fn write_setup_instance(&mut self) {
+        let module = self.unit.module;
+        let func = &module.init.func;
+        let intern = &module.init.intern;
+
+        let callbacks = self.general_callbacks(intern, "handle", "simparam");
+        let OsdiCompilationUnit { inst_data, model_data, db, .. } = &self.unit;
+        let mut cfunc =
+            CFunction::new(func, self.literals, intern.params.len(), intern.callbacks.len());
+        cfunc.callbacks = callbacks;
+        let mut prologue = String::new();
+
+        for (i, param) in inst_data.params.keys().enumerate() {
+            let is_inst_given = Self::is_given("inst", i);
+            let is_given = format!("({is_inst_given} || {})", self.is_inst_param_given(i));
+            let val = format!("({is_inst_given} ? inst->param_{i} : model->inst_param_{i})");
+
+            match *param {
+                OsdiInstanceParam::Builtin(builtin) => {
+                    let default_val = real_lit(builtin.default_value());
+                    let _ = writeln!(
+                        prologue,
+                        "  inst->param_{i} = {is_given} ? {val} : {default_val};"
+                    );
+                    if let Some(dst) = intern.params.index(&ParamKind::ParamSysFun(builtin)) {
+                        cfunc.eager_param(dst, format!("inst->param_{i}"), CTy::Real);
+                    }
+                }
+                OsdiInstanceParam::User(param) => {
+                    let dst = intern.params.unwrap_index(&ParamKind::Param(param));
+                    cfunc.eager_param(dst, val, CTy::new(&param.ty(db)));
+                    let dst = intern.params.unwrap_index(&ParamKind::ParamGiven { param });
+                    cfunc.eager_param(dst, is_given, CTy::Bool);
+                }
+            }
+        }
+
+        for (j, param) in model_data.params.keys().copied().enumerate() {
+            if let Some(dst) = intern.params.index(&ParamKind::Param(param)) {
+                cfunc.load_param(dst, format!("model->param_{j}"), CTy::new(&param.ty(db)));
+            }
+
+            if let Some(dst) = intern.params.index(&ParamKind::ParamGiven { param }) {
+                cfunc.eager_param(dst, Self::is_given("model", j), CTy::Bool);
+            }
+        }
+
+        if let Some(dst) = intern.params.index(&ParamKind::Temperature) {
+            cfunc.eager_param(dst, "temperature".to_owned(), CTy::Real);
+        }
+
+        for (node_id, unknown) in module.dae_system.unknowns.iter_enumerated() {
+            if let SimUnknownKind::KirchoffLaw(node) = unknown {
+                if let Some((dst, val)) =
+                    intern.params.index_and_val(&ParamKind::PortConnected { port: *node })
+                {
+                    if func.dfg.value_dead(*val) {
+                        continue;
+                    }
+
+                    let node_id = u32::from(node_id);
+                    let is_connected = format!("{node_id} < (int32_t)connected_terminals");
+                    cfunc.eager_param(dst, is_connected, CTy::Bool);
+                }
+            }
+        }
+
+        // store for use in eval() function
+        prologue.push_str("  inst->temperature = temperature;\n");
+        prologue.push_str("  inst->connected_ports = connected_terminals;\n");
+
+        for (call_id, call) in intern.callbacks.iter_enumerated() {
+            let cb = match call {
+                CallBackKind::ParamInfo(ParamInfoKind::Invalid, param) => inst_data
+                    .params
+                    .get_index_of(&OsdiInstanceParam::User(*param))
+                    .map(Self::invalid_param_err),
+                CallBackKind::CollapseHint(node1, node2) => {
+                    let node1 = module
+                        .dae_system
+                        .unknowns
+                        .unwrap_index(&SimUnknownKind::KirchoffLaw(*node1));
+                    let node2 = node2.map(|node2| {
+                        module.dae_system.unknowns.unwrap_index(&SimUnknownKind::KirchoffLaw(node2))
+                    });
+                    let mut state = vec![];
+                    module.node_collapse.hint(node1, node2, |pair| {
+                        state.push("inst".to_owned());
+                        state.push(format!("{}u", u32::from(pair)));
+                    });
+                    Some(CCallback::Call {
+                        fun: format!("collapse_{}", self.sym),
+                        state,
+                        num_state: 2,
+                        ret: None,
+                    })
+                }
+                _ => continue,
+            };
+
+            cfunc.callbacks[call_id] = cb;
+        }
+
+        // store parameters
+        for (i, param) in inst_data.params.keys().enumerate() {
+            let val = match param {
+                OsdiInstanceParam::Builtin(_) => continue,
+                OsdiInstanceParam::User(param) => {
+                    intern.outputs[&PlaceKind::Param(*param)].unwrap_unchecked()
+                }
+            };
+
+            let inst = func.dfg.value_def(val).unwrap_inst();
+            let bb = func.layout.inst_block(inst).unwrap();
+            let stmt = format!("inst->param_{i} = {};", cfunc.val(val));
+            cfunc.store_before_terminator(bb, stmt);
+        }
+
+        let mut epilogue = String::new();
+        for (&kind, val) in module.init.intern.outputs.iter() {
+            if let PlaceKind::CollapseImplicitEquation(eq) = kind {
+                let should_collapse = cfunc.val(val.unwrap_unchecked());
+                let eq = module.dae_system.unknowns.unwrap_index(&SimUnknownKind::Implicit(eq));
+                let _ = writeln!(epilogue, "  if ({should_collapse}) {{");
+                module.node_collapse.hint(eq, None, |pair| {
+                    let _ = writeln!(epilogue, "    inst->collapsed[{}] = true;", u32::from(pair));
+                });
+                epilogue.push_str("  }\n");
+            }
+        }
+
+        for (&val, &slot) in module.init.cached_vals.iter() {
+            let inst = func.dfg.value_def(val).unwrap_inst();
+            let bb = func.layout.inst_block(inst).unwrap();
+            let stmt = format!("inst->cache_{} = {};", u32::from(slot), cfunc.val(val));
+            cfunc.store_before_terminator(bb, stmt);
+        }
+
+        let sym = &self.sym;
+        let _ = writeln!(
+            self.out,
+            "static void setup_instance_{sym}(void *handle, void *inst_, void *model_, \
+             double temperature, uint32_t connected_terminals, OsdiSimParas *simparam, \
+             OsdiInitInfo *res) {{"
+        );
+        self.cast_inst_model();
+        self.init_res();
+        self.out.push_str(&prologue);
+        cfunc.write(&mut self.out, EXIT_LABEL);
+        let _ = writeln!(self.out, "{EXIT_LABEL}:;\n{epilogue}  return;\n}}\n");
+    }
+}
@@ -1,3 +1,6 @@
+use ahash::AHashMap;
+use basedb::line_index::LineCol;
+use basedb::{BaseDB, FileId, VfsStorage};
 use hir::CompilationDB;
 use hir_lower::fmt::{DisplayKind, FmtArg, FmtArgKind};
 use hir_lower::{CallBackKind, HirInterner};
@@ -10,8 +13,8 @@ use llvm::{
     LLVMGetParam, LLVMIsDeclaration, LLVMPositionBuilderAtEnd, LLVMSetLinkage,
     LLVMSetUnnamedAddress, UnnamedAddr, UNNAMED,
 };
-use mir::{FuncRef, Function};
-use mir_llvm::{CallbackFun, CodegenCx, LLVMBackend, ModuleLlvm};
+use mir::{FuncRef, Function, SourceLoc};
+use mir_llvm::{Builder, CallbackFun, CodegenCx, DebugInfo, LLVMBackend, ModuleLlvm};
 use sim_back::dae::DaeSystem;
 use sim_back::init::Initialization;
 use sim_back::node_collapse::NodeCollapse;
@@ -19,22 +22,25 @@ use sim_back::{CompiledModule, ModuleInfo};
 use typed_index_collections::TiVec;
 use typed_indexmap::TiSet;
 
+use crate::coverage::ModuleCoverage;
 use crate::inst_data::OsdiInstanceData;
 use crate::metadata::osdi_0_3::{
-    stdlib_bitcode, OsdiTys, LOG_FMT_ERR, LOG_LVL_DEBUG, LOG_LVL_DISPLAY, LOG_LVL_ERR,
-    LOG_LVL_FATAL, LOG_LVL_INFO, LOG_LVL_WARN,
+    OsdiTys, LOG_FMT_ERR, LOG_LVL_DEBUG, LOG_LVL_DISPLAY, LOG_LVL_ERR, LOG_LVL_FATAL, LOG_LVL_INFO,
+    LOG_LVL_WARN,
 };
 use crate::metadata::OsdiLimFunction;
 use crate::model_data::OsdiModelData;
-use crate::{lltype, OsdiLimId};
+use crate::sanitize::ModuleFloatChecks;
+use crate::{lltype, FloatSanitizer, OsdiLimId, OsdiVersion};
 
 pub fn new_codegen<'a, 'll>(
     back: &'a LLVMBackend,
     llmod: &'ll ModuleLlvm,
     literals: &'a Rodeo,
+    version: OsdiVersion,
 ) -> CodegenCx<'a, 'll> {
     let cx = unsafe { back.new_ctx(literals, llmod) };
-    cx.include_bitcode(stdlib_bitcode(back.target()));
+    cx.include_bitcode(version.stdlib_bitcode(back.target()));
 
     for fun in llvm::function_iter(llmod.llmod()) {
         unsafe {
@@ -68,6 +74,8 @@ pub struct OsdiCompilationUnit<'a, 'b, 'll> {
     pub cx: &'a CodegenCx<'b, 'll>,
     pub module: &'a OsdiModule<'b>,
     pub lim_dispatch_table: Option<&'ll llvm::Value>,
+    pub debug_info: Option<&'a DebugInfo<'ll>>,
+    pub float_sanitizer: FloatSanitizer,
 }
 
 impl<'a, 'b, 'll> OsdiCompilationUnit<'a, 'b, 'll> {
@@ -95,7 +103,17 @@ impl<'a, 'b, 'll> OsdiCompilationUnit<'a, 'b, 'll> {
             } else {
                 None
             };
-        OsdiCompilationUnit { db, inst_data, model_data, tys, cx, module, lim_dispatch_table }
+        OsdiCompilationUnit {
+            db,
+            inst_data,
+            model_data,
+            tys,
+            cx,
+            module,
+            lim_dispatch_table,
+            debug_info: None,
+            float_sanitizer: FloatSanitizer::Disabled,
+        }
     }
 
     pub fn lim_dispatch_table(&self) -> &'ll llvm::Value {
@@ -103,6 +121,57 @@ impl<'a, 'b, 'll> OsdiCompilationUnit<'a, 'b, 'll> {
     }
 }
 
+/// Resolves `loc` to the file and (zero-based) line and column of the expression it refers to.
+pub fn resolve_src_loc(
+    db: &CompilationDB,
+    intern: &HirInterner,
+    loc: SourceLoc,
+) -> Option<(FileId, LineCol)> {
+    let span = intern.expr_loc(loc)?.file_span(db)?;
+    let line_col = db.line_index(span.file).line_col(span.range.start());
+    Some((span.file, line_col))
+}
+
+/// Attaches the Verilog-A source locations of the MIR instructions
+/// to the LLVM instructions created by `builder`.
+pub fn set_debug_locs<'ll>(
+    db: &CompilationDB,
+    debug_info: &DebugInfo<'ll>,
+    builder: &mut Builder<'_, '_, 'll>,
+    name: &str,
+    intern: &HirInterner,
+) {
+    let vfs = db.vfs().read();
+    // the derivatives of an instruction share its location
+    let mut resolved = AHashMap::new();
+    let mut resolve = |loc: SourceLoc| {
+        if loc.is_default() {
+            return None;
+        }
+        *resolved.entry(loc.bits().unsigned_abs()).or_insert_with(|| {
+            let (file, line_col) = resolve_src_loc(db, intern, loc)?;
+            let file = debug_info.file(&vfs.file_path(file).to_string());
+            Some((file, line_col.line, line_col.col))
+        })
+    };
+
+    let func = builder.func;
+    let locs: Vec<_> = func.srclocs.iter().map(|loc| resolve(*loc)).collect();
+    let (file, line) = match locs.iter().flatten().next() {
+        Some(&(file, line, _)) => (file, line),
+        None => {
+            let root_file = db.compilation_unit().root_file();
+            (debug_info.file(&vfs.file_path(root_file).to_string()), 0)
+        }
+    };
+
+    let scope = unsafe { debug_info.function(builder.fun, name, file, line) };
+    builder.debug_locs = locs
+        .into_iter()
+        .map(|loc| loc.map(|(file, line, col)| debug_info.location(scope, file, line, col)))
+        .collect();
+}
+
 pub struct OsdiModule<'a> {
     pub info: &'a ModuleInfo,
     pub dae_system: &'a DaeSystem,
@@ -114,6 +183,10 @@ pub struct OsdiModule<'a> {
     pub lim_table: &'a TiSet<OsdiLimId, OsdiLimFunction>,
     pub node_collapse: &'a NodeCollapse,
     pub sym: String,
+    /// The execution counters of the module (only with `--coverage`).
+    pub coverage: Option<ModuleCoverage>,
+    /// The checks of the values stored by `eval` (only with `--sanitize float`).
+    pub float_checks: Option<ModuleFloatChecks>,
 }
 
 impl<'a> OsdiModule<'a> {
@@ -144,8 +217,18 @@ impl<'a> OsdiModule<'a> {
             model_param_setup,
             model_param_intern,
             node_collapse,
+            coverage: None,
+            float_checks: None,
         }
     }
+
+    /// Whether `val` is one of the derivatives stored by `eval_sensitivity`.
+    pub fn is_sensitivity_val(&self, val: mir::Value) -> bool {
+        self.dae_system
+            .sensitivities
+            .iter()
+            .any(|sensitivity| sensitivity.resist == val || sensitivity.react == val)
+    }
 }
 
 pub fn general_callbacks<'ll>(
@@ -236,7 +319,7 @@ pub fn general_callbacks<'ll>(
         .collect()
 }
 
-fn print_callback<'ll>(
+pub fn print_callback<'ll>(
     cx: &CodegenCx<'_, 'll>,
     kind: hir_lower::fmt::DisplayKind,
     arg_tys: &[FmtArg],
@@ -0,0 +1,249 @@
+//! Execution counters for the basic blocks and branches of the generated code (`--coverage`).
+//!
+//! Every MIR block of `setup_model`, `setup_instance` and `eval` receives a counter and every
+//! conditional branch (`if`, `case`, loops) receives one counter for each destination. All
+//! counters of a library are stored in the exported `OSDI_COVERAGE_COUNTERS` array
+//! (`OSDI_COVERAGE_LEN` entries). The [`CoverageMap`] written next to the library maps each
+//! counter back to the Verilog-A source.
+
+use std::fmt::{self, Display};
+
+use basedb::VfsStorage;
+use hir::CompilationDB;
+use hir_lower::HirInterner;
+use mir::{Function, InstructionData, SourceLoc};
+use mir_llvm::{CodegenCx, Coverage};
+use typed_index_collections::TiVec;
+
+use crate::compilation_unit::{resolve_src_loc, OsdiModule};
+
+const HEADER: &str = "openvaf-coverage 1";
+
+/// Maps the counters of a library compiled with `--coverage` to the Verilog-A source.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageMap {
+    /// The source files that are referenced by [`SourcePos::file`].
+    pub files: Vec<String>,
+    pub num_counters: u32,
+    pub regions: Vec<CoverageRegion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageRegion {
+    pub kind: RegionKind,
+    /// The counter of a block or the first of the two counters of a branch.
+    pub counter: u32,
+    /// The name of the Verilog-A module.
+    pub module: String,
+    /// The OSDI function (`setup_model`, `setup_instance` or `eval`).
+    pub function: String,
+    /// The statement the block or branch was generated from (if known).
+    pub pos: Option<SourcePos>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Block,
+    Branch,
+}
+
+/// A one-based line and column in one of the [`CoverageMap::files`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourcePos {
+    pub file: u32,
+    pub line: u32,
+    pub col: u32,
+}
+
+impl CoverageMap {
+    fn file(&mut self, path: String) -> u32 {
+        if let Some(pos) = self.files.iter().position(|file| *file == path) {
+            pos as u32
+        } else {
+            self.files.push(path);
+            self.files.len() as u32 - 1
+        }
+    }
+
+    fn counters(&mut self, num: u32) -> u32 {
+        let start = self.num_counters;
+        self.num_counters += num;
+        start
+    }
+
+    /// Parses a map in the format produced by the [`Display`] implementation.
+    pub fn parse(src: &str) -> Result<CoverageMap, String> {
+        let mut lines = src.lines().enumerate();
+        match lines.next() {
+            Some((_, HEADER)) => (),
+            _ => return Err(format!("missing header \"{HEADER}\"")),
+        }
+
+        let mut map = CoverageMap::default();
+        for (i, line) in lines {
+            let err = || format!("line {}: invalid entry \"{line}\"", i + 1);
+            let mut parts = line.splitn(2, ' ');
+            let kind = parts.next().unwrap_or_default();
+            let args = parts.next().unwrap_or_default();
+            match kind {
+                "counters" => map.num_counters = args.parse().map_err(|_| err())?,
+                "file" => map.files.push(args.to_owned()),
+                "block" | "branch" => {
+                    let args: Vec<_> = args.split(' ').collect();
+                    if args.len() != 4 {
+                        return Err(err());
+                    }
+                    let pos = if args[3] == "-" {
+                        None
+                    } else {
+                        let pos: Vec<_> = args[3].split(':').map(str::parse).collect();
+                        match pos[..] {
+                            [Ok(file), Ok(line), Ok(col)] => Some(SourcePos { file, line, col }),
+                            _ => return Err(err()),
+                        }
+                    };
+                    let region = CoverageRegion {
+                        kind: if kind == "block" { RegionKind::Block } else { RegionKind::Branch },
+                        counter: args[0].parse().map_err(|_| err())?,
+                        module: args[1].to_owned(),
+                        function: args[2].to_owned(),
+                        pos,
+                    };
+                    map.regions.push(region);
+                }
+                "" => (),
+                _ => return Err(err()),
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl Display for CoverageMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        writeln!(f, "counters {}", self.num_counters)?;
+        for file in &self.files {
+            writeln!(f, "file {file}")?;
+        }
+        for region in &self.regions {
+            let kind = match region.kind {
+                RegionKind::Block => "block",
+                RegionKind::Branch => "branch",
+            };
+            write!(f, "{kind} {} {} {} ", region.counter, region.module, region.function)?;
+            match region.pos {
+                Some(SourcePos { file, line, col }) => writeln!(f, "{file}:{line}:{col}")?,
+                None => writeln!(f, "-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The counters assigned to the blocks and branches of one MIR function.
+#[derive(Debug, Clone, Default)]
+pub struct FuncCoverage {
+    blocks: TiVec<mir::Block, Option<u32>>,
+    branches: TiVec<mir::Inst, Option<u32>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModuleCoverage {
+    pub setup_model: FuncCoverage,
+    pub setup_instance: FuncCoverage,
+    pub eval: FuncCoverage,
+}
+
+impl OsdiModule<'_> {
+    /// Assigns counters to the blocks and branches of all functions of this module.
+    pub fn instrument(&mut self, db: &CompilationDB, map: &mut CoverageMap) {
+        let name = self.info.module.name(db);
+        let setup_model = instrument_func(
+            db,
+            map,
+            &name,
+            "setup_model",
+            self.model_param_setup,
+            self.model_param_intern,
+        );
+        let setup_instance =
+            instrument_func(db, map, &name, "setup_instance", &self.init.func, &self.init.intern);
+        let eval = instrument_func(db, map, &name, "eval", self.eval, self.intern);
+        self.coverage = Some(ModuleCoverage { setup_model, setup_instance, eval });
+    }
+}
+
+fn instrument_func(
+    db: &CompilationDB,
+    map: &mut CoverageMap,
+    module: &str,
+    function: &str,
+    func: &Function,
+    intern: &HirInterner,
+) -> FuncCoverage {
+    let mut resolve = |loc: Option<&SourceLoc>| {
+        let (file, line_col) = resolve_src_loc(db, intern, *loc?)?;
+        let file = map.file(db.vfs().read().file_path(file).to_string());
+        Some(SourcePos { file, line: line_col.line + 1, col: line_col.col + 1 })
+    };
+
+    let mut regions = Vec::new();
+    let mut res = FuncCoverage {
+        blocks: vec![None; func.layout.num_blocks()].into(),
+        branches: vec![None; func.dfg.num_insts()].into(),
+    };
+    for bb in func.layout.blocks() {
+        // a block is attributed to the first statement it contains
+        let pos = func.layout.block_insts(bb).find_map(|inst| resolve(func.srclocs.get(inst)));
+        regions.push((RegionKind::Block, 1, pos));
+        res.blocks[bb] = Some(regions.len() as u32 - 1);
+
+        let term = if let Some(term) = func.layout.block_terminator(bb) { term } else { continue };
+        if let InstructionData::Branch { cond, .. } = func.dfg.insts[term] {
+            // branches usually carry the location of their condition
+            let pos = resolve(func.srclocs.get(term)).or_else(|| {
+                let inst = func.dfg.value_def(cond).inst()?;
+                resolve(func.srclocs.get(inst))
+            });
+            regions.push((RegionKind::Branch, 2, pos));
+            res.branches[term] = Some(regions.len() as u32 - 1);
+        }
+    }
+
+    // turn the region indices into counter offsets
+    let mut offsets = Vec::with_capacity(regions.len());
+    for (kind, num_counters, pos) in regions {
+        let counter = map.counters(num_counters);
+        offsets.push(counter);
+        let region = CoverageRegion {
+            kind,
+            counter,
+            module: module.to_owned(),
+            function: function.to_owned(),
+            pos,
+        };
+        map.regions.push(region);
+    }
+    for counter in res.blocks.iter_mut().chain(res.branches.iter_mut()).flatten() {
+        *counter = offsets[*counter as usize];
+    }
+    res
+}
+
+/// The counters of `func` for use by the builder. The counter array is declared in the module of
+/// `cx` (it is defined with the correct size in the main module).
+pub fn coverage_counters<'ll>(cx: &CodegenCx<'_, 'll>, func: &FuncCoverage) -> Coverage<'ll> {
+    let counters = cx.get_declared_value("OSDI_COVERAGE_COUNTERS").unwrap_or_else(|| {
+        let ty = cx.ty_array(cx.ty_aint(64), 0);
+        let ptr = cx
+            .define_global("OSDI_COVERAGE_COUNTERS", ty)
+            .unwrap_or_else(|| unreachable!("symbol OSDI_COVERAGE_COUNTERS already defined"));
+        unsafe {
+            llvm::LLVMSetLinkage(ptr, llvm::Linkage::ExternalLinkage);
+            llvm::LLVMSetUnnamedAddress(ptr, llvm::UnnamedAddr::No);
+        }
+        ptr
+    });
+    Coverage { counters, blocks: func.blocks.clone(), branches: func.branches.clone() }
+}
@@ -0,0 +1,312 @@
+//! Collects the interface of the compiled modules: the parameters, nodes, noise sources and
+//! Jacobian entries a simulator reads from the OSDI descriptor. Only the metadata of the
+//! descriptor is generated, so no object files are emitted and nothing is linked.
+
+use std::ffi::CString;
+use std::iter::zip;
+
+use basedb::{BaseDB, VfsStorage};
+use hir::{CompilationDB, ConstraintValue, ExprId, ParamConstraint, Parameter};
+use lasso::Rodeo;
+use llvm::{LLVMDisposeTargetData, OptLevel};
+use mir_llvm::LLVMBackend;
+use sim_back::ModuleInfo;
+use syntax::ast::ConstraintKind;
+use syntax::sourcemap::FileSpan;
+use target::spec::Target;
+
+use crate::compilation_unit::{new_codegen, OsdiCompilationUnit};
+use crate::inst_data::OsdiInstanceParam;
+use crate::metadata::osdi_0_3::{
+    OsdiParamOpvar, OsdiTys, JACOBIAN_ENTRY_REACT, JACOBIAN_ENTRY_REACT_CONST,
+    JACOBIAN_ENTRY_RESIST, JACOBIAN_ENTRY_RESIST_CONST, PARA_KIND_INST, PARA_KIND_MASK,
+    PARA_TY_INT, PARA_TY_MASK, PARA_TY_STR,
+};
+use crate::{lower_modules, osdi_modules, OsdiVersion};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleDescription {
+    pub name: String,
+    /// The first `num_terminals` entries of `nodes` are the terminals of the module.
+    pub num_terminals: u32,
+    pub nodes: Vec<NodeDescription>,
+    pub params: Vec<ParamDescription>,
+    pub opvars: Vec<ParamDescription>,
+    /// Pairs of nodes (indices into `nodes`) that may be collapsed into one. A node that is
+    /// collapsed into ground has no second node.
+    pub collapsible: Vec<(u32, Option<u32>)>,
+    pub noise_sources: Vec<NoiseSourceDescription>,
+    pub jacobian_entries: Vec<JacobianEntryDescription>,
+}
+
+impl ModuleDescription {
+    pub fn terminals(&self) -> &[NodeDescription] {
+        &self.nodes[..self.num_terminals as usize]
+    }
+
+    pub fn internal_nodes(&self) -> &[NodeDescription] {
+        &self.nodes[self.num_terminals as usize..]
+    }
+
+    /// Whether the node with index `node` appears in any collapsible pair.
+    pub fn is_collapsible(&self, node: u32) -> bool {
+        self.collapsible.iter().any(|&(node1, node2)| node1 == node || node2 == Some(node))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeDescription {
+    pub name: String,
+    pub units: String,
+    /// The unknown of this node is a current (flow) instead of a potential.
+    pub is_flow: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamTy {
+    Real,
+    Int,
+    Str,
+}
+
+impl ParamTy {
+    pub fn name(self) -> &'static str {
+        match self {
+            ParamTy::Real => "real",
+            ParamTy::Int => "integer",
+            ParamTy::Str => "string",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamDescription {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub ty: ParamTy,
+    /// The length of array parameters (0 for scalars).
+    pub len: u32,
+    pub is_instance: bool,
+    pub units: String,
+    pub description: String,
+    /// The source of the default value (`None` for opvars and builtin parameters).
+    pub default: Option<String>,
+    /// The `from`/`exclude` constraints as they appear in the source.
+    pub bounds: Vec<String>,
+    /// The `group` attribute (empty for opvars and builtin parameters).
+    pub group: String,
+    /// Where the parameter or opvar is declared (`None` for builtin parameters).
+    pub src: Option<SourceLine>,
+}
+
+/// A one-based line in a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub path: String,
+    pub line: u32,
+}
+
+impl SourceLine {
+    fn new(db: &CompilationDB, span: FileSpan) -> SourceLine {
+        let path = db.vfs().read().file_path(span.file).to_string();
+        let line = db.line_index(span.file).line_col(span.range.start()).line + 1;
+        SourceLine { path, line }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoiseSourceDescription {
+    pub name: String,
+    pub hi: u32,
+    pub lo: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JacobianEntryDescription {
+    pub row: u32,
+    pub col: u32,
+    pub resist: bool,
+    pub react: bool,
+    /// The resistive part of the entry does not depend on the operating point.
+    pub resist_const: bool,
+    /// The reactive part of the entry does not depend on the operating point.
+    pub react_const: bool,
+}
+
+/// Describes the interface of all `modules` of all root files in the order in which they are
+/// exported by [`crate::compile`].
+pub fn describe(
+    inputs: &[(&CompilationDB, &[ModuleInfo])],
+    target: &Target,
+    back: &LLVMBackend,
+) -> anyhow::Result<Vec<ModuleDescription>> {
+    let lowered = lower_modules(inputs);
+    let mut literals = lowered.literals.clone();
+    let modules = osdi_modules(inputs, &lowered.modules, &lowered.lim_table, &mut literals)?;
+
+    // the offsets of the descriptor are computed with the LLVM layout of the instance data
+    let target_data = unsafe {
+        let src = CString::new(target.data_layout.clone()).unwrap();
+        llvm::LLVMCreateTargetData(src.as_ptr())
+    };
+
+    let res = modules
+        .iter()
+        .map(|(file, module)| {
+            let db = inputs[*file].0;
+            let llmod = unsafe { back.new_module(&module.sym, OptLevel::None).unwrap() };
+            let cx = new_codegen(back, &llmod, &literals, OsdiVersion::V0_3);
+            let tys = OsdiTys::new(&cx, target_data);
+            let unit = OsdiCompilationUnit::new(db, module, &cx, &tys, false);
+            unit.describe(target_data, &literals)
+        })
+        .collect();
+
+    unsafe { LLVMDisposeTargetData(target_data) };
+    Ok(res)
+}
+
+impl OsdiCompilationUnit<'_, '_, '_> {
+    fn describe(&self, target_data: &llvm::TargetData, literals: &Rodeo) -> ModuleDescription {
+        let db = self.db;
+        let module = self.module;
+
+        // the parameters in the order of `param_opvar`, followed by the opvars
+        let inst_params = self.inst_data.params.keys().map(|param| match *param {
+            OsdiInstanceParam::User(param) => Some(param),
+            OsdiInstanceParam::Builtin(_) => None,
+        });
+        let model_params = self
+            .model_data
+            .params
+            .keys()
+            .filter(|param| !module.info.params[*param].is_instance)
+            .map(|&param| Some(param));
+        let params: Vec<_> = inst_params.chain(model_params).collect();
+        let mut param_opvar = self.param_opvar();
+        let opvars = param_opvar.split_off(params.len());
+        let params = zip(param_opvar, params)
+            .map(|(raw, param)| describe_param(raw, param, db, module.info))
+            .collect();
+        let opvars = zip(opvars, self.inst_data.opvars.keys())
+            .map(|(raw, opvar)| {
+                let mut opvar_desc = describe_param(raw, None, db, module.info);
+                opvar_desc.src = Some(SourceLine::new(db, opvar.file_span(db)));
+                opvar_desc
+            })
+            .collect();
+
+        let nodes = self
+            .nodes(target_data, db)
+            .into_iter()
+            .map(|node| NodeDescription {
+                name: node.name,
+                units: node.units,
+                is_flow: node.is_flow,
+            })
+            .collect();
+
+        let collapsible = self
+            .collapsible()
+            .into_iter()
+            .map(|pair| (pair.node_1, (pair.node_2 != u32::MAX).then_some(pair.node_2)))
+            .collect();
+
+        let noise_sources = module
+            .dae_system
+            .noise_sources
+            .iter()
+            .map(|source| NoiseSourceDescription {
+                name: literals.resolve(&source.name).to_owned(),
+                hi: source.hi.into(),
+                lo: source.lo.map(u32::from),
+            })
+            .collect();
+
+        let jacobian_entries = self
+            .jacobian_entries(target_data)
+            .into_iter()
+            .map(|entry| JacobianEntryDescription {
+                row: entry.nodes.node_1,
+                col: entry.nodes.node_2,
+                resist: entry.flags & JACOBIAN_ENTRY_RESIST != 0,
+                react: entry.flags & JACOBIAN_ENTRY_REACT != 0,
+                resist_const: entry.flags & JACOBIAN_ENTRY_RESIST_CONST != 0,
+                react_const: entry.flags & JACOBIAN_ENTRY_REACT_CONST != 0,
+            })
+            .collect();
+
+        ModuleDescription {
+            name: module.info.module.name(db),
+            num_terminals: module.info.module.ports(db).len() as u32,
+            nodes,
+            params,
+            opvars,
+            collapsible,
+            noise_sources,
+            jacobian_entries,
+        }
+    }
+}
+
+fn describe_param(
+    raw: OsdiParamOpvar,
+    param: Option<Parameter>,
+    db: &CompilationDB,
+    info: &ModuleInfo,
+) -> ParamDescription {
+    let ty = match raw.flags & PARA_TY_MASK {
+        PARA_TY_INT => ParamTy::Int,
+        PARA_TY_STR => ParamTy::Str,
+        _ => ParamTy::Real,
+    };
+    let mut names = raw.name.into_iter();
+    let name = names.next().unwrap();
+    let (default, bounds, group, src) = match param {
+        Some(param) => {
+            let default = expr_src(db, param, param.default(db));
+            let bounds = param.bounds(db).iter().map(|bound| bound_src(db, param, bound)).collect();
+            let src = SourceLine::new(db, param.file_span(db));
+            (default, bounds, info.params[&param].group.clone(), Some(src))
+        }
+        None => (None, Vec::new(), String::new(), None),
+    };
+
+    ParamDescription {
+        name,
+        aliases: names.collect(),
+        ty,
+        len: raw.len,
+        is_instance: raw.flags & PARA_KIND_MASK == PARA_KIND_INST,
+        units: raw.units,
+        description: raw.description,
+        default,
+        bounds,
+        group,
+        src,
+    }
+}
+
+/// The source text of `expr` in the body of `param` (`None` for expressions that are not
+/// written in the source like a missing default value).
+fn expr_src(db: &CompilationDB, param: Parameter, expr: ExprId) -> Option<String> {
+    let span = param.init(db).borrow().expr_loc(expr).file_span(db)?;
+    let text = db.file_text(span.file).ok()?;
+    Some(text[span.range].to_owned())
+}
+
+fn bound_src(db: &CompilationDB, param: Parameter, bound: &ParamConstraint) -> String {
+    let kind = match bound.kind {
+        ConstraintKind::From => "from",
+        ConstraintKind::Exclude => "exclude",
+    };
+    let src = |expr| expr_src(db, param, expr).unwrap_or_default();
+    match bound.val {
+        ConstraintValue::Value(val) => format!("{kind} {}", src(val)),
+        ConstraintValue::Range(range) => {
+            let open = if range.start_inclusive { '[' } else { '(' };
+            let close = if range.end_inclusive { ']' } else { ')' };
+            format!("{kind} {open}{}:{}{close}", src(range.start), src(range.end))
+        }
+    }
+}
@@ -1,10 +1,11 @@
 use hir_lower::{CallBackKind, CurrentKind, LimitState, ParamKind};
-use llvm::IntPredicate::{IntNE, IntULT};
+use llvm::IntPredicate::{IntEQ, IntNE, IntULT};
 use llvm::{
-    LLVMAppendBasicBlockInContext, LLVMBuildAlloca, LLVMBuildAnd, LLVMBuildBr, LLVMBuildCall2,
-    LLVMBuildCondBr, LLVMBuildICmp, LLVMBuildInBoundsGEP2, LLVMBuildIntCast2, LLVMBuildLoad2,
-    LLVMBuildOr, LLVMBuildRet, LLVMBuildStore, LLVMCreateBuilderInContext, LLVMDisposeBuilder,
-    LLVMGetParam, LLVMPositionBuilderAtEnd, UNNAMED,
+    LLVMAddIncoming, LLVMAppendBasicBlockInContext, LLVMBuildAdd, LLVMBuildAlloca, LLVMBuildAnd,
+    LLVMBuildBr, LLVMBuildCall2, LLVMBuildCondBr, LLVMBuildICmp, LLVMBuildInBoundsGEP2,
+    LLVMBuildIntCast2, LLVMBuildLoad2, LLVMBuildOr, LLVMBuildPhi, LLVMBuildRet, LLVMBuildStore,
+    LLVMCreateBuilderInContext, LLVMDisposeBuilder, LLVMGetParam, LLVMPositionBuilderAtEnd,
+    UNNAMED,
 };
 use log::info;
 use mir_llvm::{Builder, BuilderVal, CallbackFun, MemLoc};
@@ -12,7 +13,8 @@ use sim_back::SimUnknownKind;
 use typed_index_collections::TiVec;
 
 use crate::bitfield::{is_flag_set, is_flag_set_mem, is_flag_unset};
-use crate::compilation_unit::{general_callbacks, OsdiCompilationUnit};
+use crate::compilation_unit::{general_callbacks, set_debug_locs, OsdiCompilationUnit};
+use crate::coverage::coverage_counters;
 use crate::inst_data::OsdiInstanceParam;
 use crate::metadata::osdi_0_3::{
     ANALYSIS_IC, CALC_NOISE, CALC_OP, CALC_REACT_JACOBIAN, CALC_REACT_LIM_RHS, CALC_REACT_RESIDUAL,
@@ -20,6 +22,7 @@ use crate::metadata::osdi_0_3::{
     INIT_LIM,
 };
 use crate::metadata::OsdiLimFunction;
+use crate::sanitize::FloatChecks;
 use crate::OsdiLimId;
 
 impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
@@ -35,12 +38,43 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
 
     pub fn eval(&self) -> &'ll llvm::Value {
         let llfunc = self.eval_prototype();
+        self.build_eval(llfunc, false);
+        llfunc
+    }
+
+    pub fn eval_sensitivity_prototype(&self) -> &'ll llvm::Value {
+        let name = &format!("eval_sensitivity_{}", &self.module.sym);
+        let cx = &self.cx;
+
+        let ty_ptr = cx.ty_ptr();
+        let fun_ty = cx.ty_func(&[ty_ptr; 6], cx.ty_int());
+        cx.declare_ext_fn(name, fun_ty)
+    }
+
+    /// Generates `eval_sensitivity`, which behaves like `eval` but additionally stores the
+    /// derivatives of the residual by the sensitivity parameters into the two dense arrays
+    /// passed as the last arguments (see `header/openvaf_ext.h` for the layout).
+    pub fn eval_sensitivity(&self) -> &'ll llvm::Value {
+        let llfunc = self.eval_sensitivity_prototype();
+        self.build_eval(llfunc, true);
+        llfunc
+    }
+
+    fn build_eval(&self, llfunc: &'ll llvm::Value, sensitivity: bool) {
         let OsdiCompilationUnit { inst_data, model_data, cx, module, .. } = self;
 
         let func = module.eval;
         let intern = module.intern;
 
         let mut builder = Builder::new(cx, func, llfunc);
+        if let Some(debug_info) = self.debug_info {
+            let name = if sensitivity { "eval_sensitivity" } else { "eval" };
+            let name = format!("{name}_{}", &module.sym);
+            set_debug_locs(self.db, debug_info, &mut builder, &name, intern);
+        }
+        if let Some(coverage) = &module.coverage {
+            builder.coverage = Some(coverage_counters(cx, &coverage.eval));
+        }
 
         let handle = unsafe { llvm::LLVMGetParam(llfunc, 0) };
         let instance = unsafe { llvm::LLVMGetParam(llfunc, 1) };
@@ -72,6 +106,8 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
 
         let ret_flags = unsafe { builder.alloca(cx.ty_int()) };
         unsafe { builder.store(ret_flags, cx.const_int(0)) };
+        let float_checks =
+            unsafe { FloatChecks::new(&builder, self.float_sanitizer, llfunc, handle, ret_flags) };
 
         let connected_ports = unsafe { inst_data.load_connected_ports(&builder, instance) };
         let prev_solve: TiVec<_, _> = module
@@ -102,7 +138,10 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
             .raw
             .iter()
             .map(|(kind, val)| {
-                if func.dfg.value_dead(*val) && !inst_data.eval_outputs.contains_key(val) {
+                if func.dfg.value_dead(*val)
+                    && !inst_data.eval_outputs.contains_key(val)
+                    && !(sensitivity && module.is_sensitivity_val(*val))
+                {
                     return BuilderVal::Undef;
                 }
 
@@ -296,14 +335,22 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
 
                 let store_matrix = |builder: &Builder<'_, '_, 'll>| {
                     for entry in module.dae_system.jacobian.keys() {
-                        inst_data.store_jacobian(entry, instance, builder, reactive)
+                        let stored = inst_data.store_jacobian(entry, instance, builder, reactive);
+                        if let (true, Some(checks)) = (stored, &float_checks) {
+                            let check = &module.float_checks.as_ref().unwrap().jacobian[entry];
+                            checks.check(builder, &check[reactive as usize]);
+                        }
                     }
                 };
                 Self::build_store_results(&builder, llfunc, &flags, jacobian_flag, &store_matrix);
 
                 let store_residual = |builder: &Builder<'_, '_, 'll>| {
                     for unknown in module.dae_system.unknowns.indices() {
-                        inst_data.store_residual(unknown, instance, builder, reactive);
+                        let stored = inst_data.store_residual(unknown, instance, builder, reactive);
+                        if let (true, Some(checks)) = (stored, &float_checks) {
+                            let check = &module.float_checks.as_ref().unwrap().residual[unknown];
+                            checks.check(builder, &check[reactive as usize]);
+                        }
                     }
                 };
                 Self::build_store_results(&builder, llfunc, &flags, residual_flag, &store_residual);
@@ -333,9 +380,127 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
 
             inst_data.store_bound_step(instance, &builder);
 
+            if sensitivity {
+                let sens_resist = LLVMGetParam(llfunc, 4);
+                let sens_react = LLVMGetParam(llfunc, 5);
+                self.store_sensitivities(&builder, sens_resist, sens_react);
+            }
+
             let ret_flags = builder.load(cx.ty_int(), ret_flags);
             builder.ret(ret_flags);
         }
+    }
+
+    unsafe fn store_sensitivities(
+        &self,
+        builder: &Builder<'_, '_, 'll>,
+        sens_resist: &'ll llvm::Value,
+        sens_react: &'ll llvm::Value,
+    ) {
+        let OsdiCompilationUnit { cx, module, .. } = self;
+        let params = &module.info.sensitivity_params;
+        let num_nodes = module.dae_system.unknowns.len();
+
+        // all entries are written so that the simulator doesn't need to clear the arrays
+        let zero = cx.const_real(0.0);
+        let mut dense = vec![(zero, zero); params.len() * num_nodes];
+        for sensitivity in &module.dae_system.sensitivities {
+            let param = params.get_index_of(&sensitivity.param).unwrap();
+            let resist = builder.values[sensitivity.resist].get(builder);
+            let react = builder.values[sensitivity.react].get(builder);
+            dense[param * num_nodes + usize::from(sensitivity.row)] = (resist, react);
+        }
+
+        for (i, (resist, react)) in dense.into_iter().enumerate() {
+            for (dst, val) in [(sens_resist, resist), (sens_react, react)] {
+                let idx = cx.const_usize(i);
+                let ptr =
+                    LLVMBuildInBoundsGEP2(builder.llbuilder, cx.ty_double(), dst, &idx, 1, UNNAMED);
+                builder.store(ptr, val);
+            }
+        }
+    }
+
+    pub fn eval_batch_prototype(&self) -> &'ll llvm::Value {
+        let name = &format!("eval_batch_{}", &self.module.sym);
+        let cx = &self.cx;
+
+        let ty_ptr = cx.ty_ptr();
+        let args = [ty_ptr, ty_ptr, ty_ptr, ty_ptr, cx.ty_int(), ty_ptr];
+        let fun_ty = cx.ty_func(&args, cx.ty_int());
+        cx.declare_ext_fn(name, fun_ty)
+    }
+
+    /// Generates `eval_batch`, which calls `eval` (see [`Self::eval`]) for `num_instances`
+    /// instances of the same model. The flags returned for each instance are stored in the
+    /// `ret_flags` array and the union of all flags is returned.
+    ///
+    /// The instances are evaluated one after another, the work is not vectorized across
+    /// instances: each instance lives behind its own pointer in the layout of the OSDI descriptor
+    /// and `eval` branches on the instance state and calls back into the simulator (`$limit`,
+    /// `$display`), so LLVM can't combine the iterations. That would require a
+    /// structure-of-arrays instance layout, which every OSDI simulator would have to allocate
+    /// differently, so it is not provided. The batch saves the simulator one indirect call per
+    /// instance and lets it hand all instances of a model to the library at once.
+    pub fn eval_batch(&self, eval: &'ll llvm::Value) -> &'ll llvm::Value {
+        let llfunc = self.eval_batch_prototype();
+        let cx = &self.cx;
+
+        let ty_ptr = cx.ty_ptr();
+        let int = cx.ty_int();
+        let eval_ty = cx.ty_func(&[ty_ptr, ty_ptr, ty_ptr, ty_ptr], int);
+
+        unsafe {
+            let entry = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+            let body = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+            let exit = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+            let llbuilder = LLVMCreateBuilderInContext(cx.llcx);
+
+            let handles = LLVMGetParam(llfunc, 0);
+            let instances = LLVMGetParam(llfunc, 1);
+            let model = LLVMGetParam(llfunc, 2);
+            let sim_info = LLVMGetParam(llfunc, 3);
+            let num_instances = LLVMGetParam(llfunc, 4);
+            let ret_flags = LLVMGetParam(llfunc, 5);
+
+            LLVMPositionBuilderAtEnd(llbuilder, entry);
+            let len =
+                LLVMBuildIntCast2(llbuilder, num_instances, cx.ty_size(), llvm::False, UNNAMED);
+            let zero = cx.const_usize(0);
+            let empty = LLVMBuildICmp(llbuilder, IntEQ, len, zero, UNNAMED);
+            LLVMBuildCondBr(llbuilder, empty, exit, body);
+
+            LLVMPositionBuilderAtEnd(llbuilder, body);
+            let idx = LLVMBuildPhi(llbuilder, cx.ty_size(), UNNAMED);
+            let acc = LLVMBuildPhi(llbuilder, int, UNNAMED);
+
+            let handle_ptr = LLVMBuildInBoundsGEP2(llbuilder, ty_ptr, handles, &idx, 1, UNNAMED);
+            let handle = LLVMBuildLoad2(llbuilder, ty_ptr, handle_ptr, UNNAMED);
+            let instance_ptr =
+                LLVMBuildInBoundsGEP2(llbuilder, ty_ptr, instances, &idx, 1, UNNAMED);
+            let instance = LLVMBuildLoad2(llbuilder, ty_ptr, instance_ptr, UNNAMED);
+
+            let args = [handle, instance, model, sim_info];
+            let flags =
+                LLVMBuildCall2(llbuilder, eval_ty, eval, args.as_ptr(), args.len() as u32, UNNAMED);
+            let flags_ptr = LLVMBuildInBoundsGEP2(llbuilder, int, ret_flags, &idx, 1, UNNAMED);
+            LLVMBuildStore(llbuilder, flags, flags_ptr);
+
+            let next_acc = LLVMBuildOr(llbuilder, acc, flags, UNNAMED);
+            let next_idx = LLVMBuildAdd(llbuilder, idx, cx.const_usize(1), UNNAMED);
+            let done = LLVMBuildICmp(llbuilder, IntEQ, next_idx, len, UNNAMED);
+            LLVMBuildCondBr(llbuilder, done, exit, body);
+
+            LLVMAddIncoming(idx, [zero, next_idx].as_ptr(), [entry, body].as_ptr(), 2);
+            LLVMAddIncoming(acc, [cx.const_int(0), next_acc].as_ptr(), [entry, body].as_ptr(), 2);
+
+            LLVMPositionBuilderAtEnd(llbuilder, exit);
+            let res = LLVMBuildPhi(llbuilder, int, UNNAMED);
+            LLVMAddIncoming(res, [cx.const_int(0), next_acc].as_ptr(), [entry, body].as_ptr(), 2);
+            LLVMBuildRet(llbuilder, res);
+
+            LLVMDisposeBuilder(llbuilder);
+        }
 
         llfunc
     }
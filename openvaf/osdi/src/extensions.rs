@@ -0,0 +1,92 @@
+//! OpenVAF specific extensions of the OSDI interface (`header/openvaf_ext.h`).
+//!
+//! The layout of `OsdiDescriptor` is fixed by the OSDI version, so additional entry points are
+//! advertised in a separate `OsdiDescriptorExtensions` struct for each descriptor instead. The
+//! exported `OSDI_DESCRIPTOR_EXTENSIONS` array holds a pointer to the extensions of each module.
+//! Fields are only ever appended to the struct and its first field is its size so that
+//! simulators can detect which fields a library provides.
+
+use hir::Parameter;
+use llvm::{LLVMABISizeOfType, TargetData};
+use mir_llvm::CodegenCx;
+
+use crate::compilation_unit::OsdiCompilationUnit;
+use crate::inst_data::OsdiInstanceParam;
+
+/// The name of the exported array of pointers to the `OsdiDescriptorExtensions`.
+pub const DESCRIPTOR_EXTENSIONS: &str = "OSDI_DESCRIPTOR_EXTENSIONS";
+
+pub fn ty_descriptor_extensions<'ll>(cx: &CodegenCx<'_, 'll>) -> &'ll llvm::Type {
+    let fields =
+        [cx.ty_int(), cx.ty_ptr(), cx.ty_int(), cx.ty_ptr(), cx.ty_ptr(), cx.ty_int(), cx.ty_ptr()];
+    cx.ty_struct("OsdiDescriptorExtensions", &fields)
+}
+
+impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
+    /// Defines the `OsdiDescriptorExtensions` of this module and returns a pointer to them.
+    pub fn descriptor_extensions(
+        &self,
+        ty: &'ll llvm::Type,
+        target_data: &TargetData,
+    ) -> &'ll llvm::Value {
+        let cx = &self.cx;
+        let size = unsafe { LLVMABISizeOfType(target_data, ty) } as u32;
+
+        let params = &self.module.info.sensitivity_params;
+        let (sensitivity_params, eval_sensitivity) = if params.is_empty() {
+            (cx.const_null_ptr(), cx.const_null_ptr())
+        } else {
+            let params: Vec<_> = params
+                .iter()
+                .map(|&param| cx.const_unsigned_int(self.param_opvar_idx(param)))
+                .collect();
+            (cx.const_arr_ptr(cx.ty_int(), &params), self.eval_sensitivity_prototype())
+        };
+
+        let correlations = &self.module.dae_system.noise_correlations;
+        let noise_correlations = if correlations.is_empty() {
+            cx.const_null_ptr()
+        } else {
+            let ty =
+                cx.ty_struct("OsdiNoiseCorrelation", &[cx.ty_int(), cx.ty_int(), cx.ty_double()]);
+            let correlations: Vec<_> = correlations
+                .iter()
+                .map(|correlation| {
+                    let fields = [
+                        cx.const_unsigned_int(correlation.source_1),
+                        cx.const_unsigned_int(correlation.source_2),
+                        cx.const_real(correlation.coefficient),
+                    ];
+                    cx.const_struct(ty, &fields)
+                })
+                .collect();
+            cx.const_arr_ptr(ty, &correlations)
+        };
+
+        let fields = [
+            cx.const_unsigned_int(size),
+            self.eval_batch_prototype(),
+            cx.const_unsigned_int(params.len() as u32),
+            sensitivity_params,
+            eval_sensitivity,
+            cx.const_unsigned_int(correlations.len() as u32),
+            noise_correlations,
+        ];
+        cx.global_const(ty, cx.const_struct(ty, &fields))
+    }
+
+    /// The index of `param` in the `param_opvar` array of the descriptor.
+    fn param_opvar_idx(&self, param: Parameter) -> u32 {
+        let OsdiCompilationUnit { inst_data, model_data, module, .. } = self;
+        if let Some(idx) = inst_data.params.get_index_of(&OsdiInstanceParam::User(param)) {
+            return idx as u32;
+        }
+        let idx = model_data
+            .params
+            .keys()
+            .filter(|param| !module.info.params[*param].is_instance)
+            .position(|&model_param| model_param == param)
+            .unwrap();
+        (inst_data.params.len() + idx) as u32
+    }
+}
@@ -220,8 +220,19 @@ impl<'ll> OsdiInstanceData<'ll> {
             let is_live = |intern: &HirInterner, func| {
                 intern.is_param_live(func, &ParamKind::ParamSysFun(param))
             };
+            // a noise source that is not scaled by any other value uses `$mfactor` as its factor
+            // without any uses in the eval function
+            let is_noise_factor = module
+                .intern
+                .params
+                .raw
+                .get(&ParamKind::ParamSysFun(param))
+                .map_or(false, |&val| {
+                    module.dae_system.noise_sources.iter().any(|src| src.factor == val)
+                });
             let is_live = is_live(module.intern, module.eval)
-                || is_live(&module.init.intern, &module.init.func);
+                || is_live(&module.init.intern, &module.init.func)
+                || is_noise_factor;
             is_live.then_some((OsdiInstanceParam::Builtin(param), ty_f64))
         });
         let alias_inst_params = module
@@ -716,11 +727,14 @@ impl<'ll> OsdiInstanceData<'ll> {
         inst_ptr: &'ll llvm::Value,
         builder: &mir_llvm::Builder<'_, '_, 'll>,
         reactive: bool,
-    ) {
+    ) -> bool {
         let entry = &self.jacobian[entry];
         let dst = if reactive { entry.react } else { entry.resist };
         if let Some(EvalOutput::Calculated(slot)) = dst {
-            self.store_eval_output_slot(slot, inst_ptr, builder)
+            self.store_eval_output_slot(slot, inst_ptr, builder);
+            true
+        } else {
+            false
         }
     }
 
@@ -732,6 +746,7 @@ impl<'ll> OsdiInstanceData<'ll> {
         llbuilder: &llvm::Builder<'ll>,
         reactive: bool,
         val: &'ll llvm::Value,
+        offset: Option<&'ll llvm::Value>,
     ) {
         let field = if reactive { JACOBIAN_PTR_REACT } else { JACOBIAN_PTR_RESIST };
         let ptr = LLVMBuildStructGEP2(llbuilder, self.ty, ptr, field, UNNAMED);
@@ -744,7 +759,10 @@ impl<'ll> OsdiInstanceData<'ll> {
         let entry = cx.const_unsigned_int(entry);
         let ty = if reactive { self.jacobian_ptr_react } else { self.jacobian_ptr };
         let ptr = LLVMBuildGEP2(llbuilder, ty, ptr, [zero, entry].as_ptr(), 2, UNNAMED);
-        let dst = LLVMBuildLoad2(llbuilder, cx.ty_ptr(), ptr, UNNAMED);
+        let mut dst = LLVMBuildLoad2(llbuilder, cx.ty_ptr(), ptr, UNNAMED);
+        if let Some(offset) = offset {
+            dst = LLVMBuildGEP2(llbuilder, cx.ty_double(), dst, [offset].as_ptr(), 1, UNNAMED);
+        }
         let old = LLVMBuildLoad2(llbuilder, cx.ty_double(), dst, UNNAMED);
         let val = LLVMBuildFAdd(llbuilder, old, val, UNNAMED);
         LLVMSetFastMath(val);
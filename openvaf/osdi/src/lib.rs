@@ -1,109 +1,301 @@
-use base_n::CASE_INSENSITIVE;
+use anyhow::{anyhow, bail, Context, Result};
+use basedb::VfsStorage;
 use camino::{Utf8Path, Utf8PathBuf};
 use hir::{CompilationDB, ParamSysFun, Type};
 use hir_lower::{CallBackKind, HirInterner, ParamKind};
 use lasso::Rodeo;
 use llvm::{LLVMDisposeTargetData, OptLevel};
-use mir_llvm::{CodegenCx, LLVMBackend};
+use mir_llvm::{CodegenCx, DebugInfo, LLVMBackend, ModuleLlvm};
 use salsa::ParallelDatabase;
 use sim_back::{CompiledModule, ModuleInfo};
-use stdx::{impl_debug_display, impl_idx_from};
+use stdx::{impl_debug_display, impl_display, impl_idx_from};
 use target::spec::Target;
 use typed_indexmap::TiSet;
 
+use std::collections::HashMap;
 use std::ffi::CString;
+use std::fmt::Write;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Mutex;
 
 use crate::compilation_unit::{new_codegen, OsdiCompilationUnit, OsdiModule};
+use crate::extensions::{ty_descriptor_extensions, DESCRIPTOR_EXTENSIONS};
 use crate::metadata::osdi_0_3::OsdiTys;
-use crate::metadata::OsdiLimFunction;
+use crate::metadata::{osdi_0_4, NatureTable, OsdiLimFunction};
 
 mod access;
 mod bitfield;
+mod c_source;
 mod compilation_unit;
+mod coverage;
+mod describe;
+mod extensions;
 mod inst_data;
 mod metadata;
 mod model_data;
+mod sanitize;
 
 mod eval;
 mod load;
 mod noise;
 mod setup;
 
-const OSDI_VERSION: (u32, u32) = (0, 3);
+pub use c_source::{c_source, UnsupportedFeature};
+pub use coverage::{CoverageMap, CoverageRegion, RegionKind, SourcePos};
+pub use describe::{
+    describe, JacobianEntryDescription, ModuleDescription, NodeDescription, NoiseSourceDescription,
+    ParamDescription, ParamTy, SourceLine,
+};
+pub use sanitize::FloatSanitizer;
+
+/// The revisions of the OSDI interface that can be generated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum OsdiVersion {
+    /// The interface understood by all simulators that implement OSDI.
+    #[default]
+    V0_3,
+    /// Extends the descriptor with queries for given parameters, dense and offset
+    /// Jacobian loads and the natures and disciplines of the nodes.
+    V0_4,
+}
+
+impl OsdiVersion {
+    /// The major and minor version exported as `OSDI_VERSION_MAJOR`/`OSDI_VERSION_MINOR`.
+    pub const fn numbers(self) -> (u32, u32) {
+        match self {
+            OsdiVersion::V0_3 => (0, 3),
+            OsdiVersion::V0_4 => (0, 4),
+        }
+    }
+
+    fn stdlib_bitcode(self, target: &Target) -> &'static [u8] {
+        match self {
+            OsdiVersion::V0_3 => metadata::osdi_0_3::stdlib_bitcode(target),
+            OsdiVersion::V0_4 => metadata::osdi_0_4::stdlib_bitcode(target),
+        }
+    }
+}
+
+impl FromStr for OsdiVersion {
+    type Err = String;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        match version {
+            "0.3" => Ok(OsdiVersion::V0_3),
+            "0.4" => Ok(OsdiVersion::V0_4),
+            _ => Err(format!("unknown OSDI version {version} (expected 0.3 or 0.4)")),
+        }
+    }
+}
+
+impl_display! {
+    match OsdiVersion{
+        OsdiVersion::V0_3 => "0.3";
+        OsdiVersion::V0_4 => "0.4";
+    }
+}
 
+/// The global symbols that make up the interface of an OSDI library.
+const EXPORTED_SYMBOLS: [&str; 16] = [
+    "OSDI_DESCRIPTORS",
+    "OSDI_DESCRIPTOR_EXTENSIONS",
+    "OSDI_NUM_DESCRIPTORS",
+    "OSDI_VERSION_MAJOR",
+    "OSDI_VERSION_MINOR",
+    "OSDI_LIM_TABLE",
+    "OSDI_LIM_TABLE_LEN",
+    "OSDI_NATURES",
+    "OSDI_NUM_NATURES",
+    "OSDI_DISCIPLINES",
+    "OSDI_NUM_DISCIPLINES",
+    "OSDI_ATTRIBUTES",
+    "OSDI_NUM_ATTRIBUTES",
+    "OSDI_COVERAGE_COUNTERS",
+    "OSDI_COVERAGE_LEN",
+    "osdi_log",
+];
+
+/// The files [`compile`] writes for every LLVM module (and for every Verilog-A module in case of
+/// `mir`). They are placed next to the destination, e.g. `diode.eval_diode_va.ll`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Emit {
+    /// Object files, required to link the library.
+    pub obj: bool,
+    /// Textual LLVM IR (`.ll`).
+    pub llvm_ir: bool,
+    /// LLVM bitcode (`.bc`).
+    pub llvm_bc: bool,
+    /// Assembly (`.s`).
+    pub asm: bool,
+    /// The MIR of `setup_model`, `setup_instance` and `eval` in the textual format accepted by
+    /// `mir_reader` (`.mir`).
+    pub mir: bool,
+}
+
+impl Emit {
+    fn codegen(self) -> bool {
+        self.obj || self.llvm_ir || self.llvm_bc || self.asm
+    }
+
+    /// Writes the artifacts of `llmod`, the other files are placed next to `obj_file`.
+    fn write(self, llmod: &ModuleLlvm, obj_file: &Utf8Path) -> Result<()> {
+        if self.llvm_ir {
+            let path = obj_file.with_extension("ll");
+            fs::write(&path, llmod.to_str().to_bytes())
+                .with_context(|| format!("failed to write {path}"))?;
+        }
+        if self.llvm_bc {
+            let path = obj_file.with_extension("bc");
+            llmod.emit_bitcode(path.as_ref()).map_err(|err| anyhow!("{err}"))?;
+        }
+        if self.asm {
+            let path = obj_file.with_extension("s");
+            llmod
+                .emit_asm(path.as_ref())
+                .map_err(|err| anyhow!("failed to write {path}: {err}"))?;
+        }
+        if self.obj {
+            llmod
+                .emit_object(obj_file.as_ref())
+                .map_err(|err| anyhow!("failed to write {obj_file}: {err}"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The options of [`compile`].
+#[derive(Clone, Copy)]
+pub struct CompileOpts<'a> {
+    pub target: &'a Target,
+    pub emit: Emit,
+    pub opt_lvl: OptLevel,
+    /// Emit DWARF line tables that refer to the Verilog-A sources.
+    pub debug_info: bool,
+    /// Whether `eval` checks the residuals and Jacobian entries it stores.
+    pub float_sanitizer: FloatSanitizer,
+    /// Count how often each block and branch is executed. The [`CoverageMap`] of the counters is
+    /// written to `dst.with_extension("covmap")`.
+    pub coverage: bool,
+    /// Appended (as `_{sym_suffix}`) to all exported symbols so that the objects can be linked
+    /// into a binary together with other OSDI libraries. A C header that declares the renamed
+    /// symbols is written to `dst.with_extension("h")` in that case.
+    pub sym_suffix: Option<&'a str>,
+    /// The revision of the OSDI interface that the descriptors implement.
+    pub version: OsdiVersion,
+}
+
+/// Compiles the `lowered` modules of all root files (each with its own database) into object files.
+/// The descriptors of all modules are placed in a single `OSDI_DESCRIPTORS` table.
+/// The paths of the object files are returned (these only exist if `opts.emit.obj` is set).
+/// An error is returned if two modules would export the same symbols.
 pub fn compile(
-    db: &CompilationDB,
-    modules: &[ModuleInfo],
+    inputs: &[(&CompilationDB, &[ModuleInfo])],
+    lowered: &LoweredModules,
     dst: &Utf8Path,
-    target: &Target,
     back: &LLVMBackend,
-    emit: bool,
-    opt_lvl: OptLevel,
-) -> Vec<Utf8PathBuf> {
-    let mut literals = Rodeo::new();
-    let mut lim_table = TiSet::default();
-    let modules: Vec<_> = modules
-        .iter()
-        .map(|module| {
-            let mir = CompiledModule::new(db, module, &mut literals);
-            for cb in mir.intern.callbacks.iter() {
-                if let CallBackKind::BuiltinLimit { name, num_args } = *cb {
-                    lim_table.ensure(OsdiLimFunction { name, num_args: num_args - 2 });
-                }
-            }
-            mir
-        })
-        .collect();
+    opts: &CompileOpts<'_>,
+) -> Result<Vec<Utf8PathBuf>> {
+    let CompileOpts {
+        target,
+        emit,
+        opt_lvl,
+        debug_info,
+        float_sanitizer,
+        coverage,
+        sym_suffix,
+        version,
+    } = *opts;
+    let mut literals = lowered.literals.clone();
+    let lim_table = &lowered.lim_table;
     let name = dst.file_stem().expect("destition is a file").to_owned();
 
-    let mut paths: Vec<Utf8PathBuf> = (0..modules.len() * 4)
-        .map(|i| {
-            let num = base_n::encode((i + 1) as u128, CASE_INSENSITIVE);
-            let extension = format!("o{num}");
-            dst.with_extension(extension)
-        })
-        .collect();
-
-    let target_data = unsafe {
-        let src = CString::new(target.data_layout.clone()).unwrap();
-        llvm::LLVMCreateTargetData(src.as_ptr())
+    let mut modules = osdi_modules(inputs, &lowered.modules, lim_table, &mut literals)?;
+    if let Some(suffix) = sym_suffix {
+        for (_, module) in &mut modules {
+            module.sym = format!("{}_{suffix}", module.sym);
+        }
+        let path = dst.with_extension("h");
+        fs::write(&path, c_header(&name, suffix, !lim_table.is_empty(), coverage, version))
+            .with_context(|| format!("failed to write {path}"))?;
+    }
+    if float_sanitizer != FloatSanitizer::Disabled {
+        for (file, module) in &mut modules {
+            module.add_float_checks(inputs[*file].0, &mut literals);
+        }
+    }
+    let natures = (version == OsdiVersion::V0_4).then(|| {
+        let mut table = NatureTable::default();
+        for (file, module) in &modules {
+            table.insert_module(module, inputs[*file].0);
+        }
+        table.intern_strs(&mut literals);
+        table
+    });
+    let coverage_map = if coverage {
+        let mut map = CoverageMap::default();
+        for (file, module) in &mut modules {
+            module.instrument(inputs[*file].0, &mut map);
+        }
+        let path = dst.with_extension("covmap");
+        fs::write(&path, map.to_string()).with_context(|| format!("failed to write {path}"))?;
+        Some(map)
+    } else {
+        None
     };
+    let dbs: Vec<_> = inputs.iter().map(|(db, _)| db.snapshot()).collect();
 
-    let modules: Vec<_> = modules
+    let mut paths: Vec<Utf8PathBuf> = modules
         .iter()
-        .map(|module| {
-            let unit = OsdiModule::new(db, module, &lim_table);
-            unit.intern_names(&mut literals, db);
-            unit
+        .flat_map(|(_, module)| {
+            ["access", "setup_model", "setup_instance", "eval"]
+                .map(|fun| dst.with_extension(format!("{fun}_{}.o", module.sym)))
         })
         .collect();
 
-    let db = db.snapshot();
+    if emit.mir {
+        for (_, module) in &modules {
+            let path = dst.with_extension(format!("{}.mir", module.sym));
+            fs::write(&path, module_mir(module, &literals))
+                .with_context(|| format!("failed to write {path}"))?;
+        }
+    }
 
     let main_file = dst.with_extension("o");
+    // created last so that it is not leaked if writing one of the files above fails
+    let target_data = unsafe {
+        let src = CString::new(target.data_layout.clone()).unwrap();
+        llvm::LLVMCreateTargetData(src.as_ptr())
+    };
 
-    rayon_core::scope(|scope| {
-        let db = db;
+    // the modules are emitted in parallel, the first error is reported once all jobs finished
+    let errors = Mutex::new(Vec::new());
+    let res: Result<()> = rayon_core::scope(|scope| {
+        let dbs = &dbs;
         let literals_ = &literals;
         let target_data_ = &target_data;
         let paths = &paths;
+        let errors = &errors;
 
-        for (i, module) in modules.iter().enumerate() {
+        for (i, (file, module)) in modules.iter().enumerate() {
+            let db = &dbs[*file];
             let _db = db.snapshot();
             scope.spawn(move |_| {
                 let access = format!("access_{}", &module.sym);
                 let llmod = unsafe { back.new_module(&access, opt_lvl).unwrap() };
-                let cx = new_codegen(back, &llmod, literals_);
+                let cx = new_codegen(back, &llmod, literals_, version);
                 let tys = OsdiTys::new(&cx, target_data_);
                 let cguint = OsdiCompilationUnit::new(&_db, module, &cx, &tys, false);
 
                 cguint.access_function();
+                suffix_symbols(&cx, sym_suffix);
                 debug_assert!(llmod.verify_and_print());
 
-                if emit {
-                    let path = &paths[i * 4];
+                if emit.codegen() {
                     llmod.optimize();
-                    assert_eq!(llmod.emit_object(path.as_ref()), Ok(()))
+                    if let Err(err) = emit.write(&llmod, &paths[i * 4]) {
+                        errors.lock().unwrap().push(err);
+                    }
                 }
             });
 
@@ -111,17 +303,24 @@ pub fn compile(
             scope.spawn(move |_| {
                 let name = format!("setup_model_{}", &module.sym);
                 let llmod = unsafe { back.new_module(&name, opt_lvl).unwrap() };
-                let cx = new_codegen(back, &llmod, literals_);
+                let cx = new_codegen(back, &llmod, literals_, version);
                 let tys = OsdiTys::new(&cx, target_data_);
-                let cguint = OsdiCompilationUnit::new(&_db, module, &cx, &tys, false);
+                let debug_info = new_debug_info(debug_info, &_db, &cx, opt_lvl);
+                let mut cguint = OsdiCompilationUnit::new(&_db, module, &cx, &tys, false);
+                cguint.debug_info = debug_info.as_ref();
 
                 cguint.setup_model();
+                if let Some(debug_info) = &debug_info {
+                    debug_info.finalize();
+                }
+                suffix_symbols(&cx, sym_suffix);
                 debug_assert!(llmod.verify_and_print());
 
-                if emit {
-                    let path = &paths[i * 4 + 1];
+                if emit.codegen() {
                     // llmod.optimize();
-                    assert_eq!(llmod.emit_object(path.as_ref()), Ok(()))
+                    if let Err(err) = emit.write(&llmod, &paths[i * 4 + 1]) {
+                        errors.lock().unwrap().push(err);
+                    }
                 }
             });
 
@@ -129,17 +328,24 @@ pub fn compile(
             scope.spawn(move |_| {
                 let name = format!("setup_instance_{}", &module.sym);
                 let llmod = unsafe { back.new_module(&name, opt_lvl).unwrap() };
-                let cx = new_codegen(back, &llmod, literals_);
+                let cx = new_codegen(back, &llmod, literals_, version);
                 let tys = OsdiTys::new(&cx, target_data_);
+                let debug_info = new_debug_info(debug_info, &_db, &cx, opt_lvl);
                 let mut cguint = OsdiCompilationUnit::new(&_db, module, &cx, &tys, false);
+                cguint.debug_info = debug_info.as_ref();
 
                 cguint.setup_instance();
+                if let Some(debug_info) = &debug_info {
+                    debug_info.finalize();
+                }
+                suffix_symbols(&cx, sym_suffix);
                 debug_assert!(llmod.verify_and_print());
 
-                if emit {
-                    let path = &paths[i * 4 + 2];
+                if emit.codegen() {
                     llmod.optimize();
-                    assert_eq!(llmod.emit_object(path.as_ref()), Ok(()))
+                    if let Err(err) = emit.write(&llmod, &paths[i * 4 + 2]) {
+                        errors.lock().unwrap().push(err);
+                    }
                 }
             });
 
@@ -147,37 +353,63 @@ pub fn compile(
             scope.spawn(move |_| {
                 let access = format!("eval_{}", &module.sym);
                 let llmod = unsafe { back.new_module(&access, opt_lvl).unwrap() };
-                let cx = new_codegen(back, &llmod, literals_);
+                let cx = new_codegen(back, &llmod, literals_, version);
                 let tys = OsdiTys::new(&cx, target_data_);
-                let cguint = OsdiCompilationUnit::new(&_db, module, &cx, &tys, true);
+                let debug_info = new_debug_info(debug_info, &_db, &cx, opt_lvl);
+                let mut cguint = OsdiCompilationUnit::new(&_db, module, &cx, &tys, true);
+                cguint.debug_info = debug_info.as_ref();
+                cguint.float_sanitizer = float_sanitizer;
 
                 // println!("{:?}", module.eval);
-                cguint.eval();
+                let eval = cguint.eval();
+                cguint.eval_batch(eval);
+                if !module.info.sensitivity_params.is_empty() {
+                    cguint.eval_sensitivity();
+                }
+                if let Some(debug_info) = &debug_info {
+                    debug_info.finalize();
+                }
                 // println!("{}", llmod.to_str());
+                suffix_symbols(&cx, sym_suffix);
                 debug_assert!(llmod.verify_and_print());
 
-                if emit {
-                    let path = &paths[i * 4 + 3];
+                if emit.codegen() {
                     llmod.optimize();
-                    assert_eq!(llmod.emit_object(path.as_ref()), Ok(()))
+                    if let Err(err) = emit.write(&llmod, &paths[i * 4 + 3]) {
+                        errors.lock().unwrap().push(err);
+                    }
                 }
             });
         }
 
         let llmod = unsafe { back.new_module(&name, opt_lvl).unwrap() };
-        let cx = new_codegen(back, &llmod, &literals);
+        let cx = new_codegen(back, &llmod, &literals, version);
         let tys = OsdiTys::new(&cx, target_data);
-
-        let descriptors: Vec<_> = modules
+        let tys_0_4 = osdi_0_4::OsdiTys::new(&cx, target_data);
+        let descriptor_ty = match version {
+            OsdiVersion::V0_3 => tys.osdi_descriptor,
+            OsdiVersion::V0_4 => tys_0_4.osdi_descriptor,
+        };
+
+        let extensions_ty = ty_descriptor_extensions(&cx);
+        let (descriptors, extensions): (Vec<_>, Vec<_>) = modules
             .iter()
-            .map(|module| {
-                let cguint = OsdiCompilationUnit::new(&db, module, &cx, &tys, false);
-                let descriptor = cguint.descriptor(target_data, &db);
-                descriptor.to_ll_val(&cx, &tys)
+            .map(|(file, module)| {
+                let db = &dbs[*file];
+                let cguint = OsdiCompilationUnit::new(db, module, &cx, &tys, false);
+                let descriptor = match version {
+                    OsdiVersion::V0_3 => cguint.descriptor(target_data, db).to_ll_val(&cx, &tys),
+                    OsdiVersion::V0_4 => cguint
+                        .descriptor_0_4(target_data, db, natures.as_ref().unwrap())
+                        .to_ll_val(&cx, &tys_0_4),
+                };
+                let extensions = cguint.descriptor_extensions(extensions_ty, target_data);
+                (descriptor, extensions)
             })
-            .collect();
+            .unzip();
 
-        cx.export_array("OSDI_DESCRIPTORS", tys.osdi_descriptor, &descriptors, true, false);
+        cx.export_array("OSDI_DESCRIPTORS", descriptor_ty, &descriptors, true, false);
+        cx.export_array(DESCRIPTOR_EXTENSIONS, cx.ty_ptr(), &extensions, true, false);
         cx.export_val(
             "OSDI_NUM_DESCRIPTORS",
             cx.ty_int(),
@@ -187,16 +419,20 @@ pub fn compile(
         cx.export_val(
             "OSDI_VERSION_MAJOR",
             cx.ty_int(),
-            cx.const_unsigned_int(OSDI_VERSION.0),
+            cx.const_unsigned_int(version.numbers().0),
             true,
         );
         cx.export_val(
             "OSDI_VERSION_MINOR",
             cx.ty_int(),
-            cx.const_unsigned_int(OSDI_VERSION.1),
+            cx.const_unsigned_int(version.numbers().1),
             true,
         );
 
+        if let Some(natures) = &natures {
+            natures.export(&cx, &tys_0_4);
+        }
+
         if !lim_table.is_empty() {
             let lim: Vec<_> = lim_table.iter().map(|entry| entry.to_ll_val(&cx, &tys)).collect();
             cx.export_array("OSDI_LIM_TABLE", tys.osdi_lim_function, &lim, false, false);
@@ -208,6 +444,17 @@ pub fn compile(
             );
         }
 
+        if let Some(map) = &coverage_map {
+            let ty = cx.ty_array(cx.ty_aint(64), map.num_counters);
+            cx.export_val("OSDI_COVERAGE_COUNTERS", ty, cx.const_null(ty), false);
+            cx.export_val(
+                "OSDI_COVERAGE_LEN",
+                cx.ty_int(),
+                cx.const_unsigned_int(map.num_counters),
+                true,
+            );
+        }
+
         let osdi_log =
             cx.get_declared_value("osdi_log").expect("symbol osdi_log missing from std lib");
         let val = cx.const_null_ptr();
@@ -218,19 +465,177 @@ pub fn compile(
             llvm::LLVMSetDLLStorageClass(osdi_log, llvm::DLLStorageClass::Export);
         }
 
+        suffix_symbols(&cx, sym_suffix);
         debug_assert!(llmod.verify_and_print());
 
-        if emit {
+        if emit.codegen() {
             // println!("{}", llmod.to_str());
             llmod.optimize();
             // println!("{}", llmod.to_str());
-            assert_eq!(llmod.emit_object(main_file.as_ref()), Ok(()))
+            emit.write(&llmod, &main_file)?;
         }
+        Ok(())
     });
 
-    paths.push(main_file);
     unsafe { LLVMDisposeTargetData(target_data) };
-    paths
+    res?;
+    if let Some(err) = errors.into_inner().unwrap().into_iter().next() {
+        return Err(err);
+    }
+    paths.push(main_file);
+    Ok(paths)
+}
+
+/// Appends `_{suffix}` to the [`EXPORTED_SYMBOLS`] defined or referenced by the module of `cx`.
+fn suffix_symbols(cx: &CodegenCx<'_, '_>, suffix: Option<&str>) {
+    let suffix = if let Some(suffix) = suffix { suffix } else { return };
+    for sym in EXPORTED_SYMBOLS {
+        if let Some(val) = cx.get_declared_value(sym) {
+            let name = format!("{sym}_{suffix}");
+            unsafe { llvm::LLVMSetValueName2(val, name.as_ptr().cast(), name.len()) }
+        }
+    }
+}
+
+/// Declares the symbols of a library compiled with `sym_suffix` for use from C/C++.
+fn c_header(
+    name: &str,
+    suffix: &str,
+    lim_table: bool,
+    coverage: bool,
+    version: OsdiVersion,
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "/* Generated by OpenVAF: the OSDI symbols exported by {name} */\n");
+    out.push_str("#pragma once\n\n#include <stdint.h>\n\n");
+    let (major, minor) = version.numbers();
+    let _ = writeln!(out, "#include \"osdi_{major}_{minor}.h\"");
+    out.push_str("#include \"openvaf_ext.h\"\n\n");
+    out.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
+    let _ = writeln!(out, "extern const OsdiDescriptor OSDI_DESCRIPTORS_{suffix}[];");
+    let _ = writeln!(
+        out,
+        "extern const OsdiDescriptorExtensions *const OSDI_DESCRIPTOR_EXTENSIONS_{suffix}[];"
+    );
+    for sym in ["OSDI_NUM_DESCRIPTORS", "OSDI_VERSION_MAJOR", "OSDI_VERSION_MINOR"] {
+        let _ = writeln!(out, "extern const uint32_t {sym}_{suffix};");
+    }
+    if version == OsdiVersion::V0_4 {
+        for (ty, sym) in [
+            ("OsdiNature", "NATURES"),
+            ("OsdiDiscipline", "DISCIPLINES"),
+            ("OsdiAttribute", "ATTRIBUTES"),
+        ] {
+            let _ = writeln!(out, "extern const {ty} OSDI_{sym}_{suffix}[];");
+            let _ = writeln!(out, "extern const uint32_t OSDI_NUM_{sym}_{suffix};");
+        }
+    }
+    if lim_table {
+        let _ = writeln!(out, "extern OsdiLimFunction OSDI_LIM_TABLE_{suffix}[];");
+        let _ = writeln!(out, "extern const uint32_t OSDI_LIM_TABLE_LEN_{suffix};");
+    }
+    if coverage {
+        let _ = writeln!(out, "extern uint64_t OSDI_COVERAGE_COUNTERS_{suffix}[];");
+        let _ = writeln!(out, "extern const uint32_t OSDI_COVERAGE_LEN_{suffix};");
+    }
+    let _ =
+        writeln!(out, "extern void (*osdi_log_{suffix})(void *handle, char *msg, uint32_t lvl);");
+    out.push_str("\n#ifdef __cplusplus\n}\n#endif\n");
+    out
+}
+
+/// Prints the MIR functions of `module` so that they can be parsed with `mir_reader`.
+fn module_mir(module: &OsdiModule, literals: &Rodeo) -> String {
+    let funcs = [
+        ("setup_model", module.model_param_setup),
+        ("setup_instance", &module.init.func),
+        ("eval", module.eval),
+    ];
+    let mut out = String::new();
+    for (name, func) in funcs {
+        // name the functions after the LLVM modules they are compiled into
+        let mut func = func.clone();
+        func.name = format!("{name}_{}", module.sym);
+        let _ = writeln!(out, "{}", func.print(literals));
+    }
+    out
+}
+
+/// The MIR of the modules of all root files. It is shared by [`compile`] and [`c_source`] so
+/// that each module is only lowered once (and can be analysed before code generation).
+pub struct LoweredModules<'a> {
+    /// The index of the root file that declares the module and its MIR for every module in the
+    /// order in which the descriptors are exported.
+    pub modules: Vec<(usize, CompiledModule<'a>)>,
+    literals: Rodeo,
+    lim_table: TiSet<OsdiLimId, OsdiLimFunction>,
+}
+
+/// Lowers the `modules` of all root files to MIR and collects the `$limit` functions they call.
+pub fn lower_modules<'a>(inputs: &[(&CompilationDB, &'a [ModuleInfo])]) -> LoweredModules<'a> {
+    let mut literals = Rodeo::new();
+    let mut lim_table = TiSet::default();
+    let mut compiled = Vec::new();
+    for (file, &(db, modules)) in inputs.iter().enumerate() {
+        for module in modules {
+            let mir = CompiledModule::new(db, module, &mut literals);
+            for cb in mir.intern.callbacks.iter() {
+                if let CallBackKind::BuiltinLimit { name, num_args } = *cb {
+                    lim_table.ensure(OsdiLimFunction { name, num_args: num_args - 2 });
+                }
+            }
+            compiled.push((file, mir));
+        }
+    }
+    LoweredModules { modules: compiled, literals, lim_table }
+}
+
+/// Assigns each module its (unique) symbol suffix and interns the strings its descriptor refers to.
+/// Fails if the symbols of two modules collide.
+fn osdi_modules<'a>(
+    inputs: &[(&'a CompilationDB, &[ModuleInfo])],
+    compiled: &'a [(usize, CompiledModule)],
+    lim_table: &'a TiSet<OsdiLimId, OsdiLimFunction>,
+    literals: &mut Rodeo,
+) -> Result<Vec<(usize, OsdiModule<'a>)>> {
+    let modules: Vec<_> = compiled
+        .iter()
+        .map(|(file, module)| {
+            let db = inputs[*file].0;
+            let mut unit = OsdiModule::new(db, module, lim_table);
+            // the uuids of modules are only unique within one database
+            if *file != 0 {
+                unit.sym = format!("{}_{file}", unit.sym);
+            }
+            unit.intern_names(literals, db);
+            (*file, unit)
+        })
+        .collect();
+
+    let mut syms = HashMap::new();
+    for (file, module) in &modules {
+        let name = module.info.module.name(inputs[*file].0);
+        if let Some(other) = syms.get(&module.sym) {
+            bail!("the modules {other} and {name} both export the symbols {}", module.sym);
+        }
+        syms.insert(&module.sym, name);
+    }
+
+    Ok(modules)
+}
+
+fn new_debug_info<'ll>(
+    enabled: bool,
+    db: &CompilationDB,
+    cx: &CodegenCx<'_, 'll>,
+    opt_lvl: OptLevel,
+) -> Option<DebugInfo<'ll>> {
+    if !enabled {
+        return None;
+    }
+    let root_file = db.compilation_unit().root_file();
+    let main_file = db.vfs().read().file_path(root_file).to_string();
+    Some(unsafe { DebugInfo::new(cx, &main_file, opt_lvl != OptLevel::None) })
 }
 
 impl OsdiModule<'_> {
@@ -4,6 +4,7 @@ use llvm::{
     LLVMDisposeBuilder, LLVMGetParam, LLVMPositionBuilderAtEnd, LLVMSetFastMath,
     LLVMSetPartialFastMath, UNNAMED,
 };
+use mir::F_ZERO;
 use sim_back::dae::NoiseSourceKind;
 use stdx::iter::zip;
 use typed_index_collections::TiVec;
@@ -18,19 +19,19 @@ pub enum JacobianLoadType {
 }
 
 impl JacobianLoadType {
-    const fn dst_reactive(self) -> bool {
+    pub const fn dst_reactive(self) -> bool {
         matches!(self, JacobianLoadType::React)
     }
 
-    const fn read_resistive(self) -> bool {
+    pub const fn read_resistive(self) -> bool {
         matches!(self, JacobianLoadType::Resist | JacobianLoadType::Tran)
     }
 
-    const fn read_reactive(self) -> bool {
+    pub const fn read_reactive(self) -> bool {
         matches!(self, JacobianLoadType::React | JacobianLoadType::Tran)
     }
 
-    const fn name(self) -> &'static str {
+    pub const fn name(self) -> &'static str {
         match self {
             JacobianLoadType::Tran => "tran",
             JacobianLoadType::Resist => "resist",
@@ -325,6 +326,7 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
                         llbuilder,
                         kind.dst_reactive(),
                         res,
+                        None,
                     );
                 }
             }
@@ -335,4 +337,90 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
 
         llfunc
     }
+
+    /// `load_jacobian_with_offset_{resist,react}(inst, model, offset)` of OSDI 0.4: like
+    /// `load_jacobian_{resist,react}` (without scaling the reactive entries) but the values are
+    /// added `offset` elements after the matrix pointers of each entry.
+    pub fn load_jacobian_with_offset(&self, reactive: bool) -> &'ll llvm::Value {
+        let OsdiCompilationUnit { cx, module, .. } = *self;
+        let fun_ty = cx.ty_func(&[cx.ty_ptr(), cx.ty_ptr(), cx.ty_size()], cx.ty_void());
+        let kind = if reactive { "react" } else { "resist" };
+        let name = &format!("load_jacobian_with_offset_{kind}_{}", &module.sym);
+        let llfunc = cx.declare_int_c_fn(name, fun_ty);
+
+        unsafe {
+            let entry = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+            let llbuilder = LLVMCreateBuilderInContext(cx.llcx);
+
+            LLVMPositionBuilderAtEnd(llbuilder, entry);
+            // get params
+            let inst = LLVMGetParam(llfunc, 0);
+            let model = LLVMGetParam(llfunc, 1);
+            let offset = LLVMGetParam(llfunc, 2);
+
+            for entry in module.dae_system.jacobian.keys() {
+                if let Some(val) = self.load_jacobian_entry(entry, inst, model, llbuilder, reactive)
+                {
+                    self.inst_data.store_jacobian_contrib(
+                        cx,
+                        entry,
+                        inst,
+                        llbuilder,
+                        reactive,
+                        val,
+                        Some(offset),
+                    );
+                }
+            }
+
+            LLVMBuildRetVoid(llbuilder);
+            LLVMDisposeBuilder(llbuilder);
+        }
+
+        llfunc
+    }
+
+    /// `write_jacobian_array_{resist,react}(inst, model, dst)` of OSDI 0.4: writes the
+    /// resistive (reactive) Jacobian entries to `dst` in the order of `jacobian_entries`.
+    /// Only the entries with the `JACOBIAN_ENTRY_RESIST` (`JACOBIAN_ENTRY_REACT`) flag are
+    /// written, so `dst` must have room for `num_resistive_jacobian_entries`
+    /// (`num_reactive_jacobian_entries`) values.
+    pub fn write_jacobian_array(&self, reactive: bool) -> &'ll llvm::Value {
+        let OsdiCompilationUnit { cx, module, .. } = *self;
+        let fun_ty = cx.ty_func(&[cx.ty_ptr(), cx.ty_ptr(), cx.ty_ptr()], cx.ty_void());
+        let kind = if reactive { "react" } else { "resist" };
+        let name = &format!("write_jacobian_array_{kind}_{}", &module.sym);
+        let llfunc = cx.declare_int_c_fn(name, fun_ty);
+
+        unsafe {
+            let entry = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+            let llbuilder = LLVMCreateBuilderInContext(cx.llcx);
+
+            LLVMPositionBuilderAtEnd(llbuilder, entry);
+            // get params
+            let inst = LLVMGetParam(llfunc, 0);
+            let model = LLVMGetParam(llfunc, 1);
+            let dst = LLVMGetParam(llfunc, 2);
+
+            let mut pos = 0;
+            for (id, entry) in module.dae_system.jacobian.iter_enumerated() {
+                let val = if reactive { entry.react } else { entry.resist };
+                if val == F_ZERO {
+                    continue;
+                }
+                let val = self
+                    .load_jacobian_entry(id, inst, model, llbuilder, reactive)
+                    .unwrap_or_else(|| cx.const_real(0.0));
+                let off = cx.const_unsigned_int(pos);
+                let ptr = LLVMBuildGEP2(llbuilder, cx.ty_double(), dst, [off].as_ptr(), 1, UNNAMED);
+                LLVMBuildStore(llbuilder, val, ptr);
+                pos += 1;
+            }
+
+            LLVMBuildRetVoid(llbuilder);
+            LLVMDisposeBuilder(llbuilder);
+        }
+
+        llfunc
+    }
 }
@@ -1,7 +1,11 @@
 use std::iter::once;
 
-use hir::{CompilationDB, ParamSysFun, Type};
-use hir_lower::CurrentKind;
+use ahash::RandomState;
+use hir::{
+    CompilationDB, Discipline, DisciplineAttrKind, Domain, Literal, Nature, ParamSysFun, Type,
+};
+use hir_lower::{CurrentKind, ParamKind};
+use indexmap::{IndexMap, IndexSet};
 use lasso::{Rodeo, Spur};
 use llvm::{LLVMABISizeOfType, LLVMOffsetOfElement, TargetData};
 use mir::{ValueDef, F_ZERO};
@@ -21,10 +25,17 @@ use crate::metadata::osdi_0_3::{
     JACOBIAN_ENTRY_RESIST_CONST, PARA_KIND_INST, PARA_KIND_MODEL, PARA_KIND_OPVAR, PARA_TY_INT,
     PARA_TY_REAL, PARA_TY_STR,
 };
+use crate::metadata::osdi_0_4::{
+    OsdiDiscipline, OsdiNature, OsdiNatureRef, ATTR_TYPE_INT, ATTR_TYPE_REAL, ATTR_TYPE_STR,
+    DOMAIN_CONTINUOUS, DOMAIN_DISCRETE, DOMAIN_NOT_GIVEN, NATREF_DISCIPLINE_FLOW,
+    NATREF_DISCIPLINE_POTENTIAL, NATREF_NATURE, NATREF_NONE,
+};
 use crate::ty_len;
 
 #[allow(unused_parens, dead_code)]
 pub mod osdi_0_3;
+#[allow(unused_parens, dead_code)]
+pub mod osdi_0_4;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub struct OsdiLimFunction {
@@ -181,6 +192,28 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
         }
     }
 
+    pub fn jacobian_entry_flags(&self, entry: &MatrixEntry) -> u32 {
+        let mut flags = 0;
+
+        if self.is_const(entry, false) {
+            flags |= JACOBIAN_ENTRY_RESIST_CONST
+        }
+
+        if self.is_const(entry, true) {
+            flags |= JACOBIAN_ENTRY_REACT_CONST
+        }
+
+        if entry.resist != F_ZERO {
+            flags |= JACOBIAN_ENTRY_RESIST;
+        }
+
+        if entry.react != F_ZERO {
+            flags |= JACOBIAN_ENTRY_REACT;
+        }
+
+        flags
+    }
+
     pub fn jacobian_entries(&self, target_data: &TargetData) -> Vec<OsdiJacobianEntry> {
         let OsdiCompilationUnit { inst_data, module, .. } = self;
         let mut jacobian_ptr_react_offset =
@@ -191,23 +224,10 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
             .jacobian
             .iter()
             .map(|entry| {
-                let mut flags = 0;
+                let flags = self.jacobian_entry_flags(entry);
                 let mut react_ptr_off = u32::MAX;
 
-                if self.is_const(entry, false) {
-                    flags |= JACOBIAN_ENTRY_RESIST_CONST
-                }
-
-                if self.is_const(entry, true) {
-                    flags |= JACOBIAN_ENTRY_REACT_CONST
-                }
-
-                if entry.resist != F_ZERO {
-                    flags |= JACOBIAN_ENTRY_RESIST;
-                }
-
                 if entry.react != F_ZERO {
-                    flags |= JACOBIAN_ENTRY_REACT;
                     react_ptr_off = jacobian_ptr_react_offset;
                     jacobian_ptr_react_offset += 8;
                 }
@@ -311,6 +331,379 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
             }
         }
     }
+
+    pub fn descriptor_0_4(
+        &self,
+        target_data: &llvm::TargetData,
+        db: &CompilationDB,
+        natures: &NatureTable,
+    ) -> osdi_0_4::OsdiDescriptor<'ll> {
+        let descriptor = self.descriptor(target_data, db);
+        let jacobian_entries = &descriptor.jacobian_entries;
+        let num_resistive_jacobian_entries = jacobian_entries
+            .iter()
+            .filter(|entry| entry.flags & JACOBIAN_ENTRY_RESIST != 0)
+            .count();
+        let num_reactive_jacobian_entries =
+            jacobian_entries.iter().filter(|entry| entry.flags & JACOBIAN_ENTRY_REACT != 0).count();
+        let (unknown_nature, residual_nature) = natures.unknown_natures(self.module, db);
+        let inputs = self.inputs();
+
+        osdi_0_4::OsdiDescriptor {
+            name: descriptor.name,
+            num_nodes: descriptor.num_nodes,
+            num_terminals: descriptor.num_terminals,
+            nodes: descriptor.nodes.into_iter().map(Into::into).collect(),
+            num_jacobian_entries: descriptor.num_jacobian_entries,
+            jacobian_entries: descriptor.jacobian_entries.into_iter().map(Into::into).collect(),
+            num_collapsible: descriptor.num_collapsible,
+            collapsible: descriptor.collapsible.into_iter().map(Into::into).collect(),
+            collapsed_offset: descriptor.collapsed_offset,
+            noise_sources: descriptor.noise_sources.into_iter().map(Into::into).collect(),
+            num_noise_src: descriptor.num_noise_src,
+            num_params: descriptor.num_params,
+            num_instance_params: descriptor.num_instance_params,
+            num_opvars: descriptor.num_opvars,
+            param_opvar: descriptor.param_opvar.into_iter().map(Into::into).collect(),
+            node_mapping_offset: descriptor.node_mapping_offset,
+            jacobian_ptr_resist_offset: descriptor.jacobian_ptr_resist_offset,
+            num_states: descriptor.num_states,
+            state_idx_off: descriptor.state_idx_off,
+            bound_step_offset: descriptor.bound_step_offset,
+            instance_size: descriptor.instance_size,
+            model_size: descriptor.model_size,
+            access: descriptor.access,
+            setup_model: descriptor.setup_model,
+            setup_instance: descriptor.setup_instance,
+            eval: descriptor.eval,
+            load_noise: descriptor.load_noise,
+            load_residual_resist: descriptor.load_residual_resist,
+            load_residual_react: descriptor.load_residual_react,
+            load_limit_rhs_resist: descriptor.load_limit_rhs_resist,
+            load_limit_rhs_react: descriptor.load_limit_rhs_react,
+            load_spice_rhs_dc: descriptor.load_spice_rhs_dc,
+            load_spice_rhs_tran: descriptor.load_spice_rhs_tran,
+            load_jacobian_resist: descriptor.load_jacobian_resist,
+            load_jacobian_react: descriptor.load_jacobian_react,
+            load_jacobian_tran: descriptor.load_jacobian_tran,
+
+            given_flag_model: self.given_flag_model(),
+            given_flag_instance: self.given_flag_instance(),
+            num_resistive_jacobian_entries: num_resistive_jacobian_entries as u32,
+            num_reactive_jacobian_entries: num_reactive_jacobian_entries as u32,
+            write_jacobian_array_resist: self.write_jacobian_array(false),
+            write_jacobian_array_react: self.write_jacobian_array(true),
+            num_inputs: inputs.len() as u32,
+            inputs,
+            load_jacobian_with_offset_resist: self.load_jacobian_with_offset(false),
+            load_jacobian_with_offset_react: self.load_jacobian_with_offset(true),
+            unknown_nature,
+            residual_nature,
+        }
+    }
+
+    /// The node pairs whose voltages are read by `eval`. `u32::MAX` is used for nodes that are
+    /// always zero (ground).
+    pub fn inputs(&self) -> Vec<osdi_0_4::OsdiNodePair> {
+        let unknowns = &self.module.dae_system.unknowns;
+        let node_idx =
+            |node| unknowns.index(&SimUnknownKind::KirchoffLaw(node)).map_or(u32::MAX, u32::from);
+        let mut inputs: IndexSet<(u32, u32), RandomState> = IndexSet::default();
+        for (kind, &val) in self.module.intern.params.raw.iter() {
+            if let ParamKind::Voltage { hi, lo } = *kind {
+                if !self.module.eval.dfg.value_dead(val) {
+                    inputs.insert((node_idx(hi), lo.map_or(u32::MAX, node_idx)));
+                }
+            }
+        }
+        inputs
+            .into_iter()
+            .map(|(node_1, node_2)| osdi_0_4::OsdiNodePair { node_1, node_2 })
+            .collect()
+    }
+}
+
+/// The natures and disciplines of all unknowns in a library together with their attributes.
+/// OSDI 0.4 libraries export these tables as `OSDI_NATURES`, `OSDI_DISCIPLINES` and
+/// `OSDI_ATTRIBUTES`. Natures and disciplines are identified by their name so that modules
+/// compiled from different files share the same entries.
+#[derive(Default)]
+pub struct NatureTable {
+    natures: IndexMap<String, OsdiNature, RandomState>,
+    disciplines: IndexMap<String, OsdiDiscipline, RandomState>,
+    attributes: Vec<OsdiAttribute>,
+}
+
+impl NatureTable {
+    pub fn insert_module(&mut self, module: &OsdiModule, db: &CompilationDB) {
+        for &unknown in module.dae_system.unknowns.iter() {
+            if let Some(discipline) = sim_unknown_discipline(unknown, db) {
+                self.insert_discipline(discipline, db);
+            }
+        }
+    }
+
+    fn insert_discipline(&mut self, discipline: Discipline, db: &CompilationDB) -> u32 {
+        let name = discipline.name(db);
+        if let Some(idx) = self.disciplines.get_index_of(&name) {
+            return idx as u32;
+        }
+
+        let flow = discipline.flow(db).map_or(u32::MAX, |nature| self.insert_nature(nature, db));
+        let potential =
+            discipline.potential(db).map_or(u32::MAX, |nature| self.insert_nature(nature, db));
+        let domain = match discipline.domain(db) {
+            None => DOMAIN_NOT_GIVEN,
+            Some(Domain::Discrete) => DOMAIN_DISCRETE,
+            Some(Domain::Continuous) => DOMAIN_CONTINUOUS,
+        };
+
+        // the flow overwrites are followed by the potential overwrites and the user attributes
+        let attrs = discipline.attributes(db);
+        let attr_start = self.attributes.len() as u32;
+        let mut num_attrs = [
+            DisciplineAttrKind::FlowOverwrite,
+            DisciplineAttrKind::PotentialOverwrite,
+            DisciplineAttrKind::UserDefined,
+        ]
+        .map(|kind| {
+            let start = self.attributes.len();
+            self.attributes.extend(
+                attrs
+                    .iter()
+                    .filter(|attr| attr.kind(db) == kind)
+                    .filter_map(|attr| Some(OsdiAttribute::new(attr.name(db), attr.literal(db)?))),
+            );
+            (self.attributes.len() - start) as u32
+        })
+        .into_iter();
+
+        let discipline = OsdiDiscipline {
+            name: name.clone(),
+            flow,
+            potential,
+            domain,
+            attr_start,
+            num_flow_attr: num_attrs.next().unwrap(),
+            num_potential_attr: num_attrs.next().unwrap(),
+            num_user_attr: num_attrs.next().unwrap(),
+        };
+        self.disciplines.insert_full(name, discipline).0 as u32
+    }
+
+    fn insert_nature(&mut self, nature: Nature, db: &CompilationDB) -> u32 {
+        let name = nature.name(db);
+        if let Some(idx) = self.natures.get_index_of(&name) {
+            return idx as u32;
+        }
+
+        let attr_start = self.attributes.len() as u32;
+        self.attributes.extend(
+            nature
+                .attributes(db)
+                .into_iter()
+                .filter_map(|attr| Some(OsdiAttribute::new(attr.name(db), attr.literal(db)?))),
+        );
+        let num_attr = self.attributes.len() as u32 - attr_start;
+
+        // the index is reserved before the related natures are inserted because
+        // natures may refer to each other with ddt_nature/idt_nature
+        let entry = OsdiNature {
+            name: name.clone(),
+            parent_type: NATREF_NONE,
+            parent: u32::MAX,
+            ddt: u32::MAX,
+            idt: u32::MAX,
+            attr_start,
+            num_attr,
+        };
+        let (idx, _) = self.natures.insert_full(name, entry);
+
+        if let Some(parent) = nature.parent(db) {
+            let parent = self.insert_nature(parent, db);
+            self.natures[idx].parent_type = NATREF_NATURE;
+            self.natures[idx].parent = parent;
+        }
+        let ddt = self.insert_nature(nature.ddt_nature(db), db);
+        let idt = self.insert_nature(nature.idt_nature(db), db);
+        self.natures[idx].ddt = ddt;
+        self.natures[idx].idt = idt;
+        idx as u32
+    }
+
+    /// The natures of the unknowns and the residuals of `module`. They refer to the
+    /// potential/flow of the discipline of the node or branch. Implicit equations have no nature.
+    pub fn unknown_natures(
+        &self,
+        module: &OsdiModule,
+        db: &CompilationDB,
+    ) -> (Vec<OsdiNatureRef>, Vec<OsdiNatureRef>) {
+        module
+            .dae_system
+            .unknowns
+            .iter()
+            .map(|&unknown| {
+                let discipline = match sim_unknown_discipline(unknown, db) {
+                    Some(discipline) => discipline,
+                    None => {
+                        let none = || OsdiNatureRef { ref_type: NATREF_NONE, index: u32::MAX };
+                        return (none(), none());
+                    }
+                };
+                let index = self.disciplines.get_index_of(&discipline.name(db)).unwrap() as u32;
+                let (unknown, residual) = if matches!(unknown, SimUnknownKind::Current(_)) {
+                    (NATREF_DISCIPLINE_FLOW, NATREF_DISCIPLINE_POTENTIAL)
+                } else {
+                    (NATREF_DISCIPLINE_POTENTIAL, NATREF_DISCIPLINE_FLOW)
+                };
+                (
+                    OsdiNatureRef { ref_type: unknown, index },
+                    OsdiNatureRef { ref_type: residual, index },
+                )
+            })
+            .unzip()
+    }
+
+    pub fn intern_strs(&self, literals: &mut Rodeo) {
+        for name in self.natures.keys().chain(self.disciplines.keys()) {
+            literals.get_or_intern(name);
+        }
+        for attr in &self.attributes {
+            literals.get_or_intern(&attr.name);
+            if let OsdiAttributeValue::String(val) = &attr.value {
+                literals.get_or_intern(val);
+            }
+        }
+    }
+
+    /// Exports the `OSDI_NATURES`, `OSDI_DISCIPLINES` and `OSDI_ATTRIBUTES` tables.
+    pub fn export<'ll>(&self, cx: &CodegenCx<'_, 'll>, tys: &'ll osdi_0_4::OsdiTys) {
+        let natures: Vec<_> = self.natures.values().map(|it| it.to_ll_val(cx, tys)).collect();
+        let disciplines: Vec<_> =
+            self.disciplines.values().map(|it| it.to_ll_val(cx, tys)).collect();
+        let attributes: Vec<_> = self.attributes.iter().map(|it| it.to_ll_val(cx, tys)).collect();
+        for (name, ty, vals) in [
+            ("NATURES", tys.osdi_nature, natures),
+            ("DISCIPLINES", tys.osdi_discipline, disciplines),
+            ("ATTRIBUTES", tys.osdi_attribute, attributes),
+        ] {
+            cx.export_array(&format!("OSDI_{name}"), ty, &vals, true, false);
+            cx.export_val(
+                &format!("OSDI_NUM_{name}"),
+                cx.ty_int(),
+                cx.const_unsigned_int(vals.len() as u32),
+                true,
+            );
+        }
+    }
+}
+
+/// An entry of `OSDI_ATTRIBUTES`. This is written by hand because sourcegen
+/// does not generate constants for unions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsdiAttribute {
+    pub name: String,
+    pub value: OsdiAttributeValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OsdiAttributeValue {
+    Real(f64),
+    Integer(i32),
+    String(String),
+}
+
+impl OsdiAttribute {
+    pub fn new(name: String, value: Literal) -> OsdiAttribute {
+        let value = match value {
+            Literal::String(val) => OsdiAttributeValue::String(val.into()),
+            Literal::Int(val) => OsdiAttributeValue::Integer(val),
+            Literal::Float(val) => OsdiAttributeValue::Real(val.into()),
+            Literal::Inf => OsdiAttributeValue::Real(f64::INFINITY),
+        };
+        OsdiAttribute { name, value }
+    }
+
+    pub fn to_ll_val<'ll>(
+        &self,
+        cx: &CodegenCx<'_, 'll>,
+        tys: &'ll osdi_0_4::OsdiTys,
+    ) -> &'ll llvm::Value {
+        // all supported targets are 64 bit so the union is lowered to [1 x i64]
+        let int_ty = cx.ty_aint(64);
+        let (value_type, value) = unsafe {
+            match self.value {
+                OsdiAttributeValue::Real(val) => {
+                    (ATTR_TYPE_REAL, llvm::LLVMConstInt(int_ty, val.to_bits(), llvm::False))
+                }
+                OsdiAttributeValue::Integer(val) => {
+                    (ATTR_TYPE_INT, llvm::LLVMConstInt(int_ty, val as u32 as u64, llvm::False))
+                }
+                OsdiAttributeValue::String(ref val) => {
+                    (ATTR_TYPE_STR, llvm::LLVMConstPtrToInt(cx.const_str_uninterned(val), int_ty))
+                }
+            }
+        };
+        debug_assert!(std::ptr::eq(cx.ty_array(int_ty, 1), tys.osdi_attribute_value));
+        let fields = [
+            cx.const_str_uninterned(&self.name),
+            cx.const_unsigned_int(value_type),
+            cx.const_arr(int_ty, &[value]),
+        ];
+        cx.const_struct(tys.osdi_attribute, &fields)
+    }
+}
+
+// The structs below have the same layout in OSDI 0.3 and 0.4.
+
+impl From<OsdiNodePair> for osdi_0_4::OsdiNodePair {
+    fn from(pair: OsdiNodePair) -> Self {
+        osdi_0_4::OsdiNodePair { node_1: pair.node_1, node_2: pair.node_2 }
+    }
+}
+
+impl From<OsdiNode> for osdi_0_4::OsdiNode {
+    fn from(node: OsdiNode) -> Self {
+        osdi_0_4::OsdiNode {
+            name: node.name,
+            units: node.units,
+            residual_units: node.residual_units,
+            resist_residual_off: node.resist_residual_off,
+            react_residual_off: node.react_residual_off,
+            resist_limit_rhs_off: node.resist_limit_rhs_off,
+            react_limit_rhs_off: node.react_limit_rhs_off,
+            is_flow: node.is_flow,
+        }
+    }
+}
+
+impl From<OsdiJacobianEntry> for osdi_0_4::OsdiJacobianEntry {
+    fn from(entry: OsdiJacobianEntry) -> Self {
+        osdi_0_4::OsdiJacobianEntry {
+            nodes: entry.nodes.into(),
+            react_ptr_off: entry.react_ptr_off,
+            flags: entry.flags,
+        }
+    }
+}
+
+impl From<OsdiNoiseSource> for osdi_0_4::OsdiNoiseSource {
+    fn from(source: OsdiNoiseSource) -> Self {
+        osdi_0_4::OsdiNoiseSource { name: source.name, nodes: source.nodes.into() }
+    }
+}
+
+impl From<OsdiParamOpvar> for osdi_0_4::OsdiParamOpvar {
+    fn from(param: OsdiParamOpvar) -> Self {
+        osdi_0_4::OsdiParamOpvar {
+            name: param.name,
+            num_alias: param.num_alias,
+            description: param.description,
+            units: param.units,
+            flags: param.flags,
+            len: param.len,
+        }
+    }
 }
 
 impl OsdiModule<'_> {
@@ -323,15 +716,24 @@ impl OsdiModule<'_> {
     }
 }
 
-fn sim_unknown_info(unknown: SimUnknownKind, db: &CompilationDB) -> (String, String, bool) {
+/// The discipline of the node or branch that `unknown` belongs to (`None` for implicit equations).
+pub fn sim_unknown_discipline(unknown: SimUnknownKind, db: &CompilationDB) -> Option<Discipline> {
+    match unknown {
+        SimUnknownKind::KirchoffLaw(node)
+        | SimUnknownKind::Current(CurrentKind::Unnamed { hi: node, .. })
+        | SimUnknownKind::Current(CurrentKind::Port(node)) => Some(node.discipline(db)),
+        SimUnknownKind::Current(CurrentKind::Branch(br)) => Some(br.discipline(db)),
+        SimUnknownKind::Implicit(_) => None,
+    }
+}
+
+pub fn sim_unknown_info(unknown: SimUnknownKind, db: &CompilationDB) -> (String, String, bool) {
     let name;
-    let discipline;
     let is_flow;
 
     match unknown {
         SimUnknownKind::KirchoffLaw(node) => {
             name = node.name(db).to_string();
-            discipline = Some(node.discipline(db));
             is_flow = false;
         }
 
@@ -341,25 +743,22 @@ fn sim_unknown_info(unknown: SimUnknownKind, db: &CompilationDB) -> (String, Str
             } else {
                 format!("flow({})", &hi.name(db))
             };
-            discipline = Some(hi.discipline(db));
             is_flow = true;
         }
         SimUnknownKind::Current(CurrentKind::Branch(br)) => {
             name = format!("flow({})", &br.name(db));
-            discipline = Some(br.discipline(db));
             is_flow = true;
         }
         SimUnknownKind::Current(CurrentKind::Port(node)) => {
             name = format!("flow(<{}>)", &node.name(db));
-            discipline = Some(node.discipline(db));
             is_flow = true;
         }
         SimUnknownKind::Implicit(equ) => {
             name = format!("implicit_equation_{}", u32::from(equ));
-            discipline = None;
             is_flow = false;
         }
     };
+    let discipline = sim_unknown_discipline(unknown, db);
 
     // its valid to have disciplines without pot/flow nature but then we can't
     // have branches for those so its ok to unwrap here
@@ -14,6 +14,12 @@ const STDLIB_BITCODE_AARCH64_PC_WINDOWS_MSVC: &[u8] =
     include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_aarch64-pc-windows-msvc.bc"));
 const STDLIB_BITCODE_ARM64_APPLE_MACOSX11_0_0: &[u8] =
     include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_arm64-apple-macosx11.0.0.bc"));
+const STDLIB_BITCODE_RISCV64_UNKNOWN_LINUX_GNU: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_riscv64-unknown-linux-gnu.bc"));
+const STDLIB_BITCODE_POWERPC64LE_UNKNOWN_LINUX_GNU: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_powerpc64le-unknown-linux-gnu.bc"));
+const STDLIB_BITCODE_X86_64_UNKNOWN_FREEBSD: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_x86_64-unknown-freebsd.bc"));
 pub fn stdlib_bitcode(target: &target::spec::Target) -> &'static [u8] {
     match &*target.llvm_target {
         "x86_64-unknown-linux-gnu" => STDLIB_BITCODE_X86_64_UNKNOWN_LINUX_GNU,
@@ -22,6 +28,9 @@ pub fn stdlib_bitcode(target: &target::spec::Target) -> &'static [u8] {
         "aarch64-unknown-linux-gnu" => STDLIB_BITCODE_AARCH64_UNKNOWN_LINUX_GNU,
         "aarch64-pc-windows-msvc" => STDLIB_BITCODE_AARCH64_PC_WINDOWS_MSVC,
         "arm64-apple-macosx11.0.0" => STDLIB_BITCODE_ARM64_APPLE_MACOSX11_0_0,
+        "riscv64-unknown-linux-gnu" => STDLIB_BITCODE_RISCV64_UNKNOWN_LINUX_GNU,
+        "powerpc64le-unknown-linux-gnu" => STDLIB_BITCODE_POWERPC64LE_UNKNOWN_LINUX_GNU,
+        "x86_64-unknown-freebsd" => STDLIB_BITCODE_X86_64_UNKNOWN_FREEBSD,
         triple => unreachable!("unknown target triple {triple}"),
     }
 }
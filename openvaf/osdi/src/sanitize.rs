@@ -0,0 +1,273 @@
+//! Runtime checks for non-finite values computed by `eval` (`--sanitize=float`).
+
+use std::cell::RefCell;
+
+use ahash::{AHashMap, AHashSet};
+use basedb::VfsStorage;
+use hir::{CompilationDB, Parameter, Type};
+use hir_lower::fmt::{DisplayKind, FmtArg};
+use hir_lower::ParamKind;
+use lasso::{Rodeo, Spur};
+use llvm::{
+    LLVMAppendBasicBlockInContext, LLVMBuildBr, LLVMBuildCall2, LLVMBuildCondBr, LLVMBuildFCmp,
+    LLVMBuildOr, LLVMPositionBuilderAtEnd, RealPredicate, UNNAMED,
+};
+use mir::Value;
+use mir_llvm::Builder;
+use sim_back::dae::{MatrixEntryId, SimUnknown};
+use typed_index_collections::TiVec;
+
+use crate::compilation_unit::{print_callback, resolve_src_loc, OsdiModule};
+use crate::metadata::osdi_0_3::EVAL_RET_FLAG_FATAL;
+use crate::metadata::sim_unknown_info;
+
+/// Checks inserted into `eval` after the residuals and Jacobian entries are stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum FloatSanitizer {
+    #[default]
+    Disabled,
+    /// The first non-finite value is reported with `osdi_log`.
+    Report,
+    /// The first non-finite value is reported and the simulation is aborted.
+    Abort,
+}
+
+/// At most this many parameters are listed in a report.
+const MAX_REPORTED_PARAMS: usize = 16;
+
+/// A value that is checked by the sanitizer.
+#[derive(Debug, Clone)]
+pub struct FloatCheck {
+    pub val: Value,
+    /// The format string of the report: `%g` is the checked value, followed by the values of `params`.
+    pub msg: Spur,
+    /// The parameters that `val` depends on.
+    pub params: Vec<(Value, FmtArg)>,
+}
+
+/// The checks of the residuals and Jacobian entries of a module, each consists of the check of the
+/// resistive and the reactive part.
+#[derive(Debug, Clone, Default)]
+pub struct ModuleFloatChecks {
+    pub residual: TiVec<SimUnknown, [FloatCheck; 2]>,
+    pub jacobian: TiVec<MatrixEntryId, [FloatCheck; 2]>,
+}
+
+impl OsdiModule<'_> {
+    /// Creates the checks of all residuals and Jacobian entries, their messages are interned.
+    pub fn add_float_checks(&mut self, db: &CompilationDB, literals: &mut Rodeo) {
+        let residual = self
+            .dae_system
+            .unknowns
+            .indices()
+            .map(|unknown| {
+                [false, true].map(|react| self.residual_check(db, unknown, react, literals))
+            })
+            .collect();
+        let jacobian = self
+            .dae_system
+            .jacobian
+            .keys()
+            .map(|entry| [false, true].map(|react| self.jacobian_check(db, entry, react, literals)))
+            .collect();
+        self.float_checks = Some(ModuleFloatChecks { residual, jacobian });
+    }
+
+    fn residual_check(
+        &self,
+        db: &CompilationDB,
+        unknown: SimUnknown,
+        reactive: bool,
+        literals: &mut Rodeo,
+    ) -> FloatCheck {
+        let (name, _, _) = sim_unknown_info(self.dae_system.unknowns[unknown], db);
+        let residual = &self.dae_system.residual[unknown];
+        let (val, kind) =
+            if reactive { (residual.react, "reactive") } else { (residual.resist, "resistive") };
+        self.float_check(db, &format!("the {kind} residual of {name}"), val, literals)
+    }
+
+    fn jacobian_check(
+        &self,
+        db: &CompilationDB,
+        entry: MatrixEntryId,
+        reactive: bool,
+        literals: &mut Rodeo,
+    ) -> FloatCheck {
+        let entry = &self.dae_system.jacobian[entry];
+        let (row, _, _) = sim_unknown_info(self.dae_system.unknowns[entry.row], db);
+        let (col, _, _) = sim_unknown_info(self.dae_system.unknowns[entry.col], db);
+        let (val, kind) =
+            if reactive { (entry.react, "reactive") } else { (entry.resist, "resistive") };
+        let what = format!("the {kind} Jacobian entry ({row}, {col})");
+        self.float_check(db, &what, val, literals)
+    }
+
+    /// The simulator identifies the instance by the handle passed to `osdi_log`, so the
+    /// message only names the module and lists the parameters that `val` depends on.
+    fn float_check(
+        &self,
+        db: &CompilationDB,
+        what: &str,
+        val: Value,
+        literals: &mut Rodeo,
+    ) -> FloatCheck {
+        // the message is used as a format string
+        let escape = |src: &str| src.replace('%', "%%");
+        let name = self.info.module.name(db);
+        let mut msg = format!("{}: non-finite value %g in {}", escape(&name), escape(what));
+        let loc = self.eval.dfg.value_def(val).inst().and_then(|inst| {
+            let loc = *self.eval.srclocs.get(inst)?;
+            let (file, line_col) = resolve_src_loc(db, self.intern, loc)?;
+            let file = db.vfs().read().file_path(file);
+            Some(format!("{file}:{}:{}", line_col.line + 1, line_col.col + 1))
+        });
+        if let Some(loc) = loc {
+            msg.push_str(" computed at ");
+            msg.push_str(&escape(&loc));
+        }
+
+        // instance parameters are listed first
+        let mut deps = self.param_deps(val);
+        deps.sort_by_key(|(param, _)| !self.info.params[param].is_instance);
+        let mut params = Vec::new();
+        let mut list = String::new();
+        let mut kind = "";
+        for (param, param_val) in deps {
+            let spec = match param.ty(db) {
+                Type::Real => "%g",
+                Type::Integer => "%d",
+                _ => continue,
+            };
+            if params.len() == MAX_REPORTED_PARAMS {
+                list.push_str(", ...");
+                break;
+            }
+            let info = &self.info.params[&param];
+            let param_kind = if info.is_instance { "instance" } else { "model" };
+            if param_kind != kind {
+                if !kind.is_empty() {
+                    list.push_str("; ");
+                }
+                list.push_str(param_kind);
+                list.push_str(" parameters: ");
+                kind = param_kind;
+            } else {
+                list.push_str(", ");
+            }
+            list.push_str(&format!("{}={spec}", escape(&info.name)));
+            params.push((param_val, param.ty(db).into()));
+        }
+        if !list.is_empty() {
+            msg.push_str(&format!(" ({list})"));
+        }
+
+        FloatCheck { val, msg: literals.get_or_intern(msg), params }
+    }
+
+    /// The parameters that `val` (transitively) depends on in declaration order.
+    fn param_deps(&self, val: Value) -> Vec<(Parameter, Value)> {
+        let dfg = &self.eval.dfg;
+        let mut visited = AHashSet::new();
+        let mut stack = vec![val];
+        while let Some(val) = stack.pop() {
+            if !visited.insert(val) {
+                continue;
+            }
+            if let Some(inst) = dfg.value_def(val).inst() {
+                stack.extend_from_slice(dfg.instr_args(inst));
+            }
+        }
+        self.intern
+            .params
+            .iter()
+            .filter_map(|(kind, val)| match *kind {
+                ParamKind::Param(param) if visited.contains(val) => Some((param, *val)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+pub struct FloatChecks<'ll> {
+    kind: DisplayKind,
+    /// The print callbacks of each list of argument types.
+    log_funs: RefCell<AHashMap<Vec<FmtArg>, (&'ll llvm::Value, &'ll llvm::Type)>>,
+    handle: &'ll llvm::Value,
+    ret_flags: &'ll llvm::Value,
+    /// Only the first non-finite value is reported.
+    reported: &'ll llvm::Value,
+    llfunc: &'ll llvm::Value,
+    abort: bool,
+}
+
+impl<'ll> FloatChecks<'ll> {
+    /// # Safety
+    ///
+    /// Must be called while the entry block of `llfunc` is selected.
+    pub unsafe fn new(
+        builder: &Builder<'_, '_, 'll>,
+        sanitizer: FloatSanitizer,
+        llfunc: &'ll llvm::Value,
+        handle: &'ll llvm::Value,
+        ret_flags: &'ll llvm::Value,
+    ) -> Option<FloatChecks<'ll>> {
+        let abort = match sanitizer {
+            FloatSanitizer::Disabled => return None,
+            FloatSanitizer::Report => false,
+            FloatSanitizer::Abort => true,
+        };
+        let cx = builder.cx;
+        let kind = if abort { DisplayKind::Fatal } else { DisplayKind::Error };
+        let reported = builder.alloca(cx.ty_bool());
+        builder.store(reported, cx.const_bool(false));
+        let log_funs = RefCell::default();
+        Some(FloatChecks { kind, log_funs, handle, ret_flags, reported, llfunc, abort })
+    }
+
+    /// Reports the checked value (and the parameters it depends on) if it is the first
+    /// non-finite value.
+    ///
+    /// # Safety
+    ///
+    /// Must not be called when the builder has selected a block that already contains a terminator
+    pub unsafe fn check(&self, builder: &Builder<'_, '_, 'll>, check: &FloatCheck) {
+        let cx = builder.cx;
+        let llbuilder = &*builder.llbuilder;
+        let val = builder.values[check.val].get(builder);
+
+        let (fabs_ty, fabs) = cx.intrinsic("llvm.fabs.f64").unwrap();
+        let abs = LLVMBuildCall2(llbuilder, fabs_ty, fabs, [val].as_ptr(), 1, UNNAMED);
+        let inf = cx.const_real(f64::INFINITY);
+        // unordered comparisons with NaN are always false
+        let is_finite = LLVMBuildFCmp(llbuilder, RealPredicate::RealOLT, abs, inf, UNNAMED);
+        let reported = builder.load(cx.ty_bool(), self.reported);
+        let skip = LLVMBuildOr(llbuilder, is_finite, reported, UNNAMED);
+
+        let report_bb = LLVMAppendBasicBlockInContext(cx.llcx, self.llfunc, UNNAMED);
+        let next_bb = LLVMAppendBasicBlockInContext(cx.llcx, self.llfunc, UNNAMED);
+        LLVMBuildCondBr(llbuilder, skip, next_bb, report_bb);
+
+        LLVMPositionBuilderAtEnd(llbuilder, report_bb);
+        let mut arg_tys = vec![FmtArg::from(Type::Real)];
+        arg_tys.extend(check.params.iter().map(|(_, ty)| ty.clone()));
+        let (log_fun, log_fun_ty) = *self
+            .log_funs
+            .borrow_mut()
+            .entry(arg_tys)
+            .or_insert_with_key(|arg_tys| print_callback(cx, self.kind, arg_tys));
+        let mut args = vec![self.handle, cx.const_str(check.msg), val];
+        args.extend(check.params.iter().map(|(param, _)| builder.values[*param].get(builder)));
+        LLVMBuildCall2(llbuilder, log_fun_ty, log_fun, args.as_ptr(), args.len() as u32, UNNAMED);
+        builder.store(self.reported, cx.const_bool(true));
+        if self.abort {
+            let flags = builder.load(cx.ty_int(), self.ret_flags);
+            let fatal = cx.const_unsigned_int(EVAL_RET_FLAG_FATAL);
+            let flags = LLVMBuildOr(llbuilder, flags, fatal, UNNAMED);
+            builder.store(self.ret_flags, flags);
+        }
+        LLVMBuildBr(llbuilder, next_bb);
+
+        LLVMPositionBuilderAtEnd(llbuilder, next_bb);
+    }
+}
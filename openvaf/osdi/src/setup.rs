@@ -10,7 +10,8 @@ use mir::ControlFlowGraph;
 use mir_llvm::{Builder, BuilderVal, CallbackFun, CodegenCx};
 use sim_back::SimUnknownKind;
 
-use crate::compilation_unit::{general_callbacks, OsdiCompilationUnit};
+use crate::compilation_unit::{general_callbacks, set_debug_locs, OsdiCompilationUnit};
+use crate::coverage::coverage_counters;
 use crate::inst_data::OsdiInstanceParam;
 
 impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
@@ -67,6 +68,13 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
         let mut cfg = ControlFlowGraph::new();
         cfg.compute(func);
         let mut builder = Builder::new(cx, func, llfunc);
+        if let Some(debug_info) = self.debug_info {
+            let name = format!("setup_model_{}", &self.module.sym);
+            set_debug_locs(self.db, debug_info, &mut builder, &name, intern);
+        }
+        if let Some(coverage) = &self.module.coverage {
+            builder.coverage = Some(coverage_counters(cx, &coverage.setup_model));
+        }
         let postorder: Vec<_> = cfg.postorder(func).collect();
 
         let handle = unsafe { llvm::LLVMGetParam(llfunc, 0) };
@@ -220,6 +228,13 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
         let func = &module.init.func;
         let intern = &module.init.intern;
         let mut builder = Builder::new(cx, func, llfunc);
+        if let Some(debug_info) = self.debug_info {
+            let name = format!("setup_instance_{}", &module.sym);
+            set_debug_locs(self.db, debug_info, &mut builder, &name, intern);
+        }
+        if let Some(coverage) = &module.coverage {
+            builder.coverage = Some(coverage_counters(cx, &coverage.setup_instance));
+        }
 
         let handle = unsafe { llvm::LLVMGetParam(llfunc, 0) };
         let instance = unsafe { llvm::LLVMGetParam(llfunc, 1) };
@@ -1,5 +1,6 @@
 #ifdef NO_STD
 typedef int uint32_t;
+typedef int int32_t;
 typedef unsigned char bool;
 typedef __SIZE_TYPE__ size_t;
 extern size_t strlen (const char *__s);
@@ -17,7 +18,9 @@ extern int strcmp(const char*, const char*);
 #include "string.h"
 #endif
 
-#ifndef OSDI_0_3
+#ifdef OSDI_0_4
+#include "header/osdi_0_4.h"
+#elif !defined(OSDI_0_3)
 #include "header/osdi_0_3.h"
 #endif
 
@@ -139,7 +142,10 @@ int fmt_char_idx(double val) {
 }
 
 char *fmt_binary(int val) {
-  int len = 32 - __builtin_clz(val);
+  int len = 0;
+  for (unsigned int bits = (unsigned int)val; bits != 0; bits >>= 1) {
+    len++;
+  }
   char *res = malloc(len + 1);
   res[len] = '\0';
   if (len == 0) {
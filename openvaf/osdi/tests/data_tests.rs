@@ -1,30 +1,62 @@
 use std::path::Path;
+use std::{env, fs};
 
-use camino::Utf8Path;
+use camino::Utf8PathBuf;
 use hir::diagnostics::ConsoleSink;
 use hir::CompilationDB;
 use llvm::OptLevel;
 use mini_harness::{harness, Result};
 use mir_llvm::LLVMBackend;
+use osdi::{CompileOpts, Emit, FloatSanitizer, OsdiVersion};
 use paths::AbsPathBuf;
 use sim_back::collect_modules;
 use stdx::{ignore_slow_tests, project_root};
 use target::spec::Target;
 
-fn test_compile(root_file: &Path) {
+fn test_compile(root_file: &Path, name: &str) {
     let root_file = AbsPathBuf::assert(root_file.canonicalize().unwrap());
     let db = CompilationDB::new_fs(root_file, &[], &[], &[]).unwrap();
     let modules = collect_modules(&db, false, &mut ConsoleSink::new(&db)).unwrap();
     let target = Target::host_target().unwrap();
     let back = LLVMBackend::new(&[], &target, "native".to_owned(), &[]);
-    let emit = !stdx::IS_CI;
-    osdi::compile(&db, &modules, Utf8Path::new("foo.o"), &target, &back, emit, OptLevel::None);
+    let emit = Emit { obj: !stdx::IS_CI, mir: true, ..Emit::default() };
+    let inputs = [(&db, &modules[..])];
+    let dir =
+        Utf8PathBuf::from_path_buf(env::temp_dir().join(format!("osdi_data_test_{name}"))).unwrap();
+    fs::create_dir_all(&dir).unwrap();
+    let lowered = osdi::lower_modules(&inputs);
+    let opts = CompileOpts {
+        target: &target,
+        emit,
+        opt_lvl: OptLevel::None,
+        debug_info: false,
+        float_sanitizer: FloatSanitizer::Disabled,
+        coverage: false,
+        sym_suffix: None,
+        version: OsdiVersion::V0_3,
+    };
+    osdi::compile(&inputs, &lowered, &dir.join("foo.o"), &back, &opts).unwrap();
+
+    // the emitted MIR must be readable by mir_reader
+    let mut num_mir = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map_or(false, |ext| ext == "mir") {
+            let src = fs::read_to_string(&path).unwrap();
+            let (funcs, _) = mir_reader::parse_functions(&src)
+                .unwrap_or_else(|err| panic!("failed to parse {}: {err}", path.display()));
+            assert_eq!(funcs.len(), 3);
+            num_mir += 1;
+        }
+    }
+    assert_eq!(num_mir, modules.len());
+    fs::remove_dir_all(&dir).unwrap();
 }
 
 fn integration_test(dir: &Path) -> Result {
     let name = dir.file_name().unwrap().to_str().unwrap().to_lowercase();
     let main_file = dir.join(format!("{name}.va"));
-    test_compile(&main_file);
+    test_compile(&main_file, &name);
 
     Ok(())
 }
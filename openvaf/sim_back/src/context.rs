@@ -1,8 +1,9 @@
+use ahash::AHashMap;
 use bitset::{BitSet, SparseBitMatrix};
-use hir::CompilationDB;
-use hir_lower::{HirInterner, MirBuilder, PlaceKind};
+use hir::{CompilationDB, Parameter};
+use hir_lower::{HirInterner, MirBuilder, ParamKind, PlaceKind};
 use lasso::Rodeo;
-use mir::{Block, ControlFlowGraph, DominatorTree, Function, Inst, Value};
+use mir::{Block, Const, ControlFlowGraph, DominatorTree, Function, Inst, Value, TRUE};
 use mir_opt::{
     aggressive_dead_code_elimination, dead_code_elimination, inst_combine, propagate_direct_taint,
     propagate_taint, simplify_cfg, simplify_cfg_no_phi_merge,
@@ -10,6 +11,7 @@ use mir_opt::{
 };
 use stdx::packed_option::PackedOption;
 
+use crate::opt_bisect::OptBisect;
 use crate::ModuleInfo;
 
 pub(crate) struct Context<'a> {
@@ -22,6 +24,7 @@ pub(crate) struct Context<'a> {
     pub(crate) output_values: BitSet<Value>,
     pub(crate) op_dependent_insts: BitSet<Inst>,
     pub(crate) op_dependent_vals: Vec<Value>,
+    pub(crate) opt_bisect: OptBisect,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -62,28 +65,55 @@ impl<'a> Context<'a> {
             module,
             op_dependent_insts: BitSet::new_empty(0),
             op_dependent_vals: Vec::new(),
+            opt_bisect: OptBisect::default(),
+        }
+    }
+
+    /// Replaces the `frozen` parameters with their values so that the optimizations below
+    /// specialise the function on them.
+    pub fn freeze_params(&mut self, frozen: &AHashMap<Parameter, Const>) {
+        for (&param, &val) in frozen {
+            if let Some(&old) = self.intern.params.raw.get(&ParamKind::Param(param)) {
+                let new = self.func.dfg.values.make_const(val);
+                self.func.dfg.replace_uses(old, new);
+            }
+            if let Some(&old) = self.intern.params.raw.get(&ParamKind::ParamGiven { param }) {
+                self.func.dfg.replace_uses(old, TRUE);
+            }
         }
     }
 
     pub fn optimize(&mut self, stage: OptimiziationStage) -> GVN {
-        if stage == OptimiziationStage::Initial {
+        let mut should_run = |pass| self.opt_bisect.should_run(format!("{stage:?}::{pass}"));
+        if stage == OptimiziationStage::Initial && should_run("dce") {
             dead_code_elimination(&mut self.func, &self.output_values);
         }
-        sparse_conditional_constant_propagation(&mut self.func, &self.cfg);
-        inst_combine(&mut self.func);
-        if stage == OptimiziationStage::Final {
-            simplify_cfg(&mut self.func, &mut self.cfg);
-        } else {
-            simplify_cfg_no_phi_merge(&mut self.func, &mut self.cfg);
+        if should_run("sccp") {
+            sparse_conditional_constant_propagation(&mut self.func, &self.cfg);
+        }
+        if should_run("inst_combine") {
+            inst_combine(&mut self.func);
+        }
+        if should_run("simplify_cfg") {
+            if stage == OptimiziationStage::Final {
+                simplify_cfg(&mut self.func, &mut self.cfg);
+            } else {
+                simplify_cfg_no_phi_merge(&mut self.func, &mut self.cfg);
+            }
         }
         self.compute_domtree(true, true, false);
 
         let mut gvn = GVN::default();
         gvn.init(&self.func, &self.dom_tree, self.intern.params.len() as u32);
-        gvn.solve(&mut self.func);
-        gvn.remove_unnecessary_insts(&mut self.func, &self.dom_tree);
+        // without solving every instruction remains in its own equivalence class
+        if self.opt_bisect.should_run(format!("{stage:?}::gvn")) {
+            gvn.solve(&mut self.func);
+            gvn.remove_unnecessary_insts(&mut self.func, &self.dom_tree);
+        }
 
-        if stage == OptimiziationStage::Final {
+        if stage == OptimiziationStage::Final
+            && self.opt_bisect.should_run(format!("{stage:?}::adce"))
+        {
             let mut control_dep = SparseBitMatrix::new_square(0);
             self.dom_tree.compute_postdom_frontiers(&self.cfg, &mut control_dep);
             aggressive_dead_code_elimination(
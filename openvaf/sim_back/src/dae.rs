@@ -1,3 +1,5 @@
+use hir::{ParamSysFun, Parameter};
+use hir_lower::ParamKind;
 use indexmap::IndexSet;
 use mir::{strip_optbarrier, Value, F_ZERO};
 use stdx::{impl_debug_display, impl_idx_from};
@@ -6,8 +8,8 @@ use typed_indexmap::TiSet;
 
 use crate::context::Context;
 use crate::dae::builder::Builder;
-pub use crate::noise::{NoiseSource, NoiseSourceKind};
-use crate::{topology, SimUnknownKind};
+pub use crate::noise::{NoiseCorrelation, NoiseSource, NoiseSourceKind};
+use crate::{noise, topology, SimUnknownKind};
 
 mod builder;
 #[cfg(test)]
@@ -43,6 +45,11 @@ pub struct DaeSystem {
     pub small_signal_parameters: IndexSet<Value, ahash::RandomState>,
     /// noise
     pub noise_sources: Vec<NoiseSource>,
+    /// The pairs of `noise_sources` that are driven by the same noise process.
+    pub noise_correlations: Vec<NoiseCorrelation>,
+    /// The derivatives of the residual by the sensitivity parameters (dR/dp), see
+    /// [`crate::ModuleInfo::sensitivity_params`].
+    pub sensitivities: Vec<Sensitivity>,
 }
 
 impl DaeSystem {
@@ -91,12 +98,24 @@ impl DaeSystem {
                 NoiseSourceKind::NoiseTable { .. } => true,
             }
         });
-
         self.jacobian.raw.retain_mut(|matrix_entry| {
             matrix_entry.resist = sparsify(matrix_entry.resist);
             matrix_entry.react = sparsify(matrix_entry.react);
             matrix_entry.resist != F_ZERO || matrix_entry.react != F_ZERO
-        })
+        });
+
+        self.sensitivities.retain_mut(|sensitivity| {
+            sensitivity.resist = sparsify(sensitivity.resist);
+            sensitivity.react = sparsify(sensitivity.react);
+            sensitivity.resist != F_ZERO || sensitivity.react != F_ZERO
+        });
+
+        let mfactor = ctx.intern.params.raw.get(&ParamKind::ParamSysFun(ParamSysFun::mfactor));
+        self.noise_correlations = noise::correlations(
+            &self.noise_sources,
+            |val1, val2| strip_optbarrier(&ctx.func, val1) == strip_optbarrier(&ctx.func, val2),
+            |val| noise::factor_sign(&ctx.func, val, mfactor.copied()),
+        );
     }
 }
 
@@ -210,6 +229,15 @@ pub struct MatrixEntry {
     pub react: Value,
 }
 
+/// The derivative of a residual by a sensitivity parameter
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub struct Sensitivity {
+    pub param: Parameter,
+    pub row: SimUnknown,
+    pub resist: Value,
+    pub react: Value,
+}
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct MatrixEntryId(u32);
 impl_idx_from!(MatrixEntryId(u32));
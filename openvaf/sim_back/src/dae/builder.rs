@@ -3,7 +3,7 @@ use std::vec;
 
 use ahash::AHashMap;
 use bitset::BitSet;
-use hir::{BranchWrite, CompilationDB, Node, ParamSysFun};
+use hir::{BranchWrite, CompilationDB, Node, ParamSysFun, Parameter};
 use hir_lower::{HirInterner, ImplicitEquation, ParamKind};
 use indexmap::IndexSet;
 use mir::builder::InstBuilder;
@@ -16,7 +16,7 @@ use mir_autodiff::auto_diff;
 use typed_index_collections::TiVec;
 
 use crate::context::Context;
-use crate::dae::{DaeSystem, MatrixEntry, Residual, SimUnknown};
+use crate::dae::{DaeSystem, MatrixEntry, Residual, Sensitivity, SimUnknown};
 use crate::noise::NoiseSource;
 use crate::topology::{BranchInfo, Contribution};
 use crate::util::{add, is_op_dependent, update_optbarrier};
@@ -56,6 +56,7 @@ pub(super) struct Builder<'a> {
     pub(super) dom_tree: &'a mut DominatorTree,
     pub(super) op_dependent_insts: &'a BitSet<Inst>,
     pub(super) output_values: &'a mut BitSet<Value>,
+    pub(super) sensitivity_params: &'a IndexSet<Parameter, ahash::RandomState>,
 }
 
 impl<'a> Builder<'a> {
@@ -70,6 +71,7 @@ impl<'a> Builder<'a> {
             dom_tree: &mut ctx.dom_tree,
             op_dependent_insts: &ctx.op_dependent_insts,
             output_values: &mut ctx.output_values,
+            sensitivity_params: &ctx.module.sensitivity_params,
         };
 
         // ensure ports are the first unknowns and always have an unknown
@@ -86,9 +88,11 @@ impl<'a> Builder<'a> {
 
     pub(super) fn finish(mut self) -> DaeSystem {
         let sim_unknown_reads = self.sim_unknown_reads();
-        let derivative_info = self.intern.unknowns(&self.cursor, true);
-        let extra_derivatives = self
+        let mut derivative_info = self.intern.unknowns(&self.cursor, true);
+        let sensitivity_unknowns = self.sensitivity_unknowns(&mut derivative_info);
+        let mut extra_derivatives = self
             .jacobian_derivatives(sim_unknown_reads.iter().map(|&(_, val)| val), &derivative_info);
+        extra_derivatives.extend(self.sensitivity_derivatives(&sensitivity_unknowns));
         // TODO(pref): incrementially update dom_tree (for switch branches) instead
         self.dom_tree.compute(self.cursor.func, self.cfg, true, false, true);
         let derivatives =
@@ -98,6 +102,7 @@ impl<'a> Builder<'a> {
         self.cursor.goto_exit();
 
         self.build_jacobian(&sim_unknown_reads, &derivative_info, &derivatives);
+        self.build_sensitivities(&sensitivity_unknowns, &derivatives);
         self.build_lim_rhs(&derivative_info, derivatives);
         self.ensure_optbarriers();
         self.system
@@ -325,6 +330,54 @@ impl<'a> Builder<'a> {
         res
     }
 
+    /// Registers the sensitivity parameters that are read by the module as unknowns so that
+    /// the residual can be differentiated by them.
+    fn sensitivity_unknowns(
+        &self,
+        derivative_info: &mut KnownDerivatives,
+    ) -> Vec<(Parameter, Unknown)> {
+        self.sensitivity_params
+            .iter()
+            .filter_map(|&param| {
+                let val = *self.intern.params.raw.get(&ParamKind::Param(param))?;
+                if self.cursor.func.dfg.value_dead(val) {
+                    return None;
+                }
+                Some((param, derivative_info.unknowns.ensure(val).0))
+            })
+            .collect()
+    }
+
+    fn sensitivity_derivatives(
+        &self,
+        sensitivity_unknowns: &[(Parameter, Unknown)],
+    ) -> Vec<(Value, Unknown)> {
+        let dfg = &self.cursor.func.dfg;
+        self.system
+            .residual
+            .iter()
+            .flat_map(|residual| [residual.resist, residual.react])
+            .filter(|&val| dfg.value_def(val).as_const().is_none())
+            .flat_map(|val| sensitivity_unknowns.iter().map(move |&(_, unknown)| (val, unknown)))
+            .collect()
+    }
+
+    fn build_sensitivities(
+        &mut self,
+        sensitivity_unknowns: &[(Parameter, Unknown)],
+        derivatives: &AHashMap<(Value, Unknown), Value>,
+    ) {
+        for (row, residual) in self.system.residual.iter_enumerated() {
+            for &(param, unknown) in sensitivity_unknowns {
+                let ddx = |val| derivatives.get(&(val, unknown)).copied().unwrap_or(F_ZERO);
+                let (resist, react) = (ddx(residual.resist), ddx(residual.react));
+                if resist != F_ZERO || react != F_ZERO {
+                    self.system.sensitivities.push(Sensitivity { param, row, resist, react });
+                }
+            }
+        }
+    }
+
     pub(super) fn build_branch(&mut self, branch: BranchWrite, contributions: &BranchInfo) {
         let current = branch.into();
         match contributions.is_voltage_src {
@@ -556,5 +609,12 @@ impl<'a> Builder<'a> {
             entry.resist = ensure_optbarrier(entry.resist, is_kirchoff);
             entry.react = ensure_optbarrier(entry.react, is_kirchoff);
         }
+
+        for sensitivity in &mut self.system.sensitivities {
+            let is_kirchoff =
+                matches!(self.system.unknowns[sensitivity.row], SimUnknownKind::KirchoffLaw(_));
+            sensitivity.resist = ensure_optbarrier(sensitivity.resist, is_kirchoff);
+            sensitivity.react = ensure_optbarrier(sensitivity.react, is_kirchoff);
+        }
     }
 }
@@ -8,10 +8,10 @@ use lasso::Rodeo;
 use stdx::{integration_test_dir, openvaf_test_data};
 
 use crate::context::{Context, OptimiziationStage};
-use crate::dae::DaeSystem;
+use crate::dae::{DaeSystem, NoiseCorrelation, NoiseSourceKind};
 use crate::topology;
 
-fn run_test(src: &str) {
+fn build_system(src: &str, check: impl FnOnce(String, &DaeSystem, &Context)) {
     let db = CompilationDB::new_virtual(src).unwrap();
     let module = crate::collect_modules(&db, false, &mut ConsoleSink::new(&db)).unwrap().remove(0);
     let mut literals = Rodeo::new();
@@ -24,13 +24,18 @@ fn run_test(src: &str) {
     context.compute_cfg();
     context.optimize(OptimiziationStage::Final);
     dae_system.sparsify(&mut context);
-    let name = module.module.name(&db);
-    let test_dir = openvaf_test_data("dae");
-    let topology = format!("{dae_system:#?}");
     assert!(context.func.validate());
-    expect_file![test_dir.join(format!("{name}_system.snap"))].assert_eq(&topology);
-    let func = format!("{:#?}", context.func);
-    expect_file![test_dir.join(format!("{name}_mir.snap"))].assert_eq(&func)
+    check(module.module.name(&db), &dae_system, &context)
+}
+
+fn run_test(src: &str) {
+    build_system(src, |name, dae_system, context| {
+        let test_dir = openvaf_test_data("dae");
+        let topology = format!("{dae_system:#?}");
+        expect_file![test_dir.join(format!("{name}_system.snap"))].assert_eq(&topology);
+        let func = format!("{:#?}", context.func);
+        expect_file![test_dir.join(format!("{name}_mir.snap"))].assert_eq(&func)
+    })
 }
 
 #[test]
@@ -146,3 +151,64 @@ fn dyn_switch_branch() {
     "#};
     run_test(src);
 }
+
+/// Noise sources with the same name are driven by the same noise process, unless their spectra
+/// have a different shape.
+#[test]
+fn correlated_noise_sources() {
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module correlated_noise_sources(inout b, inout c, inout e);
+            electrical b, c, e;
+            parameter real ib=1e-6, ic=1e-4;
+            analog begin
+                I(b, e) <+ V(b, e) + white_noise(ib, "shot");
+                I(c, e) <+ V(c, e) + white_noise(ic, "shot");
+                I(c, e) <+ white_noise(ic, "thermal");
+                I(b, c) <+ flicker_noise(ib, 1.0, "shot");
+            end
+        endmodule
+    "#};
+    build_system(src, |_, dae_system, _| {
+        assert_eq!(dae_system.noise_sources.len(), 4);
+        assert_eq!(dae_system.noise_correlations.len(), 1);
+        let NoiseCorrelation { source_1, source_2, coefficient } = dae_system.noise_correlations[0];
+        assert_eq!(coefficient, 1.0);
+        let src1 = &dae_system.noise_sources[source_1 as usize];
+        let src2 = &dae_system.noise_sources[source_2 as usize];
+        assert_eq!(src1.name, src2.name);
+        assert_ne!(src1.hi, src2.hi);
+        assert!(matches!(src1.kind, NoiseSourceKind::WhiteNoise { .. }));
+        assert!(matches!(src2.kind, NoiseSourceKind::WhiteNoise { .. }));
+    })
+}
+
+#[test]
+fn anticorrelated_noise_sources() {
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module anticorrelated_noise_sources(inout b, inout e, inout s);
+            electrical b, e, s;
+            parameter real ib=1e-6, gain=1.0;
+            analog begin
+                I(b, e) <+ white_noise(ib, "shot");
+                I(e, b) <+ -white_noise(ib, "shot");
+                V(s, e) <+ -white_noise(ib, "shot");
+                I(b, s) <+ gain * white_noise(ib, "shot");
+            end
+        endmodule
+    "#};
+    build_system(src, |_, dae_system, _| {
+        assert_eq!(dae_system.noise_sources.len(), 4);
+        // negated current contributions are folded into the orientation of the branch and the
+        // sign of `gain` is only known at runtime
+        let correlations: Vec<_> = dae_system
+            .noise_correlations
+            .iter()
+            .map(|correlation| {
+                (correlation.source_1, correlation.source_2, correlation.coefficient)
+            })
+            .collect();
+        assert_eq!(correlations, [(0, 1, 1.0), (0, 2, -1.0), (1, 2, -1.0)]);
+    })
+}
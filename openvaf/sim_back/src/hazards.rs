@@ -0,0 +1,742 @@
+//! Detection of numerical hazards with an interval analysis.
+//!
+//! The range of every value in the MIR of a compiled module (the instance setup and `eval`) is
+//! approximated with an interval. The ranges of parameters are seeded from their `from`/`exclude`
+//! bounds, values cached by the instance setup keep their range in `eval`. Branches that compare
+//! a value refine its range within the blocks they guard. Operations whose arguments may leave
+//! their domain (for example `sqrt` of a negative number or a division by zero) are reported
+//! at the expression they were lowered from.
+
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::f64::{INFINITY, NEG_INFINITY};
+use std::fmt::{self, Display};
+
+use ahash::AHashSet;
+use basedb::lints::builtin::{exp_overflow, numerical_hazard};
+use basedb::lints::{Lint, LintSrc};
+use hir::diagnostics::{BaseDB, Diagnostic, FileId, Label, LabelStyle, Report};
+use hir::{
+    BodyRef, CompilationDB, ConstraintValue, DiagnosticSink, Expr, ExprId, Literal,
+    ParamConstraint, Parameter, Type,
+};
+use hir_lower::{HirInterner, ParamKind};
+use mir::{
+    strip_optbarrier, Block, Const, ControlFlowGraph, Function, Inst, InstructionData, Opcode,
+    Value, ValueDef,
+};
+use syntax::ast::{ConstraintKind, UnaryOp};
+use syntax::sourcemap::FileSpan;
+use typed_index_collections::TiVec;
+
+use crate::init::CacheSlot;
+use crate::CompiledModule;
+
+#[cfg(test)]
+mod tests;
+
+/// How many single predecessors are followed upwards to find guarding branches.
+const MAX_GUARD_DEPTH: usize = 8;
+
+/// How often the range of a value may grow before it is widened to infinity.
+const WIDENING_THRESHOLD: u8 = 4;
+
+/// The largest argument for which `exp` does not overflow.
+const MAX_EXP_ARG: f64 = 709.782712893384;
+
+/// Reports operations in `module` whose arguments may leave the domain of the operation.
+pub fn check_numerical_hazards(
+    db: &CompilationDB,
+    module: &CompiledModule,
+    sink: &mut impl DiagnosticSink,
+) {
+    let root_file = db.compilation_unit().root_file();
+    for diag in numerical_hazards(db, module) {
+        sink.add_diagnostic(&diag, root_file, db)
+    }
+}
+
+fn numerical_hazards(db: &CompilationDB, module: &CompiledModule) -> Vec<NumericalHazard> {
+    let mut reported = AHashSet::new();
+    let mut res = Vec::new();
+    let mut report =
+        |func: &Function, intern: &HirInterner, inst: Inst, kind: HazardKind, range: Interval| {
+            let loc = match func.srclocs.get(inst) {
+                // derivatives have a negative source location, hazards are only reported once
+                Some(loc) if loc.bits() > 0 => *loc,
+                _ => return,
+            };
+            let expr = match intern.expr_loc(loc) {
+                Some(expr) => expr,
+                None => return,
+            };
+            if !reported.insert((expr, kind)) {
+                return;
+            }
+            if let Some(span) = expr.file_span(db) {
+                let lint_src = expr.lint_src(db, kind.lint());
+                res.push(NumericalHazard { kind, range, span, lint_src })
+            }
+        };
+
+    // values that only depend on parameters are computed during the instance setup
+    let init = &module.init;
+    let cfg = ControlFlowGraph::with_function(&init.func);
+    let mut analysis = RangeAnalysis::new(db, &init.func, &init.intern, &cfg);
+    analysis.solve();
+    analysis.find_hazards(|inst, kind, range| report(&init.func, &init.intern, inst, kind, range));
+
+    // and passed to eval in cache slots
+    let mut slot_ranges: TiVec<CacheSlot, Option<Interval>> =
+        vec![None; init.cache_slots.len()].into();
+    for (&val, &slot) in init.cached_vals.iter() {
+        if let Some(range) = analysis.range(val) {
+            let slot_range = &mut slot_ranges[slot];
+            *slot_range = Some(slot_range.map_or(range, |old| old.union(range)));
+        }
+    }
+
+    let func = &module.eval;
+    let cfg = ControlFlowGraph::with_function(func);
+    let mut analysis = RangeAnalysis::new(db, func, &module.intern, &cfg);
+    for val in func.dfg.values() {
+        if let ValueDef::Param(param) = func.dfg.value_def(val) {
+            let slot = usize::from(param).checked_sub(module.intern.params.len());
+            if let Some(slot) = slot {
+                analysis.ranges[val] = slot_ranges[CacheSlot(slot as u32)];
+            }
+        }
+    }
+    analysis.solve();
+    analysis.find_hazards(|inst, kind, range| report(func, &module.intern, inst, kind, range));
+    res
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum HazardKind {
+    NegativeSqrt,
+    NonPositiveLog,
+    DivisionByZero,
+    NegativePowBase,
+    ZeroPowBase,
+    ExpOverflow,
+}
+
+impl HazardKind {
+    fn lint(self) -> Lint {
+        match self {
+            // almost every exponential of a voltage may overflow, only reported on request
+            HazardKind::ExpOverflow => exp_overflow,
+            _ => numerical_hazard,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct NumericalHazard {
+    kind: HazardKind,
+    range: Interval,
+    span: FileSpan,
+    lint_src: LintSrc,
+}
+
+impl Diagnostic for NumericalHazard {
+    fn lint(&self, _root_file: FileId, _db: &dyn BaseDB) -> Option<(Lint, LintSrc)> {
+        Some((self.kind.lint(), self.lint_src))
+    }
+
+    fn build_report(&self, _root_file: FileId, _db: &dyn BaseDB) -> Report {
+        let (message, label, help) = match self.kind {
+            HazardKind::NegativeSqrt => (
+                "square root of a value that may be negative",
+                "argument",
+                "help: restrict the parameter bounds or guard the argument with a condition",
+            ),
+            HazardKind::NonPositiveLog => (
+                "logarithm of a value that may not be positive",
+                "argument",
+                "help: restrict the parameter bounds or guard the argument with a condition",
+            ),
+            HazardKind::DivisionByZero => (
+                "division by a value that may be zero",
+                "divisor",
+                "help: use 'exclude 0' in the parameter declaration or guard the division",
+            ),
+            HazardKind::NegativePowBase => (
+                "non-integer power of a value that may be negative",
+                "base",
+                "help: restrict the parameter bounds or guard the base with a condition",
+            ),
+            HazardKind::ZeroPowBase => (
+                "negative power of a value that may be zero",
+                "base",
+                "help: use 'exclude 0' in the parameter declaration or guard the base",
+            ),
+            HazardKind::ExpOverflow => (
+                "exponential function may overflow",
+                "argument",
+                "help: use limexp to limit the exponential",
+            ),
+        };
+
+        Report::warning()
+            .with_message(message)
+            .with_labels(vec![Label {
+                style: LabelStyle::Primary,
+                file_id: self.span.file,
+                range: self.span.range.into(),
+                message: format!("{label} may be in {}", self.range),
+            }])
+            .with_notes(vec![help.to_owned()])
+    }
+}
+
+/// A closed interval that contains all values a MIR value may assume.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Interval {
+    lo: f64,
+    hi: f64,
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {}]", self.lo, self.hi)
+    }
+}
+
+impl Interval {
+    const FULL: Interval = Interval { lo: NEG_INFINITY, hi: INFINITY };
+
+    fn new(lo: f64, hi: f64) -> Interval {
+        // NaN bounds are produced by indeterminate forms like inf - inf
+        let lo = if lo.is_nan() { NEG_INFINITY } else { lo };
+        let hi = if hi.is_nan() { INFINITY } else { hi };
+        if lo > hi {
+            return Interval::FULL;
+        }
+        Interval { lo, hi }
+    }
+
+    fn point(val: f64) -> Interval {
+        Interval::new(val, val)
+    }
+
+    fn is_full(self) -> bool {
+        self.lo == NEG_INFINITY && self.hi == INFINITY
+    }
+
+    fn contains(self, val: f64) -> bool {
+        self.lo <= val && val <= self.hi
+    }
+
+    fn union(self, other: Interval) -> Interval {
+        Interval { lo: self.lo.min(other.lo), hi: self.hi.max(other.hi) }
+    }
+
+    fn intersect(self, other: Interval) -> Interval {
+        let res = Interval { lo: self.lo.max(other.lo), hi: self.hi.min(other.hi) };
+        // an empty intersection can only occur in dead code, stay conservative
+        if res.lo > res.hi {
+            self
+        } else {
+            res
+        }
+    }
+
+    fn exclude(self, val: f64) -> Interval {
+        if self.lo == self.hi {
+            return self;
+        }
+        let mut res = self;
+        if res.lo == val {
+            res.lo = next_up(val)
+        }
+        if res.hi == val {
+            res.hi = next_down(val)
+        }
+        res
+    }
+
+    fn neg(self) -> Interval {
+        Interval { lo: -self.hi, hi: -self.lo }
+    }
+
+    fn add(self, other: Interval) -> Interval {
+        Interval::new(self.lo + other.lo, self.hi + other.hi)
+    }
+
+    fn sub(self, other: Interval) -> Interval {
+        Interval::new(self.lo - other.hi, self.hi - other.lo)
+    }
+
+    fn mul(self, other: Interval) -> Interval {
+        let corners = [
+            mul_bound(self.lo, other.lo),
+            mul_bound(self.lo, other.hi),
+            mul_bound(self.hi, other.lo),
+            mul_bound(self.hi, other.hi),
+        ];
+        let lo = corners.iter().copied().fold(INFINITY, f64::min);
+        let hi = corners.iter().copied().fold(NEG_INFINITY, f64::max);
+        Interval::new(lo, hi)
+    }
+
+    fn div(self, other: Interval) -> Interval {
+        if other.contains(0.0) {
+            return Interval::FULL;
+        }
+        self.mul(Interval::new(1.0 / other.hi, 1.0 / other.lo))
+    }
+
+    fn monotonic(self, f: impl Fn(f64) -> f64) -> Interval {
+        Interval::new(f(self.lo), f(self.hi))
+    }
+
+    fn sqrt(self) -> Interval {
+        if self.hi < 0.0 {
+            return Interval::FULL;
+        }
+        Interval::new(self.lo.max(0.0).sqrt(), self.hi.sqrt())
+    }
+
+    fn log(self, f: impl Fn(f64) -> f64) -> Interval {
+        if self.hi <= 0.0 {
+            return Interval::FULL;
+        }
+        Interval::new(f(self.lo.max(0.0)), f(self.hi))
+    }
+
+    fn pow(self, exp: Interval) -> Interval {
+        if self.lo < 0.0 {
+            return Interval::FULL;
+        }
+        exp.mul(self.log(f64::ln)).monotonic(f64::exp)
+    }
+}
+
+/// Multiplication of interval bounds where `0 * inf` is treated as zero.
+fn mul_bound(lhs: f64, rhs: f64) -> f64 {
+    if lhs == 0.0 || rhs == 0.0 {
+        0.0
+    } else {
+        lhs * rhs
+    }
+}
+
+fn next_up(val: f64) -> f64 {
+    if val.is_nan() || val == INFINITY {
+        return val;
+    }
+    if val == 0.0 {
+        return f64::from_bits(1);
+    }
+    let bits = val.to_bits();
+    if val > 0.0 {
+        f64::from_bits(bits + 1)
+    } else {
+        f64::from_bits(bits - 1)
+    }
+}
+
+fn next_down(val: f64) -> f64 {
+    -next_up(-val)
+}
+
+/// The range of `param` as declared by its `from`/`exclude` bounds.
+fn param_range(db: &CompilationDB, param: Parameter) -> Interval {
+    if !matches!(param.ty(db), Type::Real | Type::Integer) {
+        return Interval::FULL;
+    }
+
+    let body = param.init(db);
+    let body = body.borrow();
+    let mut res: Option<Interval> = None;
+    let mut excluded = Vec::new();
+    for bound in param.bounds(db).iter() {
+        let range = match (bound.kind, bound.val) {
+            (ConstraintKind::From, ConstraintValue::Value(val)) => {
+                const_eval(body, val).map_or(Interval::FULL, Interval::point)
+            }
+            (ConstraintKind::From, ConstraintValue::Range(range)) => {
+                let mut lo = const_eval(body, range.start).unwrap_or(NEG_INFINITY);
+                let mut hi = const_eval(body, range.end).unwrap_or(INFINITY);
+                if !range.start_inclusive {
+                    lo = next_up(lo)
+                }
+                if !range.end_inclusive {
+                    hi = next_down(hi)
+                }
+                Interval::new(lo, hi)
+            }
+            (ConstraintKind::Exclude, ConstraintValue::Value(val)) => {
+                excluded.extend(const_eval(body, val));
+                continue;
+            }
+            (ConstraintKind::Exclude, ConstraintValue::Range(_)) => continue,
+        };
+        res = Some(res.map_or(range, |res| res.union(range)));
+    }
+
+    let mut res = res.unwrap_or(Interval::FULL);
+    for val in excluded {
+        res = res.exclude(val)
+    }
+    res
+}
+
+/// Whether `val` violates the `from`/`exclude` bounds of `param`. Bounds that are not constant
+/// (for example because they depend on other parameters) are ignored, so only values that are
+/// known to be invalid are reported.
+pub(crate) fn violates_bounds(db: &CompilationDB, param: Parameter, val: f64) -> bool {
+    let body = param.init(db);
+    let body = body.borrow();
+    let contains = |bound: &ParamConstraint| match bound.val {
+        ConstraintValue::Value(expr) => const_eval(body, expr).map(|bound| bound == val),
+        ConstraintValue::Range(range) => {
+            let lo = const_eval(body, range.start)?;
+            let hi = const_eval(body, range.end)?;
+            let above = if range.start_inclusive { lo <= val } else { lo < val };
+            let below = if range.end_inclusive { val <= hi } else { val < hi };
+            Some(above && below)
+        }
+    };
+
+    let mut allowed = None;
+    for bound in param.bounds(db).iter() {
+        match (bound.kind, contains(bound)) {
+            (ConstraintKind::Exclude, Some(true)) => return true,
+            (ConstraintKind::Exclude, _) => (),
+            (ConstraintKind::From, Some(res)) => {
+                allowed = allowed.map(|allowed| allowed || res).or(Some(res))
+            }
+            // a non-constant from bound may allow any value
+            (ConstraintKind::From, None) => allowed = Some(true),
+        }
+    }
+    allowed == Some(false)
+}
+
+fn const_eval(body: BodyRef, expr: ExprId) -> Option<f64> {
+    if let Some(lit) = body.as_literal(expr) {
+        return match *lit {
+            Literal::Int(val) => Some(val as f64),
+            Literal::Float(val) => Some(val.into()),
+            Literal::Inf => Some(INFINITY),
+            Literal::String(_) => None,
+        };
+    }
+
+    match body.get_expr(expr) {
+        Expr::UnaryOp { expr, op: UnaryOp::Neg } => const_eval(body, expr).map(|val| -val),
+        Expr::UnaryOp { expr, op: UnaryOp::Identity } => const_eval(body, expr),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Relation {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Relation {
+    fn from_opcode(opcode: Opcode) -> Option<Relation> {
+        let res = match opcode {
+            Opcode::Flt | Opcode::Ilt => Relation::Lt,
+            Opcode::Fle | Opcode::Ile => Relation::Le,
+            Opcode::Fgt | Opcode::Igt => Relation::Gt,
+            Opcode::Fge | Opcode::Ige => Relation::Ge,
+            Opcode::Feq | Opcode::Ieq => Relation::Eq,
+            Opcode::Fne | Opcode::Ine => Relation::Ne,
+            _ => return None,
+        };
+        Some(res)
+    }
+
+    fn negate(self) -> Relation {
+        match self {
+            Relation::Lt => Relation::Ge,
+            Relation::Le => Relation::Gt,
+            Relation::Gt => Relation::Le,
+            Relation::Ge => Relation::Lt,
+            Relation::Eq => Relation::Ne,
+            Relation::Ne => Relation::Eq,
+        }
+    }
+
+    /// The relation obtained by swapping both operands.
+    fn mirror(self) -> Relation {
+        match self {
+            Relation::Lt => Relation::Gt,
+            Relation::Le => Relation::Ge,
+            Relation::Gt => Relation::Lt,
+            Relation::Ge => Relation::Le,
+            rel => rel,
+        }
+    }
+}
+
+struct RangeAnalysis<'a> {
+    func: &'a Function,
+    cfg: &'a ControlFlowGraph,
+    ranges: TiVec<Value, Option<Interval>>,
+    updates: TiVec<Value, u8>,
+}
+
+impl<'a> RangeAnalysis<'a> {
+    fn new(
+        db: &CompilationDB,
+        func: &'a Function,
+        intern: &HirInterner,
+        cfg: &'a ControlFlowGraph,
+    ) -> RangeAnalysis<'a> {
+        let num_values = func.dfg.num_values();
+        let mut ranges: TiVec<Value, Option<Interval>> = vec![None; num_values].into();
+        for (kind, &val) in intern.params.iter() {
+            if let ParamKind::Param(param) = *kind {
+                ranges[val] = Some(param_range(db, param));
+            }
+        }
+        RangeAnalysis { func, cfg, ranges, updates: vec![0; num_values].into() }
+    }
+
+    fn range(&self, val: Value) -> Option<Interval> {
+        match self.func.dfg.value_def(val) {
+            ValueDef::Const(Const::Float(val)) => Some(Interval::point(val.into())),
+            ValueDef::Const(Const::Int(val)) => Some(Interval::point(val as f64)),
+            ValueDef::Const(Const::Bool(val)) => Some(Interval::point(f64::from(u8::from(val)))),
+            ValueDef::Const(Const::Str(_)) | ValueDef::Invalid => Some(Interval::FULL),
+            ValueDef::Param(_) => Some(self.ranges[val].unwrap_or(Interval::FULL)),
+            ValueDef::Result(..) => self.ranges[val],
+        }
+    }
+
+    /// The range of `val` within `bb` refined by the branches that guard `bb`.
+    fn range_at(&self, val: Value, mut bb: Block) -> Option<Interval> {
+        let mut range = self.range(val)?;
+        for _ in 0..MAX_GUARD_DEPTH {
+            let pred = match self.cfg.single_predecessor(bb) {
+                Some(pred) => pred,
+                None => break,
+            };
+            if let Some(term) = self.func.layout.block_terminator(pred) {
+                if let InstructionData::Branch { cond, then_dst, else_dst, .. } =
+                    self.func.dfg.insts[term]
+                {
+                    if then_dst != else_dst {
+                        range = self.refine(range, val, cond, then_dst == bb);
+                    }
+                }
+            }
+            bb = pred;
+        }
+        Some(range)
+    }
+
+    fn refine(&self, range: Interval, val: Value, cond: Value, taken: bool) -> Interval {
+        let inst = match self.func.dfg.value_def(cond) {
+            ValueDef::Result(inst, _) => inst,
+            _ => return range,
+        };
+        let (opcode, lhs, rhs) = match self.func.dfg.insts[inst] {
+            InstructionData::Unary { opcode: Opcode::Bnot, arg } => {
+                return self.refine(range, val, arg, !taken)
+            }
+            InstructionData::Binary { opcode, args: [lhs, rhs] } => (opcode, lhs, rhs),
+            _ => return range,
+        };
+        let rel = match Relation::from_opcode(opcode) {
+            Some(rel) if taken => rel,
+            Some(rel) => rel.negate(),
+            None => return range,
+        };
+
+        let val = strip_optbarrier(self.func, val);
+        let (rel, other) = if strip_optbarrier(self.func, lhs) == val {
+            (rel, rhs)
+        } else if strip_optbarrier(self.func, rhs) == val {
+            (rel.mirror(), lhs)
+        } else {
+            return range;
+        };
+        let other = match self.range(other) {
+            Some(other) => other,
+            None => return range,
+        };
+
+        match rel {
+            Relation::Lt => range.intersect(Interval::new(NEG_INFINITY, next_down(other.hi))),
+            Relation::Le => range.intersect(Interval::new(NEG_INFINITY, other.hi)),
+            Relation::Gt => range.intersect(Interval::new(next_up(other.lo), INFINITY)),
+            Relation::Ge => range.intersect(Interval::new(other.lo, INFINITY)),
+            Relation::Eq => range.intersect(other),
+            Relation::Ne if other.lo == other.hi => range.exclude(other.lo),
+            Relation::Ne => range,
+        }
+    }
+
+    fn eval_inst(&self, inst: Inst, bb: Block) -> Option<Interval> {
+        let res = match self.func.dfg.insts[inst] {
+            InstructionData::Unary { opcode, arg } => {
+                let arg = self.range_at(arg, bb)?;
+                match opcode {
+                    Opcode::Fneg | Opcode::Ineg => arg.neg(),
+                    Opcode::OptBarrier | Opcode::IFcast => arg,
+                    Opcode::FIcast => arg.monotonic(f64::round),
+                    Opcode::BIcast | Opcode::BFcast => Interval::new(0.0, 1.0),
+                    Opcode::Sqrt => arg.sqrt(),
+                    Opcode::Exp => arg.monotonic(f64::exp),
+                    Opcode::Ln => arg.log(f64::ln),
+                    Opcode::Log => arg.log(f64::log10),
+                    Opcode::Floor => arg.monotonic(f64::floor),
+                    Opcode::Ceil => arg.monotonic(f64::ceil),
+                    Opcode::Sinh => arg.monotonic(f64::sinh),
+                    Opcode::Tanh => arg.monotonic(f64::tanh),
+                    Opcode::Asinh => arg.monotonic(f64::asinh),
+                    Opcode::Atan => arg.monotonic(f64::atan),
+                    Opcode::Sin | Opcode::Cos => Interval::new(-1.0, 1.0),
+                    Opcode::Cosh => Interval::new(1.0, INFINITY),
+                    Opcode::Asin => Interval::new(-FRAC_PI_2, FRAC_PI_2),
+                    Opcode::Acos => Interval::new(0.0, PI),
+                    Opcode::Acosh => Interval::new(0.0, INFINITY),
+                    _ => Interval::FULL,
+                }
+            }
+            InstructionData::Binary { opcode, args: [lhs, rhs] } => {
+                let lhs = self.range_at(lhs, bb)?;
+                let rhs = self.range_at(rhs, bb)?;
+                match opcode {
+                    Opcode::Fadd | Opcode::Iadd => lhs.add(rhs),
+                    Opcode::Fsub | Opcode::Isub => lhs.sub(rhs),
+                    Opcode::Fmul | Opcode::Imul => lhs.mul(rhs),
+                    Opcode::Fdiv => lhs.div(rhs),
+                    Opcode::Idiv => lhs.div(rhs).monotonic(f64::trunc),
+                    Opcode::Pow => lhs.pow(rhs),
+                    Opcode::Hypot => Interval::new(0.0, INFINITY),
+                    Opcode::Atan2 => Interval::new(-PI, PI),
+                    _ => Interval::FULL,
+                }
+            }
+            InstructionData::PhiNode(ref phi) => {
+                let mut res: Option<Interval> = None;
+                for (pred, val) in self.func.dfg.phi_edges(phi) {
+                    if let Some(range) = self.range_at(val, pred) {
+                        res = Some(res.map_or(range, |res| res.union(range)));
+                    }
+                }
+                return res;
+            }
+            InstructionData::Call { .. } => Interval::FULL,
+            InstructionData::Branch { .. } | InstructionData::Jump { .. } => return None,
+        };
+        Some(res)
+    }
+
+    fn update(&mut self, val: Value, range: Interval) -> bool {
+        let old = match self.ranges[val] {
+            Some(old) => old,
+            None => {
+                self.ranges[val] = Some(range);
+                return true;
+            }
+        };
+
+        let mut new = old.union(range);
+        if new == old {
+            return false;
+        }
+
+        // widen values that keep growing (inside loops) to ensure termination
+        self.updates[val] = self.updates[val].saturating_add(1);
+        if self.updates[val] > WIDENING_THRESHOLD {
+            if new.lo < old.lo {
+                new.lo = NEG_INFINITY
+            }
+            if new.hi > old.hi {
+                new.hi = INFINITY
+            }
+        }
+        self.ranges[val] = Some(new);
+        true
+    }
+
+    fn solve(&mut self) {
+        let func = self.func;
+        let rpo: Vec<_> = self.cfg.reverse_postorder(func).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &bb in &rpo {
+                for inst in func.layout.block_insts(bb) {
+                    if let Some(range) = self.eval_inst(inst, bb) {
+                        for &val in func.dfg.inst_results(inst) {
+                            changed |= self.update(val, range);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn find_hazards(&self, mut report: impl FnMut(Inst, HazardKind, Interval)) {
+        // only ranges with a finite bound are meaningful, anything else would be reported for
+        // every operation that depends on a voltage
+        let informative = |range: Option<Interval>| range.filter(|range| !range.is_full());
+
+        for bb in self.cfg.reverse_postorder(self.func) {
+            for inst in self.func.layout.block_insts(bb) {
+                match self.func.dfg.insts[inst] {
+                    InstructionData::Unary { opcode: Opcode::Exp, arg } => {
+                        // exp of an unbounded value is the classic overflow that limexp prevents
+                        match self.range_at(arg, bb) {
+                            Some(arg) if arg.hi > MAX_EXP_ARG => {
+                                report(inst, HazardKind::ExpOverflow, arg)
+                            }
+                            _ => (),
+                        }
+                    }
+                    InstructionData::Unary { opcode, arg } => {
+                        let arg = match informative(self.range_at(arg, bb)) {
+                            Some(arg) => arg,
+                            None => continue,
+                        };
+                        match opcode {
+                            Opcode::Sqrt if arg.lo < 0.0 => {
+                                report(inst, HazardKind::NegativeSqrt, arg)
+                            }
+                            Opcode::Ln | Opcode::Log if arg.lo <= 0.0 => {
+                                report(inst, HazardKind::NonPositiveLog, arg)
+                            }
+                            _ => (),
+                        }
+                    }
+                    InstructionData::Binary { opcode: Opcode::Fdiv, args: [_, rhs] } => {
+                        match informative(self.range_at(rhs, bb)) {
+                            Some(rhs) if rhs.contains(0.0) => {
+                                report(inst, HazardKind::DivisionByZero, rhs)
+                            }
+                            _ => (),
+                        }
+                    }
+                    InstructionData::Binary { opcode: Opcode::Pow, args: [base, exp] } => {
+                        let base = match informative(self.range_at(base, bb)) {
+                            Some(base) => base,
+                            None => continue,
+                        };
+                        let exp = self.range_at(exp, bb).unwrap_or(Interval::FULL);
+                        let integer_exp = exp.lo == exp.hi && exp.lo.fract() == 0.0;
+                        if base.lo < 0.0 && !integer_exp {
+                            report(inst, HazardKind::NegativePowBase, base)
+                        } else if base.contains(0.0) && exp.lo < 0.0 {
+                            report(inst, HazardKind::ZeroPowBase, base)
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use hir::diagnostics::sink::Buffer;
+use hir::diagnostics::ConsoleSink;
+use hir::CompilationDB;
+use indoc::indoc;
+use lasso::Rodeo;
+
+use super::HazardKind;
+use crate::{collect_modules, CompiledModule};
+
+fn hazards(src: &str) -> Vec<HazardKind> {
+    let db = CompilationDB::new_virtual(src).unwrap();
+    let mut buf = Buffer::no_color();
+    let mut sink = ConsoleSink::buffer(&db, &mut buf);
+    let modules = collect_modules(&db, false, &mut sink).unwrap();
+    let module = CompiledModule::new(&db, &modules[0], &mut Rodeo::new());
+    super::numerical_hazards(&db, &module).into_iter().map(|hazard| hazard.kind).collect()
+}
+
+#[test]
+fn unbounded_param() {
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module test(inout p, inout n);
+            electrical p, n;
+            parameter real a = 1.0;
+            analog I(p, n) <+ sqrt(a) * V(p, n);
+        endmodule
+    "#};
+    assert_eq!(hazards(src), vec![]);
+}
+
+#[test]
+fn sqrt_bounds() {
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module test(inout p, inout n);
+            electrical p, n;
+            parameter real a = 0.5 from [-1:1];
+            analog I(p, n) <+ sqrt(a) * V(p, n);
+        endmodule
+    "#};
+    assert_eq!(hazards(src), vec![HazardKind::NegativeSqrt]);
+
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module test(inout p, inout n);
+            electrical p, n;
+            parameter real a = 0.5 from [0:1];
+            analog I(p, n) <+ sqrt(a + 1) * V(p, n);
+        endmodule
+    "#};
+    assert_eq!(hazards(src), vec![]);
+}
+
+#[test]
+fn division_by_zero() {
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module test(inout p, inout n);
+            electrical p, n;
+            parameter real r = 1.0 from [0:inf);
+            analog I(p, n) <+ V(p, n) / r;
+        endmodule
+    "#};
+    assert_eq!(hazards(src), vec![HazardKind::DivisionByZero]);
+
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module test(inout p, inout n);
+            electrical p, n;
+            parameter real r = 1.0 from [0:inf) exclude 0;
+            analog I(p, n) <+ V(p, n) / r;
+        endmodule
+    "#};
+    assert_eq!(hazards(src), vec![]);
+}
+
+#[test]
+fn guarded_log() {
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module test(inout p, inout n);
+            electrical p, n;
+            parameter real a = 0.5 from [-1:1];
+            analog I(p, n) <+ ln(a) * V(p, n);
+        endmodule
+    "#};
+    assert_eq!(hazards(src), vec![HazardKind::NonPositiveLog]);
+
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module test(inout p, inout n);
+            electrical p, n;
+            parameter real a = 0.5 from [-1:1];
+            analog begin
+                if (a > 0)
+                    I(p, n) <+ ln(a) * V(p, n);
+            end
+        endmodule
+    "#};
+    assert_eq!(hazards(src), vec![]);
+}
+
+#[test]
+fn exp_overflow() {
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module test(inout p, inout n);
+            electrical p, n;
+            analog I(p, n) <+ exp(V(p, n));
+        endmodule
+    "#};
+    assert_eq!(hazards(src), vec![HazardKind::ExpOverflow]);
+
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module test(inout p, inout n);
+            electrical p, n;
+            analog I(p, n) <+ limexp(V(p, n));
+        endmodule
+    "#};
+    assert_eq!(hazards(src), vec![]);
+}
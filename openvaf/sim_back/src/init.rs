@@ -75,7 +75,11 @@ impl<'a> Builder<'a> {
                 func: Function::with_name(format!("{}_init", &ctx.func.name)),
                 cached_vals: IndexMap::with_capacity_and_hasher(128, RandomState::new()),
                 cache_slots: TiMap::default(),
-                intern: HirInterner::default(),
+                // instructions are copied together with their source locations
+                intern: HirInterner {
+                    src_locs: ctx.intern.src_locs.clone(),
+                    ..HirInterner::default()
+                },
             },
             init_cache: IndexMap::with_capacity_and_hasher(256, RandomState::default()),
             func: &mut ctx.func,
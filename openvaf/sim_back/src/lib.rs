@@ -1,3 +1,4 @@
+use ahash::AHashMap;
 use hir::{BranchWrite, CompilationDB, Node};
 use hir_lower::{CurrentKind, HirInterner, ImplicitEquation};
 use lasso::Rodeo;
@@ -5,7 +6,9 @@ use mir::Function;
 use mir_opt::{simplify_cfg, sparse_conditional_constant_propagation};
 use stdx::impl_debug_display;
 
-pub use module_info::{collect_modules, ModuleInfo};
+pub use hazards::check_numerical_hazards;
+pub use module_info::{collect_modules, FrozenParam, ModuleInfo, ParamInfo};
+pub use opt_bisect::OptBisect;
 
 use crate::context::{Context, OptimiziationStage};
 use crate::dae::DaeSystem;
@@ -15,10 +18,12 @@ use crate::topology::Topology;
 
 mod context;
 pub mod dae;
+mod hazards;
 pub mod init;
 mod module_info;
 pub mod node_collapse;
 mod noise;
+mod opt_bisect;
 mod topology;
 
 mod util;
@@ -58,7 +63,26 @@ impl<'a> CompiledModule<'a> {
         module: &'a ModuleInfo,
         literals: &mut Rodeo,
     ) -> CompiledModule<'a> {
+        Self::with_opt_bisect(db, module, literals, &mut OptBisect::default())
+    }
+
+    /// Compiles `module` but only runs the MIR optimization passes allowed by `opt_bisect`
+    /// (which records all passes afterwards).
+    pub fn with_opt_bisect(
+        db: &CompilationDB,
+        module: &'a ModuleInfo,
+        literals: &mut Rodeo,
+        opt_bisect: &mut OptBisect,
+    ) -> CompiledModule<'a> {
+        let frozen_params: AHashMap<_, _> = module
+            .frozen_params
+            .iter()
+            .map(|(&param, val)| (param, val.to_const(literals)))
+            .collect();
+
         let mut cx = Context::new(db, literals, module);
+        cx.opt_bisect = std::mem::take(opt_bisect);
+        cx.freeze_params(&frozen_params);
         cx.compute_outputs(true);
         cx.compute_cfg();
         cx.optimize(OptimiziationStage::Initial);
@@ -85,7 +109,15 @@ impl<'a> CompiledModule<'a> {
             .iter()
             .filter_map(|(param, info)| info.is_instance.then_some(*param))
             .collect();
-        init.intern.insert_param_init(db, &mut init.func, literals, false, true, &inst_params);
+        init.intern.insert_param_init(
+            db,
+            &mut init.func,
+            literals,
+            false,
+            true,
+            &inst_params,
+            &frozen_params,
+        );
 
         let mut model_param_setup = Function::default();
         let model_params: Vec<_> = module.params.keys().copied().collect();
@@ -97,11 +129,17 @@ impl<'a> CompiledModule<'a> {
             false,
             true,
             &model_params,
+            &frozen_params,
         );
         cx.cfg.compute(&model_param_setup);
-        simplify_cfg(&mut model_param_setup, &mut cx.cfg);
-        sparse_conditional_constant_propagation(&mut model_param_setup, &cx.cfg);
-        simplify_cfg(&mut model_param_setup, &mut cx.cfg);
+        if cx.opt_bisect.should_run("ModelSetup::simplify_cfg") {
+            simplify_cfg(&mut model_param_setup, &mut cx.cfg);
+        }
+        if cx.opt_bisect.should_run("ModelSetup::sccp") {
+            sparse_conditional_constant_propagation(&mut model_param_setup, &cx.cfg);
+            simplify_cfg(&mut model_param_setup, &mut cx.cfg);
+        }
+        *opt_bisect = cx.opt_bisect;
 
         CompiledModule {
             eval: cx.func,
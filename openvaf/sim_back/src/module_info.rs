@@ -4,7 +4,9 @@ use hir::{
     CompilationDB, CompilationUnit, DiagnosticSink, Module, ParamSysFun, Parameter,
     ResolvedAliasParameter, ScopeDef, Variable,
 };
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
+use lasso::Rodeo;
+use mir::Const;
 use smol_str::SmolStr;
 use syntax::ast::{self, Expr};
 use syntax::sourcemap::FileSpan;
@@ -45,6 +47,12 @@ pub struct ModuleInfo {
     pub params: IndexMap<Parameter, ParamInfo, ahash::RandomState>,
     pub sys_fun_alias: IndexMap<ParamSysFun, Vec<SmolStr>, ahash::RandomState>,
     pub op_vars: IndexMap<Variable, OpVar, ahash::RandomState>,
+    /// Model parameters whose value is fixed at compile time. The compiled module is
+    /// specialised on these values and rejects any other value at runtime.
+    pub frozen_params: IndexMap<Parameter, FrozenParam, ahash::RandomState>,
+    /// Real parameters for which the derivatives of the residual (dR/dp) are generated, see
+    /// [`crate::dae::DaeSystem::sensitivities`].
+    pub sensitivity_params: IndexSet<Parameter, ahash::RandomState>,
 }
 
 impl ModuleInfo {
@@ -193,7 +201,14 @@ impl ModuleInfo {
             }
         }
 
-        ModuleInfo { module, params, op_vars, sys_fun_alias }
+        ModuleInfo {
+            module,
+            params,
+            op_vars,
+            sys_fun_alias,
+            frozen_params: IndexMap::default(),
+            sensitivity_params: IndexSet::default(),
+        }
     }
 }
 
@@ -255,6 +270,32 @@ pub struct ParamInfo {
     pub is_instance: bool,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrozenParam {
+    Real(f64),
+    Integer(i32),
+    String(String),
+}
+
+impl FrozenParam {
+    pub fn to_const(&self, literals: &mut Rodeo) -> Const {
+        match *self {
+            FrozenParam::Real(val) => Const::Float(val.into()),
+            FrozenParam::Integer(val) => Const::Int(val),
+            FrozenParam::String(ref val) => Const::Str(literals.get_or_intern(val)),
+        }
+    }
+
+    /// Whether this value is known to violate the `from`/`exclude` bounds of `param`.
+    pub fn violates_bounds(&self, db: &CompilationDB, param: Parameter) -> bool {
+        match *self {
+            FrozenParam::Real(val) => crate::hazards::violates_bounds(db, param, val),
+            FrozenParam::Integer(val) => crate::hazards::violates_bounds(db, param, val as f64),
+            FrozenParam::String(_) => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct OpVar {
     pub unit: String,
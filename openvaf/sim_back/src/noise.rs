@@ -1,5 +1,5 @@
 use lasso::Spur;
-use mir::Value;
+use mir::{strip_optbarrier, Const, Function, InstructionData, Opcode, Value, ValueDef, F_ZERO};
 use stdx::Ieee64;
 
 use crate::dae::SimUnknown;
@@ -33,3 +33,114 @@ impl NoiseSource {
         }
     }
 }
+
+/// Two noise sources that are driven by the same noise process. The cross-spectral density of
+/// such a pair is fully determined by the power spectral densities of both sources:
+/// `S12 = coefficient * sqrt(|S1 * S2|)`. Both spectral densities are oriented like the node pairs
+/// of their sources, so the `coefficient` is -1 if the noise process enters the two sources with
+/// opposite signs (for example `I(a, b) <+ white_noise(x, "n"); I(c, d) <+ -white_noise(y, "n")`)
+/// and 1 otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseCorrelation {
+    pub source_1: u32,
+    pub source_2: u32,
+    pub coefficient: f64,
+}
+
+/// Finds all pairs of noise sources that are correlated. hir_lower creates a unique callback for
+/// every noise function call (so `white_noise(x) - white_noise(x)` is not zero) but calls
+/// with the same name (for example `white_noise(2*q*ib, "ib")` contributed to several
+/// branches) refer to the same noise process. Sources are only correlated if their spectra have
+/// the same shape, `is_same_val` is used to compare the flicker noise exponents. `factor_sign`
+/// returns the sign of the factor of a noise source (see [`factor_sign`]), pairs whose relative
+/// sign is only known at runtime are not reported.
+pub(crate) fn correlations(
+    sources: &[NoiseSource],
+    mut is_same_val: impl FnMut(Value, Value) -> bool,
+    mut factor_sign: impl FnMut(Value) -> Option<f64>,
+) -> Vec<NoiseCorrelation> {
+    let mut res = Vec::new();
+    for (i, src1) in sources.iter().enumerate() {
+        for (j, src2) in sources.iter().enumerate().skip(i + 1) {
+            if src1.name != src2.name {
+                continue;
+            }
+            let same_spectrum = match (&src1.kind, &src2.kind) {
+                (NoiseSourceKind::WhiteNoise { .. }, NoiseSourceKind::WhiteNoise { .. }) => true,
+                (
+                    NoiseSourceKind::FlickerNoise { exp: exp1, .. },
+                    NoiseSourceKind::FlickerNoise { exp: exp2, .. },
+                ) => is_same_val(*exp1, *exp2),
+                (
+                    NoiseSourceKind::NoiseTable { log: log1, vals: vals1 },
+                    NoiseSourceKind::NoiseTable { log: log2, vals: vals2 },
+                ) => log1 == log2 && vals1 == vals2,
+                _ => false,
+            };
+            if !same_spectrum {
+                continue;
+            }
+            if let (Some(sign1), Some(sign2)) = (factor_sign(src1.factor), factor_sign(src2.factor))
+            {
+                res.push(NoiseCorrelation {
+                    source_1: i as u32,
+                    source_2: j as u32,
+                    coefficient: sign1 * sign2,
+                })
+            }
+        }
+    }
+    res
+}
+
+/// Determines the sign (1 or -1) of the factor of a noise source at compile time. hir_lower folds
+/// negated contributions into the orientation of the branch, so the factor is usually a positive
+/// constant multiplied with `$mfactor` (which is always positive). Returns `None` if the sign
+/// depends on values that are only known at runtime.
+pub(crate) fn factor_sign(func: &Function, val: Value, mfactor: Option<Value>) -> Option<f64> {
+    let val = strip_optbarrier(func, val);
+    if Some(val) == mfactor {
+        return Some(1.0);
+    }
+    match func.dfg.value_def(val) {
+        ValueDef::Const(Const::Float(val)) => {
+            let val: f64 = val.into();
+            (val != 0.0).then(|| val.signum())
+        }
+        ValueDef::Result(inst, _) => match func.dfg.insts[inst] {
+            InstructionData::Unary { opcode: Opcode::Fneg, arg } => {
+                factor_sign(func, arg, mfactor).map(|sign| -sign)
+            }
+            InstructionData::Binary { opcode: Opcode::Fmul | Opcode::Fdiv, args: [lhs, rhs] } => {
+                Some(factor_sign(func, lhs, mfactor)? * factor_sign(func, rhs, mfactor)?)
+            }
+            // switch branches select between the factor and zero, phis of phis (loops) are
+            // not followed
+            InstructionData::PhiNode(_) => {
+                let mut res = None;
+                for &arg in func.dfg.instr_args(inst) {
+                    let arg = strip_optbarrier(func, arg);
+                    if arg == F_ZERO {
+                        continue;
+                    }
+                    let is_phi = func
+                        .dfg
+                        .value_def(arg)
+                        .inst()
+                        .map_or(false, |inst| func.dfg.insts[inst].is_phi());
+                    if is_phi {
+                        return None;
+                    }
+                    let sign = factor_sign(func, arg, mfactor)?;
+                    if res.map_or(false, |res| res != sign) {
+                        return None;
+                    }
+                    res = Some(sign);
+                }
+                res
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
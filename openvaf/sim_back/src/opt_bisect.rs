@@ -0,0 +1,30 @@
+/// Limits the number of MIR optimization passes that are run while a module is compiled
+/// (similar to LLVM's `-opt-bisect-limit`).
+///
+/// Every pass asks the `OptBisect` whether it should run. Only the first `limit` passes do (all
+/// passes if there is no limit), the remaining ones are skipped. The names of all passes that
+/// were asked for are recorded (whether they ran or not), so bisecting the limit finds the pass
+/// that introduced a miscompilation.
+#[derive(Debug, Clone, Default)]
+pub struct OptBisect {
+    limit: Option<usize>,
+    passes: Vec<String>,
+}
+
+impl OptBisect {
+    pub fn new(limit: Option<usize>) -> OptBisect {
+        OptBisect { limit, passes: Vec::new() }
+    }
+
+    /// Records `pass` and returns whether it is within the limit.
+    pub(crate) fn should_run(&mut self, pass: impl Into<String>) -> bool {
+        self.passes.push(pass.into());
+        self.limit.map_or(true, |limit| self.passes.len() <= limit)
+    }
+
+    /// The passes that were encountered so far, in the order in which they were (or would have
+    /// been) run.
+    pub fn passes(&self) -> &[String] {
+        &self.passes
+    }
+}
@@ -0,0 +1,188 @@
+use std::cell::RefCell;
+use std::ffi::c_void;
+
+use hir::Parameter;
+use hir_lower::fmt::DisplayKind;
+use hir_lower::{CallBackKind, HirInterner, LimitState, ParamInfoKind, ParamKind};
+use lasso::Spur;
+use mir::{FuncRef, Function, Param, Value};
+use mir_interpret::{Data, Func, Interpreter, InterpreterState};
+use sim_back::node_collapse::CollapsePair;
+use sim_back::{CompiledModule, SimUnknownKind};
+use typed_index_collections::{TiSlice, TiVec};
+
+use crate::strings::Strings;
+use crate::{fmt, Analysis, Message, SimParams};
+
+/// The state shared by the callbacks of one function call, it plays the role of the simulator.
+pub(crate) struct Callbacks<'a, 'l> {
+    module: &'a CompiledModule<'a>,
+    intern: &'a HirInterner,
+    strings: &'a RefCell<Strings<'l>>,
+    sim_params: &'a SimParams,
+    /// `None` outside of `eval`.
+    pub(crate) analysis: Option<Analysis>,
+    pub(crate) next_state: TiVec<LimitState, f64>,
+    pub(crate) collapsed: TiVec<CollapsePair, bool>,
+    pub(crate) invalid_params: Vec<Parameter>,
+    pub(crate) messages: Vec<Message>,
+    pub(crate) fatal: bool,
+    pub(crate) lim_discontinuity: bool,
+}
+
+impl<'a, 'l> Callbacks<'a, 'l> {
+    pub(crate) fn new(
+        module: &'a CompiledModule<'a>,
+        intern: &'a HirInterner,
+        strings: &'a RefCell<Strings<'l>>,
+        sim_params: &'a SimParams,
+    ) -> Callbacks<'a, 'l> {
+        Callbacks {
+            module,
+            intern,
+            strings,
+            sim_params,
+            analysis: None,
+            next_state: TiVec::new(),
+            collapsed: vec![false; module.node_collapse.num_pairs() as usize].into(),
+            invalid_params: Vec::new(),
+            messages: Vec::new(),
+            fatal: false,
+            lim_discontinuity: false,
+        }
+    }
+
+    fn call(
+        &mut self,
+        func_ref: FuncRef,
+        state: &mut InterpreterState,
+        args: &[Value],
+        rets: &[Value],
+    ) {
+        let read_str = |state: &InterpreterState, val| -> String {
+            let key: Spur = state.read(val);
+            self.strings.borrow().resolve(key).to_owned()
+        };
+
+        match self.intern.callbacks[func_ref] {
+            CallBackKind::Print { kind, ref arg_tys } => {
+                let args: Vec<Data> = args.iter().map(|&arg| state.read(arg)).collect();
+                let text = fmt::format(&self.strings.borrow(), arg_tys, &args);
+                if kind == DisplayKind::Fatal {
+                    self.fatal = true;
+                }
+                self.messages.push(Message { kind, text });
+            }
+            CallBackKind::SimParam => {
+                let name = read_str(state, args[0]);
+                let val = match self.sim_params.real.get(&name) {
+                    Some(&val) => val,
+                    None => {
+                        self.fatal = true;
+                        let text = format!("unknown $simparam {name}");
+                        self.messages.push(Message { kind: DisplayKind::Fatal, text });
+                        0.0
+                    }
+                };
+                state.write(rets[0], val);
+            }
+            CallBackKind::SimParamOpt => {
+                let name = read_str(state, args[0]);
+                let val = match self.sim_params.real.get(&name) {
+                    Some(&val) => val,
+                    None => state.read(args[1]),
+                };
+                state.write(rets[0], val);
+            }
+            CallBackKind::SimParamStr => {
+                let name = read_str(state, args[0]);
+                let val = match self.sim_params.string.get(&name) {
+                    Some(val) => val.as_str(),
+                    None => {
+                        self.fatal = true;
+                        let text = format!("unknown $simparam_str {name}");
+                        self.messages.push(Message { kind: DisplayKind::Fatal, text });
+                        ""
+                    }
+                };
+                let val = self.strings.borrow_mut().intern(val);
+                state.write(rets[0], val);
+            }
+            // If these derivative were non zero they would have been removed
+            CallBackKind::Derivative(_) | CallBackKind::NodeDerivative(_) => {
+                state.write(rets[0], 0.0)
+            }
+            CallBackKind::ParamInfo(ParamInfoKind::Invalid, param) => {
+                if !self.invalid_params.contains(&param) {
+                    self.invalid_params.push(param)
+                }
+            }
+            CallBackKind::CollapseHint(hi, lo) => {
+                let unknowns = &self.module.dae_system.unknowns;
+                let hi = unknowns.unwrap_index(&SimUnknownKind::KirchoffLaw(hi));
+                let lo = lo.map(|lo| unknowns.unwrap_index(&SimUnknownKind::KirchoffLaw(lo)));
+                let collapsed = &mut self.collapsed;
+                self.module.node_collapse.hint(hi, lo, |pair| collapsed[pair] = true);
+            }
+            CallBackKind::LimDiscontinuity => self.lim_discontinuity = true,
+            CallBackKind::Analysis => {
+                let name = read_str(state, args[0]);
+                let res = self.analysis.map_or(false, |analysis| analysis.matches(&name));
+                state.write(rets[0], res);
+            }
+            // builtin limit functions are implemented by the simulator, the value is not limited
+            CallBackKind::BuiltinLimit { .. } => {
+                let val: f64 = state.read(args[0]);
+                state.write(rets[0], val);
+            }
+            CallBackKind::StoreLimit(lim_state) => {
+                let val: f64 = state.read(args[0]);
+                self.next_state[lim_state] = val;
+                // the new state is read after it was stored (to compute the limit rhs)
+                if let Some(&new_state) = self.intern.params.get(&ParamKind::NewState(lim_state)) {
+                    state.write(new_state, val);
+                }
+                state.write(rets[0], val);
+            }
+            CallBackKind::ParamInfo(..)
+            | CallBackKind::TimeDerivative
+            | CallBackKind::WhiteNoise { .. }
+            | CallBackKind::FlickerNoise { .. }
+            | CallBackKind::NoiseTable(_) => (),
+        }
+    }
+}
+
+struct CallbackData<'a, 'l> {
+    cx: *mut Callbacks<'a, 'l>,
+    func_ref: FuncRef,
+}
+
+fn dispatch(state: &mut InterpreterState, args: &[Value], rets: &[Value], data: *mut c_void) {
+    // SAFETY: data always points to a `CallbackData` created by `run` that outlives the
+    // interpreter, nothing else accesses the `Callbacks` while the interpreter runs
+    unsafe {
+        let data = &*(data as *const CallbackData);
+        (*data.cx).call(data.func_ref, state, args, rets)
+    }
+}
+
+/// Interprets `func` (whose callbacks are described by the interner of `cx`) and returns the
+/// values of all instructions.
+pub(crate) fn run(
+    func: &Function,
+    args: &TiSlice<Param, Data>,
+    cx: &mut Callbacks<'_, '_>,
+) -> InterpreterState {
+    let num_callbacks = cx.intern.callbacks.len();
+    let cx: *mut Callbacks = cx;
+    let data: TiVec<FuncRef, CallbackData> =
+        (0..num_callbacks).map(|i| CallbackData { cx, func_ref: i.into() }).collect();
+    let calls: TiVec<FuncRef, (Func, *mut c_void)> = data
+        .iter()
+        .map(|data| (dispatch as Func, data as *const CallbackData as *mut c_void))
+        .collect();
+    let mut interpreter = Interpreter::new(func, &calls, args);
+    interpreter.run();
+    interpreter.state
+}
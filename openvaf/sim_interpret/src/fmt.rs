@@ -0,0 +1,284 @@
+//! Formatting of `$display` and friends.
+//!
+//! The format literals of [`CallBackKind::Print`](hir_lower::CallBackKind::Print) callbacks use
+//! the syntax of C's `printf` (they are passed to `snprintf` by the LLVM backend). This module
+//! implements the subset of it that the Verilog-A format specifiers are lowered to.
+
+use std::fmt::Write;
+
+use hir_lower::fmt::{FmtArg, FmtArgKind};
+use mir_interpret::Data;
+
+use crate::strings::Strings;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Arg {
+    Int(i32),
+    Real(f64),
+    Str(String),
+    Char(char),
+}
+
+impl Arg {
+    fn int(&self) -> i32 {
+        match *self {
+            Arg::Int(val) => val,
+            Arg::Real(val) => val as i32,
+            Arg::Char(val) => val as i32,
+            Arg::Str(_) => 0,
+        }
+    }
+
+    fn real(&self) -> f64 {
+        match *self {
+            Arg::Int(val) => val as f64,
+            Arg::Real(val) => val,
+            Arg::Char(val) => val as u32 as f64,
+            Arg::Str(_) => 0.0,
+        }
+    }
+}
+
+/// Formats the arguments of a print callback (`args[0]` is the format literal).
+pub(crate) fn format(strings: &Strings, arg_tys: &[FmtArg], args: &[Data]) -> String {
+    let fmt = strings.resolve(args[0].str());
+    let mut expanded = Vec::with_capacity(arg_tys.len());
+    for (ty, &arg) in arg_tys.iter().zip(&args[1..]) {
+        match ty.kind {
+            FmtArgKind::Binary => expanded.push(Arg::Str(format!("{:b}", arg.i32()))),
+            FmtArgKind::EngineerReal => {
+                let (val, scale) = engineering_notation(arg.f64());
+                expanded.push(Arg::Real(val));
+                expanded.push(Arg::Char(scale));
+            }
+            FmtArgKind::Other => {
+                let arg = match ty.ty {
+                    hir::Type::Real => Arg::Real(arg.f64()),
+                    hir::Type::String => Arg::Str(strings.resolve(arg.str()).to_owned()),
+                    _ => Arg::Int(arg.i32()),
+                };
+                expanded.push(arg)
+            }
+        }
+    }
+    printf(fmt, &expanded)
+}
+
+/// Scales `val` so that it can be printed with a SI prefix.
+fn engineering_notation(val: f64) -> (f64, char) {
+    const PREFIXES: [char; 11] = ['a', 'f', 'p', 'n', 'u', 'm', ' ', 'k', 'M', 'G', 'T'];
+    let exp =
+        if val == 0.0 || !val.is_finite() { 0 } else { (val.abs().log10() / 3.0).floor() as i32 };
+    let idx = (exp + 6).clamp(0, PREFIXES.len() as i32 - 1);
+    let scale = 10f64.powi(3 * (6 - idx));
+    (val * scale, PREFIXES[idx as usize])
+}
+
+#[derive(Default)]
+struct Spec {
+    left_align: bool,
+    zero_pad: bool,
+    plus: bool,
+    space: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+fn printf(fmt: &str, args: &[Arg]) -> String {
+    let mut args = args.iter();
+    let mut next_arg = || args.next().cloned().unwrap_or(Arg::Int(0));
+    let mut res = String::with_capacity(fmt.len());
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            res.push(c);
+            continue;
+        }
+
+        let mut spec = Spec::default();
+        while let Some(&c) = chars.peek() {
+            match c {
+                '-' => spec.left_align = true,
+                '0' => spec.zero_pad = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => (),
+                _ => break,
+            }
+            chars.next();
+        }
+        if chars.peek() == Some(&'*') {
+            chars.next();
+            spec.width = next_arg().int().max(0) as usize;
+        }
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            spec.width = spec.width * 10 + digit as usize;
+            chars.next();
+        }
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            let mut precision = 0;
+            if chars.peek() == Some(&'*') {
+                chars.next();
+                precision = next_arg().int().max(0) as usize;
+            }
+            while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                precision = precision * 10 + digit as usize;
+                chars.next();
+            }
+            spec.precision = Some(precision);
+        }
+        // length modifiers are irrelevant here
+        while matches!(chars.peek(), Some('l' | 'h')) {
+            chars.next();
+        }
+
+        let conversion = if let Some(c) = chars.next() { c } else { break };
+        let (sign, body) = match conversion {
+            '%' => {
+                res.push('%');
+                continue;
+            }
+            'd' | 'i' | 'u' | 'o' | 'x' | 'X' => {
+                let val = next_arg().int();
+                let (negative, mut digits) = match conversion {
+                    'd' | 'i' => (val < 0, (val as i64).abs().to_string()),
+                    'u' => (false, (val as u32).to_string()),
+                    'o' => (false, format!("{:o}", val)),
+                    'x' => (false, format!("{:x}", val)),
+                    _ => (false, format!("{:X}", val)),
+                };
+                if let Some(precision) = spec.precision {
+                    // the precision is the minimum number of digits for integers
+                    if digits.len() < precision {
+                        digits.insert_str(0, &"0".repeat(precision - digits.len()));
+                    }
+                    spec.zero_pad = false;
+                }
+                (spec.sign(negative), digits)
+            }
+            'c' => {
+                let c = match next_arg() {
+                    Arg::Char(c) => c,
+                    arg => char::from_u32(arg.int() as u32).unwrap_or(char::REPLACEMENT_CHARACTER),
+                };
+                spec.zero_pad = false;
+                ("", c.to_string())
+            }
+            's' => {
+                let mut val = match next_arg() {
+                    Arg::Str(val) => val,
+                    Arg::Char(c) => c.to_string(),
+                    Arg::Int(val) => val.to_string(),
+                    Arg::Real(val) => val.to_string(),
+                };
+                if let Some(precision) = spec.precision {
+                    val = val.chars().take(precision).collect();
+                }
+                spec.zero_pad = false;
+                ("", val)
+            }
+            'e' | 'E' | 'f' | 'F' | 'g' | 'G' => {
+                let val = next_arg().real();
+                let sign = spec.sign(val.is_sign_negative() && !val.is_nan());
+                let body = if val.is_finite() {
+                    let precision = spec.precision.unwrap_or(6);
+                    match conversion {
+                        'e' => exponential(val.abs(), precision),
+                        'E' => exponential(val.abs(), precision).to_uppercase(),
+                        'f' | 'F' => format!("{:.*}", precision, val.abs()),
+                        'g' => general(val.abs(), precision),
+                        _ => general(val.abs(), precision).to_uppercase(),
+                    }
+                } else {
+                    spec.zero_pad = false;
+                    let body = if val.is_nan() { "nan" } else { "inf" };
+                    if conversion.is_ascii_uppercase() {
+                        body.to_uppercase()
+                    } else {
+                        body.to_owned()
+                    }
+                };
+                (sign, body)
+            }
+            // not produced by the lowering of format specifiers, print as is
+            c => {
+                res.push('%');
+                res.push(c);
+                continue;
+            }
+        };
+        spec.pad(&mut res, sign, &body);
+    }
+    res
+}
+
+impl Spec {
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    fn pad(&self, dst: &mut String, sign: &str, body: &str) {
+        let len = sign.len() + body.chars().count();
+        let padding = self.width.saturating_sub(len);
+        if self.left_align {
+            dst.push_str(sign);
+            dst.push_str(body);
+            dst.extend((0..padding).map(|_| ' '));
+        } else if self.zero_pad {
+            dst.push_str(sign);
+            dst.extend((0..padding).map(|_| '0'));
+            dst.push_str(body);
+        } else {
+            dst.extend((0..padding).map(|_| ' '));
+            dst.push_str(sign);
+            dst.push_str(body);
+        }
+    }
+}
+
+/// `%e` for a positive, finite `val`.
+fn exponential(val: f64, precision: usize) -> String {
+    // rust omits the sign and leading zeros of the exponent (1.5e3 instead of 1.5e+03)
+    let formatted = format!("{:.*e}", precision, val);
+    let (mantissa, exp) = formatted.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let mut res = String::with_capacity(mantissa.len() + 4);
+    res.push_str(mantissa);
+    let _ = write!(res, "e{}{:02}", if exp < 0 { '-' } else { '+' }, exp.abs());
+    res
+}
+
+/// `%g` for a positive, finite `val`.
+fn general(val: f64, precision: usize) -> String {
+    let precision = precision.max(1);
+    let exp = if val == 0.0 {
+        0
+    } else {
+        // the exponent after rounding to the requested number of significant digits
+        let formatted = format!("{:.*e}", precision - 1, val);
+        formatted.split_once('e').unwrap().1.parse().unwrap()
+    };
+    let mut res = if exp < -4 || exp >= precision as i32 {
+        exponential(val, precision - 1)
+    } else {
+        format!("{:.*}", (precision as i32 - 1 - exp) as usize, val)
+    };
+
+    // trailing zeros are removed from the fraction
+    let exp_start = res.find('e').unwrap_or(res.len());
+    let (fraction, exp) = res.split_at(exp_start);
+    if fraction.contains('.') {
+        let fraction = fraction.trim_end_matches('0').trim_end_matches('.');
+        res = format!("{fraction}{exp}");
+    }
+    res
+}
@@ -0,0 +1,517 @@
+//! An interpreter backend for [`CompiledModule`].
+//!
+//! The functions of a compiled module are normally turned into machine code by the LLVM backend
+//! (`osdi`). This crate evaluates the same MIR with [`mir_interpret`] on host side data instead.
+//! [`InterpretedModule::setup_model`], [`InterpretedModule::setup_instance`] and
+//! [`InterpretedModule::eval`] mirror the OSDI functions of the same name and produce the same
+//! residuals and jacobian entries. That allows testing the middle end without LLVM and serves as a
+//! reference to compare the generated code against.
+//!
+//! Compared to a simulator that loads the OSDI library there are a few simplifications:
+//!
+//! * the builtin `$limit` functions return the value unlimited
+//! * noise sources are not evaluated
+//! * node collapsing is only reported (see [`InterpretedModule::collapsed_nodes`]), the residual
+//!   is always returned for all unknowns
+
+use std::cell::RefCell;
+
+use ahash::{AHashMap, AHashSet, RandomState};
+use hir::{CompilationDB, ParamSysFun, Parameter, Type, Variable};
+use hir_lower::fmt::DisplayKind;
+use hir_lower::{CurrentKind, HirInterner, LimitState, ParamKind, PlaceKind};
+use indexmap::IndexMap;
+use lasso::Rodeo;
+use mir::{Function, Param};
+use mir_interpret::{Data, InterpreterState};
+use sim_back::dae::{MatrixEntryId, SimUnknown};
+use sim_back::init::CacheSlot;
+use sim_back::node_collapse::CollapsePair;
+use sim_back::{CompiledModule, SimUnknownKind};
+use typed_index_collections::{TiSlice, TiVec};
+
+use crate::callbacks::{run, Callbacks};
+use crate::strings::Strings;
+
+mod callbacks;
+mod fmt;
+mod strings;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamValue {
+    Real(f64),
+    Integer(i32),
+    String(String),
+}
+
+impl From<f64> for ParamValue {
+    fn from(val: f64) -> ParamValue {
+        ParamValue::Real(val)
+    }
+}
+
+impl From<i32> for ParamValue {
+    fn from(val: i32) -> ParamValue {
+        ParamValue::Integer(val)
+    }
+}
+
+impl From<String> for ParamValue {
+    fn from(val: String) -> ParamValue {
+        ParamValue::String(val)
+    }
+}
+
+impl From<&str> for ParamValue {
+    fn from(val: &str) -> ParamValue {
+        ParamValue::String(val.to_owned())
+    }
+}
+
+/// The parameters given to a model or an instance (the equivalent of a netlist line).
+#[derive(Debug, Clone, Default)]
+pub struct ParamValues {
+    user: AHashMap<Parameter, ParamValue>,
+    builtin: AHashMap<ParamSysFun, f64>,
+}
+
+impl ParamValues {
+    pub fn set(&mut self, param: Parameter, val: impl Into<ParamValue>) {
+        self.user.insert(param, val.into());
+    }
+
+    pub fn set_builtin(&mut self, param: ParamSysFun, val: f64) {
+        self.builtin.insert(param, val);
+    }
+
+    pub fn get(&self, param: Parameter) -> Option<&ParamValue> {
+        self.user.get(&param)
+    }
+}
+
+/// The values returned by `$simparam` and `$simparam_str`.
+#[derive(Debug, Clone, Default)]
+pub struct SimParams {
+    pub real: AHashMap<String, f64>,
+    pub string: AHashMap<String, String>,
+}
+
+/// The analysis during which the module is evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Analysis {
+    Dc,
+    Ac,
+    Noise,
+    Tran,
+    /// The initial condition of a transient analysis.
+    Ic,
+}
+
+impl Analysis {
+    /// Whether `analysis(name)` returns true during this analysis.
+    pub fn matches(self, name: &str) -> bool {
+        match name {
+            "dc" => self == Analysis::Dc,
+            "static" => self != Analysis::Tran,
+            "ac" => self == Analysis::Ac,
+            "noise" => self == Analysis::Noise,
+            "tran" => matches!(self, Analysis::Tran | Analysis::Ic),
+            "ic" => self == Analysis::Ic,
+            _ => false,
+        }
+    }
+
+    fn enable_integration(self) -> bool {
+        !matches!(self, Analysis::Dc | Analysis::Ic)
+    }
+}
+
+/// The simulator state that `eval` depends on.
+pub struct SimInfo<'a> {
+    pub analysis: Analysis,
+    pub abstime: f64,
+    /// The solution of the previous iteration (indexed by [`SimUnknown`]).
+    pub prev_solve: &'a [f64],
+    /// The limit states of the previous iteration (indexed by [`LimitState`]).
+    pub prev_state: &'a [f64],
+    pub enable_lim: bool,
+    pub sim_params: &'a SimParams,
+}
+
+/// The errors and messages produced by `setup_model` or `setup_instance`.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Parameters that were rejected, either because their value is out of bounds or because it
+    /// has the wrong type.
+    pub invalid_params: Vec<Parameter>,
+    pub messages: Vec<Message>,
+    pub fatal: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub kind: DisplayKind,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Model {
+    /// The value of every parameter after the model was setup (including instance parameters
+    /// which act as a default for the instances of the model).
+    pub params: AHashMap<Parameter, ParamValue>,
+    pub given: AHashSet<Parameter>,
+    builtin: AHashMap<ParamSysFun, f64>,
+}
+
+pub struct Instance {
+    /// The values of the instance parameters after the instance was setup.
+    pub params: AHashMap<Parameter, ParamValue>,
+    pub given: AHashSet<Parameter>,
+    pub builtin: AHashMap<ParamSysFun, f64>,
+    pub temperature: f64,
+    pub connected_terminals: u32,
+    cache: TiVec<CacheSlot, Data>,
+    collapsed: TiVec<CollapsePair, bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EvalResult {
+    pub residual_resist: TiVec<SimUnknown, f64>,
+    pub residual_react: TiVec<SimUnknown, f64>,
+    pub lim_rhs_resist: TiVec<SimUnknown, f64>,
+    pub lim_rhs_react: TiVec<SimUnknown, f64>,
+    pub jacobian_resist: TiVec<MatrixEntryId, f64>,
+    pub jacobian_react: TiVec<MatrixEntryId, f64>,
+    pub opvars: IndexMap<Variable, ParamValue, RandomState>,
+    pub bound_step: Option<f64>,
+    /// The limit states of this iteration (indexed by [`LimitState`]).
+    pub next_state: Vec<f64>,
+    pub messages: Vec<Message>,
+    pub fatal: bool,
+    pub lim_discontinuity: bool,
+}
+
+pub struct InterpretedModule<'a> {
+    db: &'a CompilationDB,
+    module: &'a CompiledModule<'a>,
+    strings: RefCell<Strings<'a>>,
+}
+
+impl<'a> InterpretedModule<'a> {
+    /// `literals` must be the interner that `module` was compiled with.
+    pub fn new(
+        db: &'a CompilationDB,
+        module: &'a CompiledModule<'a>,
+        literals: &'a Rodeo,
+    ) -> InterpretedModule<'a> {
+        InterpretedModule { db, module, strings: RefCell::new(Strings::new(literals)) }
+    }
+
+    /// Looks up a parameter by its name or one of its aliases.
+    pub fn param(&self, name: &str) -> Option<Parameter> {
+        self.module.info.params.iter().find_map(|(&param, info)| {
+            (info.name == name || info.alias.iter().any(|alias| alias == name)).then_some(param)
+        })
+    }
+
+    pub fn setup_model(&self, params: &ParamValues, sim_params: &SimParams) -> (Model, Report) {
+        let intern = &self.module.model_param_intern;
+        let mut report = Report::default();
+        let mut given = AHashSet::new();
+        let mut values = AHashMap::new();
+        for (&param, val) in &params.user {
+            match self.to_data(param, val) {
+                Some(data) => {
+                    given.insert(param);
+                    values.insert(param, data);
+                }
+                None => report.invalid_params.push(param),
+            }
+        }
+
+        let args = intern.params.raw.keys().map(|kind| match *kind {
+            ParamKind::Param(param) => values.get(&param).copied().unwrap_or(Data::UNDEF),
+            ParamKind::ParamGiven { param } => given.contains(&param).into(),
+            ParamKind::ParamSysFun(builtin) => {
+                params.builtin.get(&builtin).copied().unwrap_or(builtin.default_value()).into()
+            }
+            _ => Data::UNDEF,
+        });
+
+        let mut cx = Callbacks::new(self.module, intern, &self.strings, sim_params);
+        let state = self.run(&self.module.model_param_setup, args, TiSlice::from_ref(&[]), &mut cx);
+
+        let values = self.read_params(intern, &state, &values, |_| true);
+        self.finish_report(&mut report, cx);
+        let model = Model { params: values, given, builtin: params.builtin.clone() };
+        (model, report)
+    }
+
+    pub fn setup_instance(
+        &self,
+        model: &Model,
+        params: &ParamValues,
+        temperature: f64,
+        connected_terminals: u32,
+        sim_params: &SimParams,
+    ) -> (Instance, Report) {
+        let init = &self.module.init;
+        let intern = &init.intern;
+        let mut report = Report::default();
+        let mut given = AHashSet::new();
+        let mut values = AHashMap::new();
+        for (&param, val) in &params.user {
+            if !self.module.info.params[&param].is_instance {
+                report.invalid_params.push(param);
+                continue;
+            }
+            match self.to_data(param, val) {
+                Some(data) => {
+                    given.insert(param);
+                    values.insert(param, data);
+                }
+                None => report.invalid_params.push(param),
+            }
+        }
+
+        let builtin: AHashMap<_, _> = ParamSysFun::iter()
+            .map(|param| {
+                let val = params
+                    .builtin
+                    .get(&param)
+                    .or_else(|| model.builtin.get(&param))
+                    .copied()
+                    .unwrap_or(param.default_value());
+                (param, val)
+            })
+            .collect();
+
+        let args = intern.params.raw.keys().map(|kind| match *kind {
+            ParamKind::Param(param) => match values.get(&param) {
+                Some(&val) => val,
+                None => self.model_param(model, param),
+            },
+            ParamKind::ParamGiven { param } => {
+                (given.contains(&param) || model.given.contains(&param)).into()
+            }
+            ParamKind::ParamSysFun(param) => builtin[&param].into(),
+            ParamKind::Temperature => temperature.into(),
+            ParamKind::PortConnected { port } => {
+                self.port_connected(port, connected_terminals).into()
+            }
+            _ => Data::UNDEF,
+        });
+
+        let mut cx = Callbacks::new(self.module, intern, &self.strings, sim_params);
+        let state = self.run(&init.func, args, TiSlice::from_ref(&[]), &mut cx);
+
+        for (&kind, val) in intern.outputs.iter() {
+            if let (PlaceKind::CollapseImplicitEquation(eq), Some(val)) = (kind, val.expand()) {
+                if state.read(val) {
+                    let eq = self.unknown(SimUnknownKind::Implicit(eq));
+                    let collapsed = &mut cx.collapsed;
+                    self.module.node_collapse.hint(eq, None, |pair| collapsed[pair] = true);
+                }
+            }
+        }
+
+        let mut cache: TiVec<CacheSlot, Data> = vec![Data::UNDEF; init.cache_slots.len()].into();
+        for (&val, &slot) in init.cached_vals.iter() {
+            cache[slot] = state.read(val);
+        }
+
+        let info = &self.module.info;
+        let params =
+            self.read_params(intern, &state, &values, |param| info.params[&param].is_instance);
+        let collapsed = std::mem::take(&mut cx.collapsed);
+        self.finish_report(&mut report, cx);
+        let instance =
+            Instance { params, given, builtin, temperature, connected_terminals, cache, collapsed };
+        (instance, report)
+    }
+
+    pub fn eval(&self, instance: &Instance, model: &Model, sim: &SimInfo) -> EvalResult {
+        let module = self.module;
+        let intern = &module.intern;
+        let prev_solve = |unknown| match module.dae_system.unknowns.index(&unknown) {
+            Some(unknown) => sim.prev_solve[usize::from(unknown)],
+            None => 0.0,
+        };
+        let prev_state = |state: LimitState| sim.prev_state[usize::from(state)];
+
+        let args = intern.params.raw.keys().map(|kind| match *kind {
+            ParamKind::Param(param) => match instance.params.get(&param) {
+                Some(val) => self.to_data(param, val).unwrap_or(Data::UNDEF),
+                None => self.model_param(model, param),
+            },
+            ParamKind::Voltage { hi, lo } => {
+                let mut voltage = prev_solve(SimUnknownKind::KirchoffLaw(hi));
+                if let Some(lo) = lo {
+                    voltage -= prev_solve(SimUnknownKind::KirchoffLaw(lo));
+                }
+                voltage.into()
+            }
+            ParamKind::Current(CurrentKind::Port(_)) => 0f64.into(),
+            ParamKind::Current(kind) => prev_solve(SimUnknownKind::Current(kind)).into(),
+            ParamKind::ImplicitUnknown(eq) => prev_solve(SimUnknownKind::Implicit(eq)).into(),
+            ParamKind::Abstime => sim.abstime.into(),
+            ParamKind::Temperature => instance.temperature.into(),
+            ParamKind::ParamGiven { param } => {
+                (instance.given.contains(&param) || model.given.contains(&param)).into()
+            }
+            ParamKind::PortConnected { port } => {
+                self.port_connected(port, instance.connected_terminals).into()
+            }
+            ParamKind::ParamSysFun(param) => instance.builtin[&param].into(),
+            // hidden states that are used are replaced by their initial value while building the
+            // MIR, the remaining parameters are dead (but only removed by the optimizations)
+            ParamKind::HiddenState(_) => Data::UNDEF,
+            ParamKind::EnableIntegration => sim.analysis.enable_integration().into(),
+            ParamKind::PrevState(state) | ParamKind::NewState(state) => prev_state(state).into(),
+            ParamKind::EnableLim => sim.enable_lim.into(),
+        });
+
+        let mut cx = Callbacks::new(module, intern, &self.strings, sim.sim_params);
+        cx.analysis = Some(sim.analysis);
+        cx.next_state = intern.lim_state.keys().map(&prev_state).collect();
+        let state = self.run(&module.eval, args, &instance.cache, &mut cx);
+
+        let read = |val| state.read::<f64>(val);
+        let residual = &module.dae_system.residual;
+        let jacobian = &module.dae_system.jacobian;
+        let opvars = module
+            .info
+            .op_vars
+            .keys()
+            .filter_map(|&var| {
+                let val = intern.outputs.get(&PlaceKind::Var(var))?.expand()?;
+                Some((var, self.from_data(&var.ty(self.db), state.read(val))?))
+            })
+            .collect();
+        let bound_step = intern
+            .outputs
+            .get(&PlaceKind::BoundStep)
+            .and_then(|val| val.expand())
+            .map(&read)
+            .filter(|step| step.is_finite());
+
+        EvalResult {
+            residual_resist: residual.iter().map(|residual| read(residual.resist)).collect(),
+            residual_react: residual.iter().map(|residual| read(residual.react)).collect(),
+            lim_rhs_resist: residual.iter().map(|residual| read(residual.resist_lim_rhs)).collect(),
+            lim_rhs_react: residual.iter().map(|residual| read(residual.react_lim_rhs)).collect(),
+            jacobian_resist: jacobian.iter().map(|entry| read(entry.resist)).collect(),
+            jacobian_react: jacobian.iter().map(|entry| read(entry.react)).collect(),
+            opvars,
+            bound_step,
+            next_state: cx.next_state.raw,
+            messages: cx.messages,
+            fatal: cx.fatal,
+            lim_discontinuity: cx.lim_discontinuity,
+        }
+    }
+
+    /// The pairs of unknowns that `setup_instance` decided to collapse. A missing second unknown
+    /// means that the first one is collapsed into ground.
+    pub fn collapsed_nodes(&self, instance: &Instance) -> Vec<(SimUnknown, Option<SimUnknown>)> {
+        self.module
+            .node_collapse
+            .pairs()
+            .filter(|&(pair, _, _)| instance.collapsed[pair])
+            .map(|(_, hi, lo)| (hi, lo))
+            .collect()
+    }
+
+    fn run(
+        &self,
+        func: &Function,
+        args: impl Iterator<Item = Data>,
+        cache: &TiSlice<CacheSlot, Data>,
+        cx: &mut Callbacks<'_, '_>,
+    ) -> InterpreterState {
+        let args: TiVec<Param, Data> = args.chain(cache.iter().copied()).collect();
+        run(func, &args, cx)
+    }
+
+    fn unknown(&self, kind: SimUnknownKind) -> SimUnknown {
+        self.module.dae_system.unknowns.unwrap_index(&kind)
+    }
+
+    fn port_connected(&self, port: hir::Node, connected_terminals: u32) -> bool {
+        u32::from(self.unknown(SimUnknownKind::KirchoffLaw(port))) < connected_terminals
+    }
+
+    fn model_param(&self, model: &Model, param: Parameter) -> Data {
+        match model.params.get(&param) {
+            Some(val) => self.to_data(param, val).unwrap_or(Data::UNDEF),
+            None => Data::UNDEF,
+        }
+    }
+
+    /// Reads the values of all parameters (that match `filter`) after a setup function ran.
+    /// The setup functions only write the default values of parameters that were not `given`.
+    fn read_params(
+        &self,
+        intern: &HirInterner,
+        state: &InterpreterState,
+        given: &AHashMap<Parameter, Data>,
+        filter: impl Fn(Parameter) -> bool,
+    ) -> AHashMap<Parameter, ParamValue> {
+        intern
+            .outputs
+            .iter()
+            .filter_map(|(kind, val)| match *kind {
+                PlaceKind::Param(param) if filter(param) => {
+                    let data = match given.get(&param) {
+                        Some(&data) => data,
+                        None => state.read(val.expand()?),
+                    };
+                    let val = self.from_data(&param.ty(self.db), data)?;
+                    Some((param, val))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn finish_report(&self, report: &mut Report, cx: Callbacks<'_, '_>) {
+        for param in cx.invalid_params {
+            if !report.invalid_params.contains(&param) {
+                report.invalid_params.push(param)
+            }
+        }
+        report.messages = cx.messages;
+        report.fatal = cx.fatal;
+    }
+
+    /// Converts a parameter value to the type of `param`. Returns `None` if they are
+    /// incompatible.
+    fn to_data(&self, param: Parameter, val: &ParamValue) -> Option<Data> {
+        let data = match (param.ty(self.db), val) {
+            (Type::Real, &ParamValue::Real(val)) => val.into(),
+            (Type::Real, &ParamValue::Integer(val)) => (val as f64).into(),
+            (Type::Integer, &ParamValue::Integer(val)) => val.into(),
+            (Type::String, ParamValue::String(val)) => self.strings.borrow_mut().intern(val).into(),
+            _ => return None,
+        };
+        Some(data)
+    }
+
+    fn from_data(&self, ty: &Type, data: Data) -> Option<ParamValue> {
+        if data.is_undef() {
+            return None;
+        }
+        let val = match ty {
+            Type::Real => ParamValue::Real(data.f64()),
+            Type::Integer => ParamValue::Integer(data.i32()),
+            Type::String => {
+                ParamValue::String(self.strings.borrow().resolve(data.str()).to_owned())
+            }
+            _ => return None,
+        };
+        Some(val)
+    }
+}
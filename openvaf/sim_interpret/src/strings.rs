@@ -0,0 +1,35 @@
+use indexmap::IndexSet;
+use lasso::{Key, Rodeo, Spur};
+
+/// The strings the interpreted functions operate on.
+///
+/// String constants of the MIR are keys of the [`Rodeo`] the module was compiled with. Strings
+/// that are only known at runtime (parameter values, `$simparam_str`) can not be added to it,
+/// they receive the keys after the last literal instead. Equal strings always map to the same key
+/// so comparisons in the MIR remain correct.
+pub(crate) struct Strings<'a> {
+    literals: &'a Rodeo,
+    runtime: IndexSet<String, ahash::RandomState>,
+}
+
+impl<'a> Strings<'a> {
+    pub(crate) fn new(literals: &'a Rodeo) -> Strings<'a> {
+        Strings { literals, runtime: IndexSet::default() }
+    }
+
+    pub(crate) fn intern(&mut self, val: &str) -> Spur {
+        if let Some(key) = self.literals.get(val) {
+            return key;
+        }
+        let (idx, _) = self.runtime.insert_full(val.to_owned());
+        Spur::try_from_usize(self.literals.len() + idx).expect("too many strings")
+    }
+
+    pub(crate) fn resolve(&self, key: Spur) -> &str {
+        let idx = key.into_usize();
+        match idx.checked_sub(self.literals.len()) {
+            Some(idx) => &self.runtime[idx],
+            None => self.literals.resolve(&key),
+        }
+    }
+}
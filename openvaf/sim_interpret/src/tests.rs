@@ -0,0 +1,101 @@
+use float_cmp::assert_approx_eq;
+use hir::diagnostics::ConsoleSink;
+use hir::CompilationDB;
+use indoc::indoc;
+use lasso::Rodeo;
+use sim_back::{collect_modules, CompiledModule, ModuleInfo, OptBisect, SimUnknownKind};
+
+use crate::{Analysis, InterpretedModule, ParamValues, SimInfo, SimParams};
+
+const RESISTOR: &str = indoc! {r#"
+    `include "disciplines.vams"
+    module resistor(inout a, inout c);
+        electrical a, c;
+        parameter real r = 1.0 from (0:inf);
+        parameter integer n = 3;
+        analog begin
+            I(a, c) <+ V(a, c) / r;
+            $strobe("r=%g n=%d", r, n);
+        end
+    endmodule
+"#};
+
+fn module_info(db: &CompilationDB) -> ModuleInfo {
+    collect_modules(db, false, &mut ConsoleSink::new(db)).unwrap().remove(0)
+}
+
+fn check_resistor(opt_bisect: &mut OptBisect) {
+    let db = CompilationDB::new_virtual(RESISTOR).unwrap();
+    let info = module_info(&db);
+    let mut literals = Rodeo::new();
+    let module = CompiledModule::with_opt_bisect(&db, &info, &mut literals, opt_bisect);
+    let interpreted = InterpretedModule::new(&db, &module, &literals);
+
+    let sim_params = SimParams::default();
+    let mut params = ParamValues::default();
+    params.set(interpreted.param("r").unwrap(), 2.0);
+    let (model, report) = interpreted.setup_model(&params, &sim_params);
+    assert_eq!(report.invalid_params, vec![]);
+    let (instance, report) =
+        interpreted.setup_instance(&model, &ParamValues::default(), 300.0, 2, &sim_params);
+    assert_eq!(report.invalid_params, vec![]);
+    // the $strobe only depends on parameters so it runs during the instance setup
+    let messages: Vec<_> = report.messages.iter().map(|msg| msg.text.as_str()).collect();
+    assert_eq!(messages, vec!["r=2 n=3\n"]);
+
+    let unknowns = &module.dae_system.unknowns;
+    let ports = info.module.ports(&db);
+    let a = unknowns.unwrap_index(&SimUnknownKind::KirchoffLaw(ports[0]));
+    let c = unknowns.unwrap_index(&SimUnknownKind::KirchoffLaw(ports[1]));
+    let mut prev_solve = vec![0.0; unknowns.len()];
+    prev_solve[usize::from(a)] = 1.0;
+    let sim = SimInfo {
+        analysis: Analysis::Dc,
+        abstime: 0.0,
+        prev_solve: &prev_solve,
+        prev_state: &[],
+        enable_lim: false,
+        sim_params: &sim_params,
+    };
+    let res = interpreted.eval(&instance, &model, &sim);
+
+    assert_approx_eq!(f64, res.residual_resist[a], 0.5);
+    assert_approx_eq!(f64, res.residual_resist[c], -0.5);
+    for (id, entry) in module.dae_system.jacobian.iter_enumerated() {
+        let expected = if entry.row == entry.col { 0.5 } else { -0.5 };
+        assert_approx_eq!(f64, res.jacobian_resist[id], expected);
+    }
+    assert!(!res.fatal);
+    assert_eq!(res.messages, vec![]);
+}
+
+#[test]
+fn resistor() {
+    check_resistor(&mut OptBisect::default());
+}
+
+#[test]
+fn resistor_unoptimized() {
+    let mut opt_bisect = OptBisect::new(Some(0));
+    check_resistor(&mut opt_bisect);
+    assert!(!opt_bisect.passes().is_empty());
+}
+
+#[test]
+fn invalid_param() {
+    let db = CompilationDB::new_virtual(RESISTOR).unwrap();
+    let info = module_info(&db);
+    let mut literals = Rodeo::new();
+    let module = CompiledModule::new(&db, &info, &mut literals);
+    let interpreted = InterpretedModule::new(&db, &module, &literals);
+
+    let r = interpreted.param("r").unwrap();
+    let n = interpreted.param("n").unwrap();
+    let mut params = ParamValues::default();
+    params.set(r, -1.0);
+    params.set(n, "three");
+    let (_, report) = interpreted.setup_model(&params, &SimParams::default());
+    assert!(report.invalid_params.contains(&r));
+    assert!(report.invalid_params.contains(&n));
+    assert_eq!(report.invalid_params.len(), 2);
+}
@@ -0,0 +1,12 @@
+use crate::spec::{LinkerFlavor, TargetOptions};
+
+pub fn opts() -> TargetOptions {
+    let mut opts = TargetOptions::default();
+
+    // the FreeBSD base system links with lld which does not know --no-add-needed
+    let link_args = opts.pre_link_args.entry(LinkerFlavor::Ld).or_default();
+    for arg in "--hash-style=gnu".split(' ') {
+        link_args.push(arg.to_owned())
+    }
+    opts
+}
@@ -3,6 +3,10 @@ use std::fmt;
 
 use paths::{AbsPath, AbsPathBuf};
 
+pub use self::poll::PollHandle;
+
+mod poll;
+
 /// A set of files on the file system.
 #[derive(Debug, Clone)]
 pub enum Entry {
@@ -0,0 +1,186 @@
+//! A [`Handle`] that detects modifications by periodically checking the watched files.
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use paths::{AbsPath, AbsPathBuf};
+
+use crate::loader::{Config, Entry, Handle, Message, Sender};
+
+/// How often the watched files are checked for modifications.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug)]
+enum Task {
+    SetConfig(Config),
+    Invalidate(AbsPathBuf),
+}
+
+/// A [`Handle`] that compares the modification time and size of all watched files at a fixed
+/// interval. Polling requires no support from the OS (it also works on network file systems)
+/// and is cheap for the small number of files a compilation unit consists of.
+#[derive(Debug)]
+pub struct PollHandle {
+    // `None` once the handle is dropped, which disconnects the channel and stops the thread
+    tasks: Option<mpsc::Sender<Task>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Handle for PollHandle {
+    fn spawn(sender: Sender) -> PollHandle {
+        let (tasks, receiver) = mpsc::channel();
+        let poller = Poller { sender, watched: Vec::new(), stamps: HashMap::new() };
+        let thread = thread::Builder::new()
+            .name("VfsPoller".to_owned())
+            .spawn(move || poller.run(receiver))
+            .expect("failed to spawn thread");
+        PollHandle { tasks: Some(tasks), thread: Some(thread) }
+    }
+
+    fn set_config(&mut self, config: Config) {
+        self.send(Task::SetConfig(config))
+    }
+
+    fn invalidate(&mut self, path: AbsPathBuf) {
+        self.send(Task::Invalidate(path))
+    }
+
+    fn load_sync(&mut self, path: &AbsPath) -> Option<Vec<u8>> {
+        read(path)
+    }
+}
+
+impl PollHandle {
+    fn send(&self, task: Task) {
+        if let Some(tasks) = &self.tasks {
+            tasks.send(task).expect("VfsPoller thread died")
+        }
+    }
+}
+
+impl Drop for PollHandle {
+    fn drop(&mut self) {
+        self.tasks = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The modification time and size of a file (`None` if the file does not exist).
+type Stamp = Option<(SystemTime, u64)>;
+
+struct Poller {
+    sender: Sender,
+    watched: Vec<Entry>,
+    stamps: HashMap<AbsPathBuf, Stamp>,
+}
+
+impl Poller {
+    fn run(mut self, tasks: Receiver<Task>) {
+        loop {
+            match tasks.recv_timeout(POLL_INTERVAL) {
+                Ok(Task::SetConfig(config)) => self.set_config(config),
+                Ok(Task::Invalidate(path)) => {
+                    let contents = self.load(&path);
+                    (self.sender)(Message::Loaded { files: vec![(path, contents)] })
+                }
+                Err(RecvTimeoutError::Timeout) => self.poll(),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    fn set_config(&mut self, config: Config) {
+        self.watched.clear();
+        self.stamps.clear();
+
+        let config_version = config.version;
+        let n_total = config.load.len();
+        (self.sender)(Message::Progress { n_total, n_done: 0, config_version });
+        for (i, entry) in config.load.into_iter().enumerate() {
+            let watch = config.watch.contains(&i);
+            let files = entry_files(&entry)
+                .into_iter()
+                .map(|path| {
+                    let contents = if watch { self.load(&path) } else { read(&path) };
+                    (path, contents)
+                })
+                .collect();
+            (self.sender)(Message::Loaded { files });
+            if watch {
+                self.watched.push(entry);
+            }
+            (self.sender)(Message::Progress { n_total, n_done: i + 1, config_version });
+        }
+    }
+
+    /// Reads the file at `path` and remembers its stamp.
+    fn load(&mut self, path: &AbsPath) -> Option<Vec<u8>> {
+        self.stamps.insert(path.to_path_buf(), stamp(path));
+        read(path)
+    }
+
+    fn poll(&mut self) {
+        // previously seen files are checked too so that deleted files are noticed
+        let mut files: Vec<_> = self.watched.iter().flat_map(entry_files).collect();
+        files.extend(self.stamps.keys().cloned());
+        files.sort();
+        files.dedup();
+
+        files.retain(|path| self.stamps.get(path) != Some(&stamp(path)));
+        let files: Vec<_> = files
+            .into_iter()
+            .map(|path| {
+                let contents = self.load(&path);
+                (path, contents)
+            })
+            .collect();
+        if !files.is_empty() {
+            (self.sender)(Message::Loaded { files })
+        }
+    }
+}
+
+/// Lists the files of `entry`. Explicitly listed files are returned even if they don't exist.
+fn entry_files(entry: &Entry) -> Vec<AbsPathBuf> {
+    match entry {
+        Entry::Files(files) => files.clone(),
+        Entry::Directories(dirs) => {
+            let mut files = Vec::new();
+            let mut todo: Vec<AbsPathBuf> = dirs.include.clone();
+            while let Some(dir) = todo.pop() {
+                let read_dir = if let Ok(read_dir) = fs::read_dir(&dir) {
+                    read_dir
+                } else {
+                    continue;
+                };
+                for entry in read_dir.flatten() {
+                    let path = AbsPathBuf::assert(entry.path());
+                    let is_dir = entry.file_type().map_or(false, |ty| ty.is_dir());
+                    if is_dir && dirs.contains_dir(&path) {
+                        todo.push(path)
+                    } else if !is_dir && dirs.contains_file(&path) {
+                        files.push(path)
+                    }
+                }
+            }
+            files
+        }
+    }
+}
+
+fn stamp(path: &AbsPath) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn read(path: &AbsPath) -> Option<Vec<u8>> {
+    fs::read(path).ok()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,41 @@
+use std::env;
+use std::fs;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use paths::AbsPathBuf;
+
+use crate::loader::{Config, Entry, Handle, Message, PollHandle};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[test]
+fn reload_modified_file() {
+    let dir = env::temp_dir().join(format!("vfs_poll_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = AbsPathBuf::assert(dir.join("root.va"));
+    fs::write(&file, "module foo;").unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    let mut handle = PollHandle::spawn(Box::new(move |msg| {
+        if let Message::Loaded { files } = msg {
+            let _ = sender.send(files);
+        }
+    }));
+    handle.set_config(Config {
+        version: 1,
+        load: vec![Entry::Files(vec![file.clone()])],
+        watch: vec![0],
+    });
+    let files = receiver.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(files, vec![(file.clone(), Some(b"module foo;".to_vec()))]);
+
+    fs::write(&file, "module foobar;").unwrap();
+    let files = receiver.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(files, vec![(file.clone(), Some(b"module foobar;".to_vec()))]);
+
+    fs::remove_file(&file).unwrap();
+    let files = receiver.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(files, vec![(file, None)]);
+    let _ = fs::remove_dir(&dir);
+}
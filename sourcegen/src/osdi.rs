@@ -21,8 +21,7 @@ fn gen_osdi_structs() {
         .collect();
 
     let osdi_src_dir = project_root().join("openvaf").join("osdi").join("src").join("metadata");
-    let osdi_test_dir = project_root().join("openvaf").join("openvaf").join("tests").join("load");
-    let melange_src_dir = project_root().join("melange").join("core").join("src").join("veriloga");
+    let osdi_host_dir = project_root().join("lib").join("osdi-host").join("src");
 
     for header in &headers {
         let res = HeaderParser { header, res: ParseResults::default(), off: 0 }.run();
@@ -63,8 +62,7 @@ fn gen_osdi_structs() {
         let file_string = add_preamble("gen_osdi_structs", reformat(file_string));
         let file_name = format!("osdi_{}_{}.rs", header.version_major, header.version_minor);
 
-        ensure_file_contents(&melange_src_dir.join(&file_name), &file_string);
-        ensure_file_contents(&osdi_test_dir.join(&file_name), &file_string);
+        ensure_file_contents(&osdi_host_dir.join(&file_name), &file_string);
     }
 }
 
@@ -502,6 +500,8 @@ impl ToTokens for OsdiStructInterp<'_, '_> {
                 | "OsdiInitInfo"
                 | "OsdiInitErrorPayload"
                 | "OsdiSimInfo"
+                | "OsdiAttributeValue"
+                | "OsdiAttribute"
         ) {
             assert!(!self.info.is_union, "union code generation is not implemented (yet)");
             let ident = Ident::new(ident, Span::call_site());
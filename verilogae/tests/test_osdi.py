@@ -0,0 +1,60 @@
+from pathlib import Path
+import shutil
+import subprocess
+import numpy as np
+import pytest
+import verilogae
+
+va_file = Path(__file__).parent.parent.parent / "openvaf" / "test_data" / "osdi" / "batch.va"
+
+R = 2e3
+IS = 1e-13
+TEMP = 300.0
+VT = 1.3806488e-23 * TEMP / 1.602176565e-19
+
+
+@pytest.fixture
+def batch(tmp_path):
+    if shutil.which("openvaf") is None:
+        pytest.skip("the openvaf binary is not available")
+    osdi_file = tmp_path / "batch.osdi"
+    subprocess.run(["openvaf", str(va_file), "-o", str(osdi_file)], check=True)
+    [batch] = verilogae.load_osdi(osdi_file)
+    return batch
+
+
+def diode(vd):
+    id = vd / R + IS * (np.exp(vd / VT) - 1.0)
+    gd = 1.0 / R + IS / VT * np.exp(vd / VT)
+    return id, gd
+
+
+def test_eval(batch):
+    assert batch.module_name == "batch"
+    assert batch.terminals == ["a", "c"]
+    assert batch.parameters[1:] == ["r", "is"]
+
+    res = batch.eval(voltages={"a": 0.5}, temperature=TEMP, modelcard={"r": R, "is": IS})
+    id, gd = diode(0.5)
+    assert np.allclose(res["residual_resist"], [id, -id])
+    assert np.allclose(res["residual_react"], [0.0, 0.0])
+    assert np.allclose(res["jacobian_resist"], [[gd, -gd], [-gd, gd]])
+    assert "noise" not in res
+
+
+def test_sweep(batch):
+    vd = np.linspace(0.3, 0.6, 4)
+    res = batch.eval(voltages={"a": vd}, temperature=TEMP, modelcard={"r": R, "is": IS})
+    id, gd = diode(vd)
+    assert res["residual_resist"].shape == (4, 2)
+    assert res["jacobian_resist"].shape == (4, 2, 2)
+    assert np.allclose(res["residual_resist"], np.stack([id, -id], axis=1))
+    assert np.allclose(res["jacobian_resist"][:, 0, 0], gd)
+
+    # parameters are swept together with the voltages
+    r = np.array([1e3, 2e3, 3e3, 4e3])
+    res = batch.eval(voltages={"a": vd}, temperature=TEMP, modelcard={"r": r, "is": IS})
+    assert np.allclose(res["residual_resist"][:, 0], vd / r + IS * (np.exp(vd / VT) - 1.0))
+
+    with pytest.raises(TypeError):
+        batch.eval(voltages={"a": vd}, temperature=np.array([300.0, 310.0]))
@@ -5,6 +5,7 @@ mod ffi;
 mod load;
 mod model;
 mod numpy;
+mod osdi;
 mod typeref;
 mod unicode;
 mod util;
@@ -14,6 +15,7 @@ use std::ptr;
 
 use crate::load::{load_info_py, load_py, load_vfs};
 use crate::model::{VAE_FUNCTION_TY, VAE_MODEL_TY, VAE_PARAM_TY};
+use crate::osdi::{load_osdi_py, OSDI_DEVICE_TY};
 use crate::typeref::init_typerefs;
 use pyo3_ffi::*;
 
@@ -22,7 +24,7 @@ const FUN_FLAG: c_int = METH_FASTCALL;
 #[cfg(not(Py_3_8))]
 const FUN_FLAG: c_int = METH_VARARGS;
 
-static mut FUNCTIONS: [PyMethodDef; 4] = unsafe {
+static mut FUNCTIONS: [PyMethodDef; 5] = unsafe {
     [
     PyMethodDef {
             ml_name: "load\0".as_ptr() as *const c_char,
@@ -51,6 +53,12 @@ static mut FUNCTIONS: [PyMethodDef; 4] = unsafe {
             ml_flags: FUN_FLAG | METH_KEYWORDS,
             ml_doc: "runs the preprocessor on a Verilog-A file and exports a dict with all files.\nThe result of this functions can be passed to other functions `vfs` argument\0".as_ptr() as *const c_char,
     },
+    PyMethodDef {
+            ml_name: "load_osdi\0".as_ptr() as *const c_char,
+            ml_meth: PyMethodDefPointer{PyCFunction: load_osdi_py},
+            ml_flags: METH_O,
+            ml_doc: "loads an OSDI library (compiled with OpenVAF) and returns a list with all devices it contains\0".as_ptr() as *const c_char,
+    },
     zero!(PyMethodDef)
 ]
 };
@@ -84,6 +92,10 @@ pub unsafe extern "C" fn PyInit_verilogae() -> *mut PyObject {
         return ptr::null_mut();
     }
 
+    if PyType_Ready(&mut OSDI_DEVICE_TY) < 0 {
+        return ptr::null_mut();
+    }
+
     let mptr = PyModule_Create(Box::into_raw(Box::new(init)));
     init_typerefs();
     let version = env!("CARGO_PKG_VERSION");
@@ -93,7 +105,8 @@ pub unsafe extern "C" fn PyInit_verilogae() -> *mut PyObject {
         PyUnicode_FromStringAndSize(version.as_ptr() as *const c_char, version.len() as isize),
     );
 
-    let all = ["__all__\0", "__version__\0", "load\0", "load_info\0", "export_vfs\0"];
+    let all =
+        ["__all__\0", "__version__\0", "load\0", "load_info\0", "export_vfs\0", "load_osdi\0"];
 
     let pyall = PyTuple_New(all.len() as isize);
     for (i, obj) in all.iter().enumerate() {
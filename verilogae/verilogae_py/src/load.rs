@@ -135,7 +135,7 @@ macro_rules! parse_args {
 
 #[cold]
 #[inline(never)]
-fn raise_type_exception(msg: &str) -> *mut PyObject {
+pub(crate) fn raise_type_exception(msg: &str) -> *mut PyObject {
     unsafe {
         let err_msg =
             PyUnicode_FromStringAndSize(msg.as_ptr() as *const c_char, msg.len() as isize);
@@ -147,7 +147,7 @@ fn raise_type_exception(msg: &str) -> *mut PyObject {
 
 #[cold]
 #[inline(never)]
-fn raise_runtime_runtime_exception(msg: &str) -> *mut PyObject {
+pub(crate) fn raise_runtime_runtime_exception(msg: &str) -> *mut PyObject {
     unsafe {
         let err_msg =
             PyUnicode_FromStringAndSize(msg.as_ptr() as *const c_char, msg.len() as isize);
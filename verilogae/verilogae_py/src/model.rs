@@ -1085,7 +1085,7 @@ fn raise_eval_exception(msg: &str) -> *mut PyObject {
 }
 
 #[inline(always)]
-fn is_array(ty: *mut PyTypeObject) -> bool {
+pub(crate) fn is_array(ty: *mut PyTypeObject) -> bool {
     let arr_type = unsafe { NUMPY_ARR_TYPE };
     match arr_type {
         Some(arr_type) => ty == arr_type,
@@ -1094,12 +1094,12 @@ fn is_array(ty: *mut PyTypeObject) -> bool {
 }
 
 #[inline(always)]
-unsafe fn is_float(ty: *mut PyTypeObject) -> bool {
+pub(crate) unsafe fn is_float(ty: *mut PyTypeObject) -> bool {
     ty == &mut PyFloat_Type || PyType_IsSubtype(ty, &mut PyFloat_Type) != 0
 }
 
 #[inline(always)]
-unsafe fn is_int(ty: *mut PyTypeObject) -> bool {
+pub(crate) unsafe fn is_int(ty: *mut PyTypeObject) -> bool {
     PyType_FastSubclass(ty, Py_TPFLAGS_LONG_SUBCLASS) != 0
 }
 
@@ -1,10 +1,13 @@
 use std::ffi::c_void;
 use std::fmt::Display;
 use std::os::raw::{c_char, c_int};
+use std::ptr;
 
-use pyo3_ffi::{PyObject, PyObject_GetAttr, PyTypeObject, Py_DECREF, Py_intptr_t, Py_ssize_t};
+use pyo3_ffi::{
+    PyObject, PyObject_GetAttr, PyTypeObject, Py_DECREF, Py_INCREF, Py_intptr_t, Py_ssize_t,
+};
 
-use crate::typeref::ARRAY_STRUCT_STR;
+use crate::typeref::{ARRAY_STRUCT_STR, NUMPY_API, NUMPY_ARR_TYPE, NUMPY_CDOUBLE_DESCR};
 
 // https://docs.scipy.org/doc/numpy/reference/arrays.interface.html#c.__array_struct__
 
@@ -123,3 +126,34 @@ impl Drop for NumpyArray {
         unsafe { Py_DECREF(self.capsule as *mut pyo3_ffi::PyObject) }
     }
 }
+
+/// Creates a new (C contiguous) float64 array with the shape `dims` that contains a copy of `src`.
+/// Returns `None` if numpy is not available and a null pointer if numpy raised an exception.
+pub unsafe fn new_float_array(dims: &mut [Py_intptr_t], src: &[f64]) -> Option<*mut PyObject> {
+    debug_assert_eq!(dims.iter().product::<Py_intptr_t>(), src.len() as Py_intptr_t);
+    let new_arr = NUMPY_API?;
+    Py_INCREF(NUMPY_CDOUBLE_DESCR);
+    let arr = new_arr(
+        NUMPY_ARR_TYPE?,     // base_type (normal numpy array)
+        NUMPY_CDOUBLE_DESCR, // type descriptor
+        dims.len() as c_int, // nd
+        dims.as_mut_ptr(),   // dims
+        ptr::null_mut(),     // strides (contiguous)
+        ptr::null_mut(),     // data (to be allocated)
+        0,                   // flags
+        ptr::null_mut(),     // obj (to be created)
+    );
+    if arr.is_null() {
+        return Some(arr);
+    }
+
+    let capsule = PyObject_GetAttr(arr, ARRAY_STRUCT_STR);
+    if capsule.is_null() {
+        Py_DECREF(arr);
+        return Some(ptr::null_mut());
+    }
+    let array = (*(capsule as *mut PyCapsule)).pointer as *mut PyArrayInterface;
+    ptr::copy_nonoverlapping(src.as_ptr(), (*array).data as *mut f64, src.len());
+    Py_DECREF(capsule);
+    Some(arr)
+}
@@ -0,0 +1,628 @@
+use std::ptr;
+
+use anyhow::{bail, Result};
+use camino::Utf8Path;
+use libc::c_char;
+use osdi_host::{Descriptor, EvalFlags, Library, Param, ParamTy, ParamValue, SimInfo};
+use pyo3_ffi::structmember::{PyMemberDef, READONLY, T_OBJECT_EX};
+use pyo3_ffi::*;
+use stdx::iter::zip;
+
+use crate::ffi::{new_type, PyDict_GET_SIZE};
+use crate::load::{raise_runtime_runtime_exception, raise_type_exception};
+use crate::model::{is_array, is_float, is_int};
+use crate::numpy::{new_float_array, ItemType, NumpyArray};
+use crate::typeref::{FREQ_STR, MODELCARD_STR, TEMPERATURE_STR, VOLTAGES_STR};
+use crate::unicode::OsStr;
+use crate::util::unlikely;
+
+pub static mut OSDI_DEVICE_TY: PyTypeObject = {
+    let mut res = new_type::<OsdiDevice>();
+    res.tp_name = "verilogae.OsdiDevice\0".as_ptr() as *const c_char;
+    res.tp_doc = "A Verilog-A module compiled with OpenVAF and loaded from an OSDI library\0"
+        .as_ptr() as *const c_char;
+    res.tp_members = unsafe { &mut OSDI_DEVICE_MEMBERS } as *mut _;
+    res.tp_methods = unsafe { &mut OSDI_DEVICE_METHODS } as *mut _;
+    res.tp_dealloc = Some(OsdiDevice::dealloc);
+    res
+};
+
+static mut OSDI_DEVICE_MEMBERS: [PyMemberDef; 7] = [
+    PyMemberDef {
+        name: "module_name\0".as_ptr() as *mut c_char,
+        type_code: T_OBJECT_EX,
+        offset: OsdiDevice::offset_to.module_name as isize,
+        flags: READONLY,
+        doc: "the name of the compiled module\0".as_ptr() as *mut c_char,
+    },
+    PyMemberDef {
+        name: "nodes\0".as_ptr() as *mut c_char,
+        type_code: T_OBJECT_EX,
+        offset: OsdiDevice::offset_to.nodes as isize,
+        flags: READONLY,
+        doc: "all nodes (terminals followed by internal nodes) of the compiled module\0".as_ptr()
+            as *mut c_char,
+    },
+    PyMemberDef {
+        name: "terminals\0".as_ptr() as *mut c_char,
+        type_code: T_OBJECT_EX,
+        offset: OsdiDevice::offset_to.terminals as isize,
+        flags: READONLY,
+        doc: "Verilog-A ports of the compiled module\0".as_ptr() as *mut c_char,
+    },
+    PyMemberDef {
+        name: "parameters\0".as_ptr() as *mut c_char,
+        type_code: T_OBJECT_EX,
+        offset: OsdiDevice::offset_to.parameters as isize,
+        flags: READONLY,
+        doc: "the names of all model and instance parameters\0".as_ptr() as *mut c_char,
+    },
+    PyMemberDef {
+        name: "op_vars\0".as_ptr() as *mut c_char,
+        type_code: T_OBJECT_EX,
+        offset: OsdiDevice::offset_to.op_vars as isize,
+        flags: READONLY,
+        doc: "all scalar numeric variables marked with (*op_var*)\0".as_ptr() as *mut c_char,
+    },
+    PyMemberDef {
+        name: "noise_sources\0".as_ptr() as *mut c_char,
+        type_code: T_OBJECT_EX,
+        offset: OsdiDevice::offset_to.noise_sources as isize,
+        flags: READONLY,
+        doc: "the names of all noise sources\0".as_ptr() as *mut c_char,
+    },
+    unsafe { zero!(PyMemberDef) },
+];
+
+const EVAL_DOC: &str = "eval(*, voltages, temperature, modelcard=None, freq=None)\n\
+--\n\n\
+Evaluates the device at the node `voltages` (a dict that maps node names to voltages, missing \
+nodes are grounded) and `temperature` (in Kelvin). The `modelcard` dict assigns the model and \
+instance parameters. Returns a dict with the residuals (`residual_resist`, `residual_react`) and \
+Jacobians (`jacobian_resist`, `jacobian_react`) indexed by `nodes`, the `op_vars` and, if `freq` \
+is specified, the power spectral density of each noise source (`noise`) as numpy arrays.\n\
+Nodes that were collapsed use the voltage of the node they were collapsed into.\n\
+The voltages, the temperature, the frequency and numeric parameters may also be one dimensional \
+numpy arrays (of the same length) to evaluate a sweep. The device is then evaluated once for \
+each element and every returned array has an additional leading dimension.\0";
+
+static mut OSDI_DEVICE_METHODS: [PyMethodDef; 2] = [
+    PyMethodDef {
+        ml_name: "eval\0".as_ptr() as *const c_char,
+        ml_meth: PyMethodDefPointer { PyCFunctionWithKeywords: OsdiDevice::eval },
+        ml_flags: METH_VARARGS | METH_KEYWORDS,
+        ml_doc: EVAL_DOC.as_ptr() as *const c_char,
+    },
+    unsafe { zero!(PyMethodDef) },
+];
+
+with_offsets! {
+    #[repr(C)]
+    pub struct OsdiDevice {
+        ob_base: PyObject,
+
+        // exposed API
+        module_name: *mut PyObject,
+        nodes: *mut PyObject,
+        terminals: *mut PyObject,
+        parameters: *mut PyObject,
+        op_vars: *mut PyObject,
+        noise_sources: *mut PyObject,
+
+        descriptor: Descriptor,
+    }
+}
+
+/// The operating point variables that can be returned by [`OsdiDevice::eval`].
+fn scalar_opvars(descriptor: Descriptor) -> impl Iterator<Item = Param> {
+    descriptor.opvars().filter(|opvar| opvar.ty() != ParamTy::Str && opvar.len() == 0)
+}
+
+unsafe fn str_list<'a>(names: impl ExactSizeIterator<Item = &'a str>) -> *mut PyObject {
+    let list = PyList_New(names.len() as isize);
+    if list.is_null() {
+        return list;
+    }
+    for (i, name) in names.enumerate() {
+        let name = PyUnicode_FromStringAndSize(name.as_ptr() as *const c_char, name.len() as isize);
+        PyList_SetItem(list, i as isize, name);
+    }
+    list
+}
+
+impl OsdiDevice {
+    #[allow(clippy::new_ret_no_self)]
+    unsafe fn new(descriptor: Descriptor) -> *mut PyObject {
+        let ptr = PyType_GenericAlloc(&mut OSDI_DEVICE_TY, 0);
+        if ptr.is_null() {
+            return ptr::null_mut();
+        }
+
+        let nodes = descriptor.nodes().len() as u32;
+        let opvars: Vec<_> = scalar_opvars(descriptor).map(|opvar| opvar.name()).collect();
+        let noise_sources =
+            descriptor.noise_sources().iter().map(|src| osdi_host::osdi_str(src.name));
+        let res = OsdiDevice {
+            ob_base: ptr::read(ptr),
+            module_name: PyUnicode_InternFromString(descriptor.raw().name),
+            nodes: str_list((0..nodes).map(|node| descriptor.node_name(node))),
+            terminals: str_list(
+                (0..descriptor.num_terminals()).map(|node| descriptor.node_name(node)),
+            ),
+            parameters: str_list(descriptor.params().map(|param| param.name())),
+            op_vars: str_list(opvars.into_iter()),
+            noise_sources: str_list(noise_sources),
+            descriptor,
+        };
+        ptr::write(ptr as *mut OsdiDevice, res);
+        ptr
+    }
+
+    unsafe extern "C" fn dealloc(self_: *mut PyObject) {
+        let self_ = &mut *(self_ as *mut Self);
+        Py_XDECREF(self_.module_name);
+        Py_XDECREF(self_.nodes);
+        Py_XDECREF(self_.terminals);
+        Py_XDECREF(self_.parameters);
+        Py_XDECREF(self_.op_vars);
+        Py_XDECREF(self_.noise_sources);
+    }
+
+    unsafe extern "C" fn eval(
+        self_: *mut PyObject,
+        args: *mut PyObject,
+        kwds: *mut PyObject,
+    ) -> *mut PyObject {
+        let descriptor = (*(self_ as *mut Self)).descriptor;
+
+        let arg_cnt = PyTuple_GET_SIZE(args);
+        if unlikely(arg_cnt != 0) {
+            return raise_type_exception(&format!(
+                "eval() {} unexpected positional arguments",
+                arg_cnt
+            ));
+        }
+        if unlikely(kwds.is_null()) {
+            return raise_type_exception("eval() missing required keyword argument 'voltages'");
+        }
+
+        // the length of the sweep, 1 until the first array is found
+        let mut len = 1;
+        let mut num_kwargs = 2;
+        let temperature = PyDict_GetItem(kwds, TEMPERATURE_STR);
+        if unlikely(temperature.is_null()) {
+            return raise_type_exception("eval() missing required keyword argument 'temperature'");
+        }
+        let temperature = match Sweep::read_real("temperature", temperature, &mut len) {
+            Ok(val) => val,
+            Err(err) => return err,
+        };
+
+        let freq = PyDict_GetItem(kwds, FREQ_STR);
+        let freq = if freq.is_null() {
+            None
+        } else {
+            num_kwargs += 1;
+            match Sweep::read_real("freq", freq, &mut len) {
+                Ok(val) => Some(val),
+                Err(err) => return err,
+            }
+        };
+
+        let voltages_ = PyDict_GetItem(kwds, VOLTAGES_STR);
+        if unlikely(voltages_.is_null()) {
+            return raise_type_exception("eval() missing required keyword argument 'voltages'");
+        }
+        if unlikely(PyDict_Check(voltages_) == 0) {
+            return raise_type_exception(
+                "eval() argument 'voltages' must have type dict(str,float)",
+            );
+        }
+        let mut voltages: Vec<_> =
+            (0..descriptor.nodes().len()).map(|_| Sweep::Scalar(ParamValue::Real(0.0))).collect();
+        for (name, val) in DictIter::new(voltages_) {
+            let name = match py_to_str(name) {
+                Some(name) => name,
+                None => {
+                    return raise_type_exception(
+                        "eval() argument 'voltages' must have type dict(str,float)",
+                    )
+                }
+            };
+            let node = (0..voltages.len() as u32).find(|&node| descriptor.node_name(node) == name);
+            let node = match node {
+                Some(node) => node,
+                None => {
+                    return raise_type_exception(&format!(
+                        "eval() {} has no node '{}'",
+                        descriptor.name(),
+                        name
+                    ))
+                }
+            };
+            voltages[node as usize] = match Sweep::read_real(name, val, &mut len) {
+                Ok(val) => val,
+                Err(err) => return err,
+            };
+        }
+
+        let mut modelcard = Vec::new();
+        let modelcard_ = PyDict_GetItem(kwds, MODELCARD_STR);
+        if !modelcard_.is_null() {
+            num_kwargs += 1;
+            if unlikely(PyDict_Check(modelcard_) == 0) {
+                return raise_type_exception("eval() argument 'modelcard' must have type dict");
+            }
+            for (name, val) in DictIter::new(modelcard_) {
+                let name = match py_to_str(name) {
+                    Some(name) => name,
+                    None => return raise_type_exception("eval() parameter names must be str"),
+                };
+                let val = match Sweep::read(name, val, &mut len) {
+                    Ok(val) => val,
+                    Err(err) => return err,
+                };
+                modelcard.push((name, val));
+            }
+        }
+
+        if unlikely(PyDict_GET_SIZE(kwds) != num_kwargs) {
+            return raise_type_exception("eval() got an unexpected keyword argument");
+        }
+
+        let mut results = Vec::with_capacity(len as usize);
+        for i in 0..len {
+            let modelcard: Vec<_> =
+                modelcard.iter().map(|(name, val)| (*name, val.get(i))).collect();
+            let voltages: Vec<_> = voltages.iter().map(|val| val.get_real(i)).collect();
+            let temperature = temperature.get_real(i);
+            let freq = freq.as_ref().map(|freq| freq.get_real(i));
+            match eval_device(descriptor, &modelcard, &voltages, temperature, freq) {
+                Ok(res) => results.push(res),
+                Err(err) => return raise_runtime_runtime_exception(&format!("eval() {err:#}")),
+            }
+        }
+        EvalResult::to_py(&results, descriptor, freq.is_some(), len != 1)
+    }
+}
+
+/// An argument of [`OsdiDevice::eval`] that is either a scalar or a numpy array that is swept.
+enum Sweep<'a> {
+    Scalar(ParamValue<'a>),
+    Array(NumpyArray),
+}
+
+impl<'a> Sweep<'a> {
+    /// Reads a str, int, float or a one dimensional numpy array of ints or floats. All arrays
+    /// must have the same length `len` (arrays with a single element are broadcast).
+    unsafe fn read(
+        name: &str,
+        val: *mut PyObject,
+        len: &mut isize,
+    ) -> Result<Sweep<'a>, *mut PyObject> {
+        let ty = ob_type!(val);
+        let val = if is_array(ty) {
+            let arr = match NumpyArray::new(val) {
+                Ok(arr) => arr,
+                Err(_) => return Err(raise_illegal_data_type_exception(name)),
+            };
+            if arr.len() != 1 {
+                if *len == 1 {
+                    *len = arr.len();
+                } else if unlikely(*len != arr.len()) {
+                    return Err(raise_type_exception(&format!(
+                        "eval() all arrays must have the same length but '{}' has length {} \
+                         (expected {})",
+                        name,
+                        arr.len(),
+                        len
+                    )));
+                }
+            }
+            Sweep::Array(arr)
+        } else if PyUnicode_Check(val) != 0 {
+            match py_to_str(val) {
+                Some(val) => Sweep::Scalar(ParamValue::Str(val)),
+                None => return Err(raise_illegal_data_type_exception(name)),
+            }
+        } else if is_int(ty) {
+            let val = PyLong_AsLong(val);
+            if unlikely(!PyErr_Occurred().is_null()) {
+                return Err(ptr::null_mut());
+            }
+            Sweep::Scalar(ParamValue::Int(val as i32))
+        } else if is_float(ty) {
+            Sweep::Scalar(ParamValue::Real(PyFloat_AS_DOUBLE(val)))
+        } else {
+            return Err(raise_illegal_data_type_exception(name));
+        };
+        Ok(val)
+    }
+
+    /// Like [`Sweep::read`] but only accepts numbers (ints are converted) and float arrays.
+    unsafe fn read_real(
+        name: &str,
+        val: *mut PyObject,
+        len: &mut isize,
+    ) -> Result<Sweep<'a>, *mut PyObject> {
+        match Sweep::read(name, val, len)? {
+            Sweep::Scalar(ParamValue::Int(val)) => Ok(Sweep::Scalar(ParamValue::Real(val as f64))),
+            Sweep::Scalar(ParamValue::Str(_)) => Err(raise_illegal_data_type_exception(name)),
+            Sweep::Array(arr) if arr.kind != ItemType::Float => Err(raise_type_exception(
+                &format!("eval() expected float64 array for '{}' (found {} array)", name, arr.kind),
+            )),
+            val => Ok(val),
+        }
+    }
+
+    /// The value of the `i`th evaluation of the sweep.
+    unsafe fn get(&self, i: isize) -> ParamValue<'a> {
+        match self {
+            Sweep::Scalar(val) => *val,
+            Sweep::Array(arr) => {
+                let offset = if arr.len() == 1 { 0 } else { i * arr.stride() };
+                match arr.kind {
+                    ItemType::Float => ParamValue::Real(*(arr.data() as *const f64).offset(offset)),
+                    ItemType::Int => ParamValue::Int(*(arr.data() as *const i32).offset(offset)),
+                    ItemType::Long => {
+                        ParamValue::Int(*(arr.data() as *const i64).offset(offset) as i32)
+                    }
+                }
+            }
+        }
+    }
+
+    /// The value of the `i`th evaluation of a sweep created with [`Sweep::read_real`].
+    unsafe fn get_real(&self, i: isize) -> f64 {
+        match self.get(i) {
+            ParamValue::Real(val) => val,
+            _ => unreachable!(),
+        }
+    }
+}
+
+struct EvalResult {
+    residual_resist: Vec<f64>,
+    residual_react: Vec<f64>,
+    jacobian_resist: Vec<f64>,
+    jacobian_react: Vec<f64>,
+    op_vars: Vec<f64>,
+    noise: Option<Vec<f64>>,
+}
+
+impl EvalResult {
+    /// Converts the `results` of all evaluations to a dict of numpy arrays. If the evaluation was
+    /// `swept` the results are stacked along a new leading dimension.
+    unsafe fn to_py(
+        results: &[EvalResult],
+        descriptor: Descriptor,
+        noise: bool,
+        swept: bool,
+    ) -> *mut PyObject {
+        let num_nodes = descriptor.nodes().len() as Py_intptr_t;
+        let num_op_vars = scalar_opvars(descriptor).count() as Py_intptr_t;
+        let num_noise_sources = descriptor.noise_sources().len() as Py_intptr_t;
+        fn stack(results: &[EvalResult], field: impl Fn(&EvalResult) -> &[f64]) -> Vec<f64> {
+            results.iter().flat_map(field).copied().collect()
+        }
+        let mut arrays = vec![
+            ("residual_resist\0", vec![num_nodes], stack(results, |res| &res.residual_resist)),
+            ("residual_react\0", vec![num_nodes], stack(results, |res| &res.residual_react)),
+            (
+                "jacobian_resist\0",
+                vec![num_nodes, num_nodes],
+                stack(results, |res| &res.jacobian_resist),
+            ),
+            (
+                "jacobian_react\0",
+                vec![num_nodes, num_nodes],
+                stack(results, |res| &res.jacobian_react),
+            ),
+            ("op_vars\0", vec![num_op_vars], stack(results, |res| &res.op_vars)),
+        ];
+        if noise {
+            let noise = stack(results, |res| res.noise.as_deref().unwrap());
+            arrays.push(("noise\0", vec![num_noise_sources], noise));
+        }
+
+        let dict = PyDict_New();
+        if dict.is_null() {
+            return ptr::null_mut();
+        }
+        for (name, mut dims, data) in arrays {
+            if swept {
+                dims.insert(0, results.len() as Py_intptr_t);
+            }
+            let arr = match new_float_array(&mut dims, &data) {
+                Some(arr) => arr,
+                None => {
+                    Py_DECREF(dict);
+                    return raise_runtime_runtime_exception("eval() requires numpy");
+                }
+            };
+            if arr.is_null() {
+                Py_DECREF(dict);
+                return ptr::null_mut();
+            }
+            let code = PyDict_SetItemString(dict, name.as_ptr() as *const c_char, arr);
+            Py_DECREF(arr);
+            if code != 0 {
+                Py_DECREF(dict);
+                return ptr::null_mut();
+            }
+        }
+        dict
+    }
+}
+
+/// Sets up a single instance of `descriptor` with all terminals connected and evaluates it at
+/// the node `voltages`.
+fn eval_device(
+    descriptor: Descriptor,
+    modelcard: &[(&str, ParamValue)],
+    voltages: &[f64],
+    temperature: f64,
+    freq: Option<f64>,
+) -> Result<EvalResult> {
+    let model = descriptor.new_model();
+    for &(name, val) in modelcard {
+        let val = match (descriptor.find_param(name).map(|param| param.ty()), val) {
+            // allow conversion of integers
+            (Some(ParamTy::Real), ParamValue::Int(val)) => ParamValue::Real(val as f64),
+            _ => val,
+        };
+        model.set_param(name, val)?;
+    }
+    model.process_params()?;
+
+    let mut instance = model.new_instance();
+    let num_terminals = descriptor.num_terminals();
+    let internal_nodes = instance.process_params(temperature, num_terminals)?;
+    // unknown 0 is ground
+    instance.map_nodes(|node| if node == u32::MAX { 0 } else { node + 1 });
+
+    let mut solve = vec![0.0; 1 + num_terminals as usize + internal_nodes.len()];
+    // iterate in reverse so that collapsed nodes use the voltage of the first node
+    for (node, &unknown) in instance.node_mapping().iter().enumerate().rev() {
+        if unknown != 0 {
+            solve[unknown as usize] = voltages[node];
+        }
+    }
+
+    let entries = descriptor.jacobian_entries();
+    let mut jacobian_resist = vec![0.0; entries.len()];
+    let mut jacobian_react = vec![0.0; entries.len()];
+    for i in 0..entries.len() {
+        // SAFETY: the buffers outlive the instance and are not reallocated
+        unsafe {
+            instance.bind_jacobian(
+                i,
+                jacobian_resist.as_mut_ptr().add(i),
+                jacobian_react.as_mut_ptr().add(i),
+            )
+        }
+    }
+
+    let num_states = descriptor.num_states() as usize;
+    let prev_state = vec![0.0; num_states];
+    let mut next_state = vec![0.0; num_states];
+    let mut flags = EvalFlags::CALC_RESIST
+        | EvalFlags::CALC_REACT
+        | EvalFlags::CALC_OP
+        | EvalFlags::ANALYSIS_DC
+        | EvalFlags::ANALYSIS_STATIC;
+    if freq.is_some() {
+        flags |= EvalFlags::CALC_NOISE | EvalFlags::ANALYSIS_NOISE;
+    }
+    let mut info = SimInfo {
+        abstime: 0.0,
+        prev_solve: &solve,
+        prev_state: &prev_state,
+        next_state: &mut next_state,
+        flags,
+    };
+    if instance.eval(&mut info).is_fatal() {
+        bail!("$fatal was called during the evaluation")
+    }
+    // SAFETY: all entries were bound above
+    unsafe {
+        instance.load_jacobian_resist();
+        instance.load_jacobian_react(1.0);
+    }
+
+    let num_nodes = descriptor.nodes().len();
+    let mut res = EvalResult {
+        residual_resist: (0..num_nodes as u32).map(|node| instance.residual_resist(node)).collect(),
+        residual_react: (0..num_nodes as u32).map(|node| instance.residual_react(node)).collect(),
+        jacobian_resist: vec![0.0; num_nodes * num_nodes],
+        jacobian_react: vec![0.0; num_nodes * num_nodes],
+        op_vars: scalar_opvars(descriptor)
+            .map(|opvar| instance.read_opvar(opvar.id))
+            .collect::<Result<_>>()?,
+        noise: None,
+    };
+    for (entry, (resist, react)) in zip(entries, zip(jacobian_resist, jacobian_react)) {
+        let i = entry.nodes.node_1 as usize * num_nodes + entry.nodes.node_2 as usize;
+        res.jacobian_resist[i] += resist;
+        res.jacobian_react[i] += react;
+    }
+    if let Some(freq) = freq {
+        let mut noise = vec![0.0; descriptor.noise_sources().len()];
+        instance.load_noise(freq, &mut noise);
+        res.noise = Some(noise);
+    }
+    Ok(res)
+}
+
+struct DictIter {
+    dict: *mut PyObject,
+    pos: Py_ssize_t,
+}
+
+impl DictIter {
+    fn new(dict: *mut PyObject) -> DictIter {
+        DictIter { dict, pos: 0 }
+    }
+}
+
+impl Iterator for DictIter {
+    type Item = (*mut PyObject, *mut PyObject);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut key: *mut PyObject = ptr::null_mut();
+        let mut val: *mut PyObject = ptr::null_mut();
+        let found = unsafe { PyDict_Next(self.dict, &mut self.pos, &mut key, &mut val) };
+        (found != 0).then_some((key, val))
+    }
+}
+
+/// The returned str borrows from `val` which must be kept alive by the caller.
+unsafe fn py_to_str<'a>(val: *mut PyObject) -> Option<&'a str> {
+    let mut size = 0;
+    let data = PyUnicode_AsUTF8AndSize(val, &mut size) as *const u8;
+    if data.is_null() {
+        PyErr_Clear();
+        return None;
+    }
+    std::str::from_utf8(std::slice::from_raw_parts(data, size as usize)).ok()
+}
+
+#[cold]
+#[inline(never)]
+fn raise_illegal_data_type_exception(name: &str) -> *mut PyObject {
+    raise_type_exception(&format!("eval() received unsupprted data type for '{}'", name))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn load_osdi_py(_self: *mut PyObject, path: *mut PyObject) -> *mut PyObject {
+    let path = match OsStr::new_path(path) {
+        Some(Some(path)) => path,
+        Some(None) => {
+            return raise_type_exception(
+                "load_osdi() positional argument 'path' must be a pathlib Path or str",
+            )
+        }
+        None => return ptr::null_mut(),
+    };
+    let path = std::slice::from_raw_parts(path.data.ptr, path.data.len);
+    let path = Utf8Path::new(std::str::from_utf8(path).unwrap());
+
+    let lib = match Library::load(path) {
+        Ok(lib) => lib,
+        Err(err) => return raise_runtime_runtime_exception(&format!("load_osdi() {err:#}")),
+    };
+
+    let devices = PyList_New(lib.descriptors().len() as isize);
+    if devices.is_null() {
+        return ptr::null_mut();
+    }
+    for (i, &descriptor) in lib.descriptors().iter().enumerate() {
+        let device = OsdiDevice::new(descriptor);
+        if device.is_null() {
+            Py_DECREF(devices);
+            return ptr::null_mut();
+        }
+        PyList_SetItem(devices, i as isize, device);
+    }
+    devices
+}
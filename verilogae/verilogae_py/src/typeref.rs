@@ -43,6 +43,8 @@ pub static mut VFS_STR: *mut PyObject = 0 as *mut PyObject;
 pub static mut VOLTAGES_STR: *mut PyObject = 0 as *mut PyObject;
 pub static mut CURRENTS_STR: *mut PyObject = 0 as *mut PyObject;
 pub static mut TEMPERATURE_STR: *mut PyObject = 0 as *mut PyObject;
+pub static mut MODELCARD_STR: *mut PyObject = 0 as *mut PyObject;
+pub static mut FREQ_STR: *mut PyObject = 0 as *mut PyObject;
 
 static INIT: Once = Once::new();
 
@@ -60,6 +62,8 @@ pub fn init_typerefs() {
         VOLTAGES_STR = PyUnicode_InternFromString("voltages\0".as_ptr() as *const c_char);
         CURRENTS_STR = PyUnicode_InternFromString("currents\0".as_ptr() as *const c_char);
         TEMPERATURE_STR = PyUnicode_InternFromString("temperature\0".as_ptr() as *const c_char);
+        MODELCARD_STR = PyUnicode_InternFromString("modelcard\0".as_ptr() as *const c_char);
+        FREQ_STR = PyUnicode_InternFromString("freq\0".as_ptr() as *const c_char);
         EMPTY_UNICODE = PyUnicode_New(0, 255);
 
         ARRAY_STRUCT_STR =